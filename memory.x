@@ -2,7 +2,10 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x00000000, LENGTH = 512K
+  /* The last two 4K pages are kept out of the firmware: 0x7F000 holds the
+     saved calibration and 0x7E000 the other settings (see STORAGE_PAGE and
+     SETTINGS_PAGE in src/board.rs) */
+  FLASH : ORIGIN = 0x00000000, LENGTH = 504K
   RAM : ORIGIN = 0x20000000, LENGTH = 128K
 }
 
@@ -0,0 +1,129 @@
+//! Animations
+//!
+//! Instead of holding the calibrated color, the LED can run an animation
+//! started from the host (the `anim` command): breathing, which swells and
+//! fades the current color, a rainbow cycling through the hues, or the color
+//! sequence. `anim off` returns to manual control. Breathing can also be
+//! toggled without a host, with a long press of A+B; it then breathes at the
+//! period last given to `anim breathe`.
+//!
+//! Breathing and the rainbow are computed by the RGB task once per frame
+//! from the time since scanning started: they work on the output levels,
+//! after the corrections, and still pass the slew limit and the emergency
+//! off. The sequence is played by its own task (see the `sequence` module),
+//! which writes the requested levels instead.
+
+/// Shortest breathing or rainbow period accepted, in milliseconds
+pub const MIN_ANIMATION_PERIOD_MS: u32 = 200;
+
+/// Longest breathing or rainbow period accepted, in milliseconds
+pub const MAX_ANIMATION_PERIOD_MS: u32 = 60_000;
+
+/// Breathing period the button toggle uses until `anim breathe` sets
+/// another, in milliseconds
+pub const DEFAULT_BREATHE_PERIOD_MS: u32 = 4000;
+
+/// Animation the LED runs instead of manual control
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Animation {
+    /// Manual control: the levels are shown as set
+    #[default]
+    Off,
+    /// The current color swells from dark to full and back
+    Breathe {
+        /// Time for one breath in milliseconds
+        period_ms: u32,
+    },
+    /// All hues in turn, as bright as the brightest current channel
+    Rainbow {
+        /// Time for one trip around the color wheel in milliseconds
+        period_ms: u32,
+    },
+    /// The color sequence plays on a loop
+    Sequence,
+}
+
+impl Animation {
+    /// Name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            Animation::Off => "off",
+            Animation::Breathe { .. } => "breathe",
+            Animation::Rainbow { .. } => "rainbow",
+            Animation::Sequence => "seq",
+        }
+    }
+
+    /// Period of a timed animation
+    ///
+    /// # Returns
+    /// The period in milliseconds, or `None` for manual control and the
+    /// sequence
+    pub fn period_ms(self) -> Option<u32> {
+        match self {
+            Animation::Breathe { period_ms } | Animation::Rainbow { period_ms } => Some(period_ms),
+            Animation::Off | Animation::Sequence => None,
+        }
+    }
+
+    /// Whether the RGB task computes this animation itself
+    pub fn per_frame(self) -> bool {
+        self.period_ms().is_some()
+    }
+
+    /// Animate a frame's output levels
+    ///
+    /// # Arguments
+    /// * `elapsed_us` - Microseconds since scanning started
+    /// * `targets` - Output [red, green, blue] levels of the frame, replaced
+    ///   by the animated ones
+    pub fn apply(self, elapsed_us: u64, targets: &mut [u32; 3]) {
+        let Some(period_ms) = self.period_ms() else {
+            return;
+        };
+        let period = u64::from(period_ms.max(1));
+        // Position within the period in thousandths
+        let phase = (elapsed_us / 1000 % period * 1000 / period) as u32;
+        match self {
+            Animation::Breathe { .. } => {
+                // Triangle up and down, squared so the dim end lingers as
+                // the eye expects
+                let triangle = if phase < 500 {
+                    phase * 2
+                } else {
+                    (1000 - phase) * 2
+                };
+                let scale = triangle * triangle / 1000;
+                for level in targets.iter_mut() {
+                    *level = (*level * scale + 500) / 1000;
+                }
+            }
+            Animation::Rainbow { .. } => {
+                *targets = hue_levels(phase * 6, targets.iter().copied().max().unwrap_or(0));
+            }
+            Animation::Off | Animation::Sequence => (),
+        }
+    }
+}
+
+/// Levels of a fully saturated hue
+///
+/// # Arguments
+/// * `hue` - Position on the color wheel in thousandths of a sixth (0 red,
+///   2000 green, 4000 blue, up to 6000)
+/// * `brightness` - Level of the brightest channel
+///
+/// # Returns
+/// [red, green, blue] levels
+fn hue_levels(hue: u32, brightness: u32) -> [u32; 3] {
+    let rising = (brightness * (hue % 1000) + 500) / 1000;
+    let falling = brightness - rising;
+    match hue / 1000 % 6 {
+        0 => [brightness, rising, 0],
+        1 => [falling, brightness, 0],
+        2 => [0, brightness, rising],
+        3 => [0, falling, brightness],
+        4 => [rising, 0, brightness],
+        _ => [brightness, 0, falling],
+    }
+}
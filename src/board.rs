@@ -0,0 +1,164 @@
+//! Board Pin Assignments
+//!
+//! Every pin the tool uses is assigned here, along with how the LED pins
+//! are driven, so a different wiring or carrier board only needs changes in
+//! this file. The default mapping is the
+//! MicroBit v2 edge connector wiring described in the README.
+
+use crate::*;
+
+#[cfg(feature = "serial")]
+use microbit_bsp::embassy_nrf::peripherals::{P0_06, P1_08, UARTE0};
+use microbit_bsp::{
+    embassy_nrf::peripherals::{NVMC, P0_03, P0_04, SAADC, TEMP, WDT},
+    LedMatrix,
+};
+
+/// Pin type of the knob's ADC input (must be an analog-capable pin)
+pub type KnobPin = P0_04;
+
+/// Pin type of the LED current sense input (must be an analog-capable pin)
+pub type SensePin = P0_03;
+
+/// Edge connector names of the [red, green, blue] LED pins
+///
+/// Printed by the pin identification routine; keep in sync with
+/// [`BoardPins::new`].
+pub const LED_PIN_NAMES: [&str; 3] = ["P9", "P8", "P16"];
+
+/// Start of the flash page holding the saved calibration
+///
+/// The last 4K page of the nRF52833's 512K flash; `memory.x` ends the
+/// firmware's flash before it, so a bigger firmware can never overwrite it.
+pub const STORAGE_PAGE: u32 = 0x7_f000;
+
+/// Start of the flash page holding the saved presets and other settings
+///
+/// The page before [`STORAGE_PAGE`], also kept out of the firmware's flash
+/// by `memory.x`.
+pub const SETTINGS_PAGE: u32 = 0x7_e000;
+
+/// How each [red, green, blue] LED pin is driven
+///
+/// Every channel drives its LED directly by default. With an external
+/// switch, describe it here, e.g. a PNP high-side transistor whose base has
+/// a pull-up:
+///
+/// ```ignore
+/// OutputProfile {
+///     polarity: Polarity::ActiveLow,
+///     idle: Idle::Floating,
+///     drive: DriveStrength::Standard,
+/// }
+/// ```
+pub const LED_OUTPUTS: [OutputProfile; 3] = [OutputProfile::DIRECT; 3];
+
+/// Duty tables of the [red, green, blue] LEDs, used while gamma is on
+///
+/// Every channel follows the 2.2 gamma curve by default. The LEDs' forward
+/// voltages differ, so if white drifts toward one color at the dim end,
+/// give that channel its own table here (see [`DutyTable`]).
+pub const LED_DUTY_TABLES: [DutyTable; 3] = [GAMMA_DUTY_TABLE; 3];
+
+/// Hardware resources used by the tool, taken from the board
+pub struct BoardPins {
+    /// Red LED output
+    pub red: AnyPin,
+    /// Green LED output
+    pub green: AnyPin,
+    /// Blue LED output
+    pub blue: AnyPin,
+    /// Potentiometer wiper (ADC input)
+    pub knob: KnobPin,
+    /// LED current sense resistor (ADC input)
+    #[cfg(feature = "led-sense")]
+    pub sense: SensePin,
+    /// ADC peripheral sampling the knob
+    pub saadc: SAADC,
+    /// Button A (active low)
+    pub btn_a: Button,
+    /// Button B (active low)
+    pub btn_b: Button,
+    /// Onboard 5x5 LED matrix
+    pub display: LedMatrix,
+    /// Watchdog timer guarding the scan loop
+    pub wdt: WDT,
+    /// Die temperature sensor timing the knob ADC's recalibration
+    pub temp: TEMP,
+    /// Flash controller writing the saved calibration
+    pub nvmc: NVMC,
+    /// Rotary encoder signal A
+    #[cfg(feature = "encoder")]
+    pub encoder_a: AnyPin,
+    /// Rotary encoder signal B
+    #[cfg(feature = "encoder")]
+    pub encoder_b: AnyPin,
+    /// UART to the interface chip, carrying the serial console
+    #[cfg(feature = "serial")]
+    pub uarte: UARTE0,
+    /// Console UART receive line, from the interface chip
+    #[cfg(feature = "serial")]
+    pub uart_rx: AnyPin,
+    /// Console UART transmit line, to the interface chip
+    #[cfg(feature = "serial")]
+    pub uart_tx: AnyPin,
+}
+
+impl BoardPins {
+    /// Assign the board's pins to their functions
+    ///
+    /// Default mapping:
+    /// - Red LED: P9, green LED: P8, blue LED: P16 (see [`LED_PIN_NAMES`])
+    /// - Knob: P2
+    /// - LED current sense (with the `led-sense` feature): P1
+    /// - Buttons: the MicroBit's A and B buttons
+    /// - Brightness indicator: the MicroBit's LED matrix
+    /// - Watchdog: the nRF's WDT, which the board support crate doesn't
+    ///   hand out
+    /// - Die temperature: the nRF's TEMP sensor, taken the same way
+    /// - Saved calibration: the nRF's NVMC, taken the same way, writing the
+    ///   page at [`STORAGE_PAGE`]
+    /// - Encoder (with the `encoder` feature): P13 and P14
+    /// - Serial console (with the `serial` feature): the nRF's UARTE0 on
+    ///   P0.06 (TX) and P1.08 (RX), the lines to the interface chip, taken
+    ///   like the WDT
+    ///
+    /// # Arguments
+    /// * `board` - Initialized MicroBit board
+    ///
+    /// # Returns
+    /// The resources used by the tool
+    pub fn new(board: Microbit) -> Self {
+        Self {
+            red: AnyPin::from(board.p9),
+            green: AnyPin::from(board.p8),
+            blue: AnyPin::from(board.p16),
+            knob: board.p2,
+            #[cfg(feature = "led-sense")]
+            sense: board.p1,
+            saadc: board.saadc,
+            btn_a: board.btn_a,
+            btn_b: board.btn_b,
+            display: board.display,
+            // SAFETY: the board support crate keeps no handle to the WDT
+            // and nothing else takes it
+            wdt: unsafe { WDT::steal() },
+            // SAFETY: as for the WDT, nothing else takes the TEMP sensor
+            temp: unsafe { TEMP::steal() },
+            // SAFETY: as for the WDT, nothing else takes the flash controller
+            nvmc: unsafe { NVMC::steal() },
+            #[cfg(feature = "encoder")]
+            encoder_a: AnyPin::from(board.p13),
+            #[cfg(feature = "encoder")]
+            encoder_b: AnyPin::from(board.p14),
+            // SAFETY: as for the WDT, nothing else takes the console UART
+            // or its pins
+            #[cfg(feature = "serial")]
+            uarte: unsafe { UARTE0::steal() },
+            #[cfg(feature = "serial")]
+            uart_rx: AnyPin::from(unsafe { P1_08::steal() }),
+            #[cfg(feature = "serial")]
+            uart_tx: AnyPin::from(unsafe { P0_06::steal() }),
+        }
+    }
+}
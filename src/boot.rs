@@ -0,0 +1,131 @@
+//! Boot Mode Selection
+//!
+//! Chooses a startup behavior from the buttons held while the board boots,
+//! before any task starts. Mapping:
+//! - No buttons: Normal calibration
+//! - A held: Self-test (light each LED channel in turn), then normal
+//!   calibration
+//! - A+B held: Save the starting calibration to flash once it is set up,
+//!   then normal calibration
+//! - B held: Reserved, currently normal calibration
+//!
+//! It also resolves where the UI takes its starting levels and frame rate
+//! from (see [`BootSource`]): each value comes from the first source in the
+//! boot priority that has one.
+
+use crate::*;
+
+/// Time in milliseconds each channel is lit during the self-test
+const SELF_TEST_MS: u64 = 500;
+
+/// Startup behavior selected by the buttons held at boot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootMode {
+    /// Start calibrating immediately
+    Normal,
+    /// Run the LED self-test before calibrating
+    SelfTest,
+    /// Save the starting calibration to flash, replacing a saved one
+    Save,
+}
+
+impl BootMode {
+    /// Select the boot mode from the buttons held at boot
+    ///
+    /// # Arguments
+    /// * `button_a` - MicroBit button A
+    /// * `button_b` - MicroBit button B
+    ///
+    /// # Returns
+    /// Boot mode for the held combination
+    pub fn from_buttons(button_a: &Button, button_b: &Button) -> Self {
+        match (button_a.is_low(), button_b.is_low()) {
+            (true, false) => BootMode::SelfTest,
+            (true, true) => BootMode::Save,
+            _ => BootMode::Normal,
+        }
+    }
+
+    /// Short name for log output
+    pub fn name(self) -> &'static str {
+        match self {
+            BootMode::Normal => "normal",
+            BootMode::SelfTest => "self-test",
+            BootMode::Save => "save",
+        }
+    }
+}
+
+/// Where a starting value can come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootSource {
+    /// The saved calibration given as startup settings (see
+    /// `Ui::with_startup`), for the values it sets; `main` passes the one
+    /// loaded from flash
+    Saved,
+    /// The knob position at boot; gives the frame rate only
+    Knob,
+    /// The built-in defaults: full white at 100 fps
+    Default,
+}
+
+impl BootSource {
+    /// Short name for log output
+    pub fn name(self) -> &'static str {
+        match self {
+            BootSource::Saved => "saved",
+            BootSource::Knob => "knob",
+            BootSource::Default => "default",
+        }
+    }
+}
+
+/// Pick a starting value by boot priority
+///
+/// The built-in default is used if no source in the priority has a value,
+/// even when the priority leaves it out.
+///
+/// # Arguments
+/// * `priority` - Sources in the order they are tried
+/// * `saved` - Value of the saved calibration, if it sets one
+/// * `knob` - Value read from the knob, if it gives one
+/// * `default` - Built-in default value
+///
+/// # Returns
+/// The value and the source it came from
+pub fn resolve_boot_source<T: Copy>(
+    priority: &[BootSource],
+    saved: Option<T>,
+    knob: Option<T>,
+    default: T,
+) -> (T, BootSource) {
+    priority
+        .iter()
+        .find_map(|&source| {
+            let value = match source {
+                BootSource::Saved => saved,
+                BootSource::Knob => knob,
+                BootSource::Default => Some(default),
+            };
+            value.map(|value| (value, source))
+        })
+        .unwrap_or((default, BootSource::Default))
+}
+
+/// Light each LED channel on its own so wiring faults are easy to spot
+///
+/// Each channel is driven fully on for `SELF_TEST_MS`; only one LED is on
+/// at any time, as required by the resistor-less wiring.
+///
+/// # Arguments
+/// * `pins` - LED output pins [red, green, blue]
+pub async fn self_test(pins: &mut RgbPins) {
+    let names = ["red", "green", "blue"];
+    for ((pin, name), profile) in pins.iter_mut().zip(names).zip(LED_OUTPUTS) {
+        rprintln!("self-test: {}", name);
+        profile.set(pin, true);
+        Timer::after_millis(SELF_TEST_MS).await;
+        profile.set(pin, false);
+    }
+    rprintln!("self-test: done");
+}
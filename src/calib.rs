@@ -0,0 +1,1022 @@
+//! Calibration Math
+//!
+//! The conversions behind the knob, the frame rate and the multiplex timing,
+//! kept free of hardware and async code: plain numbers in, plain numbers
+//! out. The UI, the knob and the RGB task call these instead of carrying
+//! their own copies.
+//!
+//! The module is self-contained: it owns the frame rate range, the
+//! luminance weights and the level fractions used for blending (see
+//! [`FRACTION_ONE`]), and the number of levels is passed in (the firmware
+//! passes `LEVELS`), so it builds as part of the library target and its
+//! tests run on the host (see the README).
+//!
+//! The white point estimate shown by the UI is here too (see
+//! [`chromaticity`]), as are the hue conversion of the knob's hue mode (see
+//! [`hsv_to_rgb`]), the knee curve behind `Lut::knee` (see [`knee_curve`])
+//! and the minimum visible level remap applied after the lookup tables (see
+//! [`remap_visible`]). Color conversions that belong with their data stay
+//! there (see `ColorMatrix`).
+
+/// Lowest frame rate accepted anywhere, in fps
+///
+/// Frame rates this low visibly strobe, so they are only reachable with
+/// the strobe guard off (see `STROBE_MIN_FPS`), e.g. to find a particular
+/// LED's flicker-fusion threshold.
+pub const MIN_FRAME_RATE: u64 = 10;
+
+/// Frame rate selected with the knob at its maximum position
+pub const MAX_FRAME_RATE: u64 = 160;
+
+/// Relative luminance weight of each channel [red, green, blue], per mille
+/// (Rec. 709 primaries; the actual LED dies will differ somewhat)
+///
+/// Also used for the white point estimate (see [`chromaticity`]); tune
+/// together with [`LED_CHROMATICITY`] for a specific LED.
+pub const LUMINANCE_WEIGHTS: [f32; 3] = [212.6, 715.2, 72.2];
+
+/// Convert continuous knob level (0.0-15.0 with 16 levels) to frame rate
+///
+/// Maps the knob travel linearly onto floor..=MAX_FRAME_RATE (50-160 fps
+/// under the default strobe guard), so any frame rate in between can be
+/// selected and no knob travel is wasted below the guard.
+///
+/// # Arguments
+/// * `level` - Fractional knob position (0.0 to levels-1)
+/// * `floor` - Frame rate at the bottom of the travel, e.g. the strobe
+///   guard's floor (at least MIN_FRAME_RATE, below MAX_FRAME_RATE)
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Unrounded frame rate in fps
+pub fn level_to_frame_rate(level: f32, floor: u64, levels: u32) -> f32 {
+    let floor = floor.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE);
+    let span = (MAX_FRAME_RATE - floor) as f32;
+    floor as f32 + level / (levels - 1) as f32 * span
+}
+
+/// Convert a frame rate back to the knob level that selects it
+///
+/// Inverse of [`level_to_frame_rate`].
+///
+/// # Arguments
+/// * `frame_rate` - Frame rate in fps
+/// * `floor` - Frame rate at the bottom of the travel
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Fractional knob position (0.0 to levels-1)
+pub fn frame_rate_to_level(frame_rate: u64, floor: u64, levels: u32) -> f32 {
+    let floor = floor.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE - 1);
+    let span = (MAX_FRAME_RATE - floor) as f32;
+    (frame_rate.saturating_sub(floor) as f32 / span * (levels - 1) as f32).min((levels - 1) as f32)
+}
+
+/// Position of a raw knob reading between the calibrated ends
+///
+/// # Arguments
+/// * `raw` - Raw ADC reading
+/// * `min` - Raw reading at the low end
+/// * `max` - Raw reading at the high end (above `min`)
+///
+/// # Returns
+/// 0.0 at `min`, 1.0 at `max`; readings beyond either end fall outside
+/// that range and are clamped by [`knob_level`]
+pub fn knob_position(raw: u16, min: u16, max: u16) -> f32 {
+    (raw as f32 - min as f32) / (max - min) as f32
+}
+
+/// Map a knob position onto the levels, with a deadband at each end
+///
+/// The live travel between the deadbands spans 0.0 to levels-1; with 16
+/// levels and the default deadbands this is the original
+/// `18 * position - 2` mapping.
+///
+/// # Arguments
+/// * `position` - Knob position, 0.0 to 1.0 between the calibrated ends
+///   (may fall outside)
+/// * `low_deadband` - Dead travel at the low end, as a fraction
+/// * `high_deadband` - Dead travel at the high end, as a fraction
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Fractional level, clamped to 0.0 to levels-1
+pub fn knob_level(position: f32, low_deadband: f32, high_deadband: f32, levels: u32) -> f32 {
+    let live = 1.0 - low_deadband - high_deadband;
+    let position = (position - low_deadband) / live;
+    (position * (levels - 1) as f32).clamp(0.0, (levels - 1) as f32)
+}
+
+/// Calculate tick time in microseconds from frame rate
+///
+/// Frame rate determines how many complete RGB scans occur per second.
+/// Each frame has 3 colors × levels brightness steps, so:
+/// tick_time = 1_000_000 μs/sec ÷ (3 colors × frame_rate × levels)
+///
+/// rounded to the nearest microsecond (208 at 100 fps, for 208.3), so
+/// whole-tick frames come as close to the requested rate as they can.
+/// The divisor saturates and a frame rate of 0 counts as 1, so no frame
+/// rate can overflow or divide by zero; absurdly high rates give 0.
+///
+/// # Arguments
+/// * `frame_rate` - Target frames per second
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Microseconds per brightness tick
+pub fn frame_tick_time(frame_rate: u64, levels: u32) -> u64 {
+    let ticks = (3 * levels as u64).saturating_mul(frame_rate.max(1));
+    (1_000_000 + ticks / 2) / ticks
+}
+
+/// Calculate the length of one color's slot from frame rate
+///
+/// # Arguments
+/// * `frame_rate` - Target frames per second
+///
+/// # Returns
+/// Microseconds per color slot
+pub fn frame_slot_time(frame_rate: u64) -> u64 {
+    1_000_000 / 3u64.saturating_mul(frame_rate.max(1))
+}
+
+/// Rounding of a slot's on and off times to whole microseconds
+///
+/// The ideal on-time of a level is `level * slot_time / levels`, which is
+/// rarely a whole number of microseconds. The on-time sets the perceived
+/// brightness while the off-time is only slack, so the policy decides where
+/// the rounding error goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Whole ticks: on = level * tick_time, off = (levels - level) *
+    /// tick_time. The tick time is rounded to the nearest microsecond, so
+    /// slots (and frames) run slightly short or long.
+    Truncate,
+    /// On- and off-time each rounded to the nearest microsecond; the slot
+    /// may come out a microsecond long or short.
+    Nearest,
+    /// On-time rounded to the nearest microsecond and the off-time taking
+    /// the rest of the slot, so the on-time is as accurate as possible and
+    /// the slot length is exact.
+    #[default]
+    DistributeToOff,
+}
+
+impl Rounding {
+    /// Lowercase name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            Rounding::Truncate => "truncate",
+            Rounding::Nearest => "nearest",
+            Rounding::DistributeToOff => "distribute",
+        }
+    }
+
+    /// Split a color slot into on and off times
+    ///
+    /// # Arguments
+    /// * `level` - Brightness level (0 to levels-1)
+    /// * `tick_time` - Whole microseconds per level, for [`Rounding::Truncate`]
+    /// * `slot_time` - Microseconds per color slot
+    /// * `levels` - Number of brightness levels
+    ///
+    /// # Returns
+    /// (on-time, off-time) in microseconds
+    pub fn split(self, level: u32, tick_time: u64, slot_time: u64, levels: u32) -> (u64, u64) {
+        let level = level.min(levels) as u64;
+        let levels = levels as u64;
+        let off_level = levels - level;
+        let nearest =
+            |level: u64| level.saturating_mul(slot_time).saturating_add(levels / 2) / levels;
+        match self {
+            Rounding::Truncate => (
+                level.saturating_mul(tick_time),
+                off_level.saturating_mul(tick_time),
+            ),
+            Rounding::Nearest => (nearest(level), nearest(off_level)),
+            Rounding::DistributeToOff => {
+                let on_time = nearest(level);
+                (on_time, slot_time.saturating_sub(on_time))
+            }
+        }
+    }
+}
+
+/// Split a duration into equal integer parts without losing time
+///
+/// Part `index` of `parts` gets the difference of the cumulative shares, so
+/// the parts always sum to exactly `total` even when it is not divisible.
+///
+/// # Arguments
+/// * `total` - Duration to divide
+/// * `index` - Which part (0 to parts-1)
+/// * `parts` - Number of parts
+///
+/// # Returns
+/// Length of the requested part (all of `total` for zero parts)
+pub fn share(total: u64, index: u32, parts: u32) -> u64 {
+    // Widened so the cumulative shares cannot overflow for any total
+    let parts = parts.max(1) as u128;
+    let index = (index as u128).min(parts - 1);
+    let total = total as u128;
+    (total * (index + 1) / parts - total * index / parts) as u64
+}
+
+/// Level fraction representing a whole level
+pub const FRACTION_ONE: u32 = 1000;
+
+/// Interpolate the on-time of a level blended toward the next level
+///
+/// # Arguments
+/// * `on_time` - On-time of the level itself in microseconds
+/// * `next_on_time` - On-time of the next level in microseconds
+/// * `fraction` - Fraction of the way to the next level in
+///   [`FRACTION_ONE`]ths (clamped to one)
+///
+/// # Returns
+/// Blended on-time in microseconds, rounded to the nearest
+pub fn blend_on_time(on_time: u64, next_on_time: u64, fraction: u32) -> u64 {
+    let fraction = fraction.min(FRACTION_ONE) as u128;
+    let one = FRACTION_ONE as u128;
+    // Widened so no pair of on-times can overflow the weighted sum
+    let blended =
+        (on_time as u128 * (one - fraction) + next_on_time as u128 * fraction + one / 2) / one;
+    blended as u64
+}
+
+/// Split a slot into on and off times, blended toward the next level
+///
+/// Both levels are clamped to levels-1 first, so a bad level can never
+/// stretch the slot or underflow its off-time, and the blended on-time
+/// never leaves the slot.
+///
+/// # Arguments
+/// * `split` - (on, off) times of a level, e.g. from [`Rounding::split`]
+/// * `level` - Output level
+/// * `blend` - Next level and the fraction of the way to it in
+///   [`FRACTION_ONE`]ths, if the slot is blended
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// (on-time, off-time) in microseconds, as long as the level's own slot
+pub fn slot_split(
+    split: impl Fn(u32) -> (u64, u64),
+    level: u32,
+    blend: Option<(u32, u32)>,
+    levels: u32,
+) -> (u64, u64) {
+    let (on, off) = split(level.min(levels - 1));
+    let Some((next, fraction)) = blend else {
+        return (on, off);
+    };
+    let (next_on, _) = split(next.min(levels - 1));
+    let slot = on.saturating_add(off);
+    let on = blend_on_time(on, next_on, fraction).min(slot);
+    (on, slot - on)
+}
+
+/// Levels part way through a crossfade
+///
+/// The sequence player interpolates the requested levels, before the
+/// lookup tables, so with a gamma table uploaded the fade is even in
+/// perceptual steps rather than in duty.
+///
+/// # Arguments
+/// * `from` - Levels at the start of the fade
+/// * `to` - Levels at the end of the fade
+/// * `elapsed_ms` - Time into the fade
+/// * `fade_ms` - Length of the fade (greater than 0)
+///
+/// # Returns
+/// Linearly interpolated levels, as whole levels and the fraction of the
+/// way to the next level in [`FRACTION_ONE`]ths
+pub fn crossfade(
+    from: [u32; 3],
+    to: [u32; 3],
+    elapsed_ms: u64,
+    fade_ms: u64,
+) -> ([u32; 3], [u32; 3]) {
+    let elapsed = elapsed_ms.min(fade_ms) as i64;
+    let fade = fade_ms as i64;
+    let one = FRACTION_ONE as i64;
+    let mut levels = [0; 3];
+    let mut fractions = [0; 3];
+    let channels = levels.iter_mut().zip(fractions.iter_mut());
+    for ((level, fraction), (from, to)) in channels.zip(from.into_iter().zip(to)) {
+        // Fixed point, rounding toward the start so the value never
+        // overshoots either end
+        let value = from as i64 * one + (to as i64 - from as i64) * one * elapsed / fade;
+        *level = (value / one) as u32;
+        *fraction = (value % one) as u32;
+    }
+    (levels, fractions)
+}
+
+/// Move an output level one frame's worth toward its target
+///
+/// # Arguments
+/// * `level` - Level output in the previous frame
+/// * `target` - Level requested
+/// * `rate` - Largest change per frame (values below 1 are treated as 1)
+///
+/// # Returns
+/// The level for this frame: `target` once within `rate` of it
+pub fn slew_toward(level: u32, target: u32, rate: u32) -> u32 {
+    let rate = rate.max(1);
+    if target > level {
+        target.min(level.saturating_add(rate))
+    } else {
+        target.max(level.saturating_sub(rate))
+    }
+}
+
+/// Minimum visible level that leaves the levels unchanged
+pub const MIN_VISIBLE_NONE: u32 = 1;
+
+/// Spread the lit levels over a channel's visible range
+///
+/// Level 0 stays off; levels 1 to levels-1 are mapped evenly onto
+/// `min_visible` to levels-1, rounded to the nearest level, so the result
+/// never decreases and the top level is unchanged. With only two levels
+/// the one lit level is already the top one.
+///
+/// # Arguments
+/// * `level` - Output level (clamped to levels-1)
+/// * `min_visible` - Lowest level the channel visibly lights at (clamped to
+///   [`MIN_VISIBLE_NONE`]..=levels-1)
+/// * `levels` - Number of brightness levels (at least 2)
+///
+/// # Returns
+/// Remapped output level (0 to levels-1)
+pub fn remap_visible(level: u32, min_visible: u32, levels: u32) -> u32 {
+    let level = level.min(levels - 1);
+    let floor = min_visible.clamp(MIN_VISIBLE_NONE, levels - 1);
+    if level == 0 {
+        return 0;
+    }
+    let span = levels - 2;
+    if span == 0 {
+        return levels - 1;
+    }
+    floor + ((level - 1) * (levels - 1 - floor) + span / 2) / span
+}
+
+/// Largest exponent accepted for a knee curve
+pub const KNEE_MAX_POWER: u32 = 4;
+
+/// Shape a level with a knee curve
+///
+/// Below the knee the output follows `knee * (level / knee)^power`,
+/// rounded to the nearest level; from the knee up it is the identity.
+/// Both pieces meet at the knee (output = knee there) and neither
+/// decreases, so the curve is continuous and monotonic. A knee of 0 or a
+/// power of 1 gives the identity.
+///
+/// # Arguments
+/// * `level` - Input level (clamped to levels-1)
+/// * `knee` - Level where the curve turns linear (clamped to levels-1)
+/// * `power` - Exponent below the knee (clamped to 1..=KNEE_MAX_POWER)
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Output level (0 to levels-1)
+pub fn knee_curve(level: u32, knee: u32, power: u32, levels: u32) -> u32 {
+    let level = u64::from(level.min(levels - 1));
+    let knee = u64::from(knee.min(levels - 1));
+    let power = power.clamp(1, KNEE_MAX_POWER);
+    if level >= knee {
+        return level as u32;
+    }
+    // knee * (level / knee)^power, in integers and rounded
+    let scale = knee.pow(power - 1);
+    ((level.pow(power) + scale / 2) / scale) as u32
+}
+
+/// Duty table following a 2.2 gamma curve, built at compile time
+///
+/// Entry `level` is `round(u16::MAX * (level / (N - 1))^2.2)`, so level 0
+/// stays fully off and the last level lights the whole slot. `powf` is not
+/// available in a const fn, so x^2.2 is taken as x² times the fifth root
+/// of x.
+///
+/// # Returns
+/// The duty of each of the N levels, in 65535ths of the slot
+pub const fn gamma_duty_table<const N: usize>() -> [u16; N] {
+    let mut table = [0; N];
+    let mut level = 1;
+    while level < N {
+        let x = level as f64 / (N - 1) as f64;
+        table[level] = (x * x * fifth_root(x) * u16::MAX as f64 + 0.5) as u16;
+        level += 1;
+    }
+    table
+}
+
+/// Fifth root of x in 0..=1, by Newton's method
+///
+/// Starting from 1 the iteration approaches the root from above without
+/// overshooting; a fixed number of steps is plenty down to 1 / 255.
+const fn fifth_root(x: f64) -> f64 {
+    let mut root = 1.0;
+    let mut step = 0;
+    while step < 64 {
+        let fourth = root * root * root * root;
+        root = (4.0 * root + x / fourth) / 5.0;
+        step += 1;
+    }
+    root
+}
+
+/// CIE 1931 xy chromaticity of each LED die [red, green, blue] at full duty
+///
+/// The Rec. 709 primaries, matching [`LUMINANCE_WEIGHTS`], so equal levels
+/// come out at D65 (x 0.3127, y 0.3290). Real dies differ, typically with
+/// a deeper red and green; replace these with the datasheet's dominant
+/// colors for a better estimate.
+pub const LED_CHROMATICITY: [[f32; 2]; 3] = [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]];
+
+/// Correlated color temperature below which a white reads as warm, in K
+pub const WARM_BELOW_K: u32 = 4000;
+
+/// Correlated color temperature above which a white reads as cool, in K
+pub const COOL_ABOVE_K: u32 = 7000;
+
+/// Estimate the chromaticity of the light from the output levels
+///
+/// Each channel adds its die's color (see [`LED_CHROMATICITY`]) weighted
+/// by its luminance at full duty (see [`LUMINANCE_WEIGHTS`]) times its
+/// duty. Not colorimetrically exact, but consistent, so it can be used to
+/// steer toward a white point.
+///
+/// # Arguments
+/// * `output` - Output [red, green, blue] levels, as from `output_levels`
+/// * `levels` - Number of brightness levels (full duty)
+///
+/// # Returns
+/// CIE 1931 [x, y] of the mix, or `None` when every channel is dark
+pub fn chromaticity(output: [u32; 3], levels: u32) -> Option<[f32; 2]> {
+    let mut xyz = [0.0f32; 3];
+    for (channel, &level) in output.iter().enumerate() {
+        let [x, y] = LED_CHROMATICITY[channel];
+        let luminance = LUMINANCE_WEIGHTS[channel] * level.min(levels) as f32 / levels as f32;
+        xyz[0] += x / y * luminance;
+        xyz[1] += luminance;
+        xyz[2] += (1.0 - x - y) / y * luminance;
+    }
+    let sum = xyz[0] + xyz[1] + xyz[2];
+    if sum <= 0.0 {
+        return None;
+    }
+    Some([xyz[0] / sum, xyz[1] / sum])
+}
+
+/// Convert a color from hue, saturation and value to levels
+///
+/// Used by the knob's hue mode (`knob hue on`).
+///
+/// # Arguments
+/// * `hue` - Hue in degrees (0 red, 120 green, 240 blue; wraps at 360)
+/// * `saturation` - Saturation in percent (clamped to 100)
+/// * `value` - Level of the brightest channel (clamped to levels-1)
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// [red, green, blue] levels (0 to levels-1), rounded to the nearest level
+pub fn hsv_to_rgb(hue: u32, saturation: u32, value: u32, levels: u32) -> [u32; 3] {
+    let hue = hue % 360;
+    let saturation = saturation.min(100);
+    let max = value.min(levels - 1);
+    let min = (max * (100 - saturation) + 50) / 100;
+    // Distance of the hue into its sixth of the wheel, in degrees
+    let offset = hue % 60;
+    let rising = min + ((max - min) * offset + 30) / 60;
+    let falling = max - ((max - min) * offset + 30) / 60;
+    match hue / 60 {
+        0 => [max, rising, min],
+        1 => [falling, max, min],
+        2 => [min, max, rising],
+        3 => [min, falling, max],
+        4 => [rising, min, max],
+        _ => [max, min, falling],
+    }
+}
+
+/// Approximate the correlated color temperature of a chromaticity
+///
+/// Uses McCamy's cubic, `449n³ + 3525n² + 6823.3n + 5520.33` with
+/// `n = (x - 0.3320) / (0.1858 - y)`, good to a few tens of K from 2000 K
+/// to 12500 K near the blackbody curve. Far from white the number loses
+/// its meaning but stays repeatable.
+///
+/// # Arguments
+/// * `xy` - CIE 1931 [x, y] from [`chromaticity`]
+///
+/// # Returns
+/// Color temperature in K (6506 for D65), at least 0
+pub fn color_temperature(xy: [f32; 2]) -> u32 {
+    let [x, y] = xy;
+    let n = (x - 0.3320) / (0.1858 - y);
+    let kelvin = ((449.0 * n + 3525.0) * n + 6823.3) * n + 5520.33;
+    (kelvin + 0.5).max(0.0) as u32
+}
+
+/// Warm/cool reading of a color temperature
+///
+/// # Arguments
+/// * `kelvin` - Color temperature from [`color_temperature`]
+///
+/// # Returns
+/// `warm` below [`WARM_BELOW_K`], `cool` above [`COOL_ABOVE_K`], else
+/// `neutral`
+pub fn white_balance(kelvin: u32) -> &'static str {
+    match kelvin {
+        k if k < WARM_BELOW_K => "warm",
+        k if k > COOL_ABOVE_K => "cool",
+        _ => "neutral",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The knob's default deadbands (2/18 of travel low, 1/18 high)
+    const LOW: f32 = 2.0 / 18.0;
+    const HIGH: f32 = 1.0 / 18.0;
+
+    /// The knob's default calibration, raw / 10000
+    fn level_at(raw: u16) -> f32 {
+        knob_level(knob_position(raw, 0, 10_000), LOW, HIGH, 16)
+    }
+
+    #[test]
+    fn knob_raw_extremes() {
+        assert_eq!(level_at(0), 0.0);
+        assert_eq!(level_at(10_000), 15.0);
+        // Full scale, past the calibrated end
+        assert_eq!(level_at(0x3fff), 15.0);
+        // Inside the deadbands
+        assert_eq!(level_at(1_000), 0.0);
+        assert_eq!(level_at(9_500), 15.0);
+    }
+
+    #[test]
+    fn knob_original_mapping() {
+        // The original `18 * position - 2`
+        for raw in [2_000u16, 5_000, 8_000] {
+            let expected = 18.0 * raw as f32 / 10_000.0 - 2.0;
+            assert!((level_at(raw) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn knob_maps_onto_every_level_count() {
+        for levels in [2, 16, 32, 64, 256] {
+            let top = (levels - 1) as f32;
+            let level = |raw: u16| knob_level(knob_position(raw, 0, 10_000), LOW, HIGH, levels);
+            assert_eq!(level(0), 0.0, "{levels} levels");
+            assert_eq!(level(10_000), top, "{levels} levels");
+            // Every level is reached, in order, over the travel
+            let mut next = 0;
+            let mut previous = 0.0;
+            for raw in 0..=10_000 {
+                let fine = level(raw);
+                assert!(fine >= previous && fine <= top, "{levels} levels");
+                if fine.round() as u32 == next {
+                    next += 1;
+                }
+                previous = fine;
+            }
+            assert_eq!(next, levels, "{levels} levels");
+        }
+    }
+
+    #[test]
+    fn knob_position_between_ends() {
+        assert_eq!(knob_position(1_000, 1_000, 3_000), 0.0);
+        assert_eq!(knob_position(2_000, 1_000, 3_000), 0.5);
+        assert_eq!(knob_position(3_000, 1_000, 3_000), 1.0);
+        assert!(knob_position(0, 1_000, 3_000) < 0.0);
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(hsv_to_rgb(0, 100, 15, 16), [15, 0, 0]);
+        assert_eq!(hsv_to_rgb(120, 100, 15, 16), [0, 15, 0]);
+        assert_eq!(hsv_to_rgb(240, 100, 15, 16), [0, 0, 15]);
+        // The hue wraps
+        assert_eq!(hsv_to_rgb(360, 100, 15, 16), [15, 0, 0]);
+    }
+
+    #[test]
+    fn hsv_white_and_black() {
+        for hue in [0, 90, 200, 359] {
+            assert_eq!(hsv_to_rgb(hue, 0, 15, 16), [15, 15, 15]);
+            assert_eq!(hsv_to_rgb(hue, 100, 0, 16), [0, 0, 0]);
+        }
+        // Out of range saturation and value are clamped
+        assert_eq!(hsv_to_rgb(0, 0, 99, 16), [15, 15, 15]);
+        assert_eq!(hsv_to_rgb(0, 250, 15, 16), [15, 0, 0]);
+    }
+
+    #[test]
+    fn hsv_secondaries() {
+        assert_eq!(hsv_to_rgb(60, 100, 15, 16), [15, 15, 0]);
+        assert_eq!(hsv_to_rgb(180, 100, 15, 16), [0, 15, 15]);
+        assert_eq!(hsv_to_rgb(300, 100, 15, 16), [15, 0, 15]);
+        assert_eq!(hsv_to_rgb(30, 100, 63, 64), [63, 32, 0]);
+    }
+
+    #[test]
+    fn equal_levels_are_d65() {
+        // Rec. 709 primaries at their luminance weights mix to D65
+        for level in [1, 8, 15] {
+            let [x, y] = chromaticity([level; 3], 16).unwrap();
+            assert!((x - 0.3127).abs() < 1e-3, "x {x}");
+            assert!((y - 0.3290).abs() < 1e-3, "y {y}");
+        }
+        let kelvin = color_temperature(chromaticity([15; 3], 16).unwrap());
+        assert_eq!(kelvin, 6506);
+        assert_eq!(white_balance(kelvin), "neutral");
+    }
+
+    #[test]
+    fn single_channel_is_its_primary() {
+        for (channel, xy) in LED_CHROMATICITY.iter().enumerate() {
+            let mut output = [0; 3];
+            output[channel] = 15;
+            let [x, y] = chromaticity(output, 16).unwrap();
+            assert!((x - xy[0]).abs() < 1e-4 && (y - xy[1]).abs() < 1e-4);
+        }
+        assert_eq!(chromaticity([0; 3], 16), None);
+    }
+
+    #[test]
+    fn warm_and_cool_whites() {
+        // Illuminant A (2856 K) and a bluish 9300 K white
+        assert_eq!(white_balance(color_temperature([0.4476, 0.4074])), "warm");
+        assert_eq!(white_balance(color_temperature([0.2848, 0.2932])), "cool");
+        let kelvin = color_temperature([0.4476, 0.4074]);
+        assert!((2800..=2900).contains(&kelvin), "{kelvin} K");
+    }
+
+    #[test]
+    fn frame_rate_mapping() {
+        let floor = MIN_FRAME_RATE;
+        assert_eq!(level_to_frame_rate(0.0, floor, 16), MIN_FRAME_RATE as f32);
+        assert_eq!(level_to_frame_rate(15.0, floor, 16), MAX_FRAME_RATE as f32);
+        assert_eq!(level_to_frame_rate(7.5, floor, 16), 85.0);
+        assert_eq!(frame_rate_to_level(MIN_FRAME_RATE, floor, 16), 0.0);
+        assert_eq!(frame_rate_to_level(MAX_FRAME_RATE, floor, 16), 15.0);
+        assert_eq!(frame_rate_to_level(85, floor, 16), 7.5);
+        // Out of range rates stay on the knob's travel
+        assert_eq!(frame_rate_to_level(0, floor, 16), 0.0);
+        assert_eq!(frame_rate_to_level(1_000, floor, 16), 15.0);
+    }
+
+    #[test]
+    fn frame_rate_mapping_from_a_floor() {
+        assert_eq!(level_to_frame_rate(0.0, 50, 16), 50.0);
+        assert_eq!(level_to_frame_rate(15.0, 50, 16), 160.0);
+        assert_eq!(frame_rate_to_level(105, 50, 16), 7.5);
+        assert_eq!(frame_rate_to_level(30, 50, 16), 0.0);
+        // Floors outside the range are clamped into it
+        assert_eq!(level_to_frame_rate(0.0, 0, 16), MIN_FRAME_RATE as f32);
+        assert_eq!(level_to_frame_rate(0.0, 500, 16), MAX_FRAME_RATE as f32);
+        assert_eq!(frame_rate_to_level(160, 500, 16), 15.0);
+    }
+
+    #[test]
+    fn tick_time_zero_fps() {
+        // Counts as 1 fps instead of dividing by zero
+        assert_eq!(frame_tick_time(0, 16), frame_tick_time(1, 16));
+        assert_eq!(frame_slot_time(0), frame_slot_time(1));
+    }
+
+    #[test]
+    fn tick_time_one_fps() {
+        // 1_000_000 / 48 = 20833.3
+        assert_eq!(frame_tick_time(1, 16), 20_833);
+    }
+
+    #[test]
+    fn tick_time_rounds_to_nearest() {
+        // 1_000_000 / 5760 = 173.6, which truncation would make 173
+        assert_eq!(frame_tick_time(120, 16), 174);
+        // 1_000_000 / 4800 = 208.3
+        assert_eq!(frame_tick_time(100, 16), 208);
+    }
+
+    #[test]
+    fn tick_time_absurd_fps() {
+        assert_eq!(frame_tick_time(u64::MAX, 16), 0);
+    }
+
+    #[test]
+    fn tick_time_math() {
+        assert_eq!(frame_tick_time(160, 16), 130);
+        assert_eq!(frame_tick_time(100, 64), 52);
+        assert_eq!(frame_slot_time(100), 3_333);
+        assert_eq!(frame_slot_time(0), 333_333);
+    }
+
+    #[test]
+    fn truncate_uses_whole_ticks() {
+        // 100 fps, 16 levels: 208 us ticks in a 3333 us slot
+        let (tick, slot) = (frame_tick_time(100, 16), frame_slot_time(100));
+        let split = |level| Rounding::Truncate.split(level, tick, slot, 16);
+        assert_eq!(split(0), (0, 3_328));
+        assert_eq!(split(5), (1_040, 2_288));
+        assert_eq!(split(15), (3_120, 208));
+        for level in 0..16 {
+            // Every slot runs the same few microseconds short
+            let (on, off) = split(level);
+            assert_eq!(on + off, 16 * tick);
+        }
+    }
+
+    #[test]
+    fn nearest_rounds_each_half() {
+        let slot = frame_slot_time(100);
+        let split = |level| Rounding::Nearest.split(level, 208, slot, 16);
+        assert_eq!(split(0), (0, 3_333));
+        assert_eq!(split(5), (1_042, 2_291));
+        // Both halves round up, so the slot comes out a microsecond long
+        assert_eq!(split(8), (1_667, 1_667));
+        for level in 0..16 {
+            let (on, off) = split(level);
+            assert!(on + off >= slot && on + off <= slot + 1);
+            let exact = level as f64 * slot as f64 / 16.0;
+            assert!((on as f64 - exact).abs() <= 0.5);
+        }
+        // The default keeps the on-time and trims the off-time instead
+        assert_eq!(
+            Rounding::DistributeToOff.split(8, 208, slot, 16),
+            (1_667, 1_666)
+        );
+    }
+
+    /// Levels output frame by frame while slewing from `from` to `to`
+    fn slew_ramp(from: u32, to: u32, rate: u32) -> Vec<u32> {
+        let mut ramp = vec![from];
+        while *ramp.last().unwrap() != to {
+            ramp.push(slew_toward(*ramp.last().unwrap(), to, rate));
+            assert!(ramp.len() <= 256, "slew never reached {to}");
+        }
+        ramp
+    }
+
+    #[test]
+    fn slew_ramps_one_level_per_frame() {
+        assert_eq!(slew_ramp(0, 15, 1), (0..=15).collect::<Vec<_>>());
+        assert_eq!(slew_ramp(12, 9, 1), [12, 11, 10, 9]);
+    }
+
+    #[test]
+    fn slew_clamps_to_the_rate_and_lands_exactly() {
+        assert_eq!(slew_ramp(0, 15, 4), [0, 4, 8, 12, 15]);
+        assert_eq!(slew_ramp(15, 2, 5), [15, 10, 5, 2]);
+        assert_eq!(slew_ramp(3, 15, 16), [3, 15]);
+        // Zero is treated as one rather than freezing the output
+        assert_eq!(slew_ramp(2, 0, 0), [2, 1, 0]);
+    }
+
+    #[test]
+    fn slew_holds_at_target() {
+        for rate in [1, 3, 16] {
+            assert_eq!(slew_toward(7, 7, rate), 7);
+        }
+        assert_eq!(slew_toward(u32::MAX - 1, u32::MAX, 5), u32::MAX);
+    }
+
+    #[test]
+    fn sub_frames_keep_each_on_time() {
+        for frame_rate in [50, 100, 123, 160] {
+            let tick_time = frame_tick_time(frame_rate, 16);
+            let slot_time = frame_slot_time(frame_rate);
+            for level in 0..16 {
+                let (on, off) = Rounding::default().split(level, tick_time, slot_time, 16);
+                for sub_frames in 1..=8 {
+                    let parts = (0..sub_frames).map(|sub_frame| share(on, sub_frame, sub_frames));
+                    assert_eq!(
+                        parts.clone().sum::<u64>(),
+                        on,
+                        "level {level}, K {sub_frames}"
+                    );
+                    // Evenly cut: the flashes differ by at most a microsecond
+                    let (min, max) = (parts.clone().min(), parts.max());
+                    assert!(max.unwrap() - min.unwrap() <= 1);
+                    let offs = (0..sub_frames).map(|sub_frame| share(off, sub_frame, sub_frames));
+                    assert_eq!(offs.sum::<u64>(), off);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slots_sum_to_the_frame_period() {
+        for frame_rate in [50, 100, 123, 160] {
+            let tick_time = frame_tick_time(frame_rate, 16);
+            let slot_time = frame_slot_time(frame_rate);
+            for levels in [[0, 0, 0], [15, 15, 15], [15, 8, 3], [1, 0, 14]] {
+                let frame: u64 = levels
+                    .iter()
+                    .map(|&level| {
+                        let (on, off) = Rounding::default().split(level, tick_time, slot_time, 16);
+                        on + off
+                    })
+                    .sum();
+                // One frame period, short only by the slot's integer division
+                let period = 1_000_000 / frame_rate;
+                assert_eq!(frame, 3 * slot_time, "{frame_rate} fps, {levels:?}");
+                assert!(period - frame < 3, "{frame_rate} fps, {levels:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn visible_remap_by_hand() {
+        // 1..=15 spread over 4..=15: 4 + round((level - 1) * 11 / 14)
+        assert_eq!(remap_visible(1, 4, 16), 4);
+        assert_eq!(remap_visible(8, 4, 16), 10);
+        assert_eq!(remap_visible(14, 4, 16), 14);
+        assert_eq!(remap_visible(15, 4, 16), 15);
+        // Out-of-range levels clamp to the top
+        assert_eq!(remap_visible(40, 4, 16), 15);
+    }
+
+    #[test]
+    fn visible_remap_at_every_level_count() {
+        for levels in [2, 3, 4, 16, 256] {
+            for min_visible in [0, 1, 2, levels / 2, levels - 1, levels + 5] {
+                let floor = min_visible.clamp(MIN_VISIBLE_NONE, levels - 1);
+                let remapped: Vec<u32> = (0..levels)
+                    .map(|level| remap_visible(level, min_visible, levels))
+                    .collect();
+                let context = format!("{levels} levels, min {min_visible}");
+                assert_eq!(remapped[0], 0, "{context}");
+                assert_eq!(remapped[1], floor, "{context}");
+                assert_eq!(remapped[levels as usize - 1], levels - 1, "{context}");
+                assert!(
+                    remapped.windows(2).all(|pair| pair[0] <= pair[1]),
+                    "{context}"
+                );
+            }
+            // Without a threshold the levels pass through
+            for level in 0..levels {
+                assert_eq!(remap_visible(level, MIN_VISIBLE_NONE, levels), level);
+            }
+        }
+    }
+
+    #[test]
+    fn gamma_table_sixteen_levels() {
+        // round(65535 * (level / 15)^2.2), computed with powf
+        let expected = [
+            0, 169, 779, 1900, 3578, 5845, 8730, 12254, 16439, 21301, 26858, 33124, 40112, 47835,
+            56306, 65535,
+        ];
+        assert_eq!(gamma_duty_table::<16>(), expected);
+    }
+
+    #[test]
+    fn gamma_table_ends_and_order() {
+        fn check<const N: usize>() {
+            let table = gamma_duty_table::<N>();
+            assert_eq!(table[0], 0);
+            assert_eq!(table[N - 1], u16::MAX);
+            assert!(table.windows(2).all(|pair| pair[0] < pair[1]));
+            for (level, &duty) in table.iter().enumerate() {
+                let exact = (level as f64 / (N - 1) as f64).powf(2.2) * 65535.0;
+                assert!((duty as f64 - exact).abs() <= 0.5 + 1e-6, "level {level}");
+            }
+        }
+        check::<2>();
+        check::<32>();
+        check::<64>();
+    }
+
+    #[test]
+    fn knee_curve_meets_the_identity() {
+        for levels in [2, 16, 64] {
+            for knee in 0..levels {
+                for power in 1..=KNEE_MAX_POWER {
+                    let curve: Vec<u32> = (0..levels)
+                        .map(|level| knee_curve(level, knee, power, levels))
+                        .collect();
+                    assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+                    // Continuous at the knee: the last step below it is
+                    // never larger than the steps of the curve's power
+                    assert_eq!(curve[knee as usize], knee);
+                    if knee > 0 {
+                        let below = curve[knee as usize - 1];
+                        assert!(knee - below <= power, "{levels} {knee} {power}");
+                    }
+                    assert!(curve[knee as usize..]
+                        .iter()
+                        .zip(knee..)
+                        .all(|(&out, level)| out == level));
+                }
+            }
+        }
+        // 16 levels, knee 8, squared: a gentle start rising to the knee
+        let curve: Vec<u32> = (0..9).map(|level| knee_curve(level, 8, 2, 16)).collect();
+        assert_eq!(curve, [0, 0, 1, 1, 2, 3, 5, 6, 8]);
+        assert_eq!(knee_curve(5, 0, 3, 16), 5);
+        assert_eq!(knee_curve(5, 12, 1, 16), 5);
+    }
+
+    #[test]
+    fn blended_on_times() {
+        // Level 5 and 6 at 100 fps with 16 levels: 1042 and 1250 us
+        assert_eq!(blend_on_time(1_042, 1_250, 0), 1_042);
+        assert_eq!(blend_on_time(1_042, 1_250, 250), 1_094);
+        assert_eq!(blend_on_time(1_042, 1_250, 500), 1_146);
+        assert_eq!(blend_on_time(1_042, 1_250, 999), 1_250);
+        // Fractions past one stop at the next level
+        assert_eq!(blend_on_time(1_042, 1_250, FRACTION_ONE), 1_250);
+        assert_eq!(blend_on_time(1_042, 1_250, 5_000), 1_250);
+        // Rounded to the nearest microsecond: 0.3 and 0.7 of a microsecond
+        assert_eq!(blend_on_time(0, 1, 300), 0);
+        assert_eq!(blend_on_time(0, 1, 700), 1);
+        // Never outside the two on-times, whichever is larger
+        for fraction in (0..=FRACTION_ONE).step_by(37) {
+            let up = blend_on_time(100, 3_333, fraction);
+            let down = blend_on_time(3_333, 100, fraction);
+            assert!((100..=3_333).contains(&up));
+            assert_eq!(up + down, 3_433);
+        }
+        assert_eq!(blend_on_time(u64::MAX, u64::MAX, 500), u64::MAX);
+    }
+
+    #[test]
+    fn crossfades_step_evenly_in_perceptual_space() {
+        /// Duty of a blended level through the 2.2 gamma table
+        fn duty(levels: u32, fraction: u32) -> u64 {
+            let table = gamma_duty_table::<16>();
+            let next = (levels + 1).min(15) as usize;
+            let on = u64::from(table[levels as usize]);
+            blend_on_time(on, u64::from(table[next]), fraction)
+        }
+        // Black to full red over 1.5 s, sampled every 100 ms
+        let steps: Vec<([u32; 3], [u32; 3])> = (0..=15)
+            .map(|tenth| crossfade([0; 3], [15, 0, 0], tenth * 100, 1_500))
+            .collect();
+        // Each sample is one whole perceptual level further on
+        for (tenth, (levels, fractions)) in steps.iter().enumerate() {
+            assert_eq!(*levels, [tenth as u32, 0, 0]);
+            assert_eq!(*fractions, [0; 3]);
+        }
+        // Through the gamma table the duty steps grow toward the top, as
+        // the eye needs; a fade in linear space would step evenly in duty
+        // and rush through the dim end
+        let duties: Vec<u64> = steps
+            .iter()
+            .map(|&(levels, fractions)| duty(levels[0], fractions[0]))
+            .collect();
+        let increments: Vec<u64> = duties.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert!(increments.windows(2).all(|pair| pair[0] < pair[1]));
+        let linear: Vec<u64> = (0..=15).map(|tenth| tenth * 65_535 / 15).collect();
+        assert!(duties[1] < linear[1] / 20);
+        assert_eq!(duties[15], linear[15]);
+
+        // Between samples the fraction carries the fade on: a third of the
+        // way from level 2 to 3, and halfway down from 15 at level 7.5
+        let (levels, fractions) = crossfade([0; 3], [15, 0, 0], 233, 1_500);
+        assert_eq!((levels[0], fractions[0]), (2, 330));
+        let (levels, fractions) = crossfade([15, 0, 0], [0; 3], 750, 1_500);
+        assert_eq!((levels[0], fractions[0]), (7, 500));
+        // Never past either end
+        assert_eq!(
+            crossfade([3, 9, 0], [12, 1, 15], 9_000, 1_000),
+            ([12, 1, 15], [0; 3])
+        );
+    }
+
+    #[test]
+    fn out_of_range_levels_stay_in_the_slot() {
+        let slot = frame_slot_time(100);
+        for rounding in [
+            Rounding::Truncate,
+            Rounding::Nearest,
+            Rounding::DistributeToOff,
+        ] {
+            let split = |level| rounding.split(level, 208, slot, 16);
+            let top = slot_split(split, 15, None, 16);
+            for level in [16, 17, 255, u32::MAX] {
+                // Treated as the top level, never as a longer slot
+                assert_eq!(slot_split(split, level, None, 16), top);
+                assert_eq!(slot_split(split, 3, Some((level, 500)), 16), {
+                    let (on, off) = split(3);
+                    let blended = blend_on_time(on, top.0, 500);
+                    (blended, on + off - blended)
+                });
+                let (on, off) = slot_split(split, level, Some((level, 999)), 16);
+                assert_eq!((on, off), top);
+            }
+        }
+        // A split whose next level is longer than the slot is capped to it
+        let long = |level: u32| (u64::from(level) * 400, 1_000);
+        assert_eq!(slot_split(long, 2, Some((9, FRACTION_ONE)), 16), (1_800, 0));
+    }
+}
@@ -0,0 +1,214 @@
+//! Clock Source Configuration
+//!
+//! All frame-rate timing comes from Embassy's time driver, which counts the
+//! low-frequency clock (LFCLK). By default the nRF52 runs LFCLK from its
+//! internal RC oscillator, which can be off by a percent or more. The MicroBit
+//! v2 has a 32 MHz crystal but no 32.768 kHz crystal, so the most accurate
+//! setup is to run HFCLK from the crystal and synthesize LFCLK from it.
+//!
+//! If LFCLK still ends up on the RC oscillator, its actual rate is measured
+//! once at startup against the CPU cycle counter, which runs from HFCLK
+//! (see [`measure_timer_correction`]), and the RGB task scales its frame
+//! timing by the result. That only helps while HFCLK is on the crystal;
+//! with both clocks on RC there is no reference and timing stays as is.
+
+use crate::*;
+
+use cortex_m::peripheral::{DCB, DWT};
+use microbit_bsp::embassy_nrf::{
+    config::{Config, HfclkSource, LfclkSource},
+    pac,
+};
+
+/// CPU clock in Hz, derived from HFCLK
+const CPU_HZ: u64 = 64_000_000;
+
+/// Length of the timer measurement in milliseconds
+const MEASURE_MS: u64 = 100;
+
+/// Timer correction factor meaning "no correction", in parts per million
+pub const NO_CORRECTION: u32 = 1_000_000;
+
+/// Largest deviation from nominal accepted as a real clock error, in
+/// parts per million; anything beyond is a failed measurement
+const MAX_CORRECTION_PPM: u32 = 50_000;
+
+/// Oscillator driving one of the nRF52 clock domains
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Internal RC oscillator (imprecise)
+    Rc,
+    /// External crystal oscillator
+    Xtal,
+    /// Synthesized from HFCLK (LFCLK only)
+    Synth,
+}
+
+impl ClockSource {
+    /// Short name for log output
+    fn name(self) -> &'static str {
+        match self {
+            ClockSource::Rc => "rc",
+            ClockSource::Xtal => "xtal",
+            ClockSource::Synth => "synth",
+        }
+    }
+}
+
+/// Registers the clock checks use: the CLOCK peripheral's status and the
+/// CPU cycle counter
+pub struct ClockRegisters {
+    /// CLOCK peripheral, for the HFCLK and LFCLK sources
+    clock: pac::CLOCK,
+    /// Debug control block, to enable tracing for the cycle counter
+    dcb: DCB,
+    /// Data watchpoint and trace unit holding the cycle counter
+    dwt: DWT,
+}
+
+impl ClockRegisters {
+    /// Claim the registers from the device and core peripherals
+    ///
+    /// The HAL claims neither set, so this succeeds once at startup.
+    ///
+    /// # Returns
+    /// The registers, or `None` if they were already claimed
+    pub fn take() -> Option<Self> {
+        let core = cortex_m::Peripherals::take()?;
+        let device = pac::Peripherals::take()?;
+        Some(ClockRegisters {
+            clock: device.CLOCK,
+            dcb: core.DCB,
+            dwt: core.DWT,
+        })
+    }
+}
+
+/// Build the HAL configuration selecting the precise clock sources
+///
+/// # Returns
+/// Embassy nRF configuration with HFCLK from the crystal and LFCLK
+/// synthesized from HFCLK
+pub fn clock_config() -> Config {
+    let mut config = Config::default();
+    config.hfclk_source = HfclkSource::ExternalXtal;
+    config.lfclk_source = LfclkSource::Synthesized;
+    config
+}
+
+/// Report which oscillator is currently driving HFCLK
+///
+/// # Arguments
+/// * `registers` - Clock registers
+pub fn hfclk_source(registers: &ClockRegisters) -> ClockSource {
+    if registers.clock.hfclkstat.read().src().is_xtal() {
+        ClockSource::Xtal
+    } else {
+        ClockSource::Rc
+    }
+}
+
+/// Report which oscillator is currently driving LFCLK
+///
+/// # Arguments
+/// * `registers` - Clock registers
+pub fn lfclk_source(registers: &ClockRegisters) -> ClockSource {
+    let source = registers.clock.lfclkstat.read().src();
+    if source.is_xtal() {
+        ClockSource::Xtal
+    } else if source.is_synth() {
+        ClockSource::Synth
+    } else {
+        ClockSource::Rc
+    }
+}
+
+/// Log the active clock sources and warn if timing is imprecise
+///
+/// Timing is precise when LFCLK comes from a crystal, either directly or
+/// synthesized from a crystal-driven HFCLK.
+///
+/// # Arguments
+/// * `registers` - Clock registers
+///
+/// # Returns
+/// Whether the timer clock is crystal-accurate
+pub fn check_clock_sources(registers: &ClockRegisters) -> bool {
+    let hf = hfclk_source(registers);
+    let lf = lfclk_source(registers);
+    rprintln!("clock: hfclk {}, lfclk {}", hf.name(), lf.name());
+
+    let precise = match lf {
+        ClockSource::Xtal => true,
+        ClockSource::Synth => hf == ClockSource::Xtal,
+        ClockSource::Rc => false,
+    };
+    if !precise {
+        rprintln!("warning: no crystal clock, frame rates may be off by about 1%");
+    }
+    precise
+}
+
+/// Measure how far the timer clock is off, using the CPU cycle counter
+///
+/// Busy-waits [`MEASURE_MS`] of timer time (the core must not sleep, or
+/// the cycle counter stops) and compares the cycles counted with the
+/// nominal count. Only meaningful while HFCLK runs from the crystal.
+///
+/// # Arguments
+/// * `registers` - Clock registers
+///
+/// # Returns
+/// Timer time per real time in parts per million (above
+/// [`NO_CORRECTION`] when the timer runs fast), or `None` if HFCLK is not
+/// crystal-driven or the result is implausible
+pub fn measure_timer_correction(registers: &mut ClockRegisters) -> Option<u32> {
+    if hfclk_source(registers) != ClockSource::Xtal {
+        return None;
+    }
+    registers.dcb.enable_trace();
+    registers.dwt.enable_cycle_counter();
+    let cycles = DWT::cycle_count;
+
+    // Start on a timer tick edge so the window is exact
+    let edge = Instant::now();
+    while Instant::now() == edge {}
+    let start = Instant::now();
+    let start_cycles = cycles();
+    let end = start + Duration::from_millis(MEASURE_MS);
+    while Instant::now() < end {}
+    let elapsed = cycles().wrapping_sub(start_cycles) as u64;
+
+    let expected = CPU_HZ * MEASURE_MS / 1000;
+    let ppm = (expected * NO_CORRECTION as u64 / elapsed.max(1)) as u32;
+    (ppm.abs_diff(NO_CORRECTION) <= MAX_CORRECTION_PPM).then_some(ppm)
+}
+
+/// Work out the timer correction for the RGB task at startup
+///
+/// # Arguments
+/// * `registers` - Clock registers
+/// * `precise` - Result of [`check_clock_sources`]
+///
+/// # Returns
+/// Correction factor in parts per million, [`NO_CORRECTION`] if none
+/// is needed or possible
+pub fn timer_correction(registers: &mut ClockRegisters, precise: bool) -> u32 {
+    if precise {
+        return NO_CORRECTION;
+    }
+    match measure_timer_correction(registers) {
+        Some(ppm) => {
+            let error = ppm as i64 - NO_CORRECTION as i64;
+            rprintln!(
+                "clock: timer off by {:+} ppm, frame timing corrected",
+                error
+            );
+            ppm
+        }
+        None => {
+            rprintln!("clock: no crystal reference, frame timing uncorrected");
+            NO_CORRECTION
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Level Color Space
+//!
+//! The LEDs' brightness is linear in their duty, so by default a level is
+//! taken as linear light: level 8 is on for half as long as level 15 (less
+//! the rounding of 16 steps). Host software that thinks in sRGB sends
+//! gamma-encoded values instead, in which 8 of 15 means a mid grey of about
+//! a fifth of the light. Showing those as linear levels gives a washed-out
+//! picture, and applying an output gamma table on top (`set lut`) encodes
+//! twice.
+//!
+//! [`ColorSpace::Srgb`] declares the levels sRGB-encoded: each is decoded
+//! to linear light with the sRGB transfer curve at the start of the output
+//! pipeline, before the color matrix and the lookup tables (see
+//! [`output_levels`]), so the matrix mixes light and any gamma table sees
+//! linear input. Decoded values are rounded to the 16 levels, which cannot
+//! hold the dimmest sRGB steps: levels 1-3 decode to less than half a level
+//! and turn dark.
+
+use crate::*;
+
+/// Linear level of each sRGB-encoded level:
+/// `round(15 * decode(level / 15))`, with the sRGB decoding curve
+/// `c / 12.92` up to 0.04045 and `((c + 0.055) / 1.055)^2.4` above
+const SRGB_TO_LINEAR: [u32; LEVELS as usize] = [0, 0, 0, 0, 1, 1, 2, 3, 4, 5, 6, 7, 9, 11, 13, 15];
+
+/// How requested levels are interpreted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Levels are linear light (duty)
+    #[default]
+    Linear,
+    /// Levels are sRGB-encoded and decoded to linear light for output
+    Srgb,
+}
+
+impl ColorSpace {
+    /// Name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Linear => "linear",
+            ColorSpace::Srgb => "srgb",
+        }
+    }
+
+    /// Convert a requested level to linear light
+    ///
+    /// # Arguments
+    /// * `level` - Requested level (clamped to LEVELS-1)
+    ///
+    /// # Returns
+    /// Linear level (0 to LEVELS-1)
+    pub fn decode(self, level: u32) -> u32 {
+        let level = level.min(LEVELS - 1);
+        match self {
+            ColorSpace::Linear => level,
+            ColorSpace::Srgb => SRGB_TO_LINEAR[level as usize],
+        }
+    }
+}
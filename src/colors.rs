@@ -0,0 +1,106 @@
+//! Named Colors
+//!
+//! A small table of common colors the `color <name>` command resolves to
+//! levels. Each color is given as per-channel fractions of full scale; the
+//! white variants are fractions of the white reference instead (the levels
+//! captured with a double tap of B or in the wizard), so they come out
+//! white-balanced for the LED at hand.
+
+use crate::*;
+
+/// Fraction of full scale or of the white reference representing 1.0
+const PER_MILLE: u32 = 1000;
+
+/// A color that can be selected by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NamedColor {
+    /// Name accepted by the `color` command
+    pub name: &'static str,
+    /// [red, green, blue] fractions in per mille
+    pub mix: [u32; 3],
+    /// Whether the fractions apply to the white reference rather than to
+    /// full scale
+    pub balanced: bool,
+}
+
+impl NamedColor {
+    /// Create a color relative to full scale
+    ///
+    /// # Arguments
+    /// * `name` - Name accepted by the `color` command
+    /// * `mix` - [red, green, blue] fractions in per mille
+    ///
+    /// # Returns
+    /// The color
+    pub const fn new(name: &'static str, mix: [u32; 3]) -> Self {
+        Self {
+            name,
+            mix,
+            balanced: false,
+        }
+    }
+
+    /// Create a color relative to the white reference
+    ///
+    /// # Arguments
+    /// * `name` - Name accepted by the `color` command
+    /// * `mix` - [red, green, blue] fractions of the white reference in
+    ///   per mille
+    ///
+    /// # Returns
+    /// The color
+    pub const fn white(name: &'static str, mix: [u32; 3]) -> Self {
+        Self {
+            name,
+            mix,
+            balanced: true,
+        }
+    }
+
+    /// Levels that show this color
+    ///
+    /// # Arguments
+    /// * `white` - White reference [red, green, blue] levels
+    ///
+    /// # Returns
+    /// [red, green, blue] levels, rounded to the nearest level
+    pub fn levels(&self, white: [u32; 3]) -> [u32; 3] {
+        let base = if self.balanced {
+            white
+        } else {
+            [LEVELS - 1; 3]
+        };
+        [0, 1, 2].map(|channel| {
+            let scaled = base[channel].min(LEVELS - 1) * self.mix[channel];
+            (scaled + PER_MILLE / 2) / PER_MILLE
+        })
+    }
+}
+
+/// Colors accepted by the `color` command
+///
+/// Keep in sync with the list in [`ParseError::UnknownColor`]'s message.
+pub const NAMED_COLORS: [NamedColor; 8] = [
+    NamedColor::white("white", [1000, 1000, 1000]),
+    NamedColor::white("warm_white", [1000, 850, 550]),
+    NamedColor::white("cool_white", [800, 900, 1000]),
+    NamedColor::new("red", [1000, 0, 0]),
+    NamedColor::new("green", [0, 1000, 0]),
+    NamedColor::new("blue", [0, 0, 1000]),
+    NamedColor::new("amber", [1000, 750, 0]),
+    NamedColor::new("off", [0, 0, 0]),
+];
+
+/// Look up a color by name
+///
+/// # Arguments
+/// * `name` - Name as typed, case-sensitive
+///
+/// # Returns
+/// The color, or `None` if the name is unknown
+pub fn find_color(name: &str) -> Option<NamedColor> {
+    NAMED_COLORS
+        .iter()
+        .copied()
+        .find(|color| color.name == name)
+}
@@ -0,0 +1,2666 @@
+//! Host Command Interface
+//!
+//! Accepts text commands from the host over the RTT down channel, one per
+//! line, and applies them to the shared state. Replies are printed on the
+//! regular RTT output alongside the UI's state display. With the `serial`
+//! feature both go over the USB serial port instead (see the `serial`
+//! module).
+
+use crate::*;
+
+#[cfg(not(feature = "serial"))]
+use rtt_target::DownChannel;
+
+/// Source of command lines: the RTT terminal's down channel
+#[cfg(not(feature = "serial"))]
+pub type CommandInput = DownChannel;
+
+/// Source of command lines: the serial console's UART
+#[cfg(feature = "serial")]
+pub type CommandInput = SerialInput;
+
+/// Maximum length of a single command line in bytes (room for
+/// `set config` with its 2 * CONFIG_LEN hex digits, 112 with 16 levels)
+const LINE_MAX: usize = if 2 * CONFIG_LEN + 20 > 128 {
+    2 * CONFIG_LEN + 20
+} else {
+    128
+};
+
+/// Interval between polls of the RTT input channel in milliseconds
+const POLL_MS: u64 = 20;
+
+/// Shortest interval accepted between automatic saves, in seconds
+const MIN_AUTO_SAVE_S: u32 = 10;
+
+/// Longest interval accepted between automatic saves, in seconds (a day)
+const MAX_AUTO_SAVE_S: u32 = 86_400;
+
+/// Every host command: its keywords, argument count, help line and parser
+///
+/// Drives both [`Command::parse`] and the `help` listing, in this order:
+/// the firmware's own commands, then the level and frame rate commands.
+const COMMANDS: &[&[Syntax<Command>]] = &[FIRMWARE_COMMANDS, LevelSyntax::<Command, LEVELS>::TABLE];
+
+/// The commands beyond setting the levels and the frame rate
+const FIRMWARE_COMMANDS: &[Syntax<Command>] = &[
+    Syntax {
+        keywords: &["help"],
+        args: 0..=0,
+        usage: "help",
+        help: "list available commands",
+        parse: |_| Ok(Command::Help),
+    },
+    #[cfg(feature = "heartbeat")]
+    Syntax {
+        keywords: &["heartbeat"],
+        args: 1..=1,
+        usage: "heartbeat <seconds|off>",
+        help: "log a status line periodically",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Heartbeat(None)),
+            seconds => Ok(Command::Heartbeat(Some(parse_number(seconds)?))),
+        },
+    },
+    Syntax {
+        keywords: &["anim"],
+        args: 0..=0,
+        usage: "anim",
+        help: "show the running animation",
+        parse: |_| Ok(Command::AnimShow),
+    },
+    Syntax {
+        keywords: &["anim"],
+        args: 2..=2,
+        usage: "anim <breathe|rainbow> <ms>",
+        help: "breathe the color, or cycle the hues",
+        parse: |words| match words.next() {
+            Some("breathe") => Ok(Command::Anim(Animation::Breathe {
+                period_ms: parse_period(words.next())?,
+            })),
+            Some("rainbow") => Ok(Command::Anim(Animation::Rainbow {
+                period_ms: parse_period(words.next())?,
+            })),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["anim", "seq"],
+        args: 0..=0,
+        usage: "anim seq",
+        help: "play the color sequence",
+        parse: |_| Ok(Command::Anim(Animation::Sequence)),
+    },
+    Syntax {
+        keywords: &["anim", "off"],
+        args: 0..=0,
+        usage: "anim off",
+        help: "stop animating, back to manual control",
+        parse: |_| Ok(Command::Anim(Animation::Off)),
+    },
+    Syntax {
+        keywords: &["benchmark"],
+        args: 0..=0,
+        usage: "benchmark",
+        help: "measure the highest frame rate that keeps up",
+        parse: |_| Ok(Command::Benchmark),
+    },
+    Syntax {
+        keywords: &["buttons"],
+        args: 0..=3,
+        usage: "buttons [<a> <b> <a+b>]",
+        help: "show or set the channel of each button",
+        parse: |words| {
+            if words.clone().next().is_none() {
+                return Ok(Command::Buttons(None));
+            }
+            let mut channels = [0; 3];
+            for channel in channels.iter_mut() {
+                let word = words.next().ok_or(ParseError::MissingArgument)?;
+                *channel = parse_channel(word).ok_or(ParseError::InvalidArgument)?;
+            }
+            Ok(Command::Buttons(Some(
+                ButtonMap::new(channels).ok_or(ParseError::InvalidArgument)?,
+            )))
+        },
+    },
+    Syntax {
+        keywords: &["center"],
+        args: 1..=1,
+        usage: "center <off|log|cue>",
+        help: "report the knob at mid travel (cue: blink)",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Center(CenterIndicator::Off)),
+            Some("log") => Ok(Command::Center(CenterIndicator::Log)),
+            Some("cue") => Ok(Command::Center(CenterIndicator::Cue)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["color"],
+        args: 1..=1,
+        usage: "color <name>",
+        help: "set a named color, e.g. warm_white",
+        parse: |words| {
+            let name = words.next().ok_or(ParseError::MissingArgument)?;
+            Ok(Command::Color(
+                find_color(name).ok_or(ParseError::UnknownColor)?,
+            ))
+        },
+    },
+    Syntax {
+        keywords: &["colorspace"],
+        args: 0..=1,
+        usage: "colorspace [linear|srgb]",
+        help: "levels as linear light or sRGB-encoded",
+        parse: |words| match words.next() {
+            None => Ok(Command::ColorSpace(None)),
+            Some("linear") => Ok(Command::ColorSpace(Some(ColorSpace::Linear))),
+            Some("srgb") => Ok(Command::ColorSpace(Some(ColorSpace::Srgb))),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["compare"],
+        args: 0..=0,
+        usage: "compare",
+        help: "switch between frame rates A and B",
+        parse: |_| Ok(Command::CompareToggle),
+    },
+    Syntax {
+        keywords: &["compare", "set"],
+        args: 1..=2,
+        usage: "compare set <a|b> [rate]",
+        help: "store the current (or given) frame rate",
+        parse: |words| {
+            let setpoint = match words.next() {
+                Some("a") => 0,
+                Some("b") => 1,
+                _ => return Err(ParseError::InvalidArgument),
+            };
+            let rate = parse_optional(words.next())?;
+            Ok(Command::CompareSet { setpoint, rate })
+        },
+    },
+    Syntax {
+        keywords: &["compare", "off"],
+        args: 0..=0,
+        usage: "compare off",
+        help: "stop comparing, keep the frame rate",
+        parse: |_| Ok(Command::CompareOff),
+    },
+    Syntax {
+        keywords: &["debug"],
+        args: 1..=1,
+        usage: "debug <on|off>",
+        help: "show raw knob readings",
+        parse: |words| Ok(Command::Debug(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["edge"],
+        args: 1..=1,
+        usage: "edge <leading|alternate>",
+        help: "on-window at slot start, or alternating ends",
+        parse: |words| match words.next() {
+            Some("leading") => Ok(Command::Edge(PwmEdge::Leading)),
+            Some("alternate") => Ok(Command::Edge(PwmEdge::Alternating)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["diff"],
+        args: 1..=1,
+        usage: "diff <1-4|tag>",
+        help: "compare the settings with a preset",
+        parse: |words| Ok(Command::Diff(parse_preset_ref(words.next())?)),
+    },
+    Syntax {
+        keywords: &["display"],
+        args: 1..=1,
+        usage: "display <text|bars|json>",
+        help: "select state display format",
+        parse: |words| match words.next() {
+            Some("text") => Ok(Command::Display(OutputFormat::Text)),
+            Some("bars") => Ok(Command::Display(OutputFormat::Bars)),
+            Some("json") => Ok(Command::Display(OutputFormat::Json)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["drive"],
+        args: 0..=1,
+        usage: "drive [scan|spread]",
+        help: "show or select the drive mode",
+        parse: |words| match words.next() {
+            None => Ok(Command::Drive(None)),
+            Some("scan") => Ok(Command::Drive(Some(DriveMode::Scan))),
+            Some("spread") => Ok(Command::Drive(Some(DriveMode::Spread))),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["fade"],
+        args: 7..=7,
+        usage: "fade <r1> <g1> <b1> <r2> <g2> <b2> <ms>",
+        help: "fade once between two colors, then hold",
+        parse: |words| {
+            let mut levels = [0; 6];
+            for level in levels.iter_mut() {
+                *level = parse_level(words.next(), LEVELS)?;
+            }
+            let [r1, g1, b1, r2, g2, b2] = levels;
+            let ms = parse_ms(words.next())?;
+            Ok(Command::Fade(Fade::new([r1, g1, b1], [r2, g2, b2], ms)))
+        },
+    },
+    Syntax {
+        keywords: &["gamma"],
+        args: 0..=1,
+        usage: "gamma [on|off]",
+        help: "show or switch gamma duty tables",
+        parse: |words| match words.next() {
+            None => Ok(Command::Gamma(None)),
+            word => Ok(Command::Gamma(Some(parse_switch(word)?))),
+        },
+    },
+    Syntax {
+        keywords: &["get"],
+        args: 0..=1,
+        usage: "get [%]",
+        help: "show levels (optionally in percent)",
+        parse: |words| match words.next() {
+            None => Ok(Command::Get { percent: false }),
+            Some("%") => Ok(Command::Get { percent: true }),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["get", "config"],
+        args: 0..=0,
+        usage: "get config",
+        help: "dump the whole calibration as hex",
+        parse: |_| Ok(Command::GetConfig),
+    },
+    Syntax {
+        keywords: &["get", "inputs"],
+        args: 0..=0,
+        usage: "get inputs",
+        help: "show the knob reading and buttons",
+        parse: |_| Ok(Command::GetInputs),
+    },
+    Syntax {
+        keywords: &["get", "owners"],
+        args: 0..=0,
+        usage: "get owners",
+        help: "show whether knob or host set each value",
+        parse: |_| Ok(Command::GetOwners),
+    },
+    Syntax {
+        keywords: &["get", "lut"],
+        args: 1..=1,
+        usage: "get lut <r|g|b>",
+        help: "show a channel's output lookup table",
+        parse: |words| {
+            let name = words.next().ok_or(ParseError::MissingArgument)?;
+            Ok(Command::GetLut(
+                parse_channel(name).ok_or(ParseError::InvalidArgument)?,
+            ))
+        },
+    },
+    Syntax {
+        keywords: &["get", "knobcal"],
+        args: 0..=0,
+        usage: "get knobcal",
+        help: "show the raw knob readings at both ends",
+        parse: |_| Ok(Command::GetKnobCal),
+    },
+    Syntax {
+        keywords: &["get", "visible"],
+        args: 0..=0,
+        usage: "get visible",
+        help: "show each channel's minimum visible level",
+        parse: |_| Ok(Command::GetVisible),
+    },
+    Syntax {
+        keywords: &["get", "fps"],
+        args: 0..=1,
+        usage: "get fps [--actual]",
+        help: "show the frame rate set (or measured)",
+        parse: |words| match words.next() {
+            None => Ok(Command::GetFps { actual: false }),
+            Some("--actual") => Ok(Command::GetFps { actual: true }),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["history"],
+        args: 0..=0,
+        usage: "history",
+        help: "show the position in the undo history",
+        parse: |_| Ok(Command::History),
+    },
+    Syntax {
+        keywords: &["hold"],
+        args: 1..=1,
+        usage: "hold <r|g|b|off>",
+        help: "output one channel steadily (no scan)",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Hold(None)),
+            name => Ok(Command::Hold(Some(
+                name.and_then(parse_channel)
+                    .ok_or(ParseError::InvalidArgument)?,
+            ))),
+        },
+    },
+    Syntax {
+        keywords: &["identpins"],
+        args: 0..=1,
+        usage: "identpins [seconds]",
+        help: "light each LED pin alone (2s each)",
+        parse: |words| {
+            let seconds = parse_optional(words.next())?;
+            Ok(Command::IdentifyPins(seconds.unwrap_or(IDENTIFY_SECONDS)))
+        },
+    },
+    Syntax {
+        keywords: &["indicator"],
+        args: 0..=1,
+        usage: "indicator [off|dither|mode]",
+        help: "show brightness or knob mode on the 5x5 matrix",
+        parse: |words| match words.next() {
+            None => Ok(Command::Indicator(None)),
+            Some("off") => Ok(Command::Indicator(Some(IndicatorMode::Off))),
+            Some("dither") => Ok(Command::Indicator(Some(IndicatorMode::Dither))),
+            Some("mode") => Ok(Command::Indicator(Some(IndicatorMode::Mode))),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["indicator", "alive"],
+        args: 1..=1,
+        usage: "indicator alive <on|off>",
+        help: "blink a matrix pixel while scanning",
+        parse: |words| Ok(Command::IndicatorAlive(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob"],
+        args: 1..=1,
+        usage: "knob <linear|dim>",
+        help: "select knob brightness response",
+        parse: |words| match words.next() {
+            Some("linear") => Ok(Command::Knob(KnobResponse::Linear)),
+            Some("dim") => Ok(Command::Knob(KnobResponse::DimExpanded)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["knob", "blend"],
+        args: 1..=1,
+        usage: "knob blend <on|off>",
+        help: "blend between levels as the knob turns",
+        parse: |words| Ok(Command::KnobBlend(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob", "pickup"],
+        args: 1..=1,
+        usage: "knob pickup <on|off>",
+        help: "knob passes through a value before taking over",
+        parse: |words| Ok(Command::KnobPickup(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob", "hue"],
+        args: 1..=1,
+        usage: "knob hue <on|off>",
+        help: "knob sets the hue with no buttons held",
+        parse: |words| Ok(Command::KnobHue(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob", "fine"],
+        args: 1..=1,
+        usage: "knob fine <on|off>",
+        help: "knob nudges levels in fine steps",
+        parse: |words| Ok(Command::KnobFine(parse_switch(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob", "coalesce"],
+        args: 0..=1,
+        usage: "knob coalesce [ms]",
+        help: "batch knob level changes (0-1000 ms)",
+        parse: |words| Ok(Command::KnobCoalesce(parse_optional(words.next())?)),
+    },
+    Syntax {
+        keywords: &["knob", "deadband"],
+        args: 0..=2,
+        usage: "knob deadband [<low> <high>]",
+        help: "dead travel at each end (0-0.45)",
+        parse: |words| match words.next() {
+            None => Ok(Command::KnobDeadband(None)),
+            low => {
+                let low = parse_deadband(low)?;
+                let high = parse_deadband(words.next())?;
+                Ok(Command::KnobDeadband(Some([low, high])))
+            }
+        },
+    },
+    Syntax {
+        keywords: &["mark", "fusion"],
+        args: 0..=0,
+        usage: "mark fusion",
+        help: "keep fps at or above the current rate",
+        parse: |_| Ok(Command::MarkFusion(true)),
+    },
+    Syntax {
+        keywords: &["mark", "clear"],
+        args: 0..=0,
+        usage: "mark clear",
+        help: "remove the fusion threshold",
+        parse: |_| Ok(Command::MarkFusion(false)),
+    },
+    Syntax {
+        keywords: &["meas"],
+        args: 0..=0,
+        usage: "meas",
+        help: "show the measurement duties",
+        parse: |_| Ok(Command::MeasShow),
+    },
+    Syntax {
+        keywords: &["meas"],
+        args: 2..=2,
+        usage: "meas <r|g|b> <duty|off>",
+        help: "hold a channel at an exact duty (0-1)",
+        parse: |words| {
+            let channel = words
+                .next()
+                .and_then(parse_channel)
+                .ok_or(ParseError::InvalidArgument)?;
+            let duty = match words.next() {
+                Some("off") => None,
+                word => Some(parse_duty(word)?),
+            };
+            Ok(Command::Meas { channel, duty })
+        },
+    },
+    Syntax {
+        keywords: &["meas", "off"],
+        args: 0..=0,
+        usage: "meas off",
+        help: "clear every measurement duty",
+        parse: |_| Ok(Command::MeasOff),
+    },
+    Syntax {
+        keywords: &["matrix"],
+        args: 0..=0,
+        usage: "matrix",
+        help: "show the color correction matrix",
+        parse: |_| Ok(Command::Matrix(None)),
+    },
+    Syntax {
+        keywords: &["matrix", "identity"],
+        args: 0..=0,
+        usage: "matrix identity",
+        help: "turn color correction off",
+        parse: |_| Ok(Command::Matrix(Some(ColorMatrix::IDENTITY))),
+    },
+    Syntax {
+        keywords: &["matrix"],
+        args: 9..=9,
+        usage: "matrix <9 values>",
+        help: "set the correction matrix row by row",
+        parse: |words| {
+            let mut rows = [[0; 3]; 3];
+            for coefficient in rows.iter_mut().flatten() {
+                *coefficient = parse_coefficient(words.next())?;
+            }
+            Ok(Command::Matrix(Some(ColorMatrix(rows))))
+        },
+    },
+    Syntax {
+        keywords: &["pattern"],
+        args: 0..=1,
+        usage: "pattern [seconds]",
+        help: "show the test patterns (2s each)",
+        parse: |words| {
+            let seconds = parse_optional(words.next())?;
+            Ok(Command::Pattern(seconds.unwrap_or(PATTERN_SECONDS)))
+        },
+    },
+    Syntax {
+        keywords: &["poll"],
+        args: 0..=1,
+        usage: "poll [ms]",
+        help: "show or set the knob/button poll (5-100)",
+        parse: |words| Ok(Command::Poll(parse_optional(words.next())?)),
+    },
+    Syntax {
+        keywords: &["power"],
+        args: 0..=0,
+        usage: "power",
+        help: "show the estimated LED current",
+        parse: |_| Ok(Command::Power),
+    },
+    Syntax {
+        keywords: &["power", "budget"],
+        args: 1..=1,
+        usage: "power budget <mA|off>",
+        help: "limit the average LED current",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::PowerBudget(None)),
+            word => Ok(Command::PowerBudget(Some(parse_milliamps(word)?))),
+        },
+    },
+    Syntax {
+        keywords: &["power", "action"],
+        args: 1..=1,
+        usage: "power action <warn|cap>",
+        help: "warn or dim when over budget",
+        parse: |words| match words.next() {
+            Some("warn") => Ok(Command::PowerAction(BudgetAction::Warn)),
+            Some("cap") => Ok(Command::PowerAction(BudgetAction::Cap)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["power", "peak"],
+        args: 3..=3,
+        usage: "power peak <r> <g> <b>",
+        help: "each LED's current while lit, mA",
+        parse: |words| {
+            let red = parse_milliamps(words.next())?;
+            let green = parse_milliamps(words.next())?;
+            let blue = parse_milliamps(words.next())?;
+            Ok(Command::PowerPeak([red, green, blue]))
+        },
+    },
+    Syntax {
+        keywords: &["preset", "list"],
+        args: 0..=0,
+        usage: "preset list",
+        help: "list saved calibrations",
+        parse: |_| Ok(Command::PresetList),
+    },
+    Syntax {
+        keywords: &["preset", "save"],
+        args: 1..=2,
+        usage: "preset save <1-4> [tag]",
+        help: "save levels and fps, tagged e.g. warm",
+        parse: |words| {
+            let slot = parse_slot(words.next())?;
+            let tag = match words.next() {
+                Some(word) => Some(Tag::parse(word).ok_or(ParseError::InvalidArgument)?),
+                None => None,
+            };
+            Ok(Command::PresetSave { slot, tag })
+        },
+    },
+    Syntax {
+        keywords: &["preset", "load"],
+        args: 1..=1,
+        usage: "preset load <1-4|tag>",
+        help: "load a saved calibration",
+        parse: |words| Ok(Command::PresetLoad(parse_preset_ref(words.next())?)),
+    },
+    Syntax {
+        keywords: &["preset", "next"],
+        args: 0..=1,
+        usage: "preset next [all|white]",
+        help: "load the next tagged (or any, or white) preset",
+        parse: |words| match words.next() {
+            None => Ok(Command::PresetNext(PresetFilter::Tagged)),
+            Some(word) => Ok(Command::PresetNext(parse_preset_filter(word)?)),
+        },
+    },
+    Syntax {
+        keywords: &["next", "white"],
+        args: 0..=0,
+        usage: "next white",
+        help: "load the next preset tagged as a white",
+        parse: |_| Ok(Command::PresetNext(PresetFilter::White)),
+    },
+    Syntax {
+        keywords: &["preset", "cycle"],
+        args: 1..=1,
+        usage: "preset cycle <all|tagged|white>",
+        help: "presets a long press of A steps through",
+        parse: |words| {
+            let word = words.next().ok_or(ParseError::MissingArgument)?;
+            Ok(Command::PresetCycle(parse_preset_filter(word)?))
+        },
+    },
+    Syntax {
+        keywords: &["preset", "clear"],
+        args: 1..=1,
+        usage: "preset clear <1-4>",
+        help: "empty a preset slot",
+        parse: |words| Ok(Command::PresetClear(parse_slot(words.next())?)),
+    },
+    Syntax {
+        keywords: &["resume"],
+        args: 0..=0,
+        usage: "resume",
+        help: "release the emergency off",
+        parse: |_| Ok(Command::Resume),
+    },
+    Syntax {
+        keywords: &["save"],
+        args: 0..=0,
+        usage: "save",
+        help: "save the calibration to flash for the next boot",
+        parse: |_| Ok(Command::Save),
+    },
+    Syntax {
+        keywords: &["save", "auto"],
+        args: 0..=0,
+        usage: "save auto",
+        help: "show the automatic save policy",
+        parse: |_| Ok(Command::SaveAutoShow),
+    },
+    Syntax {
+        keywords: &["save", "auto"],
+        args: 1..=3,
+        usage: "save auto <seconds|off> [level] [fps]",
+        help: "save changes by level/fps steps, seconds apart",
+        parse: |words| match words.next() {
+            Some("off") if words.clone().next().is_none() => Ok(Command::SaveAuto(None)),
+            seconds => Ok(Command::SaveAuto(Some(SavePolicy {
+                min_interval_s: parse_bounded(seconds, MIN_AUTO_SAVE_S..=MAX_AUTO_SAVE_S)?,
+                level_threshold: match words.next() {
+                    Some(level) => parse_bounded(Some(level), 1..=LEVELS)?,
+                    None => DEFAULT_SAVE_POLICY.level_threshold,
+                },
+                frame_rate_threshold: match words.next() {
+                    Some(fps) => parse_bounded(Some(fps), 1..=MAX_FRAME_RATE)?,
+                    None => DEFAULT_SAVE_POLICY.frame_rate_threshold,
+                },
+            }))),
+        },
+    },
+    Syntax {
+        keywords: &["seq"],
+        args: 0..=0,
+        usage: "seq",
+        help: "list the color sequence",
+        parse: |_| Ok(Command::SeqList),
+    },
+    Syntax {
+        keywords: &["seq", "add"],
+        args: 4..=4,
+        usage: "seq add <r> <g> <b> <ms>",
+        help: "append a stop held for ms",
+        parse: |words| {
+            let red = parse_level(words.next(), LEVELS)?;
+            let green = parse_level(words.next(), LEVELS)?;
+            let blue = parse_level(words.next(), LEVELS)?;
+            let hold_ms = parse_ms(words.next())?;
+            Ok(Command::SeqAdd(ColorStop {
+                levels: [red, green, blue],
+                hold_ms,
+            }))
+        },
+    },
+    Syntax {
+        keywords: &["seq", "fade"],
+        args: 1..=1,
+        usage: "seq fade <ms>",
+        help: "crossfade time between stops",
+        parse: |words| Ok(Command::SeqFade(parse_ms(words.next())?)),
+    },
+    Syntax {
+        keywords: &["seq", "clear"],
+        args: 0..=0,
+        usage: "seq clear",
+        help: "remove every stop",
+        parse: |_| Ok(Command::SeqClear),
+    },
+    Syntax {
+        keywords: &["seq"],
+        args: 1..=1,
+        usage: "seq <play|stop>",
+        help: "loop the sequence, or stop it",
+        parse: |words| match words.next() {
+            Some("play") => Ok(Command::SeqPlay(true)),
+            Some("stop") => Ok(Command::SeqPlay(false)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["set", "config"],
+        args: 1..=1,
+        usage: "set config <hex>",
+        help: "apply a whole calibration at once",
+        parse: |words| {
+            let hex = words.next().ok_or(ParseError::MissingArgument)?;
+            let config = CalibrationConfig::from_hex(hex).map_err(ParseError::InvalidConfig)?;
+            Ok(Command::SetConfig(config))
+        },
+    },
+    Syntax {
+        keywords: &["set", "knobcal"],
+        args: 2..=2,
+        usage: "set knobcal <min> <max>",
+        help: "raw knob readings at both ends of travel",
+        parse: |words| {
+            let min = parse_number(words.next())?;
+            let max = parse_number(words.next())?;
+            Ok(Command::SetKnobCal(
+                KnobCalibration::new(min, max).ok_or(ParseError::InvalidArgument)?,
+            ))
+        },
+    },
+    Syntax {
+        keywords: &["set", "lut"],
+        args: 1 + LUT_LEN..=1 + LUT_LEN,
+        usage: "set lut <r|g|b> <16 levels>",
+        help: "upload a channel's lookup table",
+        parse: |words| {
+            let channel = words
+                .next()
+                .and_then(parse_channel)
+                .ok_or(ParseError::InvalidArgument)?;
+            let mut table = [0; LUT_LEN];
+            for entry in table.iter_mut() {
+                *entry = parse_number(words.next())?;
+            }
+            let lut = Lut::new(table).map_err(|err| match err {
+                LutError::OutOfRange => ParseError::LevelOutOfRange,
+                LutError::NotMonotonic => ParseError::NotMonotonic,
+            })?;
+            Ok(Command::SetLut { channel, lut })
+        },
+    },
+    Syntax {
+        keywords: &["set", "lut"],
+        args: 3..=4,
+        usage: "set lut <r|g|b> knee <level> [power]",
+        help: "power curve below the level, linear above",
+        parse: |words| {
+            let channel = words
+                .next()
+                .and_then(parse_channel)
+                .ok_or(ParseError::InvalidArgument)?;
+            if words.next() != Some("knee") {
+                return Err(ParseError::InvalidArgument);
+            }
+            let knee = parse_level(words.next(), LEVELS)?;
+            let power = match words.next() {
+                Some(word) => parse_bounded(Some(word), 1..=KNEE_MAX_POWER)?,
+                None => KNEE_POWER,
+            };
+            Ok(Command::SetLut {
+                channel,
+                lut: Lut::knee(knee, power),
+            })
+        },
+    },
+    Syntax {
+        keywords: &["refresh"],
+        args: 0..=3,
+        usage: "refresh [<r> <g> <b>]",
+        help: "show each channel every Nth frame (1-8)",
+        parse: |words| {
+            if words.clone().next().is_none() {
+                return Ok(Command::Refresh(None));
+            }
+            let mut multipliers = [1; 3];
+            for multiplier in multipliers.iter_mut() {
+                *multiplier = parse_number(words.next())?;
+            }
+            Ok(Command::Refresh(Some(multipliers)))
+        },
+    },
+    Syntax {
+        keywords: &["rounding"],
+        args: 0..=1,
+        usage: "rounding [truncate|nearest|distribute]",
+        help: "show or select slot time rounding",
+        parse: |words| match words.next() {
+            None => Ok(Command::Rounding(None)),
+            Some("truncate") => Ok(Command::Rounding(Some(Rounding::Truncate))),
+            Some("nearest") => Ok(Command::Rounding(Some(Rounding::Nearest))),
+            Some("distribute") => Ok(Command::Rounding(Some(Rounding::DistributeToOff))),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["slew"],
+        args: 0..=1,
+        usage: "slew [levels|off]",
+        help: "show or set the level change per frame",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Slew(Some(LEVELS))),
+            rate => Ok(Command::Slew(parse_optional(rate)?)),
+        },
+    },
+    Syntax {
+        keywords: &["slow"],
+        args: 1..=1,
+        usage: "slow <factor|off>",
+        help: "stretch the scan to watch it by eye",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Slow(None)),
+            factor => Ok(Command::Slow(Some(parse_number(factor)?))),
+        },
+    },
+    Syntax {
+        keywords: &["snapshot"],
+        args: 0..=1,
+        usage: "snapshot [hz]",
+        help: "show or cap settings re-reads (10-1000)",
+        parse: |words| Ok(Command::Snapshot(parse_optional(words.next())?)),
+    },
+    Syntax {
+        keywords: &["stats"],
+        args: 0..=1,
+        usage: "stats [reset]",
+        help: "summarize (or restart) this session",
+        parse: |words| match words.next() {
+            None => Ok(Command::Stats { reset: false }),
+            Some("reset") => Ok(Command::Stats { reset: true }),
+            Some(_) => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["stats", "modes"],
+        args: 0..=0,
+        usage: "stats modes",
+        help: "time spent in each knob mode",
+        parse: |_| Ok(Command::StatsModes),
+    },
+    Syntax {
+        keywords: &["stop"],
+        args: 0..=0,
+        usage: "stop",
+        help: "emergency off: zero levels, hold LEDs dark",
+        parse: |_| Ok(Command::Stop),
+    },
+    Syntax {
+        keywords: &["subframes"],
+        args: 0..=1,
+        usage: "subframes [1-8]",
+        help: "show or set color flashes per frame",
+        parse: |words| Ok(Command::SubFrames(parse_optional(words.next())?)),
+    },
+    Syntax {
+        keywords: &["strobe"],
+        args: 0..=0,
+        usage: "strobe",
+        help: "show the strobe guard",
+        parse: |_| Ok(Command::Strobe),
+    },
+    Syntax {
+        keywords: &["strobe", "allow"],
+        args: 0..=0,
+        usage: "strobe allow",
+        help: "allow frame rates below the guard",
+        parse: |_| Ok(Command::StrobeAllow),
+    },
+    Syntax {
+        keywords: &["strobe", "guard"],
+        args: 0..=1,
+        usage: "strobe guard [fps]",
+        help: "refuse fps below this (default 50)",
+        parse: |words| Ok(Command::StrobeGuard(parse_optional(words.next())?)),
+    },
+    Syntax {
+        keywords: &["stream", "adc"],
+        args: 0..=1,
+        usage: "stream adc [seconds]",
+        help: "print every raw knob sample (2s)",
+        parse: |words| {
+            let seconds = parse_optional(words.next())?;
+            Ok(Command::StreamAdc(seconds.unwrap_or(STREAM_SECONDS)))
+        },
+    },
+    Syntax {
+        keywords: &["sweep"],
+        args: 0..=1,
+        usage: "sweep [seconds]",
+        help: "record raw knob range while you turn it",
+        parse: |words| {
+            let seconds = parse_optional(words.next())?;
+            Ok(Command::Sweep(seconds.unwrap_or(SWEEP_SECONDS)))
+        },
+    },
+    Syntax {
+        keywords: &["telemetry"],
+        args: 1..=1,
+        usage: "telemetry <hz|off>",
+        help: "stream binary packets on RTT channel 1",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::Telemetry(None)),
+            rate => Ok(Command::Telemetry(Some(parse_number(rate)?))),
+        },
+    },
+    Syntax {
+        keywords: &["thermal"],
+        args: 0..=0,
+        usage: "thermal",
+        help: "show temperature and brightness ceilings",
+        parse: |_| Ok(Command::Thermal),
+    },
+    Syntax {
+        keywords: &["thermal", "temp"],
+        args: 1..=1,
+        usage: "thermal temp <C|off>",
+        help: "supply the LED temperature",
+        parse: |words| match words.next() {
+            Some("off") => Ok(Command::ThermalTemp(None)),
+            temp => Ok(Command::ThermalTemp(Some(parse_number(temp)?))),
+        },
+    },
+    Syntax {
+        keywords: &["thermal", "derate"],
+        args: 4..=4,
+        usage: "thermal derate <C> <r> <g> <b>",
+        help: "start temp, tenths of a level lost per C",
+        parse: |words| {
+            let start_c = parse_number(words.next())?;
+            let mut coefficients = [0; 3];
+            for coefficient in coefficients.iter_mut() {
+                *coefficient = parse_number(words.next())?;
+            }
+            Ok(Command::ThermalDerate(ThermalDerating {
+                start_c,
+                coefficients,
+            }))
+        },
+    },
+    Syntax {
+        keywords: &["undo"],
+        args: 0..=0,
+        usage: "undo",
+        help: "go back to the levels and fps before a change",
+        parse: |_| Ok(Command::Undo { redo: false }),
+    },
+    Syntax {
+        keywords: &["redo"],
+        args: 0..=0,
+        usage: "redo",
+        help: "reapply a change taken back with undo",
+        parse: |_| Ok(Command::Undo { redo: true }),
+    },
+    Syntax {
+        keywords: &["units"],
+        args: 1..=1,
+        usage: "units <fps|color>",
+        help: "frame rate as scans or per-color Hz",
+        parse: |words| match words.next() {
+            Some("fps") => Ok(Command::Units(FrameRateUnit::Scans)),
+            Some("color") => Ok(Command::Units(FrameRateUnit::PerColor)),
+            _ => Err(ParseError::InvalidArgument),
+        },
+    },
+    Syntax {
+        keywords: &["visible"],
+        args: 0..=0,
+        usage: "visible",
+        help: "find each channel's first visible level",
+        parse: |_| Ok(Command::Visible),
+    },
+    Syntax {
+        keywords: &["wizard"],
+        args: 0..=0,
+        usage: "wizard",
+        help: "start the guided calibration",
+        parse: |_| Ok(Command::Wizard),
+    },
+];
+
+/// Default duration of a raw ADC stream in seconds
+const STREAM_SECONDS: u64 = 2;
+
+/// Longest raw ADC stream in seconds
+const STREAM_MAX_SECONDS: u64 = 30;
+
+/// Default duration of a knob sweep in seconds
+const SWEEP_SECONDS: u64 = 5;
+
+/// Longest knob sweep in seconds
+const SWEEP_MAX_SECONDS: u64 = 60;
+
+/// Default time each test pattern is held, in seconds
+const PATTERN_SECONDS: u64 = 2;
+
+/// Default time each LED pin is lit by `identpins`, in seconds
+const IDENTIFY_SECONDS: u64 = 2;
+
+/// Longest hold per test pattern accepted, in seconds
+const PATTERN_MAX_SECONDS: u64 = 30;
+
+/// Largest current accepted by the `power` commands, in mA
+const POWER_MAX_MA: u32 = 1000;
+
+/// Largest slow-motion factor accepted
+const SLOW_MAX_FACTOR: u32 = 1000;
+
+/// A parsed host command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// List available commands: `help`
+    Help,
+    /// Set or change a level or the frame rate: `set`, `<r|g|b>`, `fps`
+    /// and `adjust` (see [`LevelCommand`])
+    Level(LevelCommand),
+    /// Show the running animation: `anim`
+    AnimShow,
+    /// Start an animation or return to manual control:
+    /// `anim <breathe <ms>|rainbow <ms>|seq|off>`
+    Anim(Animation),
+    /// Measure the highest frame rate the scan keeps up with: `benchmark`
+    Benchmark,
+    /// Show or change the button-to-channel assignment:
+    /// `buttons [<a> <b> <a+b>]`
+    Buttons(Option<ButtonMap>),
+    /// Set the levels to a named color: `color <name>`
+    Color(NamedColor),
+    /// Show or declare how the levels are encoded:
+    /// `colorspace [linear|srgb]`
+    ColorSpace(Option<ColorSpace>),
+    /// Switch to the other compared frame rate: `compare`
+    CompareToggle,
+    /// Store a compared frame rate: `compare set <a|b> [rate]`
+    CompareSet {
+        /// Setpoint index (0 = A, 1 = B)
+        setpoint: usize,
+        /// Rate in the selected unit, or `None` for the current one
+        rate: Option<u64>,
+    },
+    /// Stop comparing frame rates: `compare off`
+    CompareOff,
+    /// Select the on-window position: `edge <leading|alternate>`
+    Edge(PwmEdge),
+    /// Show or select how the channels share a frame:
+    /// `drive [scan|spread]`
+    Drive(Option<DriveMode>),
+    /// Show or switch the gamma duty tables: `gamma [on|off]`
+    Gamma(Option<bool>),
+    /// Show or set the sub-frames per frame: `subframes [count]`
+    SubFrames(Option<u32>),
+    /// Show or set the state refresh cap: `snapshot [hz]`
+    Snapshot(Option<u64>),
+    /// Show or set the output slew limit: `slew [levels|off]`
+    Slew(Option<u32>),
+    /// Show or select the slot time rounding:
+    /// `rounding [truncate|nearest|distribute]`
+    Rounding(Option<Rounding>),
+    /// Select how the knob at mid travel is indicated:
+    /// `center <off|log|cue>`
+    Center(CenterIndicator),
+    /// Enable or disable diagnostic output: `debug <on|off>`
+    Debug(bool),
+    /// Compare the current settings with a preset: `diff <1-4|tag>`
+    Diff(PresetRef),
+    /// Select the state display format: `display <text|bars|json>`
+    Display(OutputFormat),
+    /// Fade once from one color to another and hold it:
+    /// `fade <r1> <g1> <b1> <r2> <g2> <b2> <ms>`
+    Fade(Fade),
+    /// Show or select what the onboard matrix shows:
+    /// `indicator [off|dither|mode]`
+    Indicator(Option<IndicatorMode>),
+    /// Blink the liveness pixel or stop it: `indicator alive <on|off>`
+    IndicatorAlive(bool),
+    /// Show the current state, optionally in percent: `get [%]`
+    Get {
+        /// Whether to print levels as percentages
+        percent: bool,
+    },
+    /// Dump the complete calibration: `get config`
+    GetConfig,
+    /// Show the physical inputs: `get inputs`
+    GetInputs,
+    /// Show which source last set each knob parameter: `get owners`
+    GetOwners,
+    /// Show a channel's lookup table: `get lut <r|g|b>`
+    GetLut(usize),
+    /// Show the knob calibration: `get knobcal`
+    GetKnobCal,
+    /// Show the minimum visible levels: `get visible`
+    GetVisible,
+    /// Show the frame rate setting, or the measured frame rate:
+    /// `get fps [--actual]`
+    GetFps {
+        /// Whether to report the measured rate
+        actual: bool,
+    },
+    /// Output one channel alone or resume the scan: `hold <r|g|b|off>`
+    Hold(Option<usize>),
+    /// Light each LED pin alone in turn: `identpins [seconds]`
+    IdentifyPins(u64),
+    /// Select the knob response curve: `knob <linear|dim>`
+    Knob(KnobResponse),
+    /// Blend between adjacent knob levels: `knob blend <on|off>`
+    KnobBlend(bool),
+    /// Make the knob pick up values before taking over:
+    /// `knob pickup <on|off>`
+    KnobPickup(bool),
+    /// Set the hue with the knob instead of the frame rate:
+    /// `knob hue <on|off>`
+    KnobHue(bool),
+    /// Nudge levels with the knob instead of setting them:
+    /// `knob fine <on|off>`
+    KnobFine(bool),
+    /// Show or set the dead travel at each end of the knob, in thousandths
+    /// of the travel: `knob deadband [<low> <high>]`
+    KnobDeadband(Option<[u32; 2]>),
+    /// Show or set the window knob level changes are batched over, in
+    /// milliseconds: `knob coalesce [ms]`
+    KnobCoalesce(Option<u64>),
+    /// Show or set the UI poll interval in milliseconds: `poll [ms]`
+    Poll(Option<u64>),
+    /// Mark the current frame rate as the flicker-fusion threshold, or
+    /// clear it: `mark <fusion|clear>`
+    MarkFusion(bool),
+    /// Show the measurement duties: `meas`
+    MeasShow,
+    /// Override a channel's level with an exact duty, or clear it:
+    /// `meas <r|g|b> <duty|off>`
+    Meas {
+        /// Channel (0=red, 1=green, 2=blue)
+        channel: usize,
+        /// Duty in DUTY_ONEths, or `None` to clear
+        duty: Option<u32>,
+    },
+    /// Clear every measurement duty: `meas off`
+    MeasOff,
+    /// Show or set the color-correction matrix:
+    /// `matrix [identity | <9 values>]`
+    Matrix(Option<ColorMatrix>),
+    /// Show the test patterns: `pattern [seconds]`
+    Pattern(u64),
+    /// Show the power estimate and budget: `power`
+    Power,
+    /// Set or clear the average current budget in µA:
+    /// `power budget <mA|off>`
+    PowerBudget(Option<u32>),
+    /// Choose what happens over budget: `power action <warn|cap>`
+    PowerAction(BudgetAction),
+    /// Set each LED's current while lit in µA: `power peak <r> <g> <b>`
+    PowerPeak([u32; 3]),
+    /// List the preset slots: `preset list`
+    PresetList,
+    /// Save the current calibration: `preset save <1-4> [tag]`
+    PresetSave {
+        /// Preset slot (0 to PRESET_SLOTS-1)
+        slot: usize,
+        /// Condition label
+        tag: Option<Tag>,
+    },
+    /// Load a preset by slot or tag: `preset load <1-4|tag>`
+    PresetLoad(PresetRef),
+    /// Load the next tagged preset: `preset next [all|white]`, `next white`
+    PresetNext(PresetFilter),
+    /// Select the presets the gesture steps through:
+    /// `preset cycle <all|tagged|white>`
+    PresetCycle(PresetFilter),
+    /// Empty a preset slot: `preset clear <1-4>`
+    PresetClear(usize),
+    /// Release the emergency off: `resume`
+    Resume,
+    /// Save the calibration to flash: `save`
+    Save,
+    /// Show the automatic save policy: `save auto`
+    SaveAutoShow,
+    /// Set the automatic save policy, or stop saving automatically:
+    /// `save auto <seconds|off> [level] [fps]`
+    SaveAuto(Option<SavePolicy>),
+    /// List the color sequence: `seq`
+    SeqList,
+    /// Append a stop to the sequence: `seq add <r> <g> <b> <ms>`
+    SeqAdd(ColorStop),
+    /// Set the crossfade time: `seq fade <ms>`
+    SeqFade(u32),
+    /// Remove every stop: `seq clear`
+    SeqClear,
+    /// Start or stop playback: `seq <play|stop>`
+    SeqPlay(bool),
+    /// Replace the complete calibration: `set config <hex>`
+    SetConfig(CalibrationConfig),
+    /// Upload a channel's lookup table: `set lut <r|g|b> <16 levels>`
+    SetLut {
+        /// Channel index (0=red, 1=green, 2=blue)
+        channel: usize,
+        /// Validated table
+        lut: Lut,
+    },
+    /// Set the raw knob readings at both ends: `set knobcal <min> <max>`
+    SetKnobCal(KnobCalibration),
+    /// Show or set the refresh multipliers: `refresh [<r> <g> <b>]`
+    Refresh(Option<[u32; 3]>),
+    /// Stretch the scan or restore normal timing: `slow <factor|off>`
+    Slow(Option<u32>),
+    /// Print or reset the session statistics: `stats [reset]`
+    Stats {
+        /// Whether to start a new session instead of printing
+        reset: bool,
+    },
+    /// Print the time spent in each knob mode: `stats modes`
+    StatsModes,
+    /// Show the strobe guard: `strobe`
+    Strobe,
+    /// Allow strobing frame rates: `strobe allow`
+    StrobeAllow,
+    /// Guard again, optionally at a new minimum: `strobe guard [fps]`
+    StrobeGuard(Option<u64>),
+    /// Engage the emergency off: `stop`
+    Stop,
+    /// Record the raw knob range: `sweep [seconds]`
+    Sweep(u64),
+    /// Print raw knob samples for some seconds: `stream adc [seconds]`
+    StreamAdc(u64),
+    /// Enable or disable binary telemetry: `telemetry <hz|off>`
+    Telemetry(Option<u32>),
+    /// Set the heartbeat interval: `heartbeat <seconds|off>`
+    #[cfg(feature = "heartbeat")]
+    Heartbeat(Option<u32>),
+    /// Show the thermal ceilings: `thermal`
+    Thermal,
+    /// Supply or clear the LED temperature: `thermal temp <C|off>`
+    ThermalTemp(Option<i32>),
+    /// Set the thermal derating: `thermal derate <C> <r> <g> <b>`
+    ThermalDerate(ThermalDerating),
+    /// Step back or forward through the undo history: `undo` / `redo`
+    Undo {
+        /// `true` for redo
+        redo: bool,
+    },
+    /// Show the position in the undo history: `history`
+    History,
+    /// Select the frame rate unit: `units <fps|color>`
+    Units(FrameRateUnit),
+    /// Find each channel's minimum visible level: `visible`
+    Visible,
+    /// Start the guided calibration: `wizard`
+    Wizard,
+}
+
+/// Preset named in a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresetRef {
+    /// Preset slot (0 to PRESET_SLOTS-1)
+    Slot(usize),
+    /// First preset carrying this tag
+    Tag(Tag),
+}
+
+impl From<LevelCommand> for Command {
+    fn from(command: LevelCommand) -> Self {
+        Command::Level(command)
+    }
+}
+
+impl Command {
+    /// Parse one command line
+    ///
+    /// The line is matched against [`COMMANDS`], the same table `help`
+    /// prints.
+    ///
+    /// # Arguments
+    /// * `line` - Command text without the line terminator
+    ///
+    /// # Returns
+    /// The parsed command, or the reason it was rejected
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        parse_line(COMMANDS, line)
+    }
+
+    /// Apply the command to the shared state and print a reply
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    pub async fn execute(self, shared: &SharedState) {
+        match self {
+            Command::Help => {
+                for syntax in COMMANDS.iter().copied().flatten() {
+                    rprintln!("{:<30}{}", syntax.usage, syntax.help);
+                }
+            }
+            Command::Level(LevelCommand::AdjustLevel { channel, delta }) => {
+                let (level, clamped) = shared.adjust_channel_level(channel, delta).await;
+                rprintln!(
+                    "ok {} {}{}",
+                    CHANNEL_NAMES[channel][0],
+                    level,
+                    if clamped { " (clamped)" } else { "" }
+                );
+            }
+            Command::Level(LevelCommand::AdjustRate(delta)) => {
+                let (frame_rate, clamped) = shared.adjust_frame_rate(delta).await;
+                rprintln!(
+                    "ok fps {}{}",
+                    frame_rate,
+                    if clamped { " (clamped)" } else { "" }
+                );
+            }
+            Command::AnimShow => print_animation(shared.get_animation().await),
+            Command::Anim(animation) => {
+                if animation == Animation::Sequence
+                    && shared.get_sequence().await.stops().is_empty()
+                {
+                    rprintln!("error: sequence is empty (add stops with `seq add`)");
+                    return;
+                }
+                shared.set_animation(animation).await;
+                print_animation(animation);
+            }
+            Command::Benchmark => {
+                shared.request_benchmark().await;
+                rprintln!("ok benchmark");
+            }
+            Command::Buttons(map) => {
+                if let Some(map) = map {
+                    shared.set_button_map(map).await;
+                }
+                let [a, b, both] = shared.get_button_map().await.channels();
+                rprintln!(
+                    "ok buttons a {} b {} a+b {}",
+                    CHANNEL_NAMES[a][0],
+                    CHANNEL_NAMES[b][0],
+                    CHANNEL_NAMES[both][0]
+                );
+            }
+            Command::Color(color) => {
+                let levels = color.levels(shared.get_white_reference().await);
+                let [red, green, blue] = shared.set_rgb(levels).await;
+                rprintln!("ok color {} rgb {} {} {}", color.name, red, green, blue);
+            }
+            Command::ColorSpace(space) => {
+                if let Some(space) = space {
+                    shared.set_color_space(space).await;
+                }
+                rprintln!("ok colorspace {}", shared.get_color_space().await.name());
+            }
+            Command::CompareToggle => {
+                let unit = shared.get_frame_rate_unit().await;
+                let sub_frames = shared.get_sub_frames().await;
+                match toggle_rate_compare(shared).await {
+                    Some((setpoint, frame_rate)) => rprintln!(
+                        "ok compare {} {} {}",
+                        RateCompare::name(setpoint),
+                        unit.from_scans(frame_rate, sub_frames),
+                        unit.label()
+                    ),
+                    None => rprintln!("error: set both rates first (compare set <a|b>)"),
+                }
+            }
+            Command::CompareSet { setpoint, rate } => {
+                let unit = shared.get_frame_rate_unit().await;
+                let sub_frames = shared.get_sub_frames().await;
+                let frame_rate = match rate {
+                    Some(rate) => unit.to_scans(rate, sub_frames),
+                    None => shared.get_frame_rate().await,
+                };
+                let frame_rate = frame_rate.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE);
+                let mut compare = shared.get_rate_compare().await;
+                compare.setpoints[setpoint] = Some(frame_rate);
+                shared.set_rate_compare(compare).await;
+                // Keep the showing setpoint in step with its new value
+                if compare.active == Some(setpoint) {
+                    shared.set_frame_rate(frame_rate).await;
+                }
+                rprintln!(
+                    "ok compare set {} {} {}",
+                    RateCompare::name(setpoint),
+                    unit.from_scans(frame_rate, sub_frames),
+                    unit.label()
+                );
+            }
+            Command::CompareOff => {
+                let mut compare = shared.get_rate_compare().await;
+                compare.active = None;
+                shared.set_rate_compare(compare).await;
+                rprintln!("ok compare off");
+            }
+            Command::Center(indicator) => {
+                shared.set_center_indicator(indicator).await;
+                rprintln!("ok center {}", indicator.name());
+            }
+            Command::Edge(edge) => {
+                shared.set_pwm_edge(edge).await;
+                rprintln!("ok edge {}", edge.name());
+            }
+            Command::Drive(mode) => {
+                if let Some(mode) = mode {
+                    shared.set_drive_mode(mode).await;
+                }
+                rprintln!("ok drive {}", shared.get_drive_mode().await.name());
+            }
+            Command::Gamma(enabled) => {
+                if let Some(enabled) = enabled {
+                    shared.set_gamma(enabled).await;
+                }
+                rprintln!(
+                    "ok gamma {}",
+                    if shared.get_gamma().await {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+            }
+            Command::SubFrames(count) => {
+                if let Some(count) = count {
+                    shared.set_sub_frames(count).await;
+                }
+                rprintln!("ok subframes {}", shared.get_sub_frames().await);
+            }
+            Command::Rounding(policy) => {
+                if let Some(policy) = policy {
+                    shared.set_rounding(policy).await;
+                }
+                rprintln!("ok rounding {}", shared.get_rounding().await.name());
+            }
+            Command::Slew(rate) => {
+                if let Some(rate) = rate {
+                    shared.set_slew_rate(rate).await;
+                }
+                match shared.get_slew_rate().await {
+                    LEVELS => rprintln!("ok slew off"),
+                    rate => rprintln!("ok slew {} per frame", rate),
+                }
+            }
+            Command::Snapshot(refresh_hz) => {
+                if let Some(refresh_hz) = refresh_hz {
+                    shared.set_state_refresh(refresh_hz).await;
+                }
+                rprintln!("ok snapshot {} hz", shared.get_state_refresh().await);
+            }
+            Command::Debug(enabled) => {
+                shared.set_debug_output(enabled).await;
+                rprintln!("ok debug {}", if enabled { "on" } else { "off" });
+            }
+            // Compared by the command task, which reads the saved presets
+            // from flash and replies
+            Command::Diff(preset) => shared.request_diff(preset),
+            Command::Indicator(mode) => {
+                if let Some(mode) = mode {
+                    shared.set_indicator_mode(mode).await;
+                }
+                let mode = shared.get_indicator_mode().await;
+                if mode == IndicatorMode::Dither {
+                    let pixels = dither_pixels(effective_color(shared.get_output_levels().await));
+                    rprintln!("ok indicator dither {}/{} pixels", pixels, MATRIX_PIXELS);
+                } else {
+                    rprintln!("ok indicator {}", mode.name());
+                }
+            }
+            Command::IndicatorAlive(on) => {
+                shared.set_liveness(on).await;
+                rprintln!("ok indicator alive {}", if on { "on" } else { "off" });
+            }
+            Command::Fade(fade) => {
+                // A fade takes over from any animation, like setting levels
+                if shared.get_animation().await != Animation::Off {
+                    shared.set_animation(Animation::Off).await;
+                }
+                shared.request_fade(fade);
+                let [r1, g1, b1] = fade.from;
+                let [r2, g2, b2] = fade.to;
+                rprintln!(
+                    "ok fade {} {} {} to {} {} {} in {} ms",
+                    r1,
+                    g1,
+                    b1,
+                    r2,
+                    g2,
+                    b2,
+                    fade.ms
+                );
+            }
+            Command::Display(format) => {
+                shared.set_output_format(format).await;
+                let name = match format {
+                    OutputFormat::Text => "text",
+                    OutputFormat::Bars => "bars",
+                    OutputFormat::Json => "json",
+                };
+                rprintln!("ok display {}", name);
+            }
+            Command::Get { percent } => {
+                let state = shared.get_state().await;
+                let unit = shared.get_frame_rate_unit().await;
+                let sub_frames = shared.get_sub_frames().await;
+                let rate_name = match unit {
+                    FrameRateUnit::Scans => "fps",
+                    FrameRateUnit::PerColor => "hz",
+                };
+                let rate = unit.from_scans(state.frame_rate, sub_frames);
+                let [red, green, blue] = state.levels;
+                let [eff_red, eff_green, eff_blue] =
+                    effective_color(shared.get_output_levels().await);
+                if percent {
+                    let [red, green, blue] =
+                        state.levels.map(|level| level_to_percent(level, LEVELS));
+                    rprintln!(
+                        "ok rgb {}% {}% {}% {} {} effective {} {} {}",
+                        red,
+                        green,
+                        blue,
+                        rate_name,
+                        rate,
+                        eff_red,
+                        eff_green,
+                        eff_blue
+                    );
+                } else {
+                    rprintln!(
+                        "ok rgb {} {} {} {} {} effective {} {} {}",
+                        red,
+                        green,
+                        blue,
+                        rate_name,
+                        rate,
+                        eff_red,
+                        eff_green,
+                        eff_blue
+                    );
+                }
+                let animation = shared.get_animation().await;
+                if animation != Animation::Off {
+                    print_animation(animation);
+                }
+            }
+            Command::GetConfig => {
+                let blob = shared.get_calibration_config().await.encode();
+                rprint!("ok config ");
+                for byte in blob {
+                    rprint!("{:02x}", byte);
+                }
+                rprintln!();
+                let sense = match shared.get_dead_leds().await {
+                    Ok(_) => "available",
+                    Err(err) => err.message(),
+                };
+                rprintln!("caps led-sense {}", sense);
+            }
+            Command::GetKnobCal => {
+                let calibration = shared.get_knob_calibration().await;
+                rprintln!("ok knobcal {} {}", calibration.min(), calibration.max());
+            }
+            Command::GetFps { actual: false } => {
+                rprintln!("ok fps {}", shared.get_frame_rate().await);
+            }
+            Command::GetFps { actual: true } => {
+                let millihertz = shared.get_actual_frame_rate().await;
+                rprintln!(
+                    "ok fps actual {}.{:03} set {}",
+                    millihertz / 1000,
+                    millihertz % 1000,
+                    shared.get_frame_rate().await
+                );
+            }
+            Command::GetVisible => {
+                let [red, green, blue] = shared.get_min_visible().await;
+                rprintln!("ok visible r {} g {} b {}", red, green, blue);
+            }
+            Command::GetInputs => {
+                let inputs = shared.get_input_snapshot().await;
+                let [a, b] = inputs
+                    .buttons
+                    .map(|pressed| if pressed { "down" } else { "up" });
+                rprintln!(
+                    "ok inputs raw {} level {} a {} b {}",
+                    inputs.raw,
+                    inputs.level,
+                    a,
+                    b
+                );
+            }
+            Command::GetOwners => {
+                let sources = shared.get_parameter_sources().await;
+                let [red, green, blue] = sources.levels.map(InputSource::name);
+                rprintln!(
+                    "ok owners fps {} r {} g {} b {}",
+                    sources.frame_rate.name(),
+                    red,
+                    green,
+                    blue
+                );
+            }
+            Command::GetLut(channel) => {
+                let lut = shared.get_channel_luts().await[channel];
+                rprintln!("ok lut {}", CHANNEL_NAMES[channel][0]);
+                for (level, output) in lut.entries().iter().enumerate() {
+                    rprintln!("  {} {}", level, output);
+                }
+            }
+            Command::Hold(channel) => {
+                shared.set_hold_channel(channel).await;
+                match channel {
+                    Some(channel) => rprintln!("ok hold {}", CHANNEL_NAMES[channel][0]),
+                    None => rprintln!("ok hold off"),
+                }
+            }
+            Command::Knob(response) => {
+                shared.set_knob_response(response).await;
+                let name = match response {
+                    KnobResponse::Linear => "linear",
+                    KnobResponse::DimExpanded => "dim",
+                };
+                rprintln!("ok knob {}", name);
+            }
+            Command::KnobBlend(enabled) => {
+                shared.set_knob_blend(enabled).await;
+                rprintln!("ok knob blend {}", if enabled { "on" } else { "off" });
+            }
+            Command::KnobPickup(enabled) => {
+                shared.set_knob_pickup(enabled).await;
+                rprintln!("ok knob pickup {}", if enabled { "on" } else { "off" });
+            }
+            Command::KnobHue(enabled) => {
+                shared.set_knob_hue(enabled).await;
+                rprintln!("ok knob hue {}", if enabled { "on" } else { "off" });
+            }
+            Command::KnobFine(enabled) => {
+                shared.set_knob_fine(enabled).await;
+                rprintln!("ok knob fine {}", if enabled { "on" } else { "off" });
+            }
+            Command::Poll(interval_ms) => {
+                if let Some(interval_ms) = interval_ms {
+                    shared.set_poll_ms(interval_ms).await;
+                }
+                rprintln!("ok poll {} ms", shared.get_poll_ms().await);
+            }
+            Command::KnobCoalesce(window_ms) => {
+                if let Some(window_ms) = window_ms {
+                    shared.set_knob_coalesce_ms(window_ms).await;
+                }
+                rprintln!(
+                    "ok knob coalesce {} ms",
+                    shared.get_knob_coalesce_ms().await
+                );
+            }
+            Command::KnobDeadband(deadbands) => {
+                if let Some(deadbands) = deadbands {
+                    shared
+                        .set_knob_deadbands(deadbands.map(|deadband| deadband as f32 / 1000.0))
+                        .await;
+                }
+                let [low, high] = shared.get_knob_deadbands().await;
+                rprintln!("ok knob deadband {:.3} {:.3}", low, high);
+            }
+            Command::MarkFusion(true) => {
+                let frame_rate = shared.get_frame_rate().await;
+                shared.set_fusion_floor(Some(frame_rate)).await;
+                rprintln!("ok fusion {} fps", frame_rate);
+            }
+            Command::MarkFusion(false) => {
+                shared.set_fusion_floor(None).await;
+                rprintln!("ok fusion cleared");
+            }
+            Command::MeasShow => print_measure_duties(shared).await,
+            Command::Meas { channel, duty } => {
+                shared.set_measure_duty(channel, duty).await;
+                print_measure_duties(shared).await;
+            }
+            Command::MeasOff => {
+                for channel in 0..3 {
+                    shared.set_measure_duty(channel, None).await;
+                }
+                print_measure_duties(shared).await;
+            }
+            Command::Matrix(matrix) => {
+                if let Some(matrix) = matrix {
+                    shared.set_color_matrix(matrix).await;
+                }
+                let matrix = shared.get_color_matrix().await;
+                rprintln!("ok matrix");
+                for row in matrix.0 {
+                    let [r, g, b] = row.map(Coefficient);
+                    rprintln!("  {} {} {}", r, g, b);
+                }
+            }
+            Command::IdentifyPins(seconds) => {
+                let seconds = seconds.clamp(1, PATTERN_MAX_SECONDS);
+                shared.request_identify_pins(seconds);
+                rprintln!("ok identpins {}s", seconds);
+            }
+            Command::Pattern(seconds) => {
+                let seconds = seconds.clamp(1, PATTERN_MAX_SECONDS);
+                shared.request_test_pattern(seconds);
+                rprintln!("ok pattern {}s", seconds);
+            }
+            Command::Power => print_power(shared).await,
+            Command::PowerBudget(budget_ua) => {
+                shared
+                    .set_power_budget(|budget| budget.budget_ua = budget_ua)
+                    .await;
+                print_power(shared).await;
+            }
+            Command::PowerAction(action) => {
+                shared
+                    .set_power_budget(|budget| budget.action = action)
+                    .await;
+                print_power(shared).await;
+            }
+            Command::PowerPeak(peak_ua) => {
+                shared
+                    .set_power_budget(|budget| budget.peak_ua = peak_ua)
+                    .await;
+                print_power(shared).await;
+            }
+            Command::PresetList => {
+                let presets = shared.get_presets().await;
+                let active = shared.get_active_preset().await;
+                rprintln!("ok presets");
+                for (slot, preset) in presets.iter().enumerate() {
+                    let marker = if active == Some(slot) { "*" } else { " " };
+                    match preset {
+                        Some(preset) => print_preset(marker, slot, preset),
+                        None => rprintln!("{} {} empty", marker, slot + 1),
+                    }
+                }
+            }
+            Command::PresetSave { slot, tag } => {
+                let state = shared.get_state().await;
+                let preset = Preset {
+                    levels: state.levels,
+                    frame_rate: state.frame_rate,
+                    tag,
+                };
+                shared.set_preset(slot, Some(preset)).await;
+                shared.set_active_preset(Some(slot)).await;
+                print_preset("ok preset", slot, &preset);
+            }
+            Command::PresetLoad(preset) => {
+                let Some(slot) = resolve_preset(&shared.get_presets().await, preset) else {
+                    return;
+                };
+                match load_preset(shared, slot).await {
+                    Some(preset) => print_preset("ok preset", slot, &preset),
+                    None => rprintln!("error: preset {} is empty", slot + 1),
+                }
+            }
+            Command::PresetNext(filter) => match load_next(shared, filter).await {
+                Some((slot, preset)) => print_preset("ok preset", slot, &preset),
+                None if filter == PresetFilter::All => rprintln!("error: no presets"),
+                None => rprintln!("error: no {} presets", filter.name()),
+            },
+            Command::PresetCycle(filter) => {
+                shared.set_preset_cycle(filter).await;
+                rprintln!("ok preset cycle {}", filter.name());
+            }
+            Command::PresetClear(slot) => {
+                shared.set_preset(slot, None).await;
+                if shared.get_active_preset().await == Some(slot) {
+                    shared.set_active_preset(None).await;
+                }
+                rprintln!("ok preset {} cleared", slot + 1);
+            }
+            Command::Resume => {
+                shared.set_emergency_off(false).await;
+                rprintln!("ok resume");
+            }
+            // Saved by the command task, which holds the flash and replies
+            Command::Save => shared.request_save(),
+            Command::SaveAutoShow => print_auto_save(shared.get_auto_save().await),
+            Command::SaveAuto(policy) => {
+                shared.set_auto_save(policy).await;
+                print_auto_save(policy);
+            }
+            Command::Refresh(multipliers) => {
+                if let Some(multipliers) = multipliers {
+                    shared.set_refresh_multipliers(multipliers).await;
+                }
+                let [red, green, blue] = shared.get_refresh_multipliers().await;
+                rprintln!("ok refresh {} {} {}", red, green, blue);
+            }
+            Command::Slow(factor) => {
+                let factor = factor.map(|factor| factor.clamp(2, SLOW_MAX_FACTOR));
+                shared.set_slow_motion(factor).await;
+                match factor {
+                    Some(factor) => {
+                        let frame_ms = 1000 * factor as u64 / shared.get_frame_rate().await;
+                        rprintln!("ok slow x{} ({} ms per frame)", factor, frame_ms);
+                    }
+                    None => rprintln!("ok slow off"),
+                }
+            }
+            Command::Stats { reset: true } => {
+                shared.reset_session_stats().await;
+                rprintln!("ok stats reset");
+            }
+            Command::Stats { reset: false } => {
+                let stats = shared.get_session_stats().await;
+                rprintln!("ok stats (seconds at each level 0-15)");
+                for (channel, names) in CHANNEL_NAMES.iter().enumerate() {
+                    rprint!("  {}", names[0]);
+                    for ms in stats.level_ms(channel) {
+                        rprint!(" {}", ms / 1000);
+                    }
+                    rprintln!();
+                }
+                if let Some((min, max)) = stats.frame_rate_range() {
+                    rprintln!("  fps range {}-{}", min, max);
+                }
+                if let Some(([red, green, blue], frame_rate)) = stats.latest() {
+                    rprintln!("  final rgb {} {} {} fps {}", red, green, blue, frame_rate);
+                }
+            }
+            Command::StatsModes => {
+                let usage = shared.get_session_stats().await.mode_usage();
+                rprintln!("ok stats modes (seconds, entries)");
+                for (mode, (ms, entries)) in TRACKED_MODES.iter().zip(usage) {
+                    let name = match mode {
+                        // Keep the name a single word
+                        ControlMode::FrameRate => "rate",
+                        mode => mode.name(),
+                    };
+                    rprintln!("  {} {} {}", name, ms / 1000, entries);
+                }
+            }
+            Command::Strobe => print_strobe_guard(shared).await,
+            Command::StrobeAllow => {
+                let guard = shared.get_strobe_guard().await;
+                shared
+                    .set_strobe_guard(StrobeGuard {
+                        allowed: true,
+                        ..guard
+                    })
+                    .await;
+                rprintln!(
+                    "warning: strobe guard off, frame rates down to {} fps allowed; \
+                     flashing light can trigger photosensitive seizures",
+                    MIN_FRAME_RATE
+                );
+                print_strobe_guard(shared).await;
+            }
+            Command::StrobeGuard(min_fps) => {
+                let guard = shared.get_strobe_guard().await;
+                let min_fps = min_fps
+                    .unwrap_or(guard.min_fps)
+                    .clamp(MIN_FRAME_RATE, MAX_FRAME_RATE);
+                shared
+                    .set_strobe_guard(StrobeGuard {
+                        min_fps,
+                        allowed: false,
+                    })
+                    .await;
+                print_strobe_guard(shared).await;
+            }
+            Command::Stop => {
+                shared.set_emergency_off(true).await;
+                rprintln!("ok stop");
+            }
+            Command::SeqList => {
+                let sequence = shared.get_sequence().await;
+                rprintln!("ok seq fade {} ms", sequence.fade_ms());
+                for (i, stop) in sequence.stops().iter().enumerate() {
+                    let [red, green, blue] = stop.levels;
+                    rprintln!(
+                        "  {} rgb {} {} {} hold {} ms",
+                        i + 1,
+                        red,
+                        green,
+                        blue,
+                        stop.hold_ms
+                    );
+                }
+            }
+            Command::SeqAdd(stop) => {
+                let stop = ColorStop {
+                    levels: stop.levels.map(|level| level.min(LEVELS - 1)),
+                    ..stop
+                };
+                match shared.set_sequence(|sequence| sequence.push(stop)).await {
+                    Ok(()) => {
+                        let [red, green, blue] = stop.levels;
+                        rprintln!("ok seq add {} {} {} {} ms", red, green, blue, stop.hold_ms);
+                    }
+                    Err(SequenceError::Full) => {
+                        rprintln!("error: sequence full ({} stops)", MAX_STOPS)
+                    }
+                }
+            }
+            Command::SeqFade(fade_ms) => {
+                shared
+                    .set_sequence(|sequence| sequence.set_fade_ms(fade_ms))
+                    .await;
+                rprintln!("ok seq fade {} ms", fade_ms);
+            }
+            Command::SeqClear => {
+                shared.set_sequence(|sequence| sequence.clear()).await;
+                rprintln!("ok seq clear");
+            }
+            Command::SeqPlay(play) => {
+                // Playing the sequence is its animation; stopping it only
+                // ends that one
+                if play {
+                    shared.set_animation(Animation::Sequence).await;
+                } else if shared.get_animation().await == Animation::Sequence {
+                    shared.set_animation(Animation::Off).await;
+                } else {
+                    shared.request_sequence(false);
+                }
+                rprintln!("ok seq {}", if play { "play" } else { "stop" });
+            }
+            Command::Level(LevelCommand::SetRate { rate, unit }) => {
+                let unit = match unit {
+                    Some(unit) => unit,
+                    None => shared.get_frame_rate_unit().await,
+                };
+                let sub_frames = shared.get_sub_frames().await;
+                let frame_rate = unit
+                    .to_scans(rate, sub_frames)
+                    .clamp(MIN_FRAME_RATE, MAX_FRAME_RATE);
+                let guarded = shared.set_frame_rate(frame_rate).await;
+                if guarded > frame_rate {
+                    match shared.get_fusion_floor().await {
+                        Some(floor) if floor == guarded => {
+                            rprintln!("warning: below fusion threshold, using {} fps", guarded)
+                        }
+                        _ => rprintln!(
+                            "warning: below strobe guard, using {} fps (strobe allow overrides)",
+                            guarded
+                        ),
+                    }
+                }
+                rprintln!(
+                    "ok rate {} {}",
+                    unit.from_scans(guarded, sub_frames),
+                    unit.label()
+                );
+            }
+            Command::Level(LevelCommand::SetLevel { channel, level }) => {
+                let level = shared.set_channel_level(channel, level).await;
+                rprintln!("ok {} {}", CHANNEL_NAMES[channel][0], level);
+            }
+            Command::Level(LevelCommand::SetRgb(levels)) => {
+                let [red, green, blue] = shared.set_rgb(levels).await;
+                rprintln!("ok rgb {} {} {}", red, green, blue);
+            }
+            Command::SetConfig(config) => {
+                // Parsing already validated the whole blob, so this is the
+                // only point where the shared state changes
+                shared.set_calibration_config(config).await;
+                let [red, green, blue] = config.levels;
+                // The strobe guard may have raised the frame rate
+                rprintln!(
+                    "ok config rgb {} {} {} fps {}",
+                    red,
+                    green,
+                    blue,
+                    shared.get_frame_rate().await
+                );
+            }
+            Command::SetKnobCal(calibration) => {
+                shared.set_knob_calibration(calibration).await;
+                rprintln!("ok knobcal {} {}", calibration.min(), calibration.max());
+            }
+            Command::SetLut { channel, lut } => {
+                shared.set_channel_lut(channel, lut).await;
+                rprintln!("ok lut {}", CHANNEL_NAMES[channel][0]);
+            }
+            Command::Sweep(seconds) => {
+                let seconds = seconds.clamp(1, SWEEP_MAX_SECONDS);
+                shared.request_sweep(seconds);
+                rprintln!("ok sweep {}s", seconds);
+            }
+            Command::StreamAdc(seconds) => {
+                let seconds = seconds.clamp(1, STREAM_MAX_SECONDS);
+                shared.request_adc_stream(seconds);
+                rprintln!("ok stream adc {}s", seconds);
+            }
+            Command::Telemetry(rate) => {
+                let rate = rate.map(|rate| rate.clamp(1, MAX_TELEMETRY_HZ));
+                shared.set_telemetry_rate(rate).await;
+                match rate {
+                    Some(rate) => rprintln!("ok telemetry {} hz", rate),
+                    None => rprintln!("ok telemetry off"),
+                }
+            }
+            #[cfg(feature = "heartbeat")]
+            Command::Heartbeat(seconds) => {
+                let seconds = seconds.map(|seconds| seconds.clamp(1, MAX_HEARTBEAT_SECONDS));
+                shared.set_heartbeat_interval(seconds).await;
+                match seconds {
+                    Some(seconds) => rprintln!("ok heartbeat {} s", seconds),
+                    None => rprintln!("ok heartbeat off"),
+                }
+            }
+            Command::Thermal => print_thermal(shared).await,
+            Command::ThermalTemp(temp_c) => {
+                shared.set_led_temperature(temp_c).await;
+                print_thermal(shared).await;
+            }
+            Command::ThermalDerate(derating) => {
+                shared.set_thermal_derating(derating).await;
+                print_thermal(shared).await;
+            }
+            Command::Undo { redo } => {
+                let name = if redo { "redo" } else { "undo" };
+                match shared.step_history(redo).await {
+                    Some((checkpoint, (position, len))) => {
+                        let [red, green, blue] = checkpoint.levels;
+                        rprintln!(
+                            "ok {} rgb {} {} {} fps {} ({}/{})",
+                            name,
+                            red,
+                            green,
+                            blue,
+                            checkpoint.frame_rate,
+                            position,
+                            len
+                        );
+                    }
+                    None => rprintln!("error: nothing to {}", name),
+                }
+            }
+            Command::History => {
+                let (position, len) = shared.get_history_position().await;
+                rprintln!("ok history {}/{}", position, len);
+            }
+            Command::Units(unit) => {
+                shared.set_frame_rate_unit(unit).await;
+                rprintln!("ok units {}", unit.label());
+            }
+            Command::Visible => {
+                shared.request_min_visible();
+                rprintln!("ok visible");
+            }
+            Command::Wizard => {
+                shared.request_wizard();
+                rprintln!("ok wizard");
+            }
+        }
+    }
+}
+
+/// Print the thermal derating state
+///
+/// Shows the temperature, the derating model and the resulting ceilings,
+/// marking channels currently held below their requested output.
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+async fn print_thermal(shared: &SharedState) {
+    let derating = shared.get_thermal_derating().await;
+    let [r, g, b] = derating.coefficients;
+    match shared.get_thermal_temperature().await {
+        Some((temp_c, true)) => rprintln!("ok thermal {} C (supplied)", temp_c),
+        Some((temp_c, false)) => rprintln!("ok thermal {} C (die)", temp_c),
+        None => rprintln!("ok thermal no temperature (not limited)"),
+    }
+    rprintln!(
+        "derate from {} C by {} {} {} tenths/C",
+        derating.start_c,
+        r,
+        g,
+        b
+    );
+    let ceilings = shared.get_thermal_ceilings().await;
+    let throttled = shared.get_throttled_channels().await;
+    for (i, names) in CHANNEL_NAMES.iter().enumerate() {
+        let hot = if throttled[i] { " (limiting)" } else { "" };
+        rprintln!("{} ceiling {}{}", names[1], ceilings[i], hot);
+    }
+}
+
+/// Show an animation, e.g. `ok anim breathe 2000 ms`
+///
+/// # Arguments
+/// * `animation` - Animation to show
+fn print_animation(animation: Animation) {
+    match animation.period_ms() {
+        Some(period_ms) => rprintln!("ok anim {} {} ms", animation.name(), period_ms),
+        None => rprintln!("ok anim {}", animation.name()),
+    }
+}
+
+/// Show the automatic save policy, e.g. `ok save auto 60 s, 2 levels, 5 fps`
+///
+/// # Arguments
+/// * `policy` - Automatic save policy, or `None` when off
+fn print_auto_save(policy: Option<SavePolicy>) {
+    match policy {
+        Some(policy) => rprintln!(
+            "ok save auto {} s, {} levels, {} fps",
+            policy.min_interval_s,
+            policy.level_threshold,
+            policy.frame_rate_threshold
+        ),
+        None => rprintln!("ok save auto off"),
+    }
+}
+
+/// Show the strobe guard's minimum and whether it is overridden
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+async fn print_strobe_guard(shared: &SharedState) {
+    let guard = shared.get_strobe_guard().await;
+    rprintln!(
+        "ok strobe guard {} fps{}",
+        guard.min_fps,
+        if guard.allowed {
+            " (allowed below)"
+        } else {
+            ""
+        }
+    );
+}
+
+/// Show the power estimate, the budget and the on-currents
+///
+/// The estimate is of the output after the thermal ceilings, before the
+/// budget; when capping, the current actually driven follows.
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+async fn print_power(shared: &SharedState) {
+    let budget = shared.get_power_budget().await;
+    let estimate = shared.get_power_estimate().await;
+    let (ma, tenths) = milliamps(estimate);
+    rprintln!("ok power {}.{} mA", ma, tenths);
+    match budget.budget_ua {
+        Some(budget_ua) => {
+            let (budget_ma, budget_tenths) = milliamps(budget_ua);
+            rprintln!(
+                "budget {}.{} mA, {} when over",
+                budget_ma,
+                budget_tenths,
+                budget.action.name()
+            );
+        }
+        None => rprintln!("budget off"),
+    }
+    if budget.over(estimate) && budget.action == BudgetAction::Cap {
+        let (capped_ma, capped_tenths) = milliamps(budget.estimate_ua(
+            shared.get_output_levels().await,
+            shared.get_refresh_multipliers().await,
+        ));
+        rprintln!("capped to {}.{} mA", capped_ma, capped_tenths);
+    }
+    let [r, g, b] = budget.peak_ua.map(|ua| ua / 1000);
+    rprintln!("peak {} {} {} mA", r, g, b);
+    // Each pulse of a channel at full level lasts nearly a third of a frame
+    let frame_rate = shared.get_frame_rate().await.max(1);
+    rprintln!(
+        "longest pulse {} us at {} fps",
+        1_000_000 * (LEVELS as u64 - 1) / (LEVELS as u64 * 3 * frame_rate),
+        frame_rate
+    );
+}
+
+/// Parse a current argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any (whole milliamps)
+///
+/// # Returns
+/// Current in µA (at most `POWER_MAX_MA` mA)
+fn parse_milliamps(word: Option<&str>) -> Result<u32, ParseError> {
+    let ma: u32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if ma > POWER_MAX_MA {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(ma * 1000)
+}
+
+/// Parse a hold or fade time
+///
+/// # Arguments
+/// * `word` - Next argument word, if any (milliseconds)
+///
+/// # Returns
+/// Time in milliseconds (at most `MAX_STOP_MS`)
+fn parse_ms(word: Option<&str>) -> Result<u32, ParseError> {
+    let ms: u32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if ms > MAX_STOP_MS {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(ms)
+}
+
+/// Parse an animation period in milliseconds
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+///
+/// # Returns
+/// Period from MIN_ANIMATION_PERIOD_MS to MAX_ANIMATION_PERIOD_MS
+fn parse_period(word: Option<&str>) -> Result<u32, ParseError> {
+    let ms: u32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if !(MIN_ANIMATION_PERIOD_MS..=MAX_ANIMATION_PERIOD_MS).contains(&ms) {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(ms)
+}
+
+/// Parse a color-correction matrix coefficient
+///
+/// # Arguments
+/// * `word` - Next argument word, if any (a decimal such as `-0.05`)
+///
+/// # Returns
+/// The coefficient in fixed point (see [`MATRIX_ONE`])
+fn parse_coefficient(word: Option<&str>) -> Result<i32, ParseError> {
+    let value: f32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    let fixed = (value * MATRIX_ONE as f32).round();
+    if !fixed.is_finite() || fixed.abs() > MATRIX_LIMIT as f32 {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(fixed as i32)
+}
+
+/// Parse a knob deadband argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any: a fraction of the travel from 0
+///   to MAX_DEADBAND
+///
+/// # Returns
+/// The deadband in thousandths of the scaled ADC range
+fn parse_deadband(word: Option<&str>) -> Result<u32, ParseError> {
+    let value: f32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if !(0.0..=MAX_DEADBAND).contains(&value) {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok((value * 1000.0).round() as u32)
+}
+
+/// Parse a measurement duty argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any: a fraction from 0 to 1
+///
+/// # Returns
+/// The duty in DUTY_ONEths
+fn parse_duty(word: Option<&str>) -> Result<u32, ParseError> {
+    let value: f32 = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok((value * DUTY_ONE as f32).round() as u32)
+}
+
+/// Measurement duty in DUTY_ONEths, displayed as a decimal fraction
+struct Duty(u32);
+
+impl core::fmt::Display for Duty {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}.{:06}", self.0 / DUTY_ONE, self.0 % DUTY_ONE)
+    }
+}
+
+/// Print every channel's measurement duty, e.g.
+/// `ok meas r 0.333000 g off b off`
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+async fn print_measure_duties(shared: &SharedState) {
+    rprint!("ok meas");
+    for (names, duty) in CHANNEL_NAMES.iter().zip(shared.get_measure_duties().await) {
+        match duty {
+            Some(duty) => rprint!(" {} {}", names[0], Duty(duty)),
+            None => rprint!(" {} off", names[0]),
+        }
+    }
+    rprintln!();
+}
+
+/// Fixed-point matrix coefficient, displayed as a decimal
+struct Coefficient(i32);
+
+impl core::fmt::Display for Coefficient {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let value = self.0.unsigned_abs();
+        let one = MATRIX_ONE as u32;
+        write!(f, "{}{}.{:03}", sign, value / one, value % one)
+    }
+}
+
+/// Parse a preset slot argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any (slots are numbered from 1)
+///
+/// # Returns
+/// Slot index (0 to PRESET_SLOTS-1)
+fn parse_slot(word: Option<&str>) -> Result<usize, ParseError> {
+    let slot: usize = word
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)?;
+    if !(1..=PRESET_SLOTS).contains(&slot) {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(slot - 1)
+}
+
+/// Parse a preset given by slot number or tag
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+///
+/// # Returns
+/// The preset reference
+fn parse_preset_ref(word: Option<&str>) -> Result<PresetRef, ParseError> {
+    let word = word.ok_or(ParseError::MissingArgument)?;
+    match Tag::parse(word) {
+        Some(tag) => Ok(PresetRef::Tag(tag)),
+        None => Ok(PresetRef::Slot(parse_slot(Some(word))?)),
+    }
+}
+
+/// Find the slot a preset reference names
+///
+/// Prints an error if no preset carries the tag.
+///
+/// # Arguments
+/// * `presets` - Preset slots to search for a tag
+/// * `preset` - Slot or tag
+///
+/// # Returns
+/// Slot index (0 to PRESET_SLOTS-1), or `None` for an unknown tag
+fn resolve_preset(presets: &[Option<Preset>; PRESET_SLOTS], preset: PresetRef) -> Option<usize> {
+    match preset {
+        PresetRef::Slot(slot) => Some(slot),
+        PresetRef::Tag(tag) => {
+            let slot = find_tag(presets, &tag);
+            if slot.is_none() {
+                rprintln!("error: no preset tagged {}", tag.as_str());
+            }
+            slot
+        }
+    }
+}
+
+/// Print one preset slot
+///
+/// # Arguments
+/// * `prefix` - Text before the slot number
+/// * `slot` - Slot index (0 to PRESET_SLOTS-1)
+/// * `preset` - Slot contents
+fn print_preset(prefix: &str, slot: usize, preset: &Preset) {
+    let [red, green, blue] = preset.levels;
+    let tag = preset.tag.as_ref().map_or("-", Tag::as_str);
+    rprintln!(
+        "{} {} {} rgb {} {} {} fps {}",
+        prefix,
+        slot + 1,
+        tag,
+        red,
+        green,
+        blue,
+        preset.frame_rate
+    );
+}
+
+/// Parse which presets to step through
+///
+/// # Arguments
+/// * `word` - `tagged` or `white`
+///
+/// # Returns
+/// The filter, or an error for any other word
+fn parse_preset_filter(word: &str) -> Result<PresetFilter, ParseError> {
+    match word {
+        "all" => Ok(PresetFilter::All),
+        "tagged" => Ok(PresetFilter::Tagged),
+        "white" => Ok(PresetFilter::White),
+        _ => Err(ParseError::InvalidArgument),
+    }
+}
+
+/// Host command reader
+///
+/// Assembles bytes from the RTT down channel (or the UART, see
+/// [`CommandInput`]) into lines and executes each complete line as a
+/// command.
+pub struct Commands {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Channel carrying input from the host
+    input: CommandInput,
+    /// Partially received command line
+    line: [u8; LINE_MAX],
+    /// Number of valid bytes in `line`
+    len: usize,
+    /// Set when the current line exceeded `LINE_MAX` and must be discarded
+    overflow: bool,
+    /// Binary control link polled alongside the text input, if any
+    control: Option<ControlLink>,
+    /// Flash holding the saved calibration and settings, if any
+    storage: Option<CalibrationStorage>,
+    /// When the calibration was last saved, and what was saved
+    throttle: SaveThrottle,
+}
+
+impl Commands {
+    /// Create a new command reader on the given input channel
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    /// * `input` - RTT down channel or serial input receiving host commands
+    ///
+    /// # Returns
+    /// New command reader with an empty line buffer
+    pub fn new(shared: &'static SharedState, input: CommandInput) -> Self {
+        Self {
+            shared,
+            input,
+            line: [0; LINE_MAX],
+            len: 0,
+            overflow: false,
+            control: None,
+            storage: None,
+            throttle: SaveThrottle::new(None),
+        }
+    }
+
+    /// Also serve the binary control protocol
+    ///
+    /// # Arguments
+    /// * `control` - Control link on its own RTT channels
+    ///
+    /// # Returns
+    /// The reader polling both inputs
+    pub fn with_control(mut self, control: ControlLink) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Save the calibration to flash when asked, or when `save auto` finds
+    /// it worth saving, and the other settings whenever they change
+    ///
+    /// # Arguments
+    /// * `storage` - Flash holding the calibration and settings records
+    /// * `saved` - Calibration loaded from the record at boot, if any
+    ///
+    /// # Returns
+    /// The reader serving save requests
+    pub fn with_storage(
+        mut self,
+        storage: CalibrationStorage,
+        saved: Option<CalibrationConfig>,
+    ) -> Self {
+        self.storage = Some(storage);
+        self.throttle = SaveThrottle::new(saved.as_ref().map(saved_fields));
+        self
+    }
+
+    /// Serve a pending save request, or save automatically when due
+    ///
+    /// Waits with either until the UI has written the starting values, so
+    /// a save asked for at boot stores those rather than placeholders.
+    async fn serve_save(&mut self) {
+        if !self.shared.state_ready() {
+            return;
+        }
+        let requested = self.shared.take_save_request();
+        let policy = self.shared.get_auto_save().await;
+        let now = Instant::now();
+        if !requested && !policy.is_some_and(|policy| self.throttle.ready(&policy, now)) {
+            return;
+        }
+        let config = self.shared.get_calibration_config().await;
+        let fields = saved_fields(&config);
+        if !requested && !policy.is_some_and(|policy| self.throttle.due(&policy, &fields, now)) {
+            return;
+        }
+        let Some(storage) = self.storage.as_mut() else {
+            rprintln!("error: no flash storage");
+            return;
+        };
+        let result = save_calibration(storage, &config).await;
+        match result {
+            Ok(()) => self.throttle.saved(fields, now),
+            Err(_) => self.throttle.failed(now),
+        }
+        match (requested, result) {
+            (true, Ok(())) => rprintln!("ok save"),
+            (true, Err(err)) => rprintln!("error E{}: {}", err.code(), err.message()),
+            (false, Ok(())) => rprintln!("saved calibration: saved automatically"),
+            (false, Err(err)) => rprintln!(
+                "saved calibration: error E{}: {}",
+                err.code(),
+                err.message()
+            ),
+        }
+    }
+
+    /// Rewrite the saved settings if one of them has changed
+    async fn serve_settings_save(&mut self) {
+        if !self.shared.take_settings_save_request() {
+            return;
+        }
+        let Some(storage) = self.storage.as_mut() else {
+            rprintln!("error: no flash storage");
+            return;
+        };
+        let settings = self.shared.get_saved_settings().await;
+        if let Err(err) = save_settings(storage, &settings).await {
+            rprintln!("saved settings: error E{}: {}", err.code(), err.message());
+        }
+    }
+
+    /// Serve a pending diff request against a preset saved in flash
+    async fn serve_diff(&mut self) {
+        let Some(preset) = self.shared.take_diff_request() else {
+            return;
+        };
+        let Some(storage) = self.storage.as_mut() else {
+            rprintln!("error: no flash storage");
+            return;
+        };
+        let presets = match read_settings(storage) {
+            Ok(settings) => settings.presets,
+            Err(CalibrationError::Storage(StorageError::Blank)) => [None; PRESET_SLOTS],
+            Err(err) => {
+                rprintln!("error E{}: {}", err.code(), err.message());
+                return;
+            }
+        };
+        let Some(slot) = resolve_preset(&presets, preset) else {
+            return;
+        };
+        let Some(preset) = presets[slot] else {
+            rprintln!("error: preset {} is empty", slot + 1);
+            return;
+        };
+        let state = self.shared.get_state().await;
+        let diff = preset.diff(state.levels, state.frame_rate);
+        let [red, green, blue] = diff.levels;
+        let tag = preset.tag.as_ref().map_or("-", Tag::as_str);
+        rprintln!(
+            "ok diff {} {} rgb {:+} {:+} {:+} fps {:+}",
+            slot + 1,
+            tag,
+            red,
+            green,
+            blue,
+            diff.frame_rate
+        );
+    }
+
+    /// Handle one received byte, executing the line when it is complete
+    ///
+    /// # Arguments
+    /// * `byte` - Next byte from the host
+    async fn push(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflow) {
+                    rprintln!("error: line too long");
+                    return;
+                }
+                let Ok(line) = core::str::from_utf8(&self.line[..len]) else {
+                    rprintln!("error: invalid text");
+                    return;
+                };
+                match Command::parse(line) {
+                    Ok(command) => command.execute(self.shared).await,
+                    Err(ParseError::Empty) => (),
+                    Err(ParseError::UnknownCommand) => {
+                        rprintln!("error: unknown command (try `help`)")
+                    }
+                    // Config rejections carry their code, like hardware
+                    // failures
+                    Err(ParseError::InvalidConfig(err)) => {
+                        let err = CalibrationError::from(err);
+                        rprintln!("error E{}: {}", err.code(), err.message())
+                    }
+                    Err(err) => {
+                        rprintln!("error: {}", err.message());
+                        for syntax in matching(COMMANDS, line) {
+                            rprintln!("usage: {}", syntax.usage);
+                        }
+                    }
+                }
+            }
+            _ if self.len < LINE_MAX => {
+                self.line[self.len] = byte;
+                self.len += 1;
+            }
+            _ => self.overflow = true,
+        }
+    }
+
+    /// Main command processing loop
+    ///
+    /// Polls the RTT input channel and executes commands as lines arrive,
+    /// handles binary control frames if a control link is attached, saves
+    /// the calibration when asked and the other settings when they change.
+    pub async fn run(mut self) -> ! {
+        loop {
+            let mut buf = [0; 16];
+            let mut count = self.input.read(&mut buf);
+            for &byte in &buf[..count] {
+                self.push(byte).await;
+            }
+            if let Some(control) = self.control.as_mut() {
+                count += control.poll().await;
+            }
+            self.serve_save().await;
+
+            // Only sleep when idle so pasted input is drained quickly; the
+            // settings are written once the input pauses, so a pasted batch
+            // of e.g. `seq add` costs one flash write, and a diff sees them
+            if count == 0 {
+                self.serve_settings_save().await;
+                self.serve_diff().await;
+                Timer::after_millis(POLL_MS).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Frame Rate Comparison
+//!
+//! Two stored frame rates, A and B, that can be switched between at a
+//! press while the color stays put, so two close candidates can be judged
+//! side by side instead of by sweeping. Set them with `compare set <a|b>`,
+//! then `compare` (or, while comparing, a tap of any button) switches to
+//! the other one. The state lives in the shared state.
+
+use crate::*;
+
+/// Names of the two setpoints, indexed like [`RateCompare::setpoints`]
+const SETPOINT_NAMES: [&str; 2] = ["A", "B"];
+
+/// The two frame rates being compared and which one is showing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateCompare {
+    /// Frame rates [A, B] in fps, once set
+    pub setpoints: [Option<u64>; 2],
+    /// Setpoint currently applied (0 = A, 1 = B), or `None` when not
+    /// comparing
+    pub active: Option<usize>,
+}
+
+impl RateCompare {
+    /// Nothing stored, not comparing
+    pub const OFF: Self = Self {
+        setpoints: [None; 2],
+        active: None,
+    };
+
+    /// Setpoint to switch to next
+    ///
+    /// # Returns
+    /// The other setpoint's index and frame rate (A when not comparing
+    /// yet), or `None` unless both are set
+    pub fn next(&self) -> Option<(usize, u64)> {
+        let [Some(a), Some(b)] = self.setpoints else {
+            return None;
+        };
+        Some(match self.active {
+            Some(0) => (1, b),
+            _ => (0, a),
+        })
+    }
+
+    /// Name of a setpoint for log output
+    ///
+    /// # Arguments
+    /// * `setpoint` - Setpoint index (0 = A, 1 = B)
+    pub fn name(setpoint: usize) -> &'static str {
+        SETPOINT_NAMES[setpoint.min(1)]
+    }
+}
+
+/// Switch to the other stored frame rate
+///
+/// Starts comparing with A if not comparing yet.
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+///
+/// # Returns
+/// Index of the setpoint now applied and the frame rate committed for it
+/// (see [`SharedState::set_frame_rate`]), or `None` unless both setpoints
+/// are set
+pub async fn toggle_rate_compare(shared: &SharedState) -> Option<(usize, u64)> {
+    let mut compare = shared.get_rate_compare().await;
+    let (setpoint, frame_rate) = compare.next()?;
+    compare.active = Some(setpoint);
+    shared.set_rate_compare(compare).await;
+    let frame_rate = shared.set_frame_rate(frame_rate).await;
+    Some((setpoint, frame_rate))
+}
@@ -0,0 +1,278 @@
+//! Calibration Config Transfer
+//!
+//! The complete calibration (levels, frame rate, enable mask, color matrix,
+//! lookup tables, minimum visible levels, color space and flicker-fusion
+//! threshold) packed into one checksummed blob, so an automated rig can
+//! read it back with `get config` and push it with `set config <hex>` in
+//! a single transaction. A blob is decoded and validated in full before any
+//! of it reaches the shared state, and then committed at once (see
+//! [`SharedState::set_calibration_config`]).
+//!
+//! # Blob Layout
+//!
+//! Sent as [`CONFIG_LEN`] bytes in hex, two lowercase or uppercase digits per
+//! byte:
+//!
+//! | Offset | Type        | Field                                         |
+//! |--------|-------------|-----------------------------------------------|
+//! | 0      | u8          | Version, always [`CONFIG_VERSION`]            |
+//! | 1      | u8 x 3      | Red, green, blue levels (0-15)                |
+//! | 4      | u16         | Frame rate in fps, little-endian              |
+//! | 6      | u8          | Enable mask: bit 0 red, 1 green, 2 blue       |
+//! | 7      | i16 x 9     | Matrix coefficients row by row (see below)    |
+//! | 25     | u8 x 24     | Lookup tables red, green, blue (see below)    |
+//! | 49     | u8 x 3      | Minimum visible levels (1-15), red to blue    |
+//! | 52     | u8          | Color space of the levels, 0 linear, 1 sRGB   |
+//! | 53     | u16         | Fusion threshold in fps, 0 if none marked     |
+//! | 55     | u8          | CRC-8 of bytes 0 to 54 (see [`crc8`])         |
+//!
+//! The matrix coefficients are little-endian, with [`MATRIX_ONE`] standing
+//! for 1.0. Each lookup table takes 8 bytes, two entries per byte, low
+//! nibble first.
+//!
+//! This is the layout with the stock 16 [`LEVELS`]. The lookup tables take
+//! [`LUT_LEN`] nibbles each, or [`LUT_LEN`] whole bytes each above 16
+//! levels, and the fields after them move along.
+
+use crate::*;
+
+/// Version byte of the current blob layout
+pub const CONFIG_VERSION: u8 = 4;
+
+/// Length of an encoded config in bytes (56 with 16 levels)
+pub const CONFIG_LEN: usize = FUSION_OFFSET + 3;
+
+/// Offset of the matrix coefficients in the blob
+const MATRIX_OFFSET: usize = 7;
+
+/// Offset of the lookup tables in the blob
+const LUT_OFFSET: usize = 25;
+
+/// Offset of the minimum visible levels in the blob
+const MIN_VISIBLE_OFFSET: usize = LUT_OFFSET + 3 * LUT_BYTES;
+
+/// Offset of the color space in the blob
+const COLOR_SPACE_OFFSET: usize = MIN_VISIBLE_OFFSET + 3;
+
+/// Offset of the fusion threshold in the blob
+const FUSION_OFFSET: usize = COLOR_SPACE_OFFSET + 1;
+
+/// Whether lookup table entries fit in a nibble (up to 16 levels)
+const LUT_NIBBLES: bool = LEVELS <= 16;
+
+/// Bytes used by one packed lookup table
+const LUT_BYTES: usize = if LUT_NIBBLES {
+    LUT_LEN.div_ceil(2)
+} else {
+    LUT_LEN
+};
+
+/// Complete calibration applied as one unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CalibrationConfig {
+    /// Requested [red, green, blue] levels
+    pub levels: [u32; 3],
+    /// Frame rate in fps
+    pub frame_rate: u64,
+    /// Output [red, green, blue] enable flags
+    pub enabled: [bool; 3],
+    /// Color-correction matrix
+    pub matrix: ColorMatrix,
+    /// Output lookup tables for [red, green, blue]
+    pub luts: [Lut; 3],
+    /// Minimum visible [red, green, blue] output levels
+    pub min_visible: [u32; 3],
+    /// How the requested levels are encoded
+    pub color_space: ColorSpace,
+    /// Marked flicker-fusion threshold in fps, if any
+    pub fusion_floor: Option<u16>,
+}
+
+impl CalibrationConfig {
+    /// Pack the config into a blob
+    ///
+    /// # Returns
+    /// CONFIG_LEN bytes, checksum included
+    pub fn encode(&self) -> [u8; CONFIG_LEN] {
+        let mut blob = [0; CONFIG_LEN];
+        blob[0] = CONFIG_VERSION;
+        for (byte, level) in blob[1..4].iter_mut().zip(self.levels) {
+            *byte = level as u8;
+        }
+        blob[4..6].copy_from_slice(&(self.frame_rate as u16).to_le_bytes());
+        blob[6] = self
+            .enabled
+            .iter()
+            .enumerate()
+            .filter(|(_, &enabled)| enabled)
+            .fold(0, |mask, (channel, _)| mask | 1 << channel);
+        let coefficients = self.matrix.0.iter().flatten();
+        for (chunk, &coefficient) in blob[MATRIX_OFFSET..LUT_OFFSET]
+            .chunks_exact_mut(2)
+            .zip(coefficients)
+        {
+            chunk.copy_from_slice(&(coefficient as i16).to_le_bytes());
+        }
+        for (packed, lut) in blob[LUT_OFFSET..MIN_VISIBLE_OFFSET]
+            .chunks_exact_mut(LUT_BYTES)
+            .zip(self.luts)
+        {
+            pack_lut(lut.entries(), packed);
+        }
+        for (byte, level) in blob[MIN_VISIBLE_OFFSET..COLOR_SPACE_OFFSET]
+            .iter_mut()
+            .zip(self.min_visible)
+        {
+            *byte = level as u8;
+        }
+        blob[COLOR_SPACE_OFFSET] = match self.color_space {
+            ColorSpace::Linear => 0,
+            ColorSpace::Srgb => 1,
+        };
+        let fusion_floor = self.fusion_floor.unwrap_or(0);
+        blob[FUSION_OFFSET..FUSION_OFFSET + 2].copy_from_slice(&fusion_floor.to_le_bytes());
+        blob[CONFIG_LEN - 1] = crc8(&blob[..CONFIG_LEN - 1]);
+        blob
+    }
+
+    /// Unpack and validate a blob
+    ///
+    /// Every field is checked; nothing is returned unless the whole config
+    /// is valid.
+    ///
+    /// # Arguments
+    /// * `blob` - CONFIG_LEN bytes as produced by [`Self::encode`]
+    ///
+    /// # Returns
+    /// The config, or the first problem found
+    pub fn decode(blob: &[u8; CONFIG_LEN]) -> Result<Self, ConfigError> {
+        if blob[CONFIG_LEN - 1] != crc8(&blob[..CONFIG_LEN - 1]) {
+            return Err(ConfigError::Checksum);
+        }
+        if blob[0] != CONFIG_VERSION {
+            return Err(ConfigError::Version);
+        }
+        let levels = [blob[1], blob[2], blob[3]].map(u32::from);
+        if levels.iter().any(|&level| level >= LEVELS) {
+            return Err(ConfigError::LevelOutOfRange);
+        }
+        let frame_rate = u64::from(u16::from_le_bytes([blob[4], blob[5]]));
+        if !(MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&frame_rate) {
+            return Err(ConfigError::FrameRateOutOfRange);
+        }
+        if blob[6] & !0b111 != 0 {
+            return Err(ConfigError::InvalidMask);
+        }
+        let enabled = [0, 1, 2].map(|channel| blob[6] & 1 << channel != 0);
+        let mut rows = [[0; 3]; 3];
+        for (coefficient, chunk) in rows
+            .iter_mut()
+            .flatten()
+            .zip(blob[MATRIX_OFFSET..LUT_OFFSET].chunks_exact(2))
+        {
+            *coefficient = i32::from(i16::from_le_bytes([chunk[0], chunk[1]]));
+            if coefficient.abs() > MATRIX_LIMIT {
+                return Err(ConfigError::MatrixOutOfRange);
+            }
+        }
+        let mut luts = [Lut::IDENTITY; 3];
+        for (lut, packed) in luts
+            .iter_mut()
+            .zip(blob[LUT_OFFSET..MIN_VISIBLE_OFFSET].chunks_exact(LUT_BYTES))
+        {
+            *lut = Lut::new(unpack_lut(packed)).map_err(|err| match err {
+                LutError::OutOfRange => ConfigError::LevelOutOfRange,
+                LutError::NotMonotonic => ConfigError::LutNotMonotonic,
+            })?;
+        }
+        let mut min_visible = [MIN_VISIBLE_NONE; 3];
+        for (level, &byte) in min_visible
+            .iter_mut()
+            .zip(&blob[MIN_VISIBLE_OFFSET..COLOR_SPACE_OFFSET])
+        {
+            *level = u32::from(byte);
+            if !(MIN_VISIBLE_NONE..LEVELS).contains(level) {
+                return Err(ConfigError::LevelOutOfRange);
+            }
+        }
+        let color_space = match blob[COLOR_SPACE_OFFSET] {
+            0 => ColorSpace::Linear,
+            1 => ColorSpace::Srgb,
+            _ => return Err(ConfigError::InvalidColorSpace),
+        };
+        let fusion_floor = match u16::from_le_bytes([blob[FUSION_OFFSET], blob[FUSION_OFFSET + 1]])
+        {
+            0 => None,
+            fps if (MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&u64::from(fps)) => Some(fps),
+            _ => return Err(ConfigError::FrameRateOutOfRange),
+        };
+        Ok(CalibrationConfig {
+            levels,
+            frame_rate,
+            enabled,
+            matrix: ColorMatrix(rows),
+            luts,
+            min_visible,
+            color_space,
+            fusion_floor,
+        })
+    }
+
+    /// Parse and validate a blob sent as hex
+    ///
+    /// # Arguments
+    /// * `hex` - 2 * CONFIG_LEN hex digits
+    ///
+    /// # Returns
+    /// The config, or why it was rejected
+    pub fn from_hex(hex: &str) -> Result<Self, ConfigError> {
+        let hex = hex.as_bytes();
+        if hex.len() != 2 * CONFIG_LEN {
+            return Err(ConfigError::Length);
+        }
+        let mut blob = [0; CONFIG_LEN];
+        for (byte, digits) in blob.iter_mut().zip(hex.chunks_exact(2)) {
+            let digit = |c: u8| (c as char).to_digit(16).ok_or(ConfigError::NotHex);
+            *byte = (digit(digits[0])? << 4 | digit(digits[1])?) as u8;
+        }
+        Self::decode(&blob)
+    }
+}
+
+/// Pack one lookup table into its blob bytes
+///
+/// Entries take a nibble each, low nibble first, with up to 16 levels, and
+/// a whole byte each above that.
+///
+/// # Arguments
+/// * `entries` - Lookup table entries
+/// * `packed` - LUT_BYTES bytes of the blob to fill
+fn pack_lut(entries: &[u32; LUT_LEN], packed: &mut [u8]) {
+    if LUT_NIBBLES {
+        for (i, &entry) in entries.iter().enumerate() {
+            packed[i / 2] |= (entry << (4 * (i % 2))) as u8;
+        }
+    } else {
+        for (byte, &entry) in packed.iter_mut().zip(entries) {
+            *byte = entry as u8;
+        }
+    }
+}
+
+/// Unpack one lookup table from its blob bytes
+///
+/// # Arguments
+/// * `packed` - LUT_BYTES bytes as written by [`pack_lut`]
+///
+/// # Returns
+/// The entries, not yet validated
+fn unpack_lut(packed: &[u8]) -> [u32; LUT_LEN] {
+    let mut table = [0; LUT_LEN];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = match LUT_NIBBLES {
+            true => u32::from(packed[i / 2] >> (4 * (i % 2)) & 0x0f),
+            false => u32::from(packed[i]),
+        };
+    }
+    table
+}
@@ -0,0 +1,53 @@
+//! Config Blob Errors
+//!
+//! Why a calibration blob was refused. The blob arrives through the
+//! `set config` command and the binary control protocol, so the command
+//! grammar and the firmware's own error type both carry these. The saved
+//! settings blob is checked against the same reasons.
+
+/// Reasons a config blob was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Blob was not CONFIG_LEN bytes of hex
+    Length,
+    /// Blob contained a character that is not a hex digit
+    NotHex,
+    /// Version byte was not CONFIG_VERSION
+    Version,
+    /// Checksum did not match the contents
+    Checksum,
+    /// A level was above LEVELS-1, or a minimum visible level was 0
+    LevelOutOfRange,
+    /// Frame rate or fusion threshold was outside MIN_FRAME_RATE to
+    /// MAX_FRAME_RATE
+    FrameRateOutOfRange,
+    /// Enable mask had bits above bit 2 set
+    InvalidMask,
+    /// A matrix coefficient was beyond MATRIX_LIMIT
+    MatrixOutOfRange,
+    /// A lookup table's entries decreased
+    LutNotMonotonic,
+    /// Color space byte was neither 0 nor 1
+    InvalidColorSpace,
+    /// A saved setting, such as a preset's tag, was malformed
+    InvalidSetting,
+}
+
+impl ConfigError {
+    /// Short human-readable description for error replies
+    pub fn message(self) -> &'static str {
+        match self {
+            ConfigError::Length => "config has the wrong length",
+            ConfigError::NotHex => "config is not hex",
+            ConfigError::Version => "unsupported config version",
+            ConfigError::Checksum => "config checksum mismatch",
+            ConfigError::LevelOutOfRange => "config level out of range",
+            ConfigError::FrameRateOutOfRange => "config frame rate out of range",
+            ConfigError::InvalidMask => "config enable mask invalid",
+            ConfigError::MatrixOutOfRange => "config matrix coefficient out of range",
+            ConfigError::LutNotMonotonic => "config lookup table decreases",
+            ConfigError::InvalidColorSpace => "config color space invalid",
+            ConfigError::InvalidSetting => "saved setting invalid",
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Color Correction
+//!
+//! A 3x3 matrix applied to the requested levels before output. It corrects
+//! chromatic cross-talk between the LED dies (e.g. red light that also reads
+//! as a little green) on top of the per-channel white balance.
+//!
+//! [`output_levels`] runs the whole output pipeline (color space, enable
+//! mask, matrix, lookup tables and minimum visible levels) and
+//! [`effective_color`] turns its result into the color the eye averages
+//! over a frame, while [`flicker_percent`] estimates how strongly the
+//! brightness of that color pulses at the frame rate.
+//!
+//! Coefficients are fixed-point with [`MATRIX_ONE`] representing 1.0, so the
+//! RGB task needs no floating point.
+
+use crate::*;
+
+/// Fixed-point value of a coefficient of 1.0
+pub const MATRIX_ONE: i32 = 1000;
+
+/// Largest coefficient magnitude accepted (4.0)
+pub const MATRIX_LIMIT: i32 = 4 * MATRIX_ONE;
+
+/// Linear color-correction matrix
+///
+/// Output channel `i` is `sum over j of m[i][j] * input[j]`, with channels
+/// ordered [red, green, blue].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorMatrix(pub [[i32; 3]; 3]);
+
+impl ColorMatrix {
+    /// Matrix that leaves the levels unchanged
+    pub const IDENTITY: Self =
+        ColorMatrix([[MATRIX_ONE, 0, 0], [0, MATRIX_ONE, 0], [0, 0, MATRIX_ONE]]);
+
+    /// Whether this is the identity matrix
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Apply the matrix to a set of levels
+    ///
+    /// Results are rounded to the nearest level and clamped to the valid
+    /// range.
+    ///
+    /// # Arguments
+    /// * `levels` - Input [red, green, blue] levels
+    /// * `max` - Highest valid level
+    ///
+    /// # Returns
+    /// Corrected [red, green, blue] levels (0 to `max`)
+    pub fn apply(&self, levels: [u32; 3], max: u32) -> [u32; 3] {
+        if self.is_identity() {
+            return levels.map(|level| level.min(max));
+        }
+        self.0.map(|row| {
+            let sum: i64 = row
+                .iter()
+                .zip(levels)
+                .map(|(&coefficient, level)| coefficient as i64 * level as i64)
+                .sum();
+            let one = MATRIX_ONE as i64;
+            let rounded = (sum + one / 2).div_euclid(one);
+            rounded.clamp(0, max as i64) as u32
+        })
+    }
+}
+
+impl Default for ColorMatrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Levels actually driven for a requested color
+///
+/// The levels are first decoded to linear light (see [`ColorSpace`]).
+/// Disabled channels are zeroed before the matrix, so they neither
+/// contribute to nor receive a correction, and stay off after it. The
+/// remaining levels then go through each channel's lookup table and are
+/// spread over its visible range (see [`remap_visible`]).
+///
+/// # Arguments
+/// * `levels` - Requested [red, green, blue] levels
+/// * `space` - How the requested levels are encoded
+/// * `enabled` - Output enable mask [red, green, blue]
+/// * `matrix` - Color-correction matrix
+/// * `luts` - Output lookup tables [red, green, blue]
+/// * `min_visible` - Minimum visible [red, green, blue] levels
+///
+/// # Returns
+/// Output [red, green, blue] levels (0 to LEVELS-1)
+pub fn output_levels(
+    levels: [u32; 3],
+    space: ColorSpace,
+    enabled: [bool; 3],
+    matrix: &ColorMatrix,
+    luts: &[Lut; 3],
+    min_visible: [u32; 3],
+) -> [u32; 3] {
+    let mut masked = levels.map(|level| space.decode(level));
+    for (level, enabled) in masked.iter_mut().zip(enabled) {
+        if !enabled {
+            *level = 0;
+        }
+    }
+    let mut output = matrix.apply(masked, LEVELS - 1);
+    let channels = output.iter_mut().zip(enabled).zip(luts).zip(min_visible);
+    for (((level, enabled), lut), min_visible) in channels {
+        *level = if enabled {
+            remap_visible(lut.apply(*level), min_visible, LEVELS)
+        } else {
+            0
+        };
+    }
+    output
+}
+
+/// Time-averaged color produced by a set of output levels
+///
+/// The pins are only ever fully on or off, so the perceived intensity of a
+/// channel is its duty within its slot of the frame: level / LEVELS. This
+/// scales that duty to 0-255; a channel at LEVELS-1 is on 15/16 of its slot
+/// and shows as 239.
+///
+/// # Arguments
+/// * `output` - Output [red, green, blue] levels from [`output_levels`]
+///
+/// # Returns
+/// Averaged [red, green, blue] intensity (0-255)
+pub fn effective_color(output: [u32; 3]) -> [u32; 3] {
+    output.map(|level| (level.min(LEVELS) * 255 + LEVELS / 2) / LEVELS)
+}
+
+/// Ticks per frame in the flicker model: LEVELS per color slot
+const FRAME_TICKS: usize = 3 * LEVELS as usize;
+
+/// Cosine and sine of one tick's phase step, 2π / FRAME_TICKS (7.5°)
+const TICK_ROTATION: (f32, f32) = (0.991_444_9, 0.130_526_2);
+
+/// Theoretical flicker of a set of output levels
+///
+/// Models one frame as FRAME_TICKS ticks in which each channel is on for
+/// the first `level` ticks of its slot, weights each channel by its
+/// luminance and takes the component of the resulting brightness waveform
+/// at the frame rate, the fundamental, which dominates what the eye sees.
+/// The figure is the Michelson contrast (max - min) / (max + min) of that
+/// component, i.e. its amplitude over the mean brightness, capped at 100%.
+///
+/// Only three slots lit for their whole length with equal luminance would
+/// give 0%. As green dominates luminance and no channel is lit for more
+/// than a third of the frame, real settings stay high (white at full
+/// level is about 99%) and differ by a few percent; dimming the dominant
+/// channel or balancing the slots' luminance lowers the figure. The
+/// percentage does not depend on the frame rate, which only sets the
+/// frequency it flickers at.
+///
+/// # Arguments
+/// * `output` - Output [red, green, blue] levels from [`output_levels`]
+///
+/// # Returns
+/// Flicker in percent (0-100), 0 when every channel is dark
+pub fn flicker_percent(output: [u32; 3]) -> u32 {
+    let (cos_step, sin_step) = TICK_ROTATION;
+    let (mut cos, mut sin) = (1.0f32, 0.0f32);
+    let (mut real, mut imaginary, mut total) = (0.0f32, 0.0f32, 0.0f32);
+    for tick in 0..FRAME_TICKS {
+        let channel = tick / LEVELS as usize;
+        let lit = ((tick % LEVELS as usize) as u32) < output[channel].min(LEVELS);
+        if lit {
+            let weight = LUMINANCE_WEIGHTS[channel];
+            real += weight * cos;
+            imaginary += weight * sin;
+            total += weight;
+        }
+        // Advance the phasor by one tick
+        (cos, sin) = (
+            cos * cos_step - sin * sin_step,
+            sin * cos_step + cos * sin_step,
+        );
+    }
+    if total == 0.0 {
+        return 0;
+    }
+    // Fundamental amplitude is 2|sum| / FRAME_TICKS and the mean brightness
+    // total / FRAME_TICKS, so the tick count cancels; work with the square
+    // of the contrast in percent to avoid a square root
+    let magnitude_squared = real * real + imaginary * imaginary;
+    let percent_squared = (4.0 * magnitude_squared / (total * total)).min(1.0) * 10_000.0;
+    // Round to the nearest percent: the largest p with (p - 0.5)^2 <= value
+    (1..=100)
+        .rev()
+        .find(|&percent| {
+            let lower = percent as f32 - 0.5;
+            lower * lower <= percent_squared
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,146 @@
+//! Rotary Encoder Interface
+//!
+//! Alternative to the potentiometer: a detented quadrature encoder whose two
+//! signals are watched with GPIO edge interrupts. [`EncoderDecoder`] turns
+//! the edges into detent counts, and [`Encoder`] turns those into relative
+//! steps of the UI's selected parameter.
+
+use crate::*;
+
+use embassy_futures::select::select;
+use microbit_bsp::embassy_nrf::gpio::{Input, Pull};
+
+/// Quadrature transitions per mechanical detent
+const STEPS_PER_DETENT: i32 = 4;
+
+/// Level change per detent when adjusting a brightness level
+const LEVELS_PER_DETENT: f32 = 1.0;
+
+/// Direction of each quadrature transition, indexed by
+/// `previous state << 2 | current state` with the state as `A << 1 | B`.
+/// Invalid transitions (both signals changed) count as no movement.
+const TRANSITIONS: [i32; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Quadrature decoder for the encoder's A and B signals
+///
+/// Runs as its own task so no edge is missed between UI polls.
+pub struct EncoderDecoder {
+    /// State shared with the other tasks, where the detents are counted
+    shared: &'static SharedState,
+    /// Encoder signal A
+    a: Input<'static, AnyPin>,
+    /// Encoder signal B
+    b: Input<'static, AnyPin>,
+}
+
+impl EncoderDecoder {
+    /// Create a decoder on the given pins
+    ///
+    /// The encoder's common pin goes to GND; both signals use the internal
+    /// pull-ups.
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    /// * `a` - Pin connected to encoder signal A
+    /// * `b` - Pin connected to encoder signal B
+    ///
+    /// # Returns
+    /// New decoder, ready to run
+    pub fn new(shared: &'static SharedState, a: AnyPin, b: AnyPin) -> Self {
+        Self {
+            shared,
+            a: Input::new(a, Pull::Up),
+            b: Input::new(b, Pull::Up),
+        }
+    }
+
+    /// Current quadrature state as `A << 1 | B`
+    fn state(&self) -> usize {
+        ((self.a.is_high() as usize) << 1) | self.b.is_high() as usize
+    }
+
+    /// Main decoding loop
+    ///
+    /// Waits for an edge on either signal and accumulates transitions,
+    /// publishing one count per full detent.
+    pub async fn run(mut self) -> ! {
+        let mut previous = self.state();
+        let mut steps = 0;
+        loop {
+            select(self.a.wait_for_any_edge(), self.b.wait_for_any_edge()).await;
+            let current = self.state();
+            steps += TRANSITIONS[previous << 2 | current];
+            previous = current;
+            if steps.abs() >= STEPS_PER_DETENT {
+                self.shared.add_encoder_detent(steps.signum());
+                steps = 0;
+            }
+        }
+    }
+}
+
+/// Rotary encoder input for the UI
+///
+/// Keeps a virtual position in level units that detents move up or down.
+/// The UI sets the position to the selected parameter's value, so each
+/// detent changes that parameter by one step from wherever it is.
+pub struct Encoder {
+    /// State shared with the other tasks, where the decoder counts detents
+    shared: &'static SharedState,
+    /// Virtual position (0.0 to LEVELS-1)
+    position: f32,
+    /// Detents read at the last measurement, for diagnostics
+    last_detents: i32,
+}
+
+impl Encoder {
+    /// Create an encoder input at the bottom of the range
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    ///
+    /// # Returns
+    /// New encoder input; pair it with a running [`EncoderDecoder`]
+    pub fn new(shared: &'static SharedState) -> Self {
+        Self {
+            shared,
+            position: 0.0,
+            last_detents: 0,
+        }
+    }
+}
+
+impl LevelInput for Encoder {
+    /// Apply the detents turned since the last call
+    ///
+    /// # Returns
+    /// The new virtual position; never fails
+    async fn measure_fine(&mut self) -> Result<f32, CalibrationError> {
+        let detents = self.shared.take_encoder_detents();
+        self.last_detents = detents;
+        let moved = self.position + detents as f32 * LEVELS_PER_DETENT;
+        self.position = moved.clamp(0.0, (LEVELS - 1) as f32);
+        Ok(self.position)
+    }
+
+    fn last_good(&self) -> f32 {
+        self.position
+    }
+
+    /// Round to the nearest level; detents are already discrete steps, so
+    /// the response curve does not apply
+    fn level(&mut self, fine: f32) -> u32 {
+        fine.round() as u32
+    }
+
+    /// Detents applied at the last measurement, as a two's complement value
+    fn raw(&self) -> u16 {
+        self.last_detents as u16
+    }
+
+    fn set_response(&mut self, _response: KnobResponse) {}
+
+    fn set_position(&mut self, position: f32) {
+        self.position = position.clamp(0.0, (LEVELS - 1) as f32);
+    }
+}
@@ -0,0 +1,105 @@
+//! Numeric Entry by Button Taps
+//!
+//! Lets an exact value be entered without a host: the number is built one
+//! decimal digit at a time, with a tap adding one to the current digit and a
+//! long press moving to the next digit or, on the last one, confirming. The
+//! UI maps gestures onto these steps; this module only keeps the number.
+
+use crate::*;
+
+/// Most digits any target needs (frame rates up to 160)
+const MAX_DIGITS: usize = 3;
+
+/// Number being entered for one parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberEntry {
+    /// Parameter the number will be committed to
+    target: ControlMode,
+    /// Digits entered so far, most significant first
+    digits: [u8; MAX_DIGITS],
+    /// Digit currently being changed
+    index: usize,
+}
+
+impl NumberEntry {
+    /// Start entering a number for a parameter, with every digit at 0
+    ///
+    /// # Arguments
+    /// * `target` - Parameter to set (frame rate or a channel)
+    ///
+    /// # Returns
+    /// The entry, at its first digit
+    pub fn new(target: ControlMode) -> Self {
+        Self {
+            target,
+            digits: [0; MAX_DIGITS],
+            index: 0,
+        }
+    }
+
+    /// Parameter the number is for
+    pub fn target(&self) -> ControlMode {
+        self.target
+    }
+
+    /// Number of digits the target takes: three for the frame rate, two for
+    /// a level
+    pub fn digit_count(&self) -> usize {
+        match self.target {
+            ControlMode::FrameRate => 3,
+            _ => 2,
+        }
+    }
+
+    /// Switch to the next parameter, starting the number over
+    ///
+    /// Cycles frame rate, red, green, blue.
+    pub fn next_target(&mut self) {
+        let target = match self.target {
+            ControlMode::FrameRate => ControlMode::Red,
+            ControlMode::Red => ControlMode::Green,
+            ControlMode::Green => ControlMode::Blue,
+            ControlMode::Blue | ControlMode::Hue | ControlMode::Inactive => ControlMode::FrameRate,
+        };
+        *self = Self::new(target);
+    }
+
+    /// Add one to the current digit, wrapping from 9 to 0
+    pub fn increment(&mut self) {
+        let digit = &mut self.digits[self.index];
+        *digit = (*digit + 1) % 10;
+    }
+
+    /// Move to the next digit, or finish on the last one
+    ///
+    /// # Returns
+    /// The entered number once the last digit is confirmed, `None` before
+    pub fn advance(&mut self) -> Option<u32> {
+        if self.index + 1 < self.digit_count() {
+            self.index += 1;
+            return None;
+        }
+        Some(self.value())
+    }
+
+    /// Number formed by the digits entered so far
+    pub fn value(&self) -> u32 {
+        self.digits[..self.digit_count()]
+            .iter()
+            .fold(0, |value, &digit| value * 10 + digit as u32)
+    }
+
+    /// Print the number with the current digit in brackets, e.g.
+    /// `entry: frame rate 1[2]0`
+    pub fn show(&self) {
+        rprint!("entry: {} ", self.target.name());
+        for (i, digit) in self.digits[..self.digit_count()].iter().enumerate() {
+            if i == self.index {
+                rprint!("[{}]", digit);
+            } else {
+                rprint!("{}", digit);
+            }
+        }
+        rprintln!();
+    }
+}
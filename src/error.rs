@@ -0,0 +1,67 @@
+//! Calibration Errors
+//!
+//! Hardware and configuration failures share one error type,
+//! [`CalibrationError`], so callers handle them uniformly and the command
+//! interface can report each with a stable numeric code, e.g.
+//! `error E4: config checksum mismatch`. Rejected user input that never
+//! touches the hardware (command syntax, lookup tables, sequences) keeps
+//! its own narrower error type.
+
+use crate::*;
+
+/// Failure of a hardware access or a config transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationError {
+    /// An ADC conversion or calibration did not complete in time
+    AdcTimeout,
+    /// Every sample attempt returned an out-of-range value (the last one
+    /// is included)
+    AdcImplausible(i16),
+    /// The tool was built without the `led-sense` feature, or no working
+    /// sense resistor was found
+    SensorUnavailable,
+    /// A config blob was rejected
+    InvalidConfig(ConfigError),
+    /// The saved calibration could not be read or written
+    Storage(StorageError),
+}
+
+impl CalibrationError {
+    /// Stable numeric code for replies, independent of the message text
+    ///
+    /// # Returns
+    /// 1 ADC timeout, 2 implausible ADC readings, 3 sensor not available,
+    /// 4 invalid config, 5 flash storage
+    pub fn code(self) -> u8 {
+        match self {
+            CalibrationError::AdcTimeout => 1,
+            CalibrationError::AdcImplausible(_) => 2,
+            CalibrationError::SensorUnavailable => 3,
+            CalibrationError::InvalidConfig(_) => 4,
+            CalibrationError::Storage(_) => 5,
+        }
+    }
+
+    /// Short human-readable description for replies and warnings
+    pub fn message(self) -> &'static str {
+        match self {
+            CalibrationError::AdcTimeout => "ADC timed out",
+            CalibrationError::AdcImplausible(_) => "ADC readings implausible",
+            CalibrationError::SensorUnavailable => "sensor not available",
+            CalibrationError::InvalidConfig(err) => err.message(),
+            CalibrationError::Storage(err) => err.message(),
+        }
+    }
+}
+
+impl From<ConfigError> for CalibrationError {
+    fn from(err: ConfigError) -> Self {
+        CalibrationError::InvalidConfig(err)
+    }
+}
+
+impl From<StorageError> for CalibrationError {
+    fn from(err: StorageError) -> Self {
+        CalibrationError::Storage(err)
+    }
+}
@@ -0,0 +1,401 @@
+//! Button Gesture Recognition
+//!
+//! Decodes the polled A/B button states into discrete gestures (taps,
+//! double taps, long and very long presses) so the UI can offer shortcuts
+//! on top of the hold-to-select control modes, and debounces the held
+//! combination itself so a staggered A+B press doesn't pass through A or B
+//! alone.
+//!
+//! Holding a button is also how a level is adjusted, so long presses are
+//! only reported once the buttons are released, and only for a press the
+//! UI has not [cancelled](GestureDecoder::cancel) by turning the knob. A
+//! button held for a while before the knob is turned therefore triggers
+//! nothing, and a very long press is reported instead of, not after, a
+//! long one.
+
+use crate::*;
+
+/// Longest press in milliseconds that still counts as a tap
+const TAP_MS: u64 = 300;
+
+/// Longest gap in milliseconds between the two taps of a double tap
+const DOUBLE_TAP_GAP_MS: u64 = 300;
+
+/// Hold time in milliseconds after which a press counts as a long press
+pub const LONG_PRESS_MS: u64 = 1000;
+
+/// Hold time in milliseconds after which a press counts as a very long
+/// press instead
+pub const VERY_LONG_PRESS_MS: u64 = 2000;
+
+/// Time in milliseconds a new button combination must hold before it
+/// selects a parameter (see [`ButtonSettle`])
+const COMBO_SETTLE_MS: u64 = 60;
+
+/// Button combination that performed a gesture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Buttons {
+    /// Button A alone
+    A,
+    /// Button B alone
+    B,
+    /// Buttons A and B together
+    Both,
+}
+
+impl Buttons {
+    /// Classify the current button state
+    ///
+    /// # Arguments
+    /// * `a` - Whether button A is pressed
+    /// * `b` - Whether button B is pressed
+    ///
+    /// # Returns
+    /// The pressed combination, or `None` if no button is pressed
+    fn from_state(a: bool, b: bool) -> Option<Self> {
+        match (a, b) {
+            (false, false) => None,
+            (true, false) => Some(Buttons::A),
+            (false, true) => Some(Buttons::B),
+            (true, true) => Some(Buttons::Both),
+        }
+    }
+
+    /// Combine two button sets seen during the same press
+    fn merge(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            Buttons::Both
+        }
+    }
+}
+
+/// Channel each button combination selects and toggles
+///
+/// Channels are indexed 0=red, 1=green, 2=blue; combinations are ordered
+/// [A, B, A+B]. Every channel is assigned to exactly one combination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonMap([usize; 3]);
+
+impl ButtonMap {
+    /// Original assignment: A = blue, B = green, A+B = red
+    pub const DEFAULT: Self = ButtonMap([2, 1, 0]);
+
+    /// Build a mapping from the channels of A, B and A+B
+    ///
+    /// # Arguments
+    /// * `channels` - Channel index for [A, B, A+B]
+    ///
+    /// # Returns
+    /// The mapping, or `None` unless each channel appears exactly once
+    pub fn new(channels: [usize; 3]) -> Option<Self> {
+        let mut seen = [false; 3];
+        for channel in channels {
+            if channel >= 3 || seen[channel] {
+                return None;
+            }
+            seen[channel] = true;
+        }
+        Some(ButtonMap(channels))
+    }
+
+    /// Channels of [A, B, A+B]
+    pub fn channels(&self) -> [usize; 3] {
+        self.0
+    }
+
+    /// Channel controlled by a button combination
+    ///
+    /// # Arguments
+    /// * `buttons` - Button combination
+    ///
+    /// # Returns
+    /// Channel index (0=red, 1=green, 2=blue)
+    pub fn channel(&self, buttons: Buttons) -> usize {
+        match buttons {
+            Buttons::A => self.0[0],
+            Buttons::B => self.0[1],
+            Buttons::Both => self.0[2],
+        }
+    }
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A recognized button gesture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// Short press and release
+    Tap(Buttons),
+    /// Two short presses in quick succession
+    DoubleTap(Buttons),
+    /// Press held for at least `LONG_PRESS_MS`, reported on release
+    LongPress(Buttons),
+    /// Press held for at least `VERY_LONG_PRESS_MS`, reported on release
+    /// instead of a long press
+    VeryLongPress(Buttons),
+}
+
+/// Gesture decoder fed with polled button states
+///
+/// A press lasts from the first button going down until all buttons are
+/// released; its combination is every button seen during that time. Single
+/// taps are reported only after the double-tap window has passed, and long
+/// presses at release.
+#[derive(Default)]
+pub struct GestureDecoder {
+    /// Start time and button combination of the current press, if any
+    press: Option<(Instant, Buttons)>,
+    /// Whether the current press was cancelled
+    consumed: bool,
+    /// Completed tap waiting for a possible second tap, with its release time
+    pending_tap: Option<(Buttons, Instant)>,
+}
+
+impl GestureDecoder {
+    /// Discard the current press so it produces no gesture
+    ///
+    /// Called when a press is used for something else, such as adjusting a
+    /// level with the knob.
+    pub fn cancel(&mut self) {
+        self.consumed = true;
+        self.pending_tap = None;
+    }
+
+    /// Process one poll of the button states
+    ///
+    /// # Arguments
+    /// * `a` - Whether button A is pressed
+    /// * `b` - Whether button B is pressed
+    /// * `now` - Time of this poll
+    ///
+    /// # Returns
+    /// A gesture if one completed at this poll
+    pub fn update(&mut self, a: bool, b: bool, now: Instant) -> Option<Gesture> {
+        match (self.press, Buttons::from_state(a, b)) {
+            // Press begins
+            (None, Some(buttons)) => {
+                self.press = Some((now, buttons));
+                self.consumed = false;
+                None
+            }
+            // Press continues: collect the buttons
+            (Some((start, combo)), Some(buttons)) => {
+                self.press = Some((start, combo.merge(buttons)));
+                None
+            }
+            // Press ends: long presses are reported by how long they were
+            // held, short presses become (possibly double) taps
+            (Some((start, combo)), None) => {
+                self.press = None;
+                let held = now.duration_since(start);
+                if self.consumed {
+                    return None;
+                }
+                if held >= Duration::from_millis(LONG_PRESS_MS) {
+                    self.pending_tap = None;
+                    return Some(if held >= Duration::from_millis(VERY_LONG_PRESS_MS) {
+                        Gesture::VeryLongPress(combo)
+                    } else {
+                        Gesture::LongPress(combo)
+                    });
+                }
+                if held > Duration::from_millis(TAP_MS) {
+                    return None;
+                }
+                match self.pending_tap.replace((combo, now)) {
+                    Some((previous, _)) if previous == combo => {
+                        self.pending_tap = None;
+                        Some(Gesture::DoubleTap(combo))
+                    }
+                    Some((previous, _)) => Some(Gesture::Tap(previous)),
+                    None => None,
+                }
+            }
+            // Idle: a tap with no follow-up within the window is a single tap
+            (None, None) => match self.pending_tap {
+                Some((buttons, released))
+                    if now.duration_since(released) > Duration::from_millis(DOUBLE_TAP_GAP_MS) =>
+                {
+                    self.pending_tap = None;
+                    Some(Gesture::Tap(buttons))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Filter that holds back a change of the held buttons until it settles
+///
+/// Two buttons pressed "together" go down a few milliseconds apart, so a
+/// poll can catch A alone on the way to A+B, or B alone on the way back
+/// up. A new combination is only accepted once it has been read
+/// unchanged for [`COMBO_SETTLE_MS`], so those in-between states never
+/// select a parameter.
+#[derive(Default)]
+pub struct ButtonSettle {
+    /// Accepted button states [A, B]
+    settled: [bool; 2],
+    /// Differing states seen since, with the time they were first read
+    pending: Option<([bool; 2], Instant)>,
+}
+
+impl ButtonSettle {
+    /// Process one poll of the button states
+    ///
+    /// # Arguments
+    /// * `buttons` - Whether [A, B] are pressed
+    /// * `now` - Time of this poll
+    ///
+    /// # Returns
+    /// The new combination once it has settled, `None` otherwise
+    pub fn update(&mut self, buttons: [bool; 2], now: Instant) -> Option<[bool; 2]> {
+        if buttons == self.settled {
+            self.pending = None;
+            return None;
+        }
+        match self.pending {
+            Some((pending, since)) if pending == buttons => {
+                if now.duration_since(since) < Duration::from_millis(COMBO_SETTLE_MS) {
+                    return None;
+                }
+                self.settled = buttons;
+                self.pending = None;
+                Some(buttons)
+            }
+            // First sight of this combination: start timing it
+            _ => {
+                self.pending = Some((buttons, now));
+                None
+            }
+        }
+    }
+
+    /// Whether a change is waiting to settle
+    pub fn settling(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed the decoder button states, each held from the given time
+    ///
+    /// # Arguments
+    /// * `decoder` - Decoder to feed
+    /// * `polls` - [A, B] states with the time in ms they were polled at
+    ///
+    /// # Returns
+    /// The gestures reported, with the poll time that reported each
+    fn feed(decoder: &mut GestureDecoder, polls: &[(u64, [bool; 2])]) -> Vec<(u64, Gesture)> {
+        polls
+            .iter()
+            .filter_map(|&(ms, [a, b])| {
+                decoder
+                    .update(a, b, Instant::from_millis(ms))
+                    .map(|gesture| (ms, gesture))
+            })
+            .collect()
+    }
+
+    /// Button A alone, B alone, both and neither
+    const A: [bool; 2] = [true, false];
+    const B: [bool; 2] = [false, true];
+    const BOTH: [bool; 2] = [true, true];
+    const NONE: [bool; 2] = [false, false];
+
+    #[test]
+    fn taps_wait_out_the_double_tap_window() {
+        let mut decoder = GestureDecoder::default();
+        let taps = feed(
+            &mut decoder,
+            &[(0, A), (100, NONE), (300, NONE), (500, NONE)],
+        );
+        assert_eq!(taps, [(500, Gesture::Tap(Buttons::A))]);
+
+        let double = feed(
+            &mut decoder,
+            &[(1000, B), (1100, NONE), (1200, B), (1300, NONE)],
+        );
+        assert_eq!(double, [(1300, Gesture::DoubleTap(Buttons::B))]);
+
+        // A staggered press counts as both buttons
+        let both = feed(
+            &mut decoder,
+            &[(2000, A), (2020, BOTH), (2150, B), (2200, NONE)],
+        );
+        assert!(both.is_empty());
+        assert_eq!(
+            feed(&mut decoder, &[(2600, NONE)]),
+            [(2600, Gesture::Tap(Buttons::Both))]
+        );
+    }
+
+    #[test]
+    fn long_presses_are_reported_on_release() {
+        let mut decoder = GestureDecoder::default();
+        // Held past both thresholds: nothing until it is let go
+        let held = feed(&mut decoder, &[(0, B), (1500, B), (2500, B)]);
+        assert!(held.is_empty());
+        assert_eq!(
+            feed(&mut decoder, &[(2600, NONE)]),
+            [(2600, Gesture::VeryLongPress(Buttons::B))]
+        );
+
+        let long = feed(&mut decoder, &[(3000, BOTH), (4500, BOTH), (4600, NONE)]);
+        assert_eq!(long, [(4600, Gesture::LongPress(Buttons::Both))]);
+
+        // Between a tap and a long press: no gesture
+        let neither = feed(&mut decoder, &[(5000, A), (5600, NONE), (6500, NONE)]);
+        assert!(neither.is_empty());
+    }
+
+    #[test]
+    fn cancelled_presses_report_nothing() {
+        let mut decoder = GestureDecoder::default();
+        // A button held, then the knob turned: the hold adjusted a level
+        feed(&mut decoder, &[(0, A), (1200, A)]);
+        decoder.cancel();
+        let released = feed(&mut decoder, &[(2500, A), (2600, NONE), (3000, NONE)]);
+        assert!(released.is_empty());
+
+        // The next press is decoded afresh
+        let tap = feed(&mut decoder, &[(4000, A), (4100, NONE), (4500, NONE)]);
+        assert_eq!(tap, [(4500, Gesture::Tap(Buttons::A))]);
+    }
+
+    #[test]
+    fn staggered_combo_settles_on_red() {
+        let mut settle = ButtonSettle::default();
+        let mut accepted = Vec::new();
+        // A goes down 20 ms before B, then B lets go 15 ms before A
+        let polls = [
+            (0, A),
+            (20, BOTH),
+            (40, BOTH),
+            (90, BOTH),
+            (300, B),
+            (315, NONE),
+            (400, NONE),
+        ];
+        for (ms, buttons) in polls {
+            if let Some(buttons) = settle.update(buttons, Instant::from_millis(ms)) {
+                accepted.push((ms, buttons));
+            }
+        }
+        // Neither A nor B alone was ever accepted
+        assert_eq!(accepted, [(90, BOTH), (400, NONE)]);
+        assert!(!settle.settling());
+        let [a, b] = accepted[0].1;
+        let combo = Buttons::from_state(a, b).unwrap();
+        assert_eq!(ButtonMap::DEFAULT.channel(combo), 0);
+    }
+}
@@ -0,0 +1,408 @@
+//! Command Grammar
+//!
+//! The host commands are described by a table rather than matched by hand.
+//! Each [`Syntax`] entry holds the keywords that select it, how many
+//! argument words may follow, the usage and description printed by `help`,
+//! and the function building the command from its arguments. Parsing and
+//! the `help` listing walk the same table, so they cannot drift apart.
+//!
+//! [`parse_line`] picks the entries whose keywords match the most leading
+//! words of a line, e.g. `knob blend on` selects `knob blend` over `knob`.
+//! Entries may share keywords when their arguments differ (`anim` alone
+//! shows the animation, `anim <breathe|rainbow> <ms>` starts one); the
+//! first entry whose argument count fits and which accepts the arguments
+//! wins. An entry without keywords catches the lines no other entry
+//! claims, which is how `<r|g|b> <level>` works without a command word.
+//!
+//! A command set may be split over several tables, searched as one in
+//! order: the firmware's own commands are followed by the level and frame
+//! rate commands this library defines for a number of levels (see
+//! [`LevelSyntax`]).
+//!
+//! The argument parsers shared by many commands live here too; those tied
+//! to firmware types stay with the commands.
+
+use crate::*;
+
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+
+/// Argument words following an entry's keywords
+pub type Words<'a> = core::str::SplitWhitespace<'a>;
+
+/// One entry of a command table
+pub struct Syntax<T> {
+    /// Words selecting the entry, e.g. `["knob", "blend"]`
+    pub keywords: &'static [&'static str],
+    /// Accepted number of argument words after the keywords
+    pub args: RangeInclusive<usize>,
+    /// Usage shown by `help`, e.g. `knob blend <on|off>`
+    pub usage: &'static str,
+    /// One-line description shown by `help`
+    pub help: &'static str,
+    /// Build the command from the argument words
+    ///
+    /// Only called with a number of words within `args`.
+    pub parse: fn(&mut Words) -> Result<T, ParseError>,
+}
+
+impl<T> Syntax<T> {
+    /// Number of leading words of a line this entry's keywords match
+    ///
+    /// # Arguments
+    /// * `line` - Command text
+    ///
+    /// # Returns
+    /// The keyword count, or `None` if the keywords do not all match
+    fn matched(&self, line: &str) -> Option<usize> {
+        let mut words = line.split_whitespace();
+        self.keywords
+            .iter()
+            .all(|keyword| words.next() == Some(keyword))
+            .then_some(self.keywords.len())
+    }
+}
+
+/// Reasons a command line could not be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Line contained no command
+    Empty,
+    /// Command word was not recognized
+    UnknownCommand,
+    /// Fewer arguments than the command requires
+    MissingArgument,
+    /// More arguments than the command accepts
+    ExtraArgument,
+    /// Argument was not a valid number
+    InvalidNumber,
+    /// Argument was not one of the accepted words
+    InvalidArgument,
+    /// Lookup table entry was above the highest level
+    LevelOutOfRange,
+    /// Lookup table entries decreased
+    NotMonotonic,
+    /// Config blob was rejected
+    InvalidConfig(ConfigError),
+    /// Color name was not in the table
+    UnknownColor,
+}
+
+impl ParseError {
+    /// Short human-readable description for error replies
+    pub fn message(self) -> &'static str {
+        match self {
+            ParseError::Empty => "empty command",
+            ParseError::UnknownCommand => "unknown command",
+            ParseError::MissingArgument => "missing argument",
+            ParseError::ExtraArgument => "too many arguments",
+            ParseError::InvalidNumber => "invalid number",
+            ParseError::InvalidArgument => "invalid argument",
+            ParseError::LevelOutOfRange => "level out of range",
+            ParseError::NotMonotonic => "levels must not decrease",
+            ParseError::InvalidConfig(err) => err.message(),
+            // Keep in sync with NAMED_COLORS
+            ParseError::UnknownColor => {
+                "unknown color, try white, warm_white, cool_white, red, green, blue, amber or off"
+            }
+        }
+    }
+}
+
+/// Entries of a command set selected by a line
+///
+/// # Arguments
+/// * `tables` - Command tables, searched as one in order
+/// * `line` - Command text
+///
+/// # Returns
+/// The entries whose keywords match the most leading words, in table order
+pub fn matching<'a, T>(
+    tables: &'a [&'a [Syntax<T>]],
+    line: &'a str,
+) -> impl Iterator<Item = &'a Syntax<T>> + 'a {
+    let entries = || tables.iter().flat_map(|table| table.iter());
+    let longest = entries().filter_map(|syntax| syntax.matched(line)).max();
+    entries().filter(move |syntax| longest.is_some() && syntax.matched(line) == longest)
+}
+
+/// Parse one command line against a command set
+///
+/// Words are separated by whitespace. Numeric arguments are only checked
+/// for syntax here; range clamping happens when the command is applied.
+///
+/// # Arguments
+/// * `tables` - Command tables, searched as one in order
+/// * `line` - Command text without the line terminator
+///
+/// # Returns
+/// The command built by the first matching entry that accepts the line,
+/// or the reason it was rejected
+pub fn parse_line<T>(tables: &[&[Syntax<T>]], line: &str) -> Result<T, ParseError> {
+    let count = line.split_whitespace().count();
+    if count == 0 {
+        return Err(ParseError::Empty);
+    }
+    let mut rejection = None;
+    let mut most = 0;
+    let mut keywords = 0;
+    for syntax in matching(tables, line) {
+        keywords = syntax.keywords.len();
+        let args = count - keywords;
+        most = most.max(*syntax.args.end());
+        if !syntax.args.contains(&args) {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        for _ in 0..keywords {
+            words.next();
+        }
+        match (syntax.parse)(&mut words) {
+            Ok(command) => return Ok(command),
+            Err(err) => {
+                rejection.get_or_insert(err);
+            }
+        }
+    }
+    match rejection {
+        Some(err) => Err(err),
+        // A line only an entry without keywords matched names no command
+        None if keywords == 0 => Err(ParseError::UnknownCommand),
+        None if count - keywords > most => Err(ParseError::ExtraArgument),
+        None => Err(ParseError::MissingArgument),
+    }
+}
+
+/// Parse a numeric argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+///
+/// # Returns
+/// The number, or an error if it is missing or malformed
+pub fn parse_number<N: FromStr>(word: Option<&str>) -> Result<N, ParseError> {
+    word.ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber)
+}
+
+/// Parse an optional numeric argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+///
+/// # Returns
+/// The number, `None` if there is no argument, or an error if it is
+/// malformed
+pub fn parse_optional<N: FromStr>(word: Option<&str>) -> Result<Option<N>, ParseError> {
+    word.map(|word| parse_number(Some(word))).transpose()
+}
+
+/// Parse a numeric argument that must lie within a range
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+/// * `range` - Accepted values
+///
+/// # Returns
+/// The number, or an error if it is missing, malformed or out of range
+pub fn parse_bounded<N: FromStr + PartialOrd>(
+    word: Option<&str>,
+    range: RangeInclusive<N>,
+) -> Result<N, ParseError> {
+    let value = parse_number(word)?;
+    if !range.contains(&value) {
+        return Err(ParseError::InvalidArgument);
+    }
+    Ok(value)
+}
+
+/// Parse an on/off argument
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+///
+/// # Returns
+/// `true` for `on`, `false` for `off`
+pub fn parse_switch(word: Option<&str>) -> Result<bool, ParseError> {
+    match word.ok_or(ParseError::MissingArgument)? {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(ParseError::InvalidArgument),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commands of a cut-down firmware table, plus the real level and
+    /// frame rate commands
+    #[derive(Debug, PartialEq)]
+    enum Test {
+        Get,
+        GetFps,
+        Knob(bool),
+        KnobBlend(bool),
+        Show,
+        Breathe(u32),
+        Level(LevelCommand),
+    }
+
+    impl From<LevelCommand> for Test {
+        fn from(command: LevelCommand) -> Self {
+            Test::Level(command)
+        }
+    }
+
+    const TABLE: &[Syntax<Test>] = &[
+        Syntax {
+            keywords: &["get"],
+            args: 0..=0,
+            usage: "get",
+            help: "show levels",
+            parse: |_| Ok(Test::Get),
+        },
+        Syntax {
+            keywords: &["get", "fps"],
+            args: 0..=0,
+            usage: "get fps",
+            help: "show the frame rate",
+            parse: |_| Ok(Test::GetFps),
+        },
+        Syntax {
+            keywords: &["knob"],
+            args: 1..=1,
+            usage: "knob <on|off>",
+            help: "switch the knob",
+            parse: |words| Ok(Test::Knob(parse_switch(words.next())?)),
+        },
+        Syntax {
+            keywords: &["knob", "blend"],
+            args: 1..=1,
+            usage: "knob blend <on|off>",
+            help: "blend levels",
+            parse: |words| Ok(Test::KnobBlend(parse_switch(words.next())?)),
+        },
+        Syntax {
+            keywords: &["anim"],
+            args: 0..=0,
+            usage: "anim",
+            help: "show the animation",
+            parse: |_| Ok(Test::Show),
+        },
+        Syntax {
+            keywords: &["anim"],
+            args: 2..=2,
+            usage: "anim breathe <ms>",
+            help: "breathe",
+            parse: |words| match words.next() {
+                Some("breathe") => Ok(Test::Breathe(parse_number(words.next())?)),
+                _ => Err(ParseError::InvalidArgument),
+            },
+        },
+    ];
+
+    /// The cut-down table followed by the level commands, as the firmware
+    /// searches its own
+    const TABLES: &[&[Syntax<Test>]] = &[TABLE, LevelSyntax::<Test, 16>::TABLE];
+
+    fn parse(line: &str) -> Result<Test, ParseError> {
+        parse_line(TABLES, line)
+    }
+
+    #[test]
+    fn valid_lines() {
+        assert_eq!(parse("get"), Ok(Test::Get));
+        assert_eq!(parse("  get   fps "), Ok(Test::GetFps));
+        assert_eq!(parse("knob on"), Ok(Test::Knob(true)));
+        assert_eq!(parse("knob blend off"), Ok(Test::KnobBlend(false)));
+        assert_eq!(parse("anim"), Ok(Test::Show));
+        assert_eq!(parse("anim breathe 500"), Ok(Test::Breathe(500)));
+        // Found in the second table
+        assert_eq!(
+            parse("fps 120"),
+            Ok(Test::Level(LevelCommand::SetRate {
+                rate: 120,
+                unit: Some(FrameRateUnit::Scans)
+            }))
+        );
+        assert_eq!(
+            parse("r 12"),
+            Ok(Test::Level(LevelCommand::SetLevel {
+                channel: 0,
+                level: 12
+            }))
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_are_kept_for_clamping() {
+        assert_eq!(
+            parse("g 16"),
+            Ok(Test::Level(LevelCommand::SetLevel {
+                channel: 1,
+                level: 16
+            }))
+        );
+        assert_eq!(
+            parse("fps 5"),
+            Ok(Test::Level(LevelCommand::SetRate {
+                rate: 5,
+                unit: Some(FrameRateUnit::Scans)
+            }))
+        );
+        assert_eq!(parse("fps -1"), Err(ParseError::InvalidNumber));
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse(" \t "), Err(ParseError::Empty));
+        assert_eq!(parse("frobnicate"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("x 12"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("r g b a"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("fps"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("fps 120 130"), Err(ParseError::ExtraArgument));
+        assert_eq!(parse("get %"), Err(ParseError::ExtraArgument));
+        assert_eq!(parse("fps fast"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("knob maybe"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("knob blend"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("anim rainbow 500"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("anim breathe"), Err(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn longest_keywords_win() {
+        let keywords = |line| -> Vec<_> { matching(TABLES, line).map(|s| s.usage).collect() };
+        assert_eq!(keywords("knob blend on"), ["knob blend <on|off>"]);
+        assert_eq!(keywords("knob on"), ["knob <on|off>"]);
+        assert_eq!(keywords("anim breathe 5"), ["anim", "anim breathe <ms>"]);
+        assert_eq!(keywords("r 1"), ["<r|g|b> <level>"]);
+        assert_eq!(keywords("set rgb 1 2 3"), ["set rgb <r> <g> <b>"]);
+        assert_eq!(keywords("set r 1"), ["set <r|g|b> <level>"]);
+    }
+
+    #[test]
+    fn argument_parsers() {
+        assert_eq!(parse_switch(Some("on")), Ok(true));
+        assert_eq!(parse_switch(Some("off")), Ok(false));
+        assert_eq!(parse_switch(Some("yes")), Err(ParseError::InvalidArgument));
+        assert_eq!(parse_switch(None), Err(ParseError::MissingArgument));
+        assert_eq!(parse_optional::<u32>(None), Ok(None));
+        assert_eq!(parse_optional::<u32>(Some("7")), Ok(Some(7)));
+        assert_eq!(
+            parse_optional::<u32>(Some("7x")),
+            Err(ParseError::InvalidNumber)
+        );
+        assert_eq!(parse_bounded(Some("-3"), -5..=5), Ok(-3));
+        assert_eq!(
+            parse_bounded(Some("0.5"), 0.0..=0.45),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(
+            parse_bounded(Some("nan"), 0.0..=0.45),
+            Err(ParseError::InvalidArgument)
+        );
+    }
+}
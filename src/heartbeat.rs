@@ -0,0 +1,113 @@
+//! Heartbeat
+//!
+//! With the `heartbeat` feature, a task prints one line of key metrics on a
+//! fixed timer, whether or not anything changes, so a long unattended
+//! burn-in run leaves regular proof that the tool is alive and what it was
+//! doing. Each line is `key=value` pairs after a `heartbeat` tag, e.g.
+//!
+//! ```text
+//! heartbeat uptime_s=3600 r=15 g=11 b=7 fps=100 actual_mhz=99987 mode=rate temp_c=-
+//! ```
+//!
+//! `temp_c` is the temperature the thermal ceilings follow: the one
+//! supplied with `thermal temp`, else the nRF's die temperature, or `-`
+//! before either is known.
+//! The interval is set with `heartbeat <seconds|off>`.
+
+use crate::*;
+
+use embassy_futures::select::{select, Either};
+
+/// Default interval between heartbeat lines in seconds
+pub const HEARTBEAT_SECONDS: u32 = 10;
+
+/// Longest heartbeat interval accepted in seconds
+pub const MAX_HEARTBEAT_SECONDS: u32 = 3600;
+
+/// Interval between checks for the heartbeat being enabled, in milliseconds
+const DISABLED_POLL_MS: u64 = 100;
+
+/// Periodic status logger
+pub struct Heartbeat {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Latest shared state seen by the subscriber
+    state: StateUpdate,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat logger
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    ///
+    /// # Returns
+    /// New logger, printing at the interval in the shared state
+    pub fn new(shared: &'static SharedState) -> Self {
+        Self {
+            shared,
+            state: StateUpdate {
+                levels: [0; 3],
+                frame_rate: 0,
+                mode: ControlMode::FrameRate,
+            },
+        }
+    }
+
+    /// Print one heartbeat line for the latest state
+    async fn beat(&self) {
+        let [red, green, blue] = self.state.levels;
+        rprint!(
+            "heartbeat uptime_s={} r={} g={} b={} fps={} actual_mhz={} mode={} temp_c=",
+            Instant::now().as_secs(),
+            red,
+            green,
+            blue,
+            self.state.frame_rate,
+            self.shared.get_actual_frame_rate().await,
+            match self.state.mode {
+                // Keep the value free of spaces
+                ControlMode::FrameRate => "rate",
+                mode => mode.name(),
+            },
+        );
+        match self.shared.get_thermal_temperature().await {
+            Some((temp, _)) => rprintln!("{}", temp),
+            None => rprintln!("-"),
+        }
+    }
+
+    /// Main heartbeat loop
+    ///
+    /// Follows the shared state through a subscription and prints a line
+    /// each time the interval elapses.
+    pub async fn run(mut self) -> ! {
+        let mut updates = self.shared.subscribe().ok();
+        if updates.is_none() {
+            rprintln!("warning: heartbeat has no state subscription, polling instead");
+        }
+        let mut due = Instant::now();
+        loop {
+            let Some(seconds) = self.shared.get_heartbeat_interval().await else {
+                Timer::after_millis(DISABLED_POLL_MS).await;
+                due = Instant::now();
+                continue;
+            };
+            let next = due + Duration::from_secs(seconds as u64);
+            let Some(updates) = updates.as_mut() else {
+                Timer::at(next).await;
+                self.state = self.shared.get_state().await;
+                self.beat().await;
+                due = next;
+                continue;
+            };
+            match select(Timer::at(next), updates.next()).await {
+                Either::First(()) => {
+                    self.beat().await;
+                    due = next;
+                }
+                Either::Second(update) => self.state = update,
+            }
+        }
+    }
+}
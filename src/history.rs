@@ -0,0 +1,156 @@
+//! Undo History
+//!
+//! While tuning, a change often turns out worse than what came before. The
+//! UI keeps a short history of the committed levels and frame rate, and the
+//! `undo` and `redo` commands step back and forth through it.
+//!
+//! Only settled states are recorded: a state becomes a checkpoint once it
+//! has held for [`SETTLE_MS`], so a knob turn through several levels is a
+//! single step, not one per level. Recording a new checkpoint after an undo
+//! discards the states that could have been redone, as in an editor. The
+//! history holds [`HISTORY_DEPTH`] checkpoints; the oldest is dropped to
+//! make room.
+
+use crate::*;
+
+/// Number of checkpoints kept, including the current one
+pub const HISTORY_DEPTH: usize = 8;
+
+/// Time in milliseconds a state must hold before it is recorded
+const SETTLE_MS: u64 = 1000;
+
+/// Committed levels and frame rate at one point of the history
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// [red, green, blue] levels
+    pub levels: [u32; 3],
+    /// Frame rate in fps
+    pub frame_rate: u64,
+}
+
+impl Checkpoint {
+    /// Placeholder for unused history slots
+    const EMPTY: Self = Checkpoint {
+        levels: [0; 3],
+        frame_rate: 0,
+    };
+}
+
+/// Bounded undo/redo history of checkpoints
+#[derive(Clone, Copy, Debug)]
+pub struct History {
+    /// Checkpoints, oldest first; only the first `len` are used
+    entries: [Checkpoint; HISTORY_DEPTH],
+    /// Number of checkpoints recorded
+    len: usize,
+    /// Index of the checkpoint the current state was recorded or restored
+    /// as
+    cursor: usize,
+    /// State seen changing and when it was first seen, not yet settled
+    candidate: Option<(Checkpoint, Instant)>,
+}
+
+impl History {
+    /// Create an empty history
+    pub const fn new() -> Self {
+        History {
+            entries: [Checkpoint::EMPTY; HISTORY_DEPTH],
+            len: 0,
+            cursor: 0,
+            candidate: None,
+        }
+    }
+
+    /// Follow the committed state, recording it once it has settled
+    ///
+    /// # Arguments
+    /// * `checkpoint` - Current committed levels and frame rate
+    /// * `now` - Current time
+    pub fn observe(&mut self, checkpoint: Checkpoint, now: Instant) {
+        if self.current() == Some(checkpoint) {
+            self.candidate = None;
+            return;
+        }
+        match self.candidate {
+            Some((candidate, since)) if candidate == checkpoint => {
+                if now.duration_since(since) >= Duration::from_millis(SETTLE_MS) {
+                    self.record(checkpoint);
+                }
+            }
+            _ => self.candidate = Some((checkpoint, now)),
+        }
+    }
+
+    /// Record a checkpoint at once, settled or not
+    ///
+    /// Anything that could have been redone is discarded. Recording the
+    /// current checkpoint again does nothing.
+    ///
+    /// # Arguments
+    /// * `checkpoint` - State to record
+    pub fn record(&mut self, checkpoint: Checkpoint) {
+        self.candidate = None;
+        if self.current() == Some(checkpoint) {
+            return;
+        }
+        if self.len > 0 {
+            self.len = self.cursor + 1;
+        }
+        if self.len == HISTORY_DEPTH {
+            self.entries.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.entries[self.len] = checkpoint;
+        self.cursor = self.len;
+        self.len += 1;
+    }
+
+    /// Step back to the previous checkpoint
+    ///
+    /// # Returns
+    /// The checkpoint to restore, or `None` at the oldest one
+    pub fn undo(&mut self) -> Option<Checkpoint> {
+        self.candidate = None;
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        Some(self.entries[self.cursor])
+    }
+
+    /// Step forward again after an undo
+    ///
+    /// # Returns
+    /// The checkpoint to restore, or `None` at the newest one
+    pub fn redo(&mut self) -> Option<Checkpoint> {
+        self.candidate = None;
+        if self.cursor + 1 >= self.len {
+            return None;
+        }
+        self.cursor += 1;
+        Some(self.entries[self.cursor])
+    }
+
+    /// The checkpoint the current state was recorded or restored as
+    ///
+    /// # Returns
+    /// The checkpoint, or `None` before anything was recorded
+    pub fn current(&self) -> Option<Checkpoint> {
+        (self.len > 0).then_some(self.entries[self.cursor])
+    }
+
+    /// Position in the history
+    ///
+    /// # Returns
+    /// The current checkpoint's number, counted from 1 for the oldest (0
+    /// while empty), and the number of checkpoints recorded
+    pub fn position(&self) -> (usize, usize) {
+        ((self.cursor + 1).min(self.len), self.len)
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
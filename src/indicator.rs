@@ -0,0 +1,270 @@
+//! Matrix Brightness Indicator
+//!
+//! The MicroBit's 5x5 matrix is monochrome, but lighting a share of its
+//! pixels gives an at-a-glance, untethered idea of how bright the LED is:
+//! with `indicator dither`, the averaged effective brightness of the current
+//! output (the mean of [`effective_color`]'s channels) sets how many of the
+//! 25 pixels are lit, 0 for dark up to 25 for every channel at full level.
+//!
+//! The count follows perceived rather than linear brightness: a pixel is lit
+//! once the brightness reaches its threshold in [`PIXEL_THRESHOLDS`], spaced
+//! along a 2.2 gamma curve, so a level that looks half as bright lights
+//! about half the pixels. The pixels fill in the order of [`DITHER_RANKS`],
+//! from the center outwards and spread across the matrix, so any count
+//! reads as an even texture rather than a bar.
+//!
+//! With `indicator mode`, the matrix instead shows what the knob controls
+//! as a letter (see [`mode_glyph`]): R, G or B for a channel, F for the
+//! frame rate and H for the hue, so the button combinations need not be
+//! remembered or read off the RTT log.
+//!
+//! With `indicator alive on`, the bottom-right pixel ([`LIVENESS_PIXEL`])
+//! instead toggles every [`LIVENESS_MS`] for as long as the RGB task's loop
+//! keeps running, proof at a glance and without a host that the firmware
+//! has not hung: if the pixel freezes, lit or dark, the scan loop has
+//! stopped. It works alongside any mode; in dither mode that pixel, the
+//! last to light, is given over to the blink.
+//!
+//! The indicator task follows the shared state through a subscription and
+//! redraws on each change. Mode is the default, so the knob's target can be
+//! read off the board from power-up; scanning the matrix takes a little CPU
+//! time away from the RGB task, which `indicator off` gives back.
+
+use crate::*;
+
+use embassy_futures::select::select;
+use microbit_bsp::{display::Frame, LedMatrix};
+
+/// Number of pixels in the matrix
+pub const MATRIX_PIXELS: u32 = 25;
+
+/// Interval between toggles of the liveness pixel, in milliseconds
+pub const LIVENESS_MS: u64 = 500;
+
+/// Matrix position [x, y] of the liveness pixel
+const LIVENESS_PIXEL: [usize; 2] = [4, 4];
+
+/// Time a frame is shown before redrawing without a state subscription, in
+/// milliseconds
+const REDRAW_MS: u64 = 100;
+
+/// Longest a frame is shown before being redrawn unchanged, in seconds
+const HOLD_SECONDS: u64 = 3600;
+
+/// Averaged brightness, per mille of full, at which each pixel count is
+/// reached: pixel `n` (from 1) lights at `1000 * ((n - 0.5) / 25)^2.2`
+const PIXEL_THRESHOLDS: [u32; MATRIX_PIXELS as usize] = [
+    0, 2, 6, 13, 23, 36, 52, 71, 93, 119, 148, 181, 218, 258, 302, 349, 401, 456, 516, 579, 646,
+    718, 793, 873, 957,
+];
+
+/// Order the pixels light in, by row: a pixel is lit while the count is
+/// above its rank
+const DITHER_RANKS: [[u32; 5]; 5] = [
+    [1, 17, 5, 18, 2],
+    [19, 9, 13, 10, 20],
+    [6, 14, 0, 15, 7],
+    [21, 11, 16, 12, 22],
+    [3, 23, 8, 24, 4],
+];
+
+/// What the matrix shows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndicatorMode {
+    /// Matrix dark
+    Off,
+    /// Pixel count proportional to the perceived brightness
+    Dither,
+    /// Letter of the parameter the knob controls
+    #[default]
+    Mode,
+}
+
+impl IndicatorMode {
+    /// Name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            IndicatorMode::Off => "off",
+            IndicatorMode::Dither => "dither",
+            IndicatorMode::Mode => "mode",
+        }
+    }
+}
+
+/// Number of pixels to light for an averaged color
+///
+/// # Arguments
+/// * `effective` - Averaged [red, green, blue] intensity (0-255) from
+///   [`effective_color`]
+///
+/// # Returns
+/// Lit pixel count (0-25)
+pub fn dither_pixels(effective: [u32; 3]) -> u32 {
+    let sum: u32 = effective.iter().sum();
+    if sum == 0 {
+        return 0;
+    }
+    // A channel at LEVELS-1 averages to 239, not 255: scale so full output
+    // reaches the last threshold
+    let full = effective_color([LEVELS - 1; 3])[0] * 3;
+    let brightness = (sum * 1000 / full).min(1000);
+    PIXEL_THRESHOLDS
+        .iter()
+        .filter(|&&threshold| brightness >= threshold)
+        .count() as u32
+}
+
+/// Matrix frame with a number of pixels lit in dither order
+///
+/// # Arguments
+/// * `pixels` - Lit pixel count (0-25)
+///
+/// # Returns
+/// The frame to display
+fn dither_frame(pixels: u32) -> Frame<5, 5> {
+    let mut frame = Frame::empty();
+    for (y, row) in DITHER_RANKS.iter().enumerate() {
+        for (x, &rank) in row.iter().enumerate() {
+            if rank < pixels {
+                frame.set(x, y);
+            }
+        }
+    }
+    frame
+}
+
+/// Glyph showing a control mode, one row per entry from the top
+///
+/// Bit 4 of each row is the leftmost pixel. Channels show their initial,
+/// the frame rate an F, the hue an H, and no parameter a single center
+/// dot.
+///
+/// # Arguments
+/// * `mode` - Parameter the knob controls
+///
+/// # Returns
+/// The glyph's rows
+pub fn mode_glyph(mode: ControlMode) -> [u8; 5] {
+    match mode {
+        ControlMode::Red => [0b11110, 0b10001, 0b11110, 0b10100, 0b10010],
+        ControlMode::Green => [0b01111, 0b10000, 0b10011, 0b10001, 0b01111],
+        ControlMode::Blue => [0b11110, 0b10001, 0b11110, 0b10001, 0b11110],
+        ControlMode::FrameRate => [0b11111, 0b10000, 0b11110, 0b10000, 0b10000],
+        ControlMode::Hue => [0b10001, 0b10001, 0b11111, 0b10001, 0b10001],
+        ControlMode::Inactive => [0b00000, 0b00000, 0b00100, 0b00000, 0b00000],
+    }
+}
+
+/// Matrix frame drawing a glyph
+///
+/// # Arguments
+/// * `glyph` - Rows as returned by [`mode_glyph`]
+///
+/// # Returns
+/// The frame to display
+fn glyph_frame(glyph: [u8; 5]) -> Frame<5, 5> {
+    let mut frame = Frame::empty();
+    for (y, row) in glyph.iter().enumerate() {
+        for x in 0..5 {
+            if row & (0b10000 >> x) != 0 {
+                frame.set(x, y);
+            }
+        }
+    }
+    frame
+}
+
+/// Brightness indicator on the onboard LED matrix
+pub struct Indicator {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Onboard 5x5 LED matrix
+    display: LedMatrix,
+}
+
+impl Indicator {
+    /// Create the indicator
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    /// * `display` - The MicroBit's LED matrix
+    ///
+    /// # Returns
+    /// New indicator, showing what the shared indicator mode selects
+    pub fn new(shared: &'static SharedState, display: LedMatrix) -> Self {
+        Self { shared, display }
+    }
+
+    /// Main indicator loop
+    ///
+    /// Scans the matrix until the shared state changes or the liveness
+    /// pixel is due, then redraws. Without a state subscription it redraws
+    /// on a timer instead.
+    pub async fn run(mut self) -> ! {
+        let mut updates = self.shared.subscribe().ok();
+        if updates.is_none() {
+            rprintln!("warning: indicator has no state subscription, polling instead");
+        }
+        let mut beat = false;
+        let mut next_beat = Instant::now();
+        loop {
+            // Toggle the liveness pixel only if the RGB loop ran since the
+            // last toggle
+            let liveness = self.shared.get_liveness().await;
+            let now = Instant::now();
+            if liveness && now >= next_beat {
+                next_beat = now + Duration::from_millis(LIVENESS_MS);
+                if self.shared.take_rgb_alive() {
+                    beat = !beat;
+                }
+            }
+
+            let mut frame = match self.shared.get_indicator_mode().await {
+                IndicatorMode::Off => None,
+                IndicatorMode::Dither => Some(dither_frame(dither_pixels(effective_color(
+                    self.shared.get_output_levels().await,
+                )))),
+                IndicatorMode::Mode => Some(glyph_frame(mode_glyph(
+                    self.shared.get_control_mode().await,
+                ))),
+            };
+            if liveness {
+                let frame = frame.get_or_insert_with(Frame::empty);
+                let [x, y] = LIVENESS_PIXEL;
+                if beat {
+                    frame.set(x, y);
+                } else {
+                    frame.unset(x, y);
+                }
+            }
+
+            let hold = match (liveness, updates.is_some()) {
+                (true, _) => Duration::from_millis(LIVENESS_MS),
+                (false, true) => Duration::from_secs(HOLD_SECONDS),
+                (false, false) => Duration::from_millis(REDRAW_MS),
+            };
+            match (frame, updates.as_mut()) {
+                // Shown until the next change or redraw; a row cut short is
+                // redrawn straight away
+                (Some(frame), Some(updates)) => {
+                    select(updates.next(), self.display.display(frame, hold)).await;
+                }
+                (Some(frame), None) => self.display.display(frame, hold).await,
+                (None, Some(updates)) => {
+                    self.blank();
+                    updates.next().await;
+                }
+                (None, None) => {
+                    self.blank();
+                    Timer::after(hold).await;
+                }
+            }
+        }
+    }
+
+    /// Turn every pixel off
+    fn blank(&mut self) {
+        self.display.clear();
+        self.display.render();
+    }
+}
@@ -0,0 +1,376 @@
+//! Position Input Abstraction
+//!
+//! The UI reads its adjustments through the [`LevelInput`] trait, so it works
+//! the same whether a potentiometer ([`Knob`]) or a rotary encoder
+//! ([`Encoder`]) is fitted.
+//!
+//! Parameters can also be set from the host, so the UI tracks which source
+//! last set each one ([`ParameterSources`]). A host write to the parameter
+//! the knob controls holds it for the host: the knob lets go and only takes
+//! over again once it is physically turned.
+//!
+//! With pickup on, an absolute knob that was moved while controlling
+//! something else must first pass through the value of the parameter it
+//! returns to before taking over ([`Pickup`]), like a MIDI controller's
+//! pickup mode, so the value never jumps to wherever the knob was left.
+//!
+//! For parameters whose middle value is neutral, the UI can also report the
+//! knob reaching the center of its travel ([`CenterDetector`]), so the user
+//! can find it without a physical detent.
+
+use crate::*;
+
+/// Physical input state as last seen by the UI, for remote monitoring
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputSnapshot {
+    /// Raw reading behind the last measurement (see [`LevelInput::raw`])
+    pub raw: u16,
+    /// Level derived from the last measurement (0 to LEVELS-1)
+    pub level: u32,
+    /// Whether buttons [A, B] are pressed
+    pub buttons: [bool; 2],
+}
+
+/// Where a parameter's current value came from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputSource {
+    /// The knob (or encoder), or the buttons
+    #[default]
+    Knob,
+    /// Anything else: host commands, binary control, presets or the
+    /// sequencer; the knob leaves the value alone until it is turned
+    Host,
+}
+
+impl InputSource {
+    /// Short name used in replies
+    pub fn name(self) -> &'static str {
+        match self {
+            InputSource::Knob => "knob",
+            InputSource::Host => "host",
+        }
+    }
+}
+
+/// Source that last set each knob-controlled parameter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParameterSources {
+    /// Source of the frame rate
+    pub frame_rate: InputSource,
+    /// Sources of the [red, green, blue] levels
+    pub levels: [InputSource; 3],
+}
+
+impl ParameterSources {
+    /// All parameters last set by the knob
+    pub const KNOB: Self = ParameterSources {
+        frame_rate: InputSource::Knob,
+        levels: [InputSource::Knob; 3],
+    };
+
+    /// Source of the parameter a control mode adjusts
+    ///
+    /// # Arguments
+    /// * `mode` - Control mode
+    ///
+    /// # Returns
+    /// The source, or `None` for a mode that adjusts nothing
+    pub fn get(&self, mode: ControlMode) -> Option<InputSource> {
+        match mode {
+            ControlMode::FrameRate => Some(self.frame_rate),
+            ControlMode::Inactive => None,
+            mode => mode.channel().map(|channel| self.levels[channel]),
+        }
+    }
+
+    /// Record the source of the parameter a control mode adjusts
+    ///
+    /// # Arguments
+    /// * `mode` - Control mode (modes that adjust nothing are ignored)
+    /// * `source` - Source that set it
+    pub fn set(&mut self, mode: ControlMode, source: InputSource) {
+        match mode {
+            ControlMode::FrameRate => self.frame_rate = source,
+            ControlMode::Inactive => (),
+            mode => {
+                if let Some(channel) = mode.channel() {
+                    self.levels[channel] = source;
+                }
+            }
+        }
+    }
+}
+
+/// How close in levels the knob must come to a parameter's value to pick
+/// it up
+pub const PICKUP_TOLERANCE: f32 = 0.5;
+
+/// Pending knob pickups, one per knob-controlled parameter
+///
+/// A pickup is armed when the knob is handed a parameter whose value it is
+/// not at, and completes once the knob comes within [`PICKUP_TOLERANCE`] of
+/// the value or crosses it. Positions are in knob units (0.0 to
+/// LEVELS-1), as for [`LevelInput::measure_fine`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pickup {
+    /// For each parameter, whether the knob was above its value when the
+    /// pickup was armed, or `None` if no pickup is pending; indexed as in
+    /// [`Self::index`]
+    pending: [Option<bool>; 4],
+}
+
+impl Pickup {
+    /// Slot of the parameter a control mode adjusts
+    ///
+    /// # Returns
+    /// 0-2 for the [red, green, blue] levels, 3 for the frame rate, or
+    /// `None` for a mode that adjusts nothing
+    fn index(mode: ControlMode) -> Option<usize> {
+        match mode {
+            ControlMode::FrameRate => Some(3),
+            mode => mode.channel(),
+        }
+    }
+
+    /// Arm a parameter's pickup unless the knob is already at its value
+    ///
+    /// # Arguments
+    /// * `mode` - Control mode adjusting the parameter
+    /// * `position` - Knob position
+    /// * `value` - Parameter value in knob units
+    pub fn arm(&mut self, mode: ControlMode, position: f32, value: f32) {
+        if let Some(index) = Self::index(mode) {
+            self.pending[index] =
+                ((position - value).abs() > PICKUP_TOLERANCE).then_some(position > value);
+        }
+    }
+
+    /// Check whether the knob has picked up a parameter
+    ///
+    /// Completes a pending pickup once the knob reaches the value.
+    ///
+    /// # Arguments
+    /// * `mode` - Control mode adjusting the parameter
+    /// * `position` - Knob position
+    /// * `value` - Parameter value in knob units
+    ///
+    /// # Returns
+    /// `true` if no pickup is pending (any more)
+    pub fn update(&mut self, mode: ControlMode, position: f32, value: f32) -> bool {
+        let Some(index) = Self::index(mode) else {
+            return true;
+        };
+        if let Some(above) = self.pending[index] {
+            let reached = (position - value).abs() <= PICKUP_TOLERANCE;
+            if reached || (position > value) != above {
+                self.pending[index] = None;
+            }
+        }
+        self.pending[index].is_none()
+    }
+
+    /// Direction the knob has to go to pick up a parameter
+    ///
+    /// # Arguments
+    /// * `mode` - Control mode adjusting the parameter
+    ///
+    /// # Returns
+    /// `Some(true)` if the knob is above the value, `Some(false)` if below,
+    /// or `None` if no pickup is pending
+    pub fn pending(&self, mode: ControlMode) -> Option<bool> {
+        Self::index(mode).and_then(|index| self.pending[index])
+    }
+
+    /// Drop every pending pickup
+    pub fn clear(&mut self) {
+        self.pending = [None; 4];
+    }
+}
+
+/// Source of a continuous position in level units (0.0 to LEVELS-1)
+///
+/// Absolute inputs such as the potentiometer report where they are set;
+/// relative inputs such as an encoder keep a virtual position that the UI
+/// moves to the selected parameter's value with [`LevelInput::set_position`].
+#[allow(async_fn_in_trait)]
+pub trait LevelInput {
+    /// Read the current position
+    ///
+    /// # Returns
+    /// Fractional level (0.0 = minimum, LEVELS-1 = maximum), or the error
+    async fn measure_fine(&mut self) -> Result<f32, CalibrationError>;
+
+    /// Most recent successful reading
+    ///
+    /// Callers use this to hold their previous value when a measurement
+    /// fails.
+    ///
+    /// # Returns
+    /// Fractional level from the last good measurement
+    fn last_good(&self) -> f32;
+
+    /// Convert a continuous reading to a brightness level
+    ///
+    /// Inputs may remember the level they reported last, e.g. for
+    /// hysteresis.
+    ///
+    /// # Arguments
+    /// * `fine` - Reading from [`LevelInput::measure_fine`]
+    ///
+    /// # Returns
+    /// Brightness level (0 = minimum, LEVELS-1 = maximum)
+    fn level(&mut self, fine: f32) -> u32;
+
+    /// How far a continuous reading sits past its brightness level
+    ///
+    /// Inputs without travel between levels report 0.
+    ///
+    /// # Arguments
+    /// * `fine` - Reading from [`LevelInput::measure_fine`]
+    ///
+    /// # Returns
+    /// Fraction of the way to the next level in [`FRACTION_ONE`]ths
+    fn fraction(&self, fine: f32) -> u32 {
+        let _ = fine;
+        0
+    }
+
+    /// Raw reading behind the most recent measurement, for diagnostics
+    fn raw(&self) -> u16;
+
+    /// Select the response curve used for brightness levels
+    ///
+    /// # Arguments
+    /// * `response` - New response curve
+    fn set_response(&mut self, response: KnobResponse);
+
+    /// Set the raw readings at the ends of the travel
+    ///
+    /// Only meaningful for analog inputs; others ignore this.
+    ///
+    /// # Arguments
+    /// * `calibration` - New endpoints
+    fn set_calibration(&mut self, calibration: KnobCalibration) {
+        let _ = calibration;
+    }
+
+    /// Set how much travel at each end reads as the end level
+    ///
+    /// Within the low deadband the input reads 0, within the high deadband
+    /// LEVELS-1; the travel in between maps linearly onto the levels, so
+    /// both extremes are reachable despite pot tolerances. Only meaningful
+    /// for analog inputs; others ignore this.
+    ///
+    /// # Arguments
+    /// * `deadbands` - Fractions of the travel dead at the [low, high] end
+    fn set_deadbands(&mut self, deadbands: [f32; 2]) {
+        let _ = deadbands;
+    }
+
+    /// Move the position to a parameter's current value
+    ///
+    /// Called when the UI selects a new parameter so relative inputs adjust
+    /// it from where it is. Absolute inputs ignore this.
+    ///
+    /// # Arguments
+    /// * `position` - Fractional level (0.0 to LEVELS-1)
+    fn set_position(&mut self, position: f32) {
+        let _ = position;
+    }
+
+    /// Record raw reading statistics while the user sweeps the input
+    ///
+    /// Only meaningful for analog inputs; others report nothing.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to sample
+    ///
+    /// # Returns
+    /// Statistics of the readings, or `None` if unavailable
+    async fn sweep_diagnostics(&mut self, duration: Duration) -> Option<SweepReport> {
+        let _ = duration;
+        None
+    }
+
+    /// Print every raw reading as fast as the input produces them
+    ///
+    /// Each reading is printed as `adc <index> <raw>`, numbered from 0, so
+    /// lines lost on the way to the host show up as gaps. Only meaningful
+    /// for analog inputs; others print nothing.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to stream
+    ///
+    /// # Returns
+    /// Number of readings taken, or `None` if unavailable
+    async fn stream_raw(&mut self, duration: Duration) -> Option<u32> {
+        let _ = duration;
+        None
+    }
+}
+
+/// How the knob reaching the center of its travel is indicated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CenterIndicator {
+    /// Not indicated
+    #[default]
+    Off,
+    /// A line over RTT
+    Log,
+    /// A line over RTT and a brief blank of the LED
+    Cue,
+}
+
+impl CenterIndicator {
+    /// Name used in replies
+    pub fn name(self) -> &'static str {
+        match self {
+            CenterIndicator::Off => "off",
+            CenterIndicator::Log => "log",
+            CenterIndicator::Cue => "cue",
+        }
+    }
+}
+
+/// Level the knob reads at the center of its travel
+pub const CENTER_LEVEL: u32 = LEVELS / 2;
+
+/// Half-width of the center zone in [`FRACTION_ONE`]ths of a level: the
+/// knob must come this close to the middle of [`CENTER_LEVEL`] to count as
+/// centered, and stays centered until it leaves the level
+const CENTER_ZONE: u32 = FRACTION_ONE / 4;
+
+/// Detector for the knob reaching the center of its travel
+///
+/// Entering needs the knob well inside the center level, while leaving
+/// needs it out of the level altogether, so noise at the edge of the zone
+/// does not report the center over and over.
+#[derive(Default)]
+pub struct CenterDetector {
+    /// Whether the knob is currently at the center
+    centered: bool,
+}
+
+impl CenterDetector {
+    /// Process one knob reading
+    ///
+    /// Only inputs that report fractions (see [`LevelInput::fraction`]) can
+    /// enter the zone.
+    ///
+    /// # Arguments
+    /// * `level` - Level of the reading
+    /// * `fraction` - Fraction of the reading toward the next level
+    ///
+    /// # Returns
+    /// `true` when the reading just reached the center
+    pub fn update(&mut self, level: u32, fraction: u32) -> bool {
+        let middle = FRACTION_ONE / 2;
+        let entered = level == CENTER_LEVEL && fraction.abs_diff(middle) <= CENTER_ZONE;
+        let was_centered = self.centered;
+        self.centered = match self.centered {
+            true => level == CENTER_LEVEL,
+            false => entered,
+        };
+        self.centered && !was_centered
+    }
+}
@@ -3,11 +3,207 @@
 //! Provides calibrated analog input from a potentiometer connected to the MicroBit's
 //! ADC. Converts raw ADC readings to discrete brightness levels (0 to LEVELS-1)
 //! with proper scaling and clamping.
+//!
+//! The SAADC's reference drifts with temperature, so over a long session the
+//! ADC is recalibrated periodically, and also when the chip's own die
+//! temperature sensor has moved by more than a threshold since the last
+//! calibration (see [`RECALIBRATE_INTERVAL`] and [`RECALIBRATE_DELTA_C`]).
+//! The reference sits on the same die, so that is the temperature that
+//! matters, and it needs nothing supplied from outside. A recalibration
+//! takes a few milliseconds between two samples, so the UI carries on
+//! undisturbed. Each reading also goes to the shared state, where the
+//! thermal derating uses it unless an LED temperature was supplied.
+//!
+//! Levels change with hysteresis: a knob parked on the boundary between two
+//! levels keeps reporting the one it came from until the reading moves
+//! [`KNOB_HYSTERESIS`] of a level from that level's middle, a little past
+//! the boundary. The two ends of the travel always report 0 and LEVELS-1.
+//!
+//! Each measurement averages [`KNOB_OVERSAMPLE`] conversions, so a knob
+//! resting near a level boundary doesn't flicker between the two levels on
+//! ADC noise. The averaging is done in software, as the SAADC's hardware
+//! oversampling only works with a single channel enabled and the current
+//! sense may use a second one. Sweeps and raw streams still see every
+//! single conversion.
 
 use crate::*;
 
-/// Type alias for the SAADC (Successive Approximation ADC) with 1 channel
-pub type Adc = saadc::Saadc<'static, 1>;
+use embassy_time::with_timeout;
+use microbit_bsp::embassy_nrf::temp::Temp;
+
+/// Number of ADC channels: the knob, plus the LED current sense input
+#[cfg(not(feature = "led-sense"))]
+pub const ADC_CHANNELS: usize = 1;
+/// Number of ADC channels: the knob, plus the LED current sense input
+#[cfg(feature = "led-sense")]
+pub const ADC_CHANNELS: usize = 2;
+
+/// Type alias for the SAADC (Successive Approximation ADC); channel 0 is the
+/// knob, channel 1 (if present) the LED current sense
+pub type Adc = saadc::Saadc<'static, ADC_CHANNELS>;
+
+/// Lowest raw reading accepted as plausible (small negative values are
+/// normal ADC noise near ground)
+const RAW_MIN_PLAUSIBLE: i16 = -64;
+
+/// Highest raw reading accepted as plausible (14-bit full scale plus noise)
+const RAW_MAX_PLAUSIBLE: i16 = 0x3fff + 64;
+
+/// Highest raw reading a 14-bit single-ended conversion produces
+pub const RAW_FULL_SCALE: u16 = 0x3fff;
+
+/// Distance in levels from the middle of the current level the reading
+/// must reach before the level changes (0.5 for none)
+pub const KNOB_HYSTERESIS: f32 = 0.6;
+
+/// Number of conversions averaged per knob measurement
+pub const KNOB_OVERSAMPLE: u32 = 8;
+
+/// Number of samples attempted per measurement before giving up
+const SAMPLE_ATTEMPTS: u32 = 3;
+
+/// Longest a single conversion may take before the ADC is considered
+/// stalled (a conversion normally completes in tens of microseconds)
+const SAMPLE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Longest an ADC calibration may take
+const CALIBRATE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Time between scheduled recalibrations
+const RECALIBRATE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Temperature change in °C since the last calibration that triggers a
+/// recalibration
+const RECALIBRATE_DELTA_C: u32 = 5;
+
+/// Consecutive failed measurements before a warning is logged
+const FAILURE_LOG_THRESHOLD: u32 = 3;
+
+/// Default dead travel at the low end, as a fraction of the scaled ADC
+/// range: the bottom 2/18 all read as level 0
+///
+/// Tuned on the original hardware with 16 levels (two level widths out of
+/// 18). It covers the potentiometer's mechanical end, so it stays the same
+/// fraction of travel whatever [`LEVELS`] is.
+pub const DEFAULT_LOW_DEADBAND: f32 = 2.0 / 18.0;
+
+/// Default dead travel at the high end, as a fraction of the scaled ADC
+/// range: the top 1/18 all reads as level LEVELS-1 (see
+/// [`DEFAULT_LOW_DEADBAND`])
+pub const DEFAULT_HIGH_DEADBAND: f32 = 1.0 / 18.0;
+
+/// Largest deadband accepted at either end, so some travel stays live
+pub const MAX_DEADBAND: f32 = 0.45;
+
+/// Result of a knob sweep: raw ADC statistics over the sweep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SweepReport {
+    /// Smallest plausible raw reading seen
+    pub min: u16,
+    /// Largest plausible raw reading seen
+    pub max: u16,
+    /// Mean of all plausible raw readings
+    pub mean: u16,
+    /// Number of plausible samples taken
+    pub samples: u32,
+    /// Number of samples rejected as implausible
+    pub rejected: u32,
+}
+
+/// Raw ADC readings at the two ends of the knob's travel
+///
+/// The raw reading is mapped linearly from `min` (one end) to `max` (the
+/// other) before the deadbands and response curve are applied. Set from
+/// the host with `set knobcal`, e.g. from the extremes a `sweep` reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KnobCalibration {
+    /// Raw reading at the low end
+    min: u16,
+    /// Raw reading at the high end
+    max: u16,
+}
+
+impl KnobCalibration {
+    /// Endpoints the original fixed scaling used (raw / 10000)
+    pub const DEFAULT: Self = KnobCalibration {
+        min: 0,
+        max: 10_000,
+    };
+
+    /// Build a calibration from raw endpoints
+    ///
+    /// # Arguments
+    /// * `min` - Raw reading at the low end
+    /// * `max` - Raw reading at the high end
+    ///
+    /// # Returns
+    /// The calibration, or `None` unless `min < max <= RAW_FULL_SCALE`
+    pub fn new(min: u16, max: u16) -> Option<Self> {
+        (min < max && max <= RAW_FULL_SCALE).then_some(KnobCalibration { min, max })
+    }
+
+    /// Raw reading at the low end
+    pub fn min(&self) -> u16 {
+        self.min
+    }
+
+    /// Raw reading at the high end
+    pub fn max(&self) -> u16 {
+        self.max
+    }
+
+    /// Position of a raw reading between the endpoints
+    ///
+    /// # Arguments
+    /// * `raw` - Raw ADC reading
+    ///
+    /// # Returns
+    /// 0.0 at `min`, 1.0 at `max`; readings beyond either end fall outside
+    /// that range and are clamped by the caller
+    pub fn position(&self, raw: u16) -> f32 {
+        knob_position(raw, self.min, self.max)
+    }
+}
+
+impl Default for KnobCalibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Mapping from knob travel to brightness level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KnobResponse {
+    /// Levels spread evenly over the knob travel
+    Linear,
+    /// Dim region expanded: brightness perception is roughly logarithmic,
+    /// so the first half of the travel covers only the lowest quarter of the
+    /// levels and the bright end is compressed
+    DimExpanded,
+}
+
+impl KnobResponse {
+    /// Apply the response curve to a continuous level
+    ///
+    /// Every curve is monotonic and maps 0.0 to 0.0 and LEVELS-1 to
+    /// LEVELS-1, so the full range stays reachable.
+    ///
+    /// # Arguments
+    /// * `level` - Linear fractional level (0.0 to LEVELS-1)
+    ///
+    /// # Returns
+    /// Reshaped fractional level (0.0 to LEVELS-1)
+    pub fn apply(self, level: f32) -> f32 {
+        match self {
+            KnobResponse::Linear => level,
+            KnobResponse::DimExpanded => {
+                let max = (LEVELS - 1) as f32;
+                let position = level / max;
+                position * position * max
+            }
+        }
+    }
+}
 
 /// Potentiometer interface for user input
 ///
@@ -16,52 +212,411 @@ pub type Adc = saadc::Saadc<'static, 1>;
 /// - Pin 1 to GND
 /// - Pin 2 to P2 (ADC input)
 /// - Pin 3 to +3.3V
-pub struct Knob(Adc);
+pub struct Knob {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Calibrated ADC sampling the potentiometer
+    adc: Adc,
+    /// Most recent raw ADC reading, kept for diagnostics
+    last_raw: u16,
+    /// Response curve used when converting to brightness levels
+    response: KnobResponse,
+    /// Raw readings at the ends of the travel
+    calibration: KnobCalibration,
+    /// Most recent successful continuous reading
+    last_good: f32,
+    /// Level reported last, which the hysteresis holds on to
+    last_level: u32,
+    /// Number of consecutive failed measurements
+    failures: u32,
+    /// Fraction of the scaled range at the low end that reads as 0
+    low_deadband: f32,
+    /// Fraction of the scaled range at the high end that reads as LEVELS-1
+    high_deadband: f32,
+    /// On-chip die temperature sensor
+    temp: Temp<'static>,
+    /// Time of the last calibration
+    calibrated_at: Instant,
+    /// Die temperature in °C at the last calibration
+    calibrated_temp_c: i32,
+}
+
+/// Read the chip's die temperature
+///
+/// A conversion takes about 36 µs, short enough to run before every knob
+/// measurement.
+///
+/// # Arguments
+/// * `temp` - Die temperature sensor
+///
+/// # Returns
+/// Temperature in °C, rounded to the nearest degree
+async fn die_temperature(temp: &mut Temp<'static>) -> i32 {
+    temp.read().await.round().to_num::<i32>()
+}
 
 impl Knob {
-    /// Create a new knob interface with calibrated ADC
+    /// Create a new knob interface
     ///
-    /// Performs ADC calibration to ensure accurate readings across
-    /// the full voltage range.
+    /// The ADC is not calibrated yet; call [`Knob::calibrate`] before the
+    /// first measurement for accurate readings across the full voltage
+    /// range.
     ///
     /// # Arguments
+    /// * `shared` - State shared with the other tasks
     /// * `adc` - Configured SAADC instance
+    /// * `temp` - Die temperature sensor, which schedules recalibrations
+    ///   and stands in for the LED temperature (see `ThermalDerating`)
+    ///
+    /// # Returns
+    /// Knob interface with the default settings
+    pub async fn new(shared: &'static SharedState, adc: Adc, mut temp: Temp<'static>) -> Self {
+        let calibrated_temp_c = die_temperature(&mut temp).await;
+        shared.set_die_temperature(calibrated_temp_c).await;
+        Self {
+            shared,
+            adc,
+            last_raw: 0,
+            response: KnobResponse::Linear,
+            calibration: KnobCalibration::DEFAULT,
+            last_good: 0.0,
+            last_level: 0,
+            failures: 0,
+            low_deadband: DEFAULT_LOW_DEADBAND,
+            high_deadband: DEFAULT_HIGH_DEADBAND,
+            temp,
+            calibrated_at: Instant::now(),
+            calibrated_temp_c,
+        }
+    }
+
+    /// Calibrate the ADC
+    ///
+    /// Bounded by `CALIBRATE_TIMEOUT`, so a stuck ADC cannot stall the
+    /// caller. Restarts the recalibration schedule either way.
     ///
     /// # Returns
-    /// Calibrated knob interface ready for measurements
-    pub async fn new(adc: Adc) -> Self {
-        // Calibrate ADC for accurate voltage measurements
-        adc.calibrate().await;
-        Self(adc)
+    /// `Ok` once calibrated, or [`CalibrationError::AdcTimeout`]
+    pub async fn calibrate(&mut self) -> Result<(), CalibrationError> {
+        let calibrated = with_timeout(CALIBRATE_TIMEOUT, self.adc.calibrate()).await;
+        self.calibrated_at = Instant::now();
+        calibrated.map_err(|_| CalibrationError::AdcTimeout)
+    }
+
+    /// Check whether a working LED current sense input is connected
+    ///
+    /// See [`probe_led_sense`]; must run before the RGB task starts.
+    ///
+    /// # Arguments
+    /// * `pins` - LED pins, all low
+    ///
+    /// # Returns
+    /// `Ok` if the sense input works, or
+    /// [`CalibrationError::SensorUnavailable`]
+    #[cfg(feature = "led-sense")]
+    pub async fn probe_sense(&mut self, pins: &mut RgbPins) -> Result<(), CalibrationError> {
+        probe_led_sense(&mut self.adc, pins).await
+    }
+
+    /// Recalibrate the ADC if the schedule or the temperature calls for it
+    ///
+    /// The ADC is recalibrated once [`RECALIBRATE_INTERVAL`] has passed
+    /// since the last calibration, or as soon as the die temperature
+    /// differs from the one at the last calibration by more than
+    /// [`RECALIBRATE_DELTA_C`].
+    ///
+    /// Called before each measurement. The calibration is bounded by
+    /// `CALIBRATE_TIMEOUT`, so a stuck ADC cannot stall the UI here either.
+    async fn recalibrate_if_due(&mut self) {
+        let temp_c = die_temperature(&mut self.temp).await;
+        self.shared.set_die_temperature(temp_c).await;
+        let drifted = temp_c.abs_diff(self.calibrated_temp_c) > RECALIBRATE_DELTA_C;
+        let due = self.calibrated_at.elapsed() >= RECALIBRATE_INTERVAL;
+        if drifted || due {
+            let calibrated = self.calibrate().await;
+            self.calibrated_temp_c = temp_c;
+            if self.shared.get_debug_output().await {
+                match calibrated {
+                    Ok(()) => rprintln!("knob ADC recalibrated"),
+                    Err(err) => rprintln!("knob ADC recalibration failed: {}", err.message()),
+                }
+            }
+        }
+    }
+
+    /// Take one plausible raw ADC sample
+    ///
+    /// Retries up to `SAMPLE_ATTEMPTS` times if the ADC returns a value
+    /// outside the range a 14-bit single-ended conversion can produce.
+    ///
+    /// A conversion that never completes (e.g. a missed interrupt) is
+    /// abandoned after `SAMPLE_TIMEOUT` and the ADC recalibrated (which also
+    /// restarts the recalibration schedule), so the caller can carry on
+    /// with its last good value instead of hanging.
+    ///
+    /// # Returns
+    /// Raw reading clamped to the positive range, or the error
+    async fn sample(&mut self) -> Result<u16, CalibrationError> {
+        let mut buf = [0; ADC_CHANNELS];
+        for _ in 0..SAMPLE_ATTEMPTS {
+            // Sample ADC (blocks until conversion complete or stalled)
+            let led = self.shared.active_led();
+            if with_timeout(SAMPLE_TIMEOUT, self.adc.sample(&mut buf))
+                .await
+                .is_err()
+            {
+                let _ = self.calibrate().await;
+                return Err(CalibrationError::AdcTimeout);
+            }
+            // Only trust the sense reading if one channel stayed lit
+            // throughout the conversion
+            #[cfg(feature = "led-sense")]
+            if let Some(led) = led.filter(|&led| self.shared.active_led() == Some(led)) {
+                self.shared.record_led_sense(led, buf[1]).await;
+            }
+            #[cfg(not(feature = "led-sense"))]
+            let _ = led;
+            if (RAW_MIN_PLAUSIBLE..=RAW_MAX_PLAUSIBLE).contains(&buf[0]) {
+                // Clamp raw reading to positive 15-bit range (14-bit ADC + sign)
+                return Ok(buf[0].clamp(0, 0x7fff) as u16);
+            }
+        }
+        Err(CalibrationError::AdcImplausible(buf[0]))
+    }
+
+    /// Take the mean of `KNOB_OVERSAMPLE` plausible raw samples
+    ///
+    /// # Returns
+    /// Rounded mean raw reading, or the first error from [`Self::sample`]
+    async fn sample_averaged(&mut self) -> Result<u16, CalibrationError> {
+        let mut sum: u32 = 0;
+        for _ in 0..KNOB_OVERSAMPLE {
+            sum += self.sample().await? as u32;
+        }
+        Ok(((sum + KNOB_OVERSAMPLE / 2) / KNOB_OVERSAMPLE) as u16)
     }
 
     /// Read potentiometer position and convert to brightness level
     ///
-    /// Performs ADC sampling and converts the raw reading to a discrete
-    /// brightness level from 0 to LEVELS-1 (0 to 15).
+    /// Converts the continuous reading from [`LevelInput::measure_fine`] to a
+    /// discrete brightness level from 0 to LEVELS-1 (0 to 15) using the
+    /// selected response curve.
+    ///
+    /// # Returns
+    /// Brightness level (0 = minimum, LEVELS-1 = maximum), or the error
+    pub async fn measure(&mut self) -> Result<u32, CalibrationError> {
+        let fine = self.measure_fine().await?;
+        Ok(self.level(fine))
+    }
+}
+
+impl LevelInput for Knob {
+    /// Read potentiometer position as a continuous level
+    ///
+    /// Averages `KNOB_OVERSAMPLE` ADC samples and converts the mean to a
+    /// fractional level from 0.0 to LEVELS-1 (0.0 to 15.0), for callers that
+    /// want finer resolution than the discrete brightness levels.
+    ///
+    /// The conversion maps the raw reading between the calibrated endpoints
+    /// (see [`KnobCalibration`]) to levels with a configurable deadband at
+    /// each end for mechanical tolerances (the `knob deadband` command).
+    ///
+    /// If the ADC keeps returning implausible values, an error is returned
+    /// and [`LevelInput::last_good`] still holds the previous reading. A
+    /// warning is logged once per run of repeated failures.
+    ///
+    /// # Returns
+    /// Fractional level (0.0 = minimum, LEVELS-1 = maximum)
+    async fn measure_fine(&mut self) -> Result<f32, CalibrationError> {
+        self.recalibrate_if_due().await;
+        let raw = match self.sample_averaged().await {
+            Ok(raw) => raw,
+            Err(err) => {
+                self.failures = self.failures.saturating_add(1);
+                match err {
+                    // A stall is logged straight away: each one already
+                    // cost a timeout
+                    CalibrationError::AdcTimeout if self.failures == 1 => {
+                        rprintln!("warning: knob ADC stalled, recalibrated, holding last value");
+                    }
+                    CalibrationError::AdcImplausible(_)
+                        if self.failures == FAILURE_LOG_THRESHOLD =>
+                    {
+                        rprintln!("warning: knob ADC readings implausible, holding last value");
+                    }
+                    _ => {}
+                }
+                return Err(err);
+            }
+        };
+        self.failures = 0;
+        self.last_raw = raw;
+
+        // Scale to 0.0-1.0 between the calibrated endpoints (by default
+        // raw / 10000, tuned for the original hardware)
+        let scaled = self.calibration.position(raw);
+
+        // Map the live travel between the deadbands onto the levels
+        let fine = knob_level(scaled, self.low_deadband, self.high_deadband, LEVELS);
+        self.last_good = fine;
+        Ok(fine)
+    }
+
+    /// Most recent successful continuous reading
     ///
-    /// The conversion applies scaling and offset to map the ADC range
-    /// to brightness levels with some margin for mechanical tolerances.
+    /// Callers use this to hold their previous value when a measurement
+    /// fails.
+    ///
+    /// # Returns
+    /// Fractional level from the last good measurement (0.0 before any)
+    fn last_good(&self) -> f32 {
+        self.last_good
+    }
+
+    /// Convert a continuous reading to a brightness level
+    ///
+    /// Applies the selected response curve before quantizing, then keeps
+    /// the previous level unless the reading is at least `KNOB_HYSTERESIS`
+    /// from its middle. Readings at either end of the range give 0 or
+    /// LEVELS-1 regardless.
+    ///
+    /// # Arguments
+    /// * `fine` - Reading from [`LevelInput::measure_fine`]
     ///
     /// # Returns
     /// Brightness level (0 = minimum, LEVELS-1 = maximum)
-    pub async fn measure(&mut self) -> u32 {
-        let mut buf = [0];
-        // Sample ADC (blocks until conversion complete)
-        self.0.sample(&mut buf).await;
+    fn level(&mut self, fine: f32) -> u32 {
+        let shaped = self.response.apply(fine);
+        let top = (LEVELS - 1) as f32;
+        let middle = self.last_level as f32 + 0.5;
+        self.last_level = if shaped <= 0.0 {
+            0
+        } else if shaped >= top {
+            LEVELS - 1
+        } else if (shaped - middle).abs() >= KNOB_HYSTERESIS {
+            (shaped.floor() as u32).min(LEVELS - 1)
+        } else {
+            self.last_level
+        };
+        self.last_level
+    }
 
-        // Clamp raw reading to positive 15-bit range (14-bit ADC + sign)
-        let raw = buf[0].clamp(0, 0x7fff) as u16;
+    /// How far a continuous reading sits past its brightness level
+    ///
+    /// Measured after the response curve, like [`LevelInput::level`], and
+    /// from the level reported last, so a level held by the hysteresis
+    /// reads 0 below it and nearly a whole level above it.
+    ///
+    /// # Arguments
+    /// * `fine` - Reading from [`LevelInput::measure_fine`]
+    ///
+    /// # Returns
+    /// Fraction of the way to the next level in [`FRACTION_ONE`]ths
+    fn fraction(&self, fine: f32) -> u32 {
+        let shaped = self.response.apply(fine);
+        let past = (shaped - self.last_level as f32).clamp(0.0, 1.0);
+        ((past * FRACTION_ONE as f32) as u32).min(FRACTION_ONE - 1)
+    }
 
-        // Scale to 0.0-1.0 range (division factor tuned for hardware)
-        let scaled = raw as f32 / 10_000.0;
+    /// Raw ADC value of the most recent measurement
+    ///
+    /// Useful for telling ADC problems apart from scaling problems.
+    ///
+    /// # Returns
+    /// Clamped raw reading (0 to 0x7fff) from the last sample
+    fn raw(&self) -> u16 {
+        self.last_raw
+    }
 
-        // Map to brightness levels with offset for better range coverage
-        // Formula provides some margin at both ends of knob travel
-        let result = ((LEVELS + 2) as f32 * scaled - 2.0)
-            .clamp(0.0, (LEVELS - 1) as f32) // Ensure valid range
-            .floor(); // Convert to integer level
+    /// Select the response curve used for brightness levels
+    ///
+    /// # Arguments
+    /// * `response` - New knob response curve
+    fn set_response(&mut self, response: KnobResponse) {
+        self.response = response;
+    }
 
-        result as u32
+    /// Set the raw readings at the ends of the travel
+    ///
+    /// # Arguments
+    /// * `calibration` - New endpoints
+    fn set_calibration(&mut self, calibration: KnobCalibration) {
+        self.calibration = calibration;
+    }
+
+    /// Set how much travel at each end of the knob is dead
+    ///
+    /// # Arguments
+    /// * `deadbands` - Fractions of the scaled ADC range dead at the [low,
+    ///   high] end, each clamped to 0.0..=MAX_DEADBAND
+    fn set_deadbands(&mut self, deadbands: [f32; 2]) {
+        let [low, high] = deadbands.map(|deadband| deadband.clamp(0.0, MAX_DEADBAND));
+        self.low_deadband = low;
+        self.high_deadband = high;
+    }
+
+    /// Record the raw ADC range while the user sweeps the knob
+    ///
+    /// Samples as fast as the ADC allows for the given time, tracking the
+    /// extremes and the mean of the raw readings. Turning the knob from end
+    /// to end meanwhile shows whether both rails are reached.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to sample
+    ///
+    /// # Returns
+    /// Statistics of the readings, or `None` if no sample was plausible
+    async fn sweep_diagnostics(&mut self, duration: Duration) -> Option<SweepReport> {
+        let mut report = SweepReport {
+            min: u16::MAX,
+            max: 0,
+            mean: 0,
+            samples: 0,
+            rejected: 0,
+        };
+        let mut sum: u64 = 0;
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            match self.sample().await {
+                Ok(raw) => {
+                    report.min = report.min.min(raw);
+                    report.max = report.max.max(raw);
+                    report.samples += 1;
+                    sum += raw as u64;
+                }
+                Err(_) => report.rejected += 1,
+            }
+        }
+        if report.samples == 0 {
+            return None;
+        }
+        report.mean = (sum / report.samples as u64) as u16;
+        Some(report)
+    }
+
+    /// Print every raw ADC reading as fast as the ADC produces them
+    ///
+    /// Readings are printed unfiltered, straight from the ADC, including
+    /// negative and other values the knob would reject as implausible: no
+    /// conversion is retried or dropped. A stalled conversion prints
+    /// `timeout` in place of the value, without recalibrating.
+    ///
+    /// # Arguments
+    /// * `duration` - How long to stream
+    ///
+    /// # Returns
+    /// Number of readings taken
+    async fn stream_raw(&mut self, duration: Duration) -> Option<u32> {
+        let mut index = 0;
+        let start = Instant::now();
+        let mut buf = [0; ADC_CHANNELS];
+        while start.elapsed() < duration {
+            match with_timeout(SAMPLE_TIMEOUT, self.adc.sample(&mut buf)).await {
+                Ok(()) => rprintln!("adc {} {}", index, buf[0]),
+                Err(_) => rprintln!("adc {} timeout", index),
+            }
+            index += 1;
+        }
+        Some(index)
     }
 }
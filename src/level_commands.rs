@@ -0,0 +1,362 @@
+//! Level and Frame Rate Commands
+//!
+//! The host commands setting the three levels and the frame rate, with the
+//! channel, level and rate arguments they share. Their ranges depend on
+//! the build's number of levels, so the table is generic over it (see
+//! [`LevelSyntax`]): the firmware searches it with `LEVELS` ahead of its
+//! own commands, and the tests below parse with the very same entries.
+//!
+//! Out-of-range values are clamped, never rejected: a level above the top
+//! (`g 99`) and a frame rate outside the valid range (`fps 5`) parse as
+//! given and are clamped when the command is applied, as the knob's values
+//! are. The frame rate limits can change at run time (the strobe guard and
+//! the fusion threshold), so only the command applying it knows them.
+//! Words that are not numbers, and negative levels, are still errors.
+
+use crate::*;
+
+use core::marker::PhantomData;
+
+/// Channel names accepted by commands, indexed like the levels array
+pub const CHANNEL_NAMES: [[&str; 2]; 3] = [["r", "red"], ["g", "green"], ["b", "blue"]];
+
+/// Unit in which the frame rate is shown and commanded
+///
+/// Internally the frame rate always counts full red-green-blue scans per
+/// second. What decides flicker is how often each color is flashed, which
+/// is the scan rate times the sub-frames per scan (the `subframes`
+/// command); with the default single sub-frame the two numbers are equal
+/// and only the label differs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameRateUnit {
+    /// Full RGB scans per second ("fps")
+    Scans,
+    /// Flashes of each color per second ("Hz per color")
+    PerColor,
+}
+
+impl FrameRateUnit {
+    /// Convert a scan rate to this unit
+    ///
+    /// # Arguments
+    /// * `frame_rate` - Full scans per second
+    /// * `sub_frames` - Sub-frames per scan
+    ///
+    /// # Returns
+    /// The rate in this unit
+    pub fn from_scans(self, frame_rate: u64, sub_frames: u32) -> u64 {
+        match self {
+            FrameRateUnit::Scans => frame_rate,
+            FrameRateUnit::PerColor => frame_rate * sub_frames.max(1) as u64,
+        }
+    }
+
+    /// Convert a rate in this unit to full scans per second
+    ///
+    /// # Arguments
+    /// * `rate` - Rate in this unit
+    /// * `sub_frames` - Sub-frames per scan
+    ///
+    /// # Returns
+    /// Full scans per second (rounded to the nearest)
+    pub fn to_scans(self, rate: u64, sub_frames: u32) -> u64 {
+        match self {
+            FrameRateUnit::Scans => rate,
+            FrameRateUnit::PerColor => {
+                let sub_frames = sub_frames.max(1) as u64;
+                (rate + sub_frames / 2) / sub_frames
+            }
+        }
+    }
+
+    /// Short label printed after a rate in this unit
+    pub fn label(self) -> &'static str {
+        match self {
+            FrameRateUnit::Scans => "fps",
+            FrameRateUnit::PerColor => "Hz per color",
+        }
+    }
+}
+
+/// A parsed level or frame rate command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelCommand {
+    /// Change one channel by a signed amount: `adjust <r|g|b> <delta>`
+    AdjustLevel {
+        /// Channel index (0=red, 1=green, 2=blue)
+        channel: usize,
+        /// Signed change in levels
+        delta: i32,
+    },
+    /// Change the frame rate by a signed amount: `adjust fps <delta>`
+    AdjustRate(i64),
+    /// Set the frame rate: `set rate <rate>` in the selected unit, or
+    /// `fps <fps>` in fps whatever the unit
+    SetRate {
+        /// Unclamped rate
+        rate: u64,
+        /// Unit of `rate`, or `None` for the selected one
+        unit: Option<FrameRateUnit>,
+    },
+    /// Set one channel: `set <r|g|b> <level>`, or `<r|g|b> <level>`
+    SetLevel {
+        /// Channel index (0=red, 1=green, 2=blue)
+        channel: usize,
+        /// Unclamped level
+        level: u32,
+    },
+    /// Set all three channels: `set rgb <r> <g> <b>`
+    SetRgb([u32; 3]),
+}
+
+/// The level and frame rate command table for a number of levels
+///
+/// `T` is the command type of the table the entries join; each parsed
+/// [`LevelCommand`] is converted into it.
+pub struct LevelSyntax<T, const LEVELS: u32>(PhantomData<T>);
+
+impl<T: From<LevelCommand> + 'static, const LEVELS: u32> LevelSyntax<T, LEVELS> {
+    /// Entries of the level and frame rate commands
+    pub const TABLE: &'static [Syntax<T>] = &[
+        Syntax {
+            keywords: &["adjust"],
+            args: 2..=2,
+            usage: "adjust <r|g|b|fps> <delta>",
+            help: "change a level or the fps by +/- delta",
+            parse: |words| {
+                let command = match words.next() {
+                    Some("fps") => LevelCommand::AdjustRate(parse_number(words.next())?),
+                    name => LevelCommand::AdjustLevel {
+                        channel: name
+                            .and_then(parse_channel)
+                            .ok_or(ParseError::InvalidArgument)?,
+                        delta: parse_number(words.next())?,
+                    },
+                };
+                Ok(command.into())
+            },
+        },
+        Syntax {
+            keywords: &["set", "rate"],
+            args: 1..=1,
+            usage: "set rate <rate>",
+            help: "set the frame rate in the selected unit",
+            parse: |words| {
+                let rate = parse_number(words.next())?;
+                Ok(LevelCommand::SetRate { rate, unit: None }.into())
+            },
+        },
+        Syntax {
+            keywords: &["set"],
+            args: 2..=2,
+            usage: "set <r|g|b> <level>",
+            help: "set one level (0-15 or 0%-100%)",
+            parse: |words| Ok(parse_set_level(words, LEVELS)?.into()),
+        },
+        Syntax {
+            keywords: &[],
+            args: 2..=2,
+            usage: "<r|g|b> <level>",
+            help: "same as set <r|g|b> <level>",
+            parse: |words| Ok(parse_set_level(words, LEVELS)?.into()),
+        },
+        Syntax {
+            keywords: &["fps"],
+            args: 1..=1,
+            usage: "fps <fps>",
+            help: "set the frame rate in fps, whatever the unit",
+            parse: |words| {
+                let rate = parse_number(words.next())?;
+                let unit = Some(FrameRateUnit::Scans);
+                Ok(LevelCommand::SetRate { rate, unit }.into())
+            },
+        },
+        Syntax {
+            keywords: &["set", "rgb"],
+            args: 3..=3,
+            usage: "set rgb <r> <g> <b>",
+            help: "set all three levels at once",
+            parse: |words| {
+                let red = parse_level(words.next(), LEVELS)?;
+                let green = parse_level(words.next(), LEVELS)?;
+                let blue = parse_level(words.next(), LEVELS)?;
+                Ok(LevelCommand::SetRgb([red, green, blue]).into())
+            },
+        },
+    ];
+}
+
+/// Look up a channel by name
+///
+/// # Arguments
+/// * `name` - Channel name (`r`, `red`, `g`, `green`, `b` or `blue`)
+///
+/// # Returns
+/// Channel index (0=red, 1=green, 2=blue), or `None` if unknown
+pub fn parse_channel(name: &str) -> Option<usize> {
+    CHANNEL_NAMES.iter().position(|names| names.contains(&name))
+}
+
+/// Convert a brightness percentage to the nearest level
+///
+/// # Arguments
+/// * `percent` - Brightness in percent (clamped to 100)
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Level from 0 to levels-1
+pub fn percent_to_level(percent: u32, levels: u32) -> u32 {
+    (percent.min(100) * (levels - 1) + 50) / 100
+}
+
+/// Convert a level to the nearest brightness percentage
+///
+/// # Arguments
+/// * `level` - Level from 0 to levels-1
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// Brightness in percent (0-100)
+pub fn level_to_percent(level: u32, levels: u32) -> u32 {
+    (level * 100 + (levels - 1) / 2) / (levels - 1)
+}
+
+/// Parse a brightness level argument
+///
+/// Accepts either a raw level (`12`) or a percentage of full brightness
+/// (`80%`). Percentages above 100% are treated as 100%.
+///
+/// # Arguments
+/// * `word` - Next argument word, if any
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// The level value; raw levels are not yet clamped
+pub fn parse_level(word: Option<&str>, levels: u32) -> Result<u32, ParseError> {
+    let word = word.ok_or(ParseError::MissingArgument)?;
+    match word.strip_suffix('%') {
+        Some(percent) => percent
+            .parse()
+            .map(|percent| percent_to_level(percent, levels))
+            .map_err(|_| ParseError::InvalidNumber),
+        None => word.parse().map_err(|_| ParseError::InvalidNumber),
+    }
+}
+
+/// Parse a channel and its level, as in `set <r|g|b> <level>`
+///
+/// # Arguments
+/// * `words` - Channel name and level words
+/// * `levels` - Number of brightness levels
+///
+/// # Returns
+/// The set-level command; an unknown channel is an unknown command, so
+/// stray words are not taken for the `<r|g|b> <level>` shorthand
+fn parse_set_level(words: &mut Words, levels: u32) -> Result<LevelCommand, ParseError> {
+    let channel = words
+        .next()
+        .and_then(parse_channel)
+        .ok_or(ParseError::UnknownCommand)?;
+    let level = parse_level(words.next(), levels)?;
+    Ok(LevelCommand::SetLevel { channel, level })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<LevelCommand, ParseError> {
+        parse_line(&[LevelSyntax::<LevelCommand, 16>::TABLE], line)
+    }
+
+    fn set_level(channel: usize, level: u32) -> Result<LevelCommand, ParseError> {
+        Ok(LevelCommand::SetLevel { channel, level })
+    }
+
+    fn set_rate(rate: u64, unit: Option<FrameRateUnit>) -> Result<LevelCommand, ParseError> {
+        Ok(LevelCommand::SetRate { rate, unit })
+    }
+
+    #[test]
+    fn valid_lines() {
+        assert_eq!(parse("set r 12"), set_level(0, 12));
+        assert_eq!(parse("  set   green 3 "), set_level(1, 3));
+        assert_eq!(parse("b 15"), set_level(2, 15));
+        assert_eq!(parse("red 0"), set_level(0, 0));
+        assert_eq!(parse("g 50%"), set_level(1, 8));
+        assert_eq!(
+            parse("set rgb 1 2 100%"),
+            Ok(LevelCommand::SetRgb([1, 2, 15]))
+        );
+        assert_eq!(parse("fps 120"), set_rate(120, Some(FrameRateUnit::Scans)));
+        assert_eq!(parse("set rate 240"), set_rate(240, None));
+        assert_eq!(
+            parse("adjust blue -2"),
+            Ok(LevelCommand::AdjustLevel {
+                channel: 2,
+                delta: -2
+            })
+        );
+        assert_eq!(parse("adjust fps 10"), Ok(LevelCommand::AdjustRate(10)));
+    }
+
+    #[test]
+    fn out_of_range_values_are_kept_for_clamping() {
+        // Levels above the top and frame rates outside the valid range
+        // parse as given; the command applying them clamps
+        assert_eq!(parse("g 16"), set_level(1, 16));
+        assert_eq!(parse("set b 4000000000"), set_level(2, 4_000_000_000));
+        assert_eq!(parse("r 250%"), set_level(0, 15));
+        assert_eq!(parse("fps 5"), set_rate(5, Some(FrameRateUnit::Scans)));
+        assert_eq!(
+            parse("fps 1001"),
+            set_rate(1001, Some(FrameRateUnit::Scans))
+        );
+        assert_eq!(parse("set rate 0"), set_rate(0, None));
+        // Beyond the argument types they are not numbers at all
+        assert_eq!(parse("g -1"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("r 5000000000"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("fps -1"), Err(ParseError::InvalidNumber));
+        assert_eq!(
+            parse("fps 99999999999999999999999"),
+            Err(ParseError::InvalidNumber)
+        );
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(parse("x 12"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("r g b a"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("r"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("set x 12"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("fps"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("fps 120 130"), Err(ParseError::ExtraArgument));
+        assert_eq!(parse("fps fast"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("g 5x"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("g x%"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("set rgb 1 2"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("adjust w 1"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("adjust r up"), Err(ParseError::InvalidNumber));
+    }
+
+    #[test]
+    fn levels_follow_the_level_count() {
+        let parse = |line| parse_line(&[LevelSyntax::<LevelCommand, 4>::TABLE], line);
+        assert_eq!(parse("r 100%"), set_level(0, 3));
+        assert_eq!(parse("r 50%"), set_level(0, 2));
+        assert_eq!(parse("r 16"), set_level(0, 16));
+    }
+
+    #[test]
+    fn percentages_round_trip() {
+        // Every level has its own percentage up to 101 levels
+        for levels in [2, 4, 16, 64, 101] {
+            for level in 0..levels {
+                let percent = level_to_percent(level, levels);
+                assert_eq!(percent_to_level(percent, levels), level, "{levels} levels");
+            }
+            assert_eq!(percent_to_level(0, levels), 0);
+            assert_eq!(percent_to_level(100, levels), levels - 1);
+        }
+    }
+}
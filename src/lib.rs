@@ -0,0 +1,43 @@
+//! RGB LED Calibration Core
+//!
+//! The hardware-free part of the calibration tool: the calibration math,
+//! the command grammar with the level and frame rate commands, the button
+//! gesture decoder, the flash record format, the scan scheduling, the
+//! strobe guard, the thermal derating model and the time source
+//! abstraction. The firmware binary re-exports all of it, so its modules
+//! use these items as if they were its own.
+//!
+//! Nothing here touches a peripheral, so besides the firmware the library
+//! builds for the host, where its unit tests run:
+//!
+//! ```text
+//! cargo test --lib --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! (substitute your host's target triple; `.cargo/config.toml` builds for
+//! the MicroBit by default).
+
+#![cfg_attr(not(test), no_std)]
+
+mod calib;
+mod config_error;
+mod gesture;
+mod grammar;
+mod level_commands;
+mod scan;
+mod storage;
+mod strobe;
+mod thermal;
+mod time_source;
+pub use calib::*;
+pub use config_error::*;
+pub use gesture::*;
+pub use grammar::*;
+pub use level_commands::*;
+pub use scan::*;
+pub use storage::*;
+pub use strobe::*;
+pub use thermal::*;
+pub use time_source::*;
+
+use embassy_time::{Duration, Instant, Timer};
@@ -0,0 +1,123 @@
+//! Output Lookup Tables
+//!
+//! Each channel has a lookup table that maps the corrected level to the level
+//! actually driven. Tables can be computed on a host (gamma, photometric or
+//! per-LED fits) and uploaded with the `set lut` command, and read back with
+//! `get lut`. The tables are part of the calibration config, so `save`
+//! keeps them in flash and they are back after a reset; without a saved
+//! calibration every channel starts at the identity table, and the gamma
+//! duty table (see `gamma`) alone shapes the output.
+//!
+//! The device can also build a knee curve itself (`set lut <r|g|b> knee`):
+//! a power curve below a knee level that stays linear above it. Unlike a full
+//! gamma curve it keeps every step at the bright end, where white is tuned,
+//! while the dim end rises gently.
+//!
+//! # Minimum Visible Level
+//! Each die first emits visible light at a different level, so the lowest
+//! few levels of a channel may all look off. After the lookup table, the
+//! output levels 1 to LEVELS-1 are spread over the channel's minimum visible
+//! level to LEVELS-1 (see [`remap_visible`]), so level 1 is always visibly
+//! on. The thresholds are found with the guided `visible` routine and travel
+//! with the calibration config.
+
+use crate::*;
+
+/// Number of entries in a lookup table (one per level)
+pub const LUT_LEN: usize = LEVELS as usize;
+
+/// Default exponent of the knee curve below the knee
+pub const KNEE_POWER: u32 = 2;
+
+/// Reasons a lookup table was rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LutError {
+    /// An entry was above LEVELS-1
+    OutOfRange,
+    /// An entry was smaller than the one before it
+    NotMonotonic,
+}
+
+/// Per-channel level lookup table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lut([u32; LUT_LEN]);
+
+impl Lut {
+    /// Table that leaves every level unchanged
+    pub const IDENTITY: Self = {
+        let mut table = [0; LUT_LEN];
+        let mut level = 0;
+        while level < LUT_LEN {
+            table[level] = level as u32;
+            level += 1;
+        }
+        Lut(table)
+    };
+
+    /// Build a table from uploaded values
+    ///
+    /// Entries must be valid levels and must not decrease, so brighter
+    /// requests never produce dimmer output.
+    ///
+    /// # Arguments
+    /// * `table` - Output level for each input level
+    ///
+    /// # Returns
+    /// The table, or the reason it was rejected
+    pub fn new(table: [u32; LUT_LEN]) -> Result<Self, LutError> {
+        if table.iter().any(|&level| level >= LEVELS) {
+            return Err(LutError::OutOfRange);
+        }
+        if table.windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(LutError::NotMonotonic);
+        }
+        Ok(Lut(table))
+    }
+
+    /// Build a knee curve
+    ///
+    /// Each entry is [`knee_curve`] of its level: a power curve below the
+    /// knee that is the identity from the knee up, continuous and
+    /// monotonic.
+    ///
+    /// # Arguments
+    /// * `knee` - Level where the curve turns linear (clamped to LEVELS-1)
+    /// * `power` - Exponent below the knee (clamped to 1..=KNEE_MAX_POWER)
+    ///
+    /// # Returns
+    /// The table
+    pub fn knee(knee: u32, power: u32) -> Self {
+        let mut table = Self::IDENTITY.0;
+        for entry in table.iter_mut() {
+            *entry = knee_curve(*entry, knee, power, LEVELS);
+        }
+        Lut(table)
+    }
+
+    /// Whether this is the identity table
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Entries of the table, indexed by input level
+    pub fn entries(&self) -> &[u32; LUT_LEN] {
+        &self.0
+    }
+
+    /// Look up the output level for an input level
+    ///
+    /// # Arguments
+    /// * `level` - Input level (clamped to LEVELS-1)
+    ///
+    /// # Returns
+    /// Output level (0 to LEVELS-1)
+    pub fn apply(&self, level: u32) -> u32 {
+        self.0[level.min(LEVELS - 1) as usize]
+    }
+}
+
+impl Default for Lut {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
@@ -7,117 +7,256 @@
 //!
 //! # Hardware Setup
 //! - RGB LED connected to pins P9 (red), P8 (green), P16 (blue)
-//! - Potentiometer connected to P2 for analog input
+//! - Potentiometer connected to P2 for analog input, or (with the `encoder`
+//!   feature) a rotary encoder on P13/P14
 //! - Uses MicroBit v2 buttons A and B for mode selection
 //!
+//! All pin assignments live in the `board` module.
+//!
 //! # Architecture
-//! The application uses Embassy async framework with two concurrent tasks:
+//! The application uses Embassy async framework with four concurrent tasks:
 //! - RGB task: Handles time-division multiplexing of LED colors
 //! - UI task: Processes user input from knob and buttons
+//! - Command task: Applies text commands sent by the host over RTT, or
+//!   over the USB serial port with the `serial` feature
+//! - Telemetry task: Streams binary state packets over RTT when enabled,
+//!   alongside the periodic status line with the `heartbeat` feature
+//!
+//! The tasks communicate through one [`SharedState`] (see the `state`
+//! module), created in `main` and handed to each of them by reference,
+//! which also publishes every committed change to subscribers.
 
 #![no_std]
 #![no_main]
 
+mod animation;
+mod board;
+mod boot;
+mod clock;
+mod color_space;
+mod colors;
+mod commands;
+mod compare;
+mod config;
+mod correction;
+mod encoder;
+mod entry;
+mod error;
+#[cfg(feature = "heartbeat")]
+mod heartbeat;
+mod history;
+mod indicator;
+mod input;
 mod knob;
+mod lut;
+mod output;
+mod packet;
+mod pattern;
+mod persist;
+mod power;
+mod presets;
 mod rgb;
+mod sense;
+mod sequence;
+#[cfg(feature = "serial")]
+mod serial;
+mod settings;
+mod state;
+mod stats;
+mod telemetry;
 mod ui;
+mod watchdog;
+mod wizard;
+pub use animation::*;
+pub use board::*;
+pub use boot::*;
+pub use clock::*;
+pub use color_space::*;
+pub use colors::*;
+pub use commands::*;
+pub use compare::*;
+pub use config::*;
+pub use correction::*;
+pub use encoder::*;
+pub use entry::*;
+pub use error::*;
+#[cfg(feature = "heartbeat")]
+pub use heartbeat::*;
+pub use history::*;
+pub use indicator::*;
+pub use input::*;
 pub use knob::*;
+pub use lut::*;
+pub use output::*;
+pub use packet::*;
+pub use pattern::*;
+pub use persist::*;
+pub use power::*;
+pub use presets::*;
 pub use rgb::*;
+pub use sense::*;
+pub use sequence::*;
+#[cfg(feature = "serial")]
+pub use serial::*;
+pub use settings::*;
+pub use state::*;
+pub use stats::*;
+pub use telemetry::*;
 pub use ui::*;
+pub use watchdog::*;
+pub use wizard::*;
+
+// Hardware-free calibration core, shared with the host tests
+pub use mb2_embassy_rgb::*;
 
 // Panic handler for embedded environment
 use panic_rtt_target as _;
-// RTT (Real-Time Transfer) for debug printing over probe
-use rtt_target::{rprintln, rtt_init_print};
+// RTT (Real-Time Transfer) for debug printing over probe; with the
+// `serial` feature the prints go to the serial console instead
+#[cfg(not(feature = "serial"))]
+use rtt_target::{rprint, rprintln};
+use rtt_target::{rtt_init, set_print_channel};
 
 // Embassy async runtime for embedded systems
 use embassy_executor::Spawner;
 use embassy_futures::join;
 // Synchronization primitives for sharing data between async tasks
 use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 // MicroBit hardware abstraction layer
 use microbit_bsp::{
     embassy_nrf::{
         bind_interrupts,
         gpio::{AnyPin, Level, Output, OutputDrive},
+        nvmc,  // Flash controller for the saved calibration
         saadc, // Successive Approximation ADC for analog input
+        temp,  // On-chip die temperature sensor
     },
     Button, Microbit,
 };
 use num_traits::float::FloatCore;
 
-/// Global shared state for RGB brightness levels [red, green, blue]
-/// Protected by mutex for safe access between async tasks
-pub static RGB_LEVELS: Mutex<ThreadModeRawMutex, [u32; 3]> = Mutex::new([0; 3]);
-
-/// Global shared state for frame rate (frames per second)
-/// Protected by mutex for safe access between async tasks
-pub static FRAME_RATE: Mutex<ThreadModeRawMutex, u64> = Mutex::new(100);
-
 /// Number of brightness levels per color (0-15, giving 16 total levels)
-pub const LEVELS: u32 = 16;
-
-/// Safely read the current RGB brightness levels from shared state
 ///
-/// Returns: Array of [red, green, blue] brightness values (0-15)
-async fn get_rgb_levels() -> [u32; 3] {
-    let rgb_levels = RGB_LEVELS.lock().await;
-    *rgb_levels
-}
-
-/// Safely modify the RGB brightness levels in shared state
-///
-/// # Arguments
-/// * `setter` - Closure that modifies the RGB levels array
-async fn set_rgb_levels<F>(setter: F)
-where
-    F: FnOnce(&mut [u32; 3]),
-{
-    let mut rgb_levels = RGB_LEVELS.lock().await;
-    setter(&mut rgb_levels);
-}
-
-/// Safely read the current frame rate from shared state
+/// Everything else is derived from this: the frame timing, the knob
+/// scaling (its deadbands are fractions of travel, not of levels), the
+/// lookup tables and the config blob. One literal table holds one entry per
+/// level and must be regenerated from the formula in its doc comment when
+/// this changes: `SRGB_TO_LINEAR` (color_space.rs). [`GAMMA_DUTY_TABLE`]
+/// follows at compile time.
 ///
-/// Returns: Current frame rate in frames per second
-async fn get_frame_rate() -> u64 {
-    let frame_rate = FRAME_RATE.lock().await;
-    *frame_rate
-}
+/// Practical limits: levels are stored in a byte, so at most 256. Each level
+/// is one timer tick of 1_000_000 / (3 * LEVELS * fps) µs, rounded to whole
+/// microseconds: 208 µs with 16 levels at 100 fps, 33 µs with 64 levels at
+/// 160 fps. Past a few hundred levels ticks get short enough that rounding
+/// visibly shifts the frame rate and the timer wake-up latency (tens of
+/// µs) dominates the dim levels; ticks under 1 µs round to 0 and the scan
+/// free-runs.
+pub const LEVELS: u32 = 16;
 
-/// Safely modify the frame rate in shared state
-///
-/// # Arguments
-/// * `new_rate` - New frame rate in frames per second
-async fn set_frame_rate(new_rate: u64) {
-    let mut frame_rate = FRAME_RATE.lock().await;
-    *frame_rate = new_rate;
-}
+const _: () = assert!(LEVELS >= 2 && LEVELS <= 256, "LEVELS must be 2 to 256");
 
 /// Main entry point for the RGB LED calibration application
 ///
-/// Sets up hardware peripherals and launches concurrent RGB and UI tasks.
-/// The function never returns (indicated by `!` return type).
+/// Sets up hardware peripherals and launches concurrent RGB, UI and command
+/// tasks. The function never returns (indicated by `!` return type).
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) -> ! {
-    // Initialize RTT for debug output
-    rtt_init_print!();
-    // Get default MicroBit hardware configuration
-    let board = Microbit::default();
+    // The state shared between the tasks, only reachable through the
+    // handles given out below
+    static SHARED_STATE: SharedState = SharedState::new();
+    let shared = &SHARED_STATE;
 
-    // Bind SAADC interrupt handler for ADC conversions
+    // Initialize RTT: up channels for debug output and binary telemetry,
+    // down channel for host commands
+    let channels = rtt_init! {
+        up: {
+            0: {
+                size: 1024,
+                name: "Terminal"
+            }
+            1: {
+                size: 1024,
+                name: "Telemetry"
+            }
+            2: {
+                size: 64,
+                name: "Control"
+            }
+        }
+        down: {
+            0: {
+                size: 64,
+                name: "Terminal"
+            }
+            1: {
+                size: 64,
+                name: "Control"
+            }
+        }
+    };
+    set_print_channel(channels.up.0);
+    // Get MicroBit hardware with crystal-accurate clocks for precise timing
+    let board = Microbit::new(clock_config());
+    // Claimed once here, before anything else could, so always present
+    let Some(mut clock_registers) = ClockRegisters::take() else {
+        panic!("clock registers already claimed");
+    };
+    let precise_clock = check_clock_sources(&clock_registers);
+    // Assign the pins to their functions (see the `board` module)
+    let board = BoardPins::new(board);
+
+    // Bind the SAADC and die temperature interrupt handlers
     bind_interrupts!(struct Irqs {
         SAADC => saadc::InterruptHandler;
+        TEMP => temp::InterruptHandler;
     });
 
-    // Configure GPIO pins for RGB LED control (active high, standard drive)
-    let led_pin = |p| Output::new(p, Level::Low, OutputDrive::Standard);
-    let red = led_pin(AnyPin::from(board.p9)); // Red LED on pin P9
-    let green = led_pin(AnyPin::from(board.p8)); // Green LED on pin P8
-    let blue = led_pin(AnyPin::from(board.p16)); // Blue LED on pin P16
-                                                 // Create RGB controller with 100 fps initial frame rate
-    let rgb: Rgb = Rgb::new([red, green, blue], 100);
+    // Configure GPIO pins for RGB LED control as the board's output
+    // profiles say (by default active high, standard drive), all off
+    let [red, green, blue] = LED_OUTPUTS;
+    let mut pins = [
+        red.output(board.red),
+        green.output(board.green),
+        blue.output(board.blue),
+    ];
+    let names = ["red", "green", "blue"];
+    for (name, profile) in names.iter().zip(LED_OUTPUTS) {
+        if profile != OutputProfile::DIRECT {
+            let [polarity, idle, drive] = profile.names();
+            rprintln!("led output {}: {} {} {}", name, polarity, idle, drive);
+        }
+    }
+
+    // Pick the boot mode from the buttons held at power-up
+    let boot_mode = BootMode::from_buttons(&board.btn_a, &board.btn_b);
+    rprintln!("boot mode: {}", boot_mode.name());
+    if boot_mode == BootMode::SelfTest {
+        self_test(&mut pins).await;
+    }
+
+    // Load the saved calibration from its flash page: it sets the whole
+    // calibration now, and the UI starts at its levels and frame rate
+    let mut storage = Storage::new(nvmc::Nvmc::new(board.nvmc), STORAGE_PAGE);
+    let saved = load_calibration(&mut storage);
+    let startup = match saved {
+        Some(config) => {
+            shared.set_calibration_config(config).await;
+            Startup {
+                levels: Some(config.levels),
+                frame_rate: Some(config.frame_rate),
+            }
+        }
+        None => STARTUP,
+    };
+    // The presets and other settings are kept in a record of their own
+    if let Some(settings) = load_settings(&mut storage) {
+        shared.set_saved_settings(settings).await;
+    }
+    // Holding A+B saves the calibration the UI starts with
+    if boot_mode == BootMode::Save {
+        shared.request_save();
+    }
 
     // Configure ADC for potentiometer reading with 14-bit resolution
     let mut saadc_config = saadc::Config::default();
@@ -126,15 +265,114 @@ async fn main(_spawner: Spawner) -> ! {
         board.saadc,
         Irqs,
         saadc_config,
-        [saadc::ChannelConfig::single_ended(board.p2)], // Potentiometer on P2
+        [
+            saadc::ChannelConfig::single_ended(board.knob),
+            #[cfg(feature = "led-sense")]
+            saadc::ChannelConfig::single_ended(board.sense),
+        ],
     );
-    // Create knob interface with calibrated ADC
-    let knob = Knob::new(saadc).await;
+    // Create knob interface and calibrate its ADC, carrying on with an
+    // uncalibrated one if that fails
+    let mut knob = Knob::new(shared, saadc, temp::Temp::new(board.temp, Irqs)).await;
+    if let Err(err) = knob.calibrate().await {
+        rprintln!(
+            "knob: error E{}: {}, readings may be off",
+            err.code(),
+            err.message()
+        );
+    }
+
+    // Check for the optional LED current sense resistor while the LEDs are
+    // still ours, so a board without one doesn't report every LED dead
+    #[cfg(feature = "led-sense")]
+    match knob.probe_sense(&mut pins).await {
+        Ok(()) => rprintln!("led sense: available"),
+        Err(err) => {
+            shared.disable_led_sense().await;
+            rprintln!(
+                "led sense: error E{}: {}, failed-open detection off",
+                err.code(),
+                err.message()
+            );
+        }
+    }
+
+    // Create RGB controller at the saved frame rate (100 fps without one),
+    // correcting its timing if the timer clock is not crystal-accurate
+    let rgb: Rgb = Rgb::new(shared, pins, startup.frame_rate.unwrap_or(100))
+        .with_outputs(LED_OUTPUTS)
+        .with_duty_tables(LED_DUTY_TABLES)
+        .with_timer_correction(timer_correction(&mut clock_registers, precise_clock));
+    // Start the watchdog last, as the slow setup above doesn't feed it; the
+    // scan loop feeds it from here on
+    let rgb = match start_watchdog(board.wdt) {
+        Some(watchdog) => rgb.with_watchdog(watchdog),
+        None => rgb,
+    };
+
+    // Create host command reader on the RTT down channel, or the serial
+    // console's UART, also serving the binary control protocol on its own
+    // RTT channel pair and saving the calibration to flash
+    #[cfg(not(feature = "serial"))]
+    let input = channels.down.0;
+    #[cfg(feature = "serial")]
+    let (serial, input) = Serial::new(board.uarte, board.uart_rx, board.uart_tx);
+    let control = ControlLink::new(shared, channels.down.1, channels.up.2);
+    let commands = Commands::new(shared, input)
+        .with_control(control)
+        .with_storage(storage, saved);
+    // With the serial console built in, its UART driver shares the command
+    // task's slot
+    #[cfg(feature = "serial")]
+    let commands = async { join::join(commands.run(), serial.run()).await.0 };
+    #[cfg(not(feature = "serial"))]
+    let commands = commands.run();
+    // Create binary telemetry writer on its own RTT channel
+    let telemetry = Telemetry::new(shared, channels.up.1);
+    // With the heartbeat built in, it shares the telemetry task's slot
+    #[cfg(feature = "heartbeat")]
+    let telemetry = async {
+        join::join(telemetry.run(), Heartbeat::new(shared).run())
+            .await
+            .0
+    };
+    #[cfg(not(feature = "heartbeat"))]
+    let telemetry = telemetry.run();
+
+    // Create the color sequence player, idle until asked to play
+    let sequencer = Sequencer::new(shared);
+    // Create the matrix indicator, showing the knob mode until changed; it
+    // shares the sequencer's slot
+    let sequencer = async {
+        join::join(sequencer.run(), Indicator::new(shared, board.display).run())
+            .await
+            .0
+    };
+
+    // With a rotary encoder fitted, it replaces the knob and its decoder
+    // runs as an extra task
+    #[cfg(feature = "encoder")]
+    {
+        // The knob is not read with an encoder fitted
+        let _ = knob;
+        let decoder = EncoderDecoder::new(shared, board.encoder_a, board.encoder_b);
+        let mut ui =
+            Ui::new(shared, Encoder::new(shared), board.btn_a, board.btn_b).with_startup(startup);
+        join::join(
+            join::join5(rgb.run(), ui.run(), commands, telemetry, sequencer),
+            decoder.run(),
+        )
+        .await;
+    }
+
     // Create UI handler with knob and button inputs
-    let mut ui = Ui::new(knob, board.btn_a, board.btn_b);
+    #[cfg(not(feature = "encoder"))]
+    let mut ui = Ui::new(shared, knob, board.btn_a, board.btn_b).with_startup(startup);
 
-    // Run RGB scanning and UI tasks concurrently - this never returns
-    join::join(rgb.run(), ui.run()).await;
+    // Run RGB scanning, UI, command, telemetry, sequencer and indicator tasks
+    // concurrently - this never returns
+    #[cfg(not(feature = "encoder"))]
+    join::join5(rgb.run(), ui.run(), commands, telemetry, sequencer).await;
 
     // Should never reach here
     panic!("fell off end of main loop");
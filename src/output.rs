@@ -0,0 +1,152 @@
+//! LED Output Profiles
+//!
+//! By default each LED hangs straight off its GPIO pin: high lights it, low
+//! turns it off, at standard drive. A brighter LED than a pin can source
+//! needs an external switch (a transistor or MOSFET), which may invert the
+//! signal, may want its input released rather than driven while off (when
+//! it has its own pull resistor), and may need more drive to switch fast.
+//!
+//! An [`OutputProfile`] describes all three per channel. The profiles are
+//! wiring, so they are set with the pins in the `board` module
+//! ([`LED_OUTPUTS`]); every place that switches an LED goes through
+//! [`OutputProfile::set`], so the scan, the self-test and the sense probe
+//! all work whichever way the LEDs are driven.
+
+use crate::*;
+
+/// Pin level that lights the LED
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Polarity {
+    /// High lights the LED (direct drive, NPN or N-MOSFET low-side switch)
+    #[default]
+    ActiveHigh,
+    /// Low lights the LED (PNP or P-MOSFET high-side switch)
+    ActiveLow,
+}
+
+/// What the pin does while the LED is off
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Idle {
+    /// Drive the off level
+    #[default]
+    Driven,
+    /// Release the pin (high impedance), leaving the off level to the
+    /// switch's pull resistor
+    Floating,
+}
+
+/// Pin drive strength while driving
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DriveStrength {
+    /// Standard drive (a few mA)
+    #[default]
+    Standard,
+    /// High drive, for faster edges into a switch's gate or base
+    High,
+}
+
+/// How one LED channel's pin is driven
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutputProfile {
+    /// Pin level that lights the LED
+    pub polarity: Polarity,
+    /// What the pin does while the LED is off
+    pub idle: Idle,
+    /// Drive strength
+    pub drive: DriveStrength,
+}
+
+impl OutputProfile {
+    /// LED driven directly by the pin: active high, driven low when off,
+    /// standard drive
+    pub const DIRECT: Self = OutputProfile {
+        polarity: Polarity::ActiveHigh,
+        idle: Idle::Driven,
+        drive: DriveStrength::Standard,
+    };
+
+    /// Pin level that lights the LED
+    pub fn on_level(self) -> Level {
+        match self.polarity {
+            Polarity::ActiveHigh => Level::High,
+            Polarity::ActiveLow => Level::Low,
+        }
+    }
+
+    /// Pin level that turns the LED off (or that the pull resistor
+    /// provides while floating)
+    pub fn off_level(self) -> Level {
+        match self.polarity {
+            Polarity::ActiveHigh => Level::Low,
+            Polarity::ActiveLow => Level::High,
+        }
+    }
+
+    /// Pin drive configuration for this profile
+    ///
+    /// A floating idle disconnects the pin at the off level; the on level
+    /// is always driven at the chosen strength.
+    pub fn pin_drive(self) -> OutputDrive {
+        match (self.polarity, self.idle, self.drive) {
+            (_, Idle::Driven, DriveStrength::Standard) => OutputDrive::Standard,
+            (_, Idle::Driven, DriveStrength::High) => OutputDrive::HighDrive,
+            (Polarity::ActiveHigh, Idle::Floating, DriveStrength::Standard) => {
+                OutputDrive::Disconnect0Standard1
+            }
+            (Polarity::ActiveHigh, Idle::Floating, DriveStrength::High) => {
+                OutputDrive::Disconnect0HighDrive1
+            }
+            (Polarity::ActiveLow, Idle::Floating, DriveStrength::Standard) => {
+                OutputDrive::Standard0Disconnect1
+            }
+            (Polarity::ActiveLow, Idle::Floating, DriveStrength::High) => {
+                OutputDrive::HighDrive0Disconnect1
+            }
+        }
+    }
+
+    /// Configure a pin as this channel's LED output, with the LED off
+    ///
+    /// # Arguments
+    /// * `pin` - LED pin
+    ///
+    /// # Returns
+    /// The output pin
+    pub fn output(self, pin: AnyPin) -> Output<'static, AnyPin> {
+        Output::new(pin, self.off_level(), self.pin_drive())
+    }
+
+    /// Turn the LED on or off
+    ///
+    /// # Arguments
+    /// * `pin` - Output pin configured with [`Self::output`]
+    /// * `on` - `true` to light the LED
+    pub fn set(self, pin: &mut Output<'static, AnyPin>, on: bool) {
+        pin.set_level(if on {
+            self.on_level()
+        } else {
+            self.off_level()
+        });
+    }
+
+    /// Short description for log output, e.g. `active-low floating high`
+    ///
+    /// # Returns
+    /// Names of the polarity, idle behavior and drive strength
+    pub fn names(self) -> [&'static str; 3] {
+        [
+            match self.polarity {
+                Polarity::ActiveHigh => "active-high",
+                Polarity::ActiveLow => "active-low",
+            },
+            match self.idle {
+                Idle::Driven => "driven",
+                Idle::Floating => "floating",
+            },
+            match self.drive {
+                DriveStrength::Standard => "standard",
+                DriveStrength::High => "high",
+            },
+        ]
+    }
+}
@@ -0,0 +1,349 @@
+//! Binary Control Protocol
+//!
+//! A framed, checksummed alternative to the text commands for automated test
+//! rigs. Frames arrive on their own RTT down channel ("Control", channel 1)
+//! and every frame is answered on RTT up channel 2 ("Control").
+//!
+//! # Request Frame
+//!
+//! | Offset  | Type      | Field                                       |
+//! |---------|-----------|---------------------------------------------|
+//! | 0       | u8        | Start byte, always [`START`] (0x7E)         |
+//! | 1       | u8        | Command id (see below)                      |
+//! | 2       | u8        | Payload length N (at most [`MAX_PAYLOAD`])  |
+//! | 3       | u8 x N    | Payload                                     |
+//! | 3 + N   | u8        | CRC-8 of bytes 1 to 2 + N (see [`crc8`])    |
+//!
+//! | Id     | Command         | Payload                                  |
+//! |--------|-----------------|------------------------------------------|
+//! | 0x01   | Set levels      | red, green, blue (u8 each, 0-15)         |
+//! | 0x02   | Set frame rate  | fps (u16, little-endian)                 |
+//! | 0x03   | Set both        | red, green, blue, fps (as above)         |
+//!
+//! # Reply Frame
+//! `[START, ACK (0x06), command id, crc]` when the command was applied, or
+//! `[START, NAK (0x15), reason, crc]` when it was rejected, with the CRC
+//! over the middle two bytes. Reasons are the [`FrameError`] codes.
+//!
+//! The parser drops bytes until it sees a start byte, so after a corrupted
+//! frame it resynchronizes on the next one.
+
+use crate::*;
+
+use rtt_target::{DownChannel, UpChannel};
+
+/// Byte starting every frame
+pub const START: u8 = 0x7e;
+
+/// Reply code for an applied command
+pub const ACK: u8 = 0x06;
+
+/// Reply code for a rejected frame
+pub const NAK: u8 = 0x15;
+
+/// Largest payload accepted
+pub const MAX_PAYLOAD: usize = 8;
+
+/// Command id: set the three levels
+const SET_LEVELS: u8 = 0x01;
+
+/// Command id: set the frame rate
+const SET_FRAME_RATE: u8 = 0x02;
+
+/// Command id: set the levels and the frame rate
+const SET_ALL: u8 = 0x03;
+
+/// CRC-8 with polynomial 0x07 and initial value 0 (CRC-8/SMBUS)
+///
+/// # Arguments
+/// * `bytes` - Data to check
+///
+/// # Returns
+/// The checksum
+pub fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Reason a frame was rejected, sent as the NAK code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameError {
+    /// Checksum did not match
+    BadCrc = 1,
+    /// Command id not recognized
+    UnknownCommand = 2,
+    /// Payload length wrong for the command or above `MAX_PAYLOAD`
+    BadLength = 3,
+    /// A level or the frame rate was outside the accepted range
+    OutOfRange = 4,
+}
+
+/// Checked frame as received
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Command id
+    pub id: u8,
+    /// Payload storage; only the first `len` bytes are used
+    payload: [u8; MAX_PAYLOAD],
+    /// Payload length
+    len: usize,
+}
+
+impl Frame {
+    /// Payload bytes
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.len]
+    }
+}
+
+/// Command carried by a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostPacket {
+    /// Set [red, green, blue] levels
+    SetLevels([u32; 3]),
+    /// Set the frame rate in fps
+    SetFrameRate(u64),
+    /// Set levels and frame rate together
+    SetAll([u32; 3], u64),
+}
+
+impl HostPacket {
+    /// Decode a checked frame
+    ///
+    /// # Arguments
+    /// * `frame` - Frame with a valid checksum
+    ///
+    /// # Returns
+    /// The command, or why it was rejected
+    pub fn decode(frame: &Frame) -> Result<Self, FrameError> {
+        let payload = frame.payload();
+        let levels = |bytes: &[u8]| -> Result<[u32; 3], FrameError> {
+            let levels = [bytes[0], bytes[1], bytes[2]].map(u32::from);
+            if levels.iter().any(|&level| level >= LEVELS) {
+                return Err(FrameError::OutOfRange);
+            }
+            Ok(levels)
+        };
+        let frame_rate = |bytes: &[u8]| -> Result<u64, FrameError> {
+            let frame_rate = u16::from_le_bytes([bytes[0], bytes[1]]) as u64;
+            if !(MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&frame_rate) {
+                return Err(FrameError::OutOfRange);
+            }
+            Ok(frame_rate)
+        };
+        match (frame.id, payload.len()) {
+            (SET_LEVELS, 3) => Ok(HostPacket::SetLevels(levels(payload)?)),
+            (SET_FRAME_RATE, 2) => Ok(HostPacket::SetFrameRate(frame_rate(payload)?)),
+            (SET_ALL, 5) => Ok(HostPacket::SetAll(
+                levels(&payload[..3])?,
+                frame_rate(&payload[3..])?,
+            )),
+            (SET_LEVELS | SET_FRAME_RATE | SET_ALL, _) => Err(FrameError::BadLength),
+            _ => Err(FrameError::UnknownCommand),
+        }
+    }
+}
+
+/// Position of the parser within a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParseState {
+    /// Waiting for a start byte
+    Start,
+    /// Expecting the command id
+    Id,
+    /// Expecting the payload length
+    Len,
+    /// Receiving the payload
+    Payload,
+    /// Expecting the checksum
+    Crc,
+}
+
+/// Byte-at-a-time frame parser
+pub struct FrameParser {
+    /// Where in a frame the next byte belongs
+    state: ParseState,
+    /// Frame being received
+    frame: Frame,
+    /// Payload bytes received so far
+    received: usize,
+}
+
+impl FrameParser {
+    /// Create a parser waiting for a start byte
+    pub fn new() -> Self {
+        Self {
+            state: ParseState::Start,
+            frame: Frame {
+                id: 0,
+                payload: [0; MAX_PAYLOAD],
+                len: 0,
+            },
+            received: 0,
+        }
+    }
+
+    /// Feed one received byte
+    ///
+    /// # Arguments
+    /// * `byte` - Next byte from the host
+    ///
+    /// # Returns
+    /// A complete frame with a valid checksum, the error if a frame was
+    /// rejected, or `None` while a frame is still incomplete
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame, FrameError>> {
+        match self.state {
+            ParseState::Start => {
+                if byte == START {
+                    self.state = ParseState::Id;
+                }
+            }
+            ParseState::Id => {
+                self.frame.id = byte;
+                self.state = ParseState::Len;
+            }
+            ParseState::Len => {
+                let len = byte as usize;
+                if len > MAX_PAYLOAD {
+                    self.state = ParseState::Start;
+                    return Some(Err(FrameError::BadLength));
+                }
+                self.frame.len = len;
+                self.received = 0;
+                self.state = if len == 0 {
+                    ParseState::Crc
+                } else {
+                    ParseState::Payload
+                };
+            }
+            ParseState::Payload => {
+                self.frame.payload[self.received] = byte;
+                self.received += 1;
+                if self.received == self.frame.len {
+                    self.state = ParseState::Crc;
+                }
+            }
+            ParseState::Crc => {
+                self.state = ParseState::Start;
+                let mut checked = [0; MAX_PAYLOAD + 2];
+                checked[0] = self.frame.id;
+                checked[1] = self.frame.len as u8;
+                checked[2..2 + self.frame.len].copy_from_slice(self.frame.payload());
+                if crc8(&checked[..2 + self.frame.len]) != byte {
+                    return Some(Err(FrameError::BadCrc));
+                }
+                return Some(Ok(self.frame));
+            }
+        }
+        None
+    }
+}
+
+impl Default for FrameParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Binary control link on its own pair of RTT channels
+pub struct ControlLink {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// RTT channel carrying frames from the host
+    input: DownChannel,
+    /// RTT channel carrying replies to the host
+    output: UpChannel,
+    /// Frame parser
+    parser: FrameParser,
+}
+
+impl ControlLink {
+    /// Create a control link on the given RTT channels
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    /// * `input` - RTT down channel receiving frames
+    /// * `output` - RTT up channel for ACK/NAK replies
+    ///
+    /// # Returns
+    /// New link waiting for a start byte
+    pub fn new(shared: &'static SharedState, input: DownChannel, output: UpChannel) -> Self {
+        Self {
+            shared,
+            input,
+            output,
+            parser: FrameParser::new(),
+        }
+    }
+
+    /// Send a reply frame
+    ///
+    /// # Arguments
+    /// * `code` - [`ACK`] or [`NAK`]
+    /// * `detail` - Command id for an ACK, reason for a NAK
+    fn reply(&mut self, code: u8, detail: u8) {
+        self.output
+            .write(&[START, code, detail, crc8(&[code, detail])]);
+    }
+
+    /// Apply a decoded command
+    ///
+    /// # Arguments
+    /// * `packet` - Command to apply
+    ///
+    /// # Returns
+    /// `Ok(())`, or [`FrameError::OutOfRange`] if the frame rate was below
+    /// the marked flicker-fusion threshold or the strobe guard's floor and
+    /// was held there (see [`SharedState::set_frame_rate`])
+    async fn apply(&self, packet: HostPacket) -> Result<(), FrameError> {
+        let (levels, frame_rate) = match packet {
+            HostPacket::SetLevels(levels) => (Some(levels), None),
+            HostPacket::SetFrameRate(frame_rate) => (None, Some(frame_rate)),
+            HostPacket::SetAll(levels, frame_rate) => (Some(levels), Some(frame_rate)),
+        };
+        if let Some(levels) = levels {
+            self.shared.set_rgb(levels).await;
+        }
+        if let Some(frame_rate) = frame_rate {
+            if self.shared.set_frame_rate(frame_rate).await != frame_rate {
+                return Err(FrameError::OutOfRange);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read and handle whatever bytes have arrived
+    ///
+    /// # Returns
+    /// Number of bytes read (0 when the channel was idle)
+    pub async fn poll(&mut self) -> usize {
+        let mut buf = [0; 16];
+        let count = self.input.read(&mut buf);
+        for &byte in &buf[..count] {
+            let result = match self.parser.push(byte) {
+                None => continue,
+                Some(Ok(frame)) => match HostPacket::decode(&frame) {
+                    Ok(packet) => self.apply(packet).await.map(|()| frame.id),
+                    Err(err) => Err(err),
+                },
+                Some(Err(err)) => Err(err),
+            };
+            match result {
+                Ok(id) => self.reply(ACK, id),
+                Err(err) => self.reply(NAK, err as u8),
+            }
+        }
+        count
+    }
+}
@@ -0,0 +1,51 @@
+//! Test Patterns
+//!
+//! A fixed sequence of known colors for checking the whole output path:
+//! primaries, secondaries, full white and a grayscale ramp. The UI shows
+//! each in turn as the requested levels, so they pass through the enable
+//! mask, color correction and lookup tables exactly like user settings and
+//! show the calibrated output. This module only defines the sequence.
+
+use crate::*;
+
+/// One test pattern: a name and the levels requested for it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestPattern {
+    /// Name printed while the pattern is shown
+    pub name: &'static str,
+    /// Requested [red, green, blue] levels (0 to LEVELS-1)
+    pub levels: [u32; 3],
+}
+
+impl TestPattern {
+    /// Create a pattern
+    ///
+    /// # Arguments
+    /// * `name` - Name printed while the pattern is shown
+    /// * `levels` - Requested [red, green, blue] levels
+    ///
+    /// # Returns
+    /// The pattern
+    pub const fn new(name: &'static str, levels: [u32; 3]) -> Self {
+        Self { name, levels }
+    }
+}
+
+/// Full-scale level used by the colored patterns
+const FULL: u32 = LEVELS - 1;
+
+/// Test patterns in the order they are shown
+pub const TEST_PATTERNS: [TestPattern; 12] = [
+    TestPattern::new("red", [FULL, 0, 0]),
+    TestPattern::new("green", [0, FULL, 0]),
+    TestPattern::new("blue", [0, 0, FULL]),
+    TestPattern::new("yellow", [FULL, FULL, 0]),
+    TestPattern::new("cyan", [0, FULL, FULL]),
+    TestPattern::new("magenta", [FULL, 0, FULL]),
+    TestPattern::new("white", [FULL; 3]),
+    TestPattern::new("gray 1", [1; 3]),
+    TestPattern::new("gray 4", [4; 3]),
+    TestPattern::new("gray 8", [8; 3]),
+    TestPattern::new("gray 12", [12; 3]),
+    TestPattern::new("gray 15", [FULL; 3]),
+];
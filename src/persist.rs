@@ -0,0 +1,168 @@
+//! Saved Calibration
+//!
+//! Keeps the calibration across resets: the whole config blob (see
+//! [`CalibrationConfig`]), levels and frame rate included, is stored as
+//! the payload of the flash record at [`STORAGE_PAGE`]. It is loaded in
+//! `main` before any task starts and becomes the startup calibration; a
+//! blank, damaged or outdated record leaves the built-in defaults.
+//!
+//! Saving is asked for with the `save` command or by holding A+B at boot
+//! (see [`BootMode::Save`]), and done by the command task once the UI has
+//! written the starting values. With `save auto` on, the command task also
+//! saves by itself when its [`SaveThrottle`] finds the calibration has
+//! moved far enough from the saved one.
+//!
+//! The presets and the other settings are kept in a record of their own at
+//! [`SETTINGS_PAGE`] (see [`SavedSettings`]), loaded alongside the
+//! calibration and rewritten by the command task whenever one changes.
+
+use crate::*;
+
+use microbit_bsp::embassy_nrf::nvmc::Nvmc;
+
+/// The saved calibration's flash record, and the flash holding the saved
+/// settings' record
+pub type CalibrationStorage = Storage<Nvmc<'static>>;
+
+/// Read the saved calibration
+///
+/// Prints where the calibration came from, or why none was used.
+///
+/// # Arguments
+/// * `storage` - Flash record holding the calibration
+///
+/// # Returns
+/// The saved calibration, or `None` to start from the defaults
+pub fn load_calibration(storage: &mut CalibrationStorage) -> Option<CalibrationConfig> {
+    let mut blob = [0; CONFIG_LEN];
+    let loaded = storage
+        .load(&mut blob)
+        .map_err(CalibrationError::from)
+        .and_then(|()| CalibrationConfig::decode(&blob).map_err(CalibrationError::from));
+    match loaded {
+        Ok(config) => {
+            rprint!("saved calibration: loaded");
+            for (lut, name) in config.luts.iter().zip(["r", "g", "b"]) {
+                if !lut.is_identity() {
+                    rprint!(", lut {}", name);
+                }
+            }
+            rprintln!();
+            Some(config)
+        }
+        Err(CalibrationError::Storage(StorageError::Blank)) => {
+            rprintln!("saved calibration: none, using defaults");
+            None
+        }
+        Err(err) => {
+            rprintln!(
+                "saved calibration: error E{}: {}, using defaults",
+                err.code(),
+                err.message()
+            );
+            None
+        }
+    }
+}
+
+/// Save a calibration
+///
+/// Failed attempts are retried (see [`SAVE_ATTEMPTS`]); an error means the
+/// last one failed too.
+///
+/// # Arguments
+/// * `storage` - Flash record holding the calibration
+/// * `config` - Calibration to save
+///
+/// # Returns
+/// `Ok` once saved, or why it could not be
+pub async fn save_calibration(
+    storage: &mut CalibrationStorage,
+    config: &CalibrationConfig,
+) -> Result<(), CalibrationError> {
+    storage.save(&config.encode(), &mut EmbassyClock).await?;
+    Ok(())
+}
+
+/// Read the saved settings
+///
+/// # Arguments
+/// * `storage` - Flash holding the records
+///
+/// # Returns
+/// The settings in flash, or why there are none
+pub fn read_settings(storage: &mut CalibrationStorage) -> Result<SavedSettings, CalibrationError> {
+    let mut blob = [0; SETTINGS_LEN];
+    storage.page(SETTINGS_PAGE).load(&mut blob)?;
+    Ok(SavedSettings::decode(&blob)?)
+}
+
+/// Read the saved settings at boot
+///
+/// Prints where the settings came from, or why none were used.
+///
+/// # Arguments
+/// * `storage` - Flash holding the records
+///
+/// # Returns
+/// The saved settings, or `None` to start from the defaults
+pub fn load_settings(storage: &mut CalibrationStorage) -> Option<SavedSettings> {
+    match read_settings(storage) {
+        Ok(settings) => {
+            let count = settings.presets.iter().flatten().count();
+            rprintln!("saved settings: loaded, {} presets", count);
+            Some(settings)
+        }
+        Err(CalibrationError::Storage(StorageError::Blank)) => {
+            rprintln!("saved settings: none, using defaults");
+            None
+        }
+        Err(err) => {
+            rprintln!(
+                "saved settings: error E{}: {}, using defaults",
+                err.code(),
+                err.message()
+            );
+            None
+        }
+    }
+}
+
+/// Save the settings
+///
+/// # Arguments
+/// * `storage` - Flash holding the records
+/// * `settings` - Settings to save
+///
+/// # Returns
+/// `Ok` once saved, or why they could not be
+pub async fn save_settings(
+    storage: &mut CalibrationStorage,
+    settings: &SavedSettings,
+) -> Result<(), CalibrationError> {
+    storage
+        .page(SETTINGS_PAGE)
+        .save(&settings.encode(), &mut EmbassyClock)
+        .await?;
+    Ok(())
+}
+
+/// What an automatic save compares of a calibration
+///
+/// # Arguments
+/// * `config` - Calibration
+///
+/// # Returns
+/// Its levels and frame rate, and a checksum of the rest of its blob
+pub fn saved_fields(config: &CalibrationConfig) -> SavedFields {
+    let rest = CalibrationConfig {
+        levels: [0; 3],
+        frame_rate: 0,
+        ..*config
+    };
+    SavedFields {
+        levels: config.levels,
+        frame_rate: config.frame_rate,
+        settings: crc32(&rest.encode()),
+    }
+}
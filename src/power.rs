@@ -0,0 +1,123 @@
+//! Power Budget
+//!
+//! Without current-limiting resistors, each LED draws whatever the pin
+//! driving it delivers while it is on. The average drawn over a frame is
+//! estimated here from each channel's on-current and duty: a channel is on
+//! for level/LEVELS of its third of the frame, on every Nth frame with a
+//! refresh multiplier of N. The frame rate does not change the average, only
+//! how long each pulse lasts, so a low frame rate makes longer pulses of the
+//! same current rather than more current.
+//!
+//! With a budget set, an estimate over it is either reported or capped. The
+//! cap scales all three output levels by the same factor, so the color
+//! ratio is kept while the brightness drops.
+
+use crate::*;
+
+/// What to do when the estimated current exceeds the budget
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BudgetAction {
+    /// Print a warning, leave the output as it is
+    Warn,
+    /// Scale the output down to the budget
+    #[default]
+    Cap,
+}
+
+impl BudgetAction {
+    /// Name of the action for log output
+    pub fn name(self) -> &'static str {
+        match self {
+            BudgetAction::Warn => "warn",
+            BudgetAction::Cap => "cap",
+        }
+    }
+}
+
+/// Average current limit and the on-currents it is estimated from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerBudget {
+    /// Current through each [red, green, blue] LED while lit, in µA
+    pub peak_ua: [u32; 3],
+    /// Highest average current allowed in µA, or `None` for no limit
+    pub budget_ua: Option<u32>,
+    /// What to do when the estimate exceeds the budget
+    pub action: BudgetAction,
+}
+
+impl PowerBudget {
+    /// No limit, 5 mA per channel while lit, capping once a budget is set
+    ///
+    /// The on-currents are a rough guess for a standard-drive pin; measure
+    /// yours and set them with `power peak`.
+    pub const DEFAULT: Self = PowerBudget {
+        peak_ua: [5000; 3],
+        budget_ua: None,
+        action: BudgetAction::Cap,
+    };
+
+    /// Estimate the average current over a frame
+    ///
+    /// # Arguments
+    /// * `output` - Output [red, green, blue] levels actually driven
+    /// * `multipliers` - Frames per refresh of each channel (0 is taken
+    ///   as 1)
+    ///
+    /// # Returns
+    /// Average current in µA
+    pub fn estimate_ua(&self, output: [u32; 3], multipliers: [u32; 3]) -> u32 {
+        let mut total: u64 = 0;
+        for ((level, peak), multiplier) in output.iter().zip(self.peak_ua).zip(multipliers) {
+            let frames = multiplier.max(1) as u64;
+            total += *level as u64 * peak as u64 / (LEVELS as u64 * 3 * frames);
+        }
+        total as u32
+    }
+
+    /// Check whether an estimate exceeds the budget
+    ///
+    /// # Arguments
+    /// * `estimate_ua` - Average current in µA (see [`Self::estimate_ua`])
+    pub fn over(&self, estimate_ua: u32) -> bool {
+        self.budget_ua.is_some_and(|budget| estimate_ua > budget)
+    }
+
+    /// Apply the budget to the output levels
+    ///
+    /// When capping and over budget, every level is scaled by budget /
+    /// estimate, rounding down, which keeps the color ratio and brings the
+    /// estimate down to about the budget.
+    ///
+    /// # Arguments
+    /// * `output` - Output [red, green, blue] levels actually driven
+    /// * `multipliers` - Frames per refresh of each channel
+    ///
+    /// # Returns
+    /// The levels to drive, unchanged unless capped
+    pub fn limit(&self, output: [u32; 3], multipliers: [u32; 3]) -> [u32; 3] {
+        let estimate = self.estimate_ua(output, multipliers);
+        match self.budget_ua {
+            Some(budget) if self.action == BudgetAction::Cap && estimate > budget => {
+                output.map(|level| (level as u64 * budget as u64 / estimate as u64) as u32)
+            }
+            _ => output,
+        }
+    }
+}
+
+impl Default for PowerBudget {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Format a current for log output
+///
+/// # Arguments
+/// * `ua` - Current in µA
+///
+/// # Returns
+/// Whole milliamps and the tenths digit, printed as `{}.{} mA`
+pub fn milliamps(ua: u32) -> (u32, u32) {
+    (ua / 1000, ua % 1000 / 100)
+}
@@ -0,0 +1,226 @@
+//! Calibration Presets
+//!
+//! A few slots holding saved calibrations (levels and frame rate). A slot can
+//! carry a short tag naming the condition it was calibrated for, such as the
+//! ambient light (`warm`, `daylight`), so the matching white can be recalled
+//! by name. Tags containing `white` (`white`, `warm_white`, `white-d65`)
+//! mark calibrated whites, which can be stepped through on their own.
+//! Presets live in the shared state, and are kept in flash across resets
+//! (see [`SavedSettings`]).
+//!
+//! Without a host, the buttons work the slots directly: a long press of A
+//! selects the next slot and loads it (warning if it is empty), and a very
+//! long press of A saves the current calibration to the selected slot.
+
+use crate::*;
+
+/// Number of preset slots
+pub const PRESET_SLOTS: usize = 4;
+
+/// Longest tag in bytes
+pub const TAG_MAX: usize = 12;
+
+/// Short ASCII label attached to a preset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tag {
+    /// Tag characters; only the first `len` are used
+    bytes: [u8; TAG_MAX],
+    /// Number of characters in the tag
+    len: u8,
+}
+
+impl Tag {
+    /// Validate and store a tag
+    ///
+    /// Tags are 1 to TAG_MAX letters, digits, `-` or `_`, and must contain
+    /// a non-digit so they can't be confused with slot numbers.
+    ///
+    /// # Arguments
+    /// * `text` - Proposed tag
+    ///
+    /// # Returns
+    /// The tag, or `None` if it is not acceptable
+    pub fn parse(text: &str) -> Option<Self> {
+        let valid = |c: u8| c.is_ascii_alphanumeric() || c == b'-' || c == b'_';
+        if text.is_empty()
+            || text.len() > TAG_MAX
+            || !text.bytes().all(valid)
+            || text.bytes().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let mut bytes = [0; TAG_MAX];
+        bytes[..text.len()].copy_from_slice(text.as_bytes());
+        Some(Self {
+            bytes,
+            len: text.len() as u8,
+        })
+    }
+
+    /// The tag text
+    pub fn as_str(&self) -> &str {
+        // Only validated ASCII is stored, so this cannot fail
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("")
+    }
+
+    /// Whether the tag marks a calibrated white
+    ///
+    /// # Returns
+    /// `true` if the tag contains `white`, in any case
+    pub fn is_white(&self) -> bool {
+        self.bytes[..self.len as usize]
+            .windows(WHITE_TAG.len())
+            .any(|window| window.eq_ignore_ascii_case(WHITE_TAG))
+    }
+}
+
+/// Tag text marking a calibrated white
+const WHITE_TAG: &[u8] = b"white";
+
+/// Which presets a "next preset" step visits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresetFilter {
+    /// Every slot in turn, empty or not (the buttons' preset slots)
+    All,
+    /// Every tagged preset
+    #[default]
+    Tagged,
+    /// Only presets tagged as a white (see [`Tag::is_white`])
+    White,
+}
+
+impl PresetFilter {
+    /// Check whether a preset is visited
+    ///
+    /// # Arguments
+    /// * `preset` - Preset to check
+    ///
+    /// # Returns
+    /// `true` if the preset passes the filter
+    pub fn matches(self, preset: &Preset) -> bool {
+        match (self, &preset.tag) {
+            (PresetFilter::All, _) => true,
+            (_, None) => false,
+            (PresetFilter::Tagged, Some(_)) => true,
+            (PresetFilter::White, Some(tag)) => tag.is_white(),
+        }
+    }
+
+    /// Lowercase name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            PresetFilter::All => "all",
+            PresetFilter::Tagged => "tagged",
+            PresetFilter::White => "white",
+        }
+    }
+}
+
+/// A saved calibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preset {
+    /// RGB brightness levels [red, green, blue] (0 to LEVELS-1)
+    pub levels: [u32; 3],
+    /// Frame rate in frames per second
+    pub frame_rate: u64,
+    /// Condition the preset was calibrated for, if tagged
+    pub tag: Option<Tag>,
+}
+
+/// Signed difference of the current settings from a preset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PresetDiff {
+    /// Current minus saved level, per channel [red, green, blue]
+    pub levels: [i32; 3],
+    /// Current minus saved frame rate in fps
+    pub frame_rate: i64,
+}
+
+impl Preset {
+    /// Compare settings against this preset
+    ///
+    /// # Arguments
+    /// * `levels` - Current [red, green, blue] levels
+    /// * `frame_rate` - Current frame rate in fps
+    ///
+    /// # Returns
+    /// How far the settings have drifted from the preset (positive = above)
+    pub fn diff(&self, levels: [u32; 3], frame_rate: u64) -> PresetDiff {
+        let mut delta = [0; 3];
+        for ((delta, current), saved) in delta.iter_mut().zip(levels).zip(self.levels) {
+            *delta = current as i32 - saved as i32;
+        }
+        PresetDiff {
+            levels: delta,
+            frame_rate: frame_rate as i64 - self.frame_rate as i64,
+        }
+    }
+}
+
+/// Find the slot holding a tag
+///
+/// # Arguments
+/// * `presets` - Preset slots
+/// * `tag` - Tag to look for
+///
+/// # Returns
+/// The first slot with that tag, if any
+pub fn find_tag(presets: &[Option<Preset>; PRESET_SLOTS], tag: &Tag) -> Option<usize> {
+    presets
+        .iter()
+        .position(|preset| preset.is_some_and(|preset| preset.tag.as_ref() == Some(tag)))
+}
+
+/// Apply a preset to the shared state
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+/// * `slot` - Preset slot (0 to PRESET_SLOTS-1)
+///
+/// # Returns
+/// The loaded preset, or `None` if the slot is empty
+pub async fn load_preset(shared: &SharedState, slot: usize) -> Option<Preset> {
+    let preset = shared.get_presets().await[slot]?;
+    shared.set_rgb(preset.levels).await;
+    shared.set_frame_rate(preset.frame_rate).await;
+    shared.set_active_preset(Some(slot)).await;
+    Some(preset)
+}
+
+/// Find the next slot after a given one whose preset passes a filter
+///
+/// # Arguments
+/// * `presets` - Preset slots
+/// * `after` - Slot to start after, wrapping around, or `None` to start
+///   from the first
+/// * `filter` - Which presets qualify
+///
+/// # Returns
+/// The slot, or `None` if no preset qualifies
+pub fn find_next(
+    presets: &[Option<Preset>; PRESET_SLOTS],
+    after: Option<usize>,
+    filter: PresetFilter,
+) -> Option<usize> {
+    let start = after.map_or(0, |slot| slot + 1);
+    (0..PRESET_SLOTS)
+        .map(|offset| (start + offset) % PRESET_SLOTS)
+        .find(|&slot| presets[slot].is_some_and(|preset| filter.matches(&preset)))
+}
+
+/// Apply the next matching preset after the active one, wrapping around
+///
+/// Other slots are skipped, so repeated calls step through e.g. the
+/// calibrations saved for the different conditions, or just the whites.
+///
+/// # Arguments
+/// * `shared` - State shared with the other tasks
+/// * `filter` - Which presets to step through
+///
+/// # Returns
+/// Slot and contents of the loaded preset, or `None` if none matches
+pub async fn load_next(shared: &SharedState, filter: PresetFilter) -> Option<(usize, Preset)> {
+    let presets = shared.get_presets().await;
+    let slot = find_next(&presets, shared.get_active_preset().await, filter)?;
+    load_preset(shared, slot).await.map(|preset| (slot, preset))
+}
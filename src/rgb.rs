@@ -4,109 +4,981 @@
 //! mixed colors including white. Since only one LED can be on at a time due to
 //! hardware constraints (no current-limiting resistors), rapid switching between
 //! colors creates the illusion of mixed colors through persistence of vision.
+//!
+//! # Update Contract
+//! The UI, command and sequencer tasks are the only writers of the shared
+//! state; this module only reads it, apart from reporting the measured frame
+//! rate. Reads happen only at frame boundaries, in [`Rgb::snapshot`], before
+//! the first color step. All three steps of a frame then use that snapshot,
+//! so a write that lands mid-frame only takes effect at the start of a later
+//! frame and a frame never mixes old and new colors. Snapshots are taken at
+//! most [`DEFAULT_STATE_REFRESH_HZ`] times a second (the `snapshot`
+//! command), so at high frame rates several frames share one.
+//!
+//! Every wait is scheduled against a running deadline (see [`Pacer`])
+//! rather than from whenever the previous wait returned, so scheduling
+//! latency doesn't stretch frames and a frame with a new tick time starts
+//! exactly where the previous one ended: no gap, no overlap and no partial
+//! frame.
+//!
+//! The one exception is the emergency-off flag, a lock-free atomic checked at
+//! every frame boundary: while it is set the snapshot is refreshed each frame
+//! and the outputs drop to zero at once, skipping the slew limit, so a stop
+//! takes effect within one frame.
+//!
+//! # Slew Limit
+//! The output levels don't latch the requested ones: each frame every
+//! channel moves toward its target by at most the slew rate (default
+//! [`DEFAULT_SLEW_RATE`], one level per frame; see [`slew_toward`]), so a
+//! knob jump or a preset recall ramps over a few frames instead of
+//! snapping, e.g. 150 ms for the whole range at 100 fps. Once a channel
+//! reaches its target it stays there exactly. Blanking is never ramped: the
+//! emergency off and the LED cues go dark within the frame.
+//!
+//! # Animations
+//! A breathing or rainbow animation (see [`Animation`]) is taken in the
+//! snapshot like the levels and applied to a copy of the targets every
+//! frame, from the time since scanning started. The
+//! snapshot's level fractions are ignored while one runs.
+//!
+//! # Level Blending
+//! With knob blending on (`knob blend on`), a channel may carry a fraction
+//! of the way to its next level. Its on-time is then interpolated between
+//! the on-times of the two output levels (see [`blend_on_time`]) rather than
+//! snapping to one of them, so brightness follows the knob continuously
+//! despite the 16 levels. The blend is a direct fractional on-time within
+//! every frame, not a pattern over several frames. The next level's output
+//! goes through the same corrections as the level itself, except that the
+//! color matrix's cross terms only ever see whole levels. A channel still
+//! ramping under the slew limit is not blended.
+//!
+//! # Refresh Multipliers
+//! Each channel can be refreshed only every Nth frame (the `refresh`
+//! command), e.g. red every other frame to probe how sensitive the eye is
+//! to flicker in one color. On the frames in between, the channel's slot
+//! stays in the scan but dark, so the other channels keep their timing.
+//! Each channel counts frames on its own; with every multiplier at 1 (the
+//! default) every channel is shown every frame, exactly as without them.
+//!
+//! # PWM Edge
+//! Each slot normally starts with its on-window (leading-edge PWM). With
+//! [`PwmEdge::Alternating`] (the `edge` command), every other frame puts
+//! the on-window at the end of the slot instead (trailing edge), which
+//! spreads the drive over the slot for LED and driver combinations that
+//! suffer from a fixed on-phase. The on- and off-times themselves are
+//! unchanged, so every channel's average duty stays the same.
+//!
+//! # Duty Tables
+//! By default a level's on-time is linear in the level, `level / LEVELS`
+//! of the slot. Since the eye is far more sensitive to changes at the dim
+//! end, the `gamma` command switches each channel to a table of the duty
+//! for every level instead (the board's [`LED_DUTY_TABLES`], set with
+//! [`Rgb::with_duty_tables`]), in [`DUTY_TABLE_ONE`]ths of the slot,
+//! so level 0 stays fully off and a table's full entry lights the whole
+//! slot. Gamma starts off, as the power and color estimates assume on-times
+//! linear in the level. The tables are constants, so
+//! the scan only does an integer multiply per slot. They are separate per
+//! channel, as the three LEDs' different forward voltages can call for
+//! different curves to balance white. Everything else (blending, the off
+//! time filling the slot) works on the table's on-times as on the linear
+//! ones; the lookup tables (`set lut`) are applied before, to the levels.
+//!
+//! # Measurement Duty
+//! For nulling a photometer against the LED, the host can give a channel
+//! an exact duty (the `meas` command) in millionths of its slot
+//! ([`DUTY_ONE`]) instead of a level. The duty bypasses everything the
+//! level would go through: quantization to 16 levels, the corrections,
+//! blending, the slew limit, animations and the refresh multiplier. It is
+//! taken in the snapshot and held until cleared; only the emergency off
+//! overrides it.
+//!
+//! # Drive Mode
+//! [`DriveMode::Scan`], the default, lights one LED at a time as above.
+//! With [`DriveMode::Spread`] (the `drive` command), one LED is still lit
+//! at a time, but instead of one pulse per color the frame is cut into
+//! 3 * LEVELS ticks and each tick goes to the channel owed the most on-time
+//! so far (see [`Rgb::spread_step`]). A channel's on-time arrives as several
+//! short pulses spread over the frame rather than in one clump, which looks
+//! smoother at low frame rates. The on-time per frame is the same as when
+//! scanning; the price is more timer wake-ups per frame.
+//!
+//! The mode is read with the snapshot and switched between frames; a held
+//! channel and the benchmark always scan.
+//!
+//! In slow motion (the `slow` command) every time is stretched by a large
+//! factor, so each color step lasts long enough to see, and each step is
+//! logged as it starts.
+//!
+//! # Benchmark
+//! On request (the `benchmark` command), the loop stops following the
+//! shared state for a moment and scans a fixed mid-level mix at ever higher
+//! frame rates, timing each trial with the clock. A rate counts as
+//! sustained if the measured frame rate comes within a few percent of it;
+//! the search doubles the rate until a trial falls behind or the tick time
+//! would round to zero, then bisects. The highest sustained rate is
+//! printed, and scanning picks up again from a fresh snapshot.
+//!
+//! Snapshots are rate-limited to the state refresh rate (50 Hz by default),
+//! independent of the multiplexing frame rate, so high frame rates don't
+//! spend their time re-reading unchanged state.
 
 use crate::*;
 
+use core::{future::Future, pin::pin};
+use embassy_futures::select::{select, Either};
+use microbit_bsp::embassy_nrf::wdt::WatchdogHandle;
+
 /// Type alias for the three RGB LED output pins [red, green, blue]
-type RgbPins = [Output<'static, AnyPin>; 3];
+pub type RgbPins = [Output<'static, AnyPin>; 3];
 
 /// RGB LED controller using TDM
 ///
 /// Controls three LED pins with precise timing to create mixed colors.
 /// Each color is displayed for a time proportional to its brightness level.
+///
+/// All timing goes through a [`Pacer`] on the Embassy timer, which keeps
+/// the running deadline.
 pub struct Rgb {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
     /// GPIO output pins for [red, green, blue] LEDs
     rgb: RgbPins,
-    /// Shadow copy of brightness levels to minimize mutex lock contention
+    /// Brightness levels output in the current frame, moved toward
+    /// `targets` by at most `slew_rate` per frame
     /// Values range from 0 (off) to LEVELS-1 (full brightness)
     levels: [u32; 3],
-    /// Time in microseconds for each brightness tick
+    /// Snapshot of the requested brightness levels, taken in `snapshot`
+    targets: [u32; 3],
+    /// Output levels one requested level above `targets`, taken in
+    /// `snapshot`
+    next_targets: [u32; 3],
+    /// Fraction of the way from `targets` to `next_targets` in
+    /// FRACTION_ONEths, taken in `snapshot`
+    fractions: [u32; 3],
+    /// Maximum change of a channel's output level per frame, taken in
+    /// `snapshot`
+    slew_rate: u32,
+    /// Time in microseconds for each brightness tick, taken in `snapshot`
     /// Calculated from frame rate: 1_000_000 / (3 * frame_rate * LEVELS)
     tick_time: u64,
+    /// Exact length in microseconds of one color's slot per frame, taken in
+    /// `snapshot`: 1_000_000 / (3 * frame_rate)
+    slot_time: u64,
+    /// How each slot is split into whole-microsecond on and off times,
+    /// taken in `snapshot`
+    rounding: Rounding,
+    /// Duty of every level of each [red, green, blue] channel, used while
+    /// gamma is on
+    duty_tables: [DutyTable; 3],
+    /// Whether on-times come from the duty tables rather than linear in
+    /// the level, taken in `snapshot`
+    gamma: bool,
+    /// Number of sub-frames each frame is divided into (at least 1), taken
+    /// in `snapshot`
+    /// Each color is shown once per sub-frame for 1/sub_frames of its time
+    sub_frames: u32,
+    /// Minimum time between shared state snapshots, taken in `snapshot`
+    refresh_interval: Duration,
+    /// Time of the last snapshot, or `None` before the first one
+    last_snapshot: Option<Instant>,
+    /// Start of the current frame rate measurement window and the number
+    /// of frames completed in it, or `None` to start a new window
+    frame_count: Option<(Instant, u32)>,
+    /// Whether an LED cue blanks the output, skipping the slew limit, taken
+    /// in `snapshot`
+    cue: bool,
+    /// Channel output alone instead of scanning, taken in `snapshot`
+    hold: Option<usize>,
+    /// Factor the tick and slot times are stretched by, or `None` for
+    /// normal timing, taken in `snapshot`
+    slow_motion: Option<u32>,
+    /// Which channels each frame shows, with the refresh multipliers
+    /// taken in `snapshot`
+    refresh: RefreshCounter,
+    /// Where the on-window sits in each slot, taken in `snapshot`
+    edge: PwmEdge,
+    /// Whether the channels are scanned in turn or spread, taken in
+    /// `snapshot`
+    drive: DriveMode,
+    /// On-time each channel is still owed in spread mode, carried from
+    /// tick to tick and frame to frame, in microseconds
+    spread_owed: [u64; 3],
+    /// Exact duty of each channel in DUTY_ONEths overriding its level, or
+    /// `None` to follow the level, taken in `snapshot`
+    duties: [Option<u32>; 3],
+    /// Animation applied to the targets each frame, taken in `snapshot`
+    animation: Animation,
+    /// Whether this frame puts the on-window at the end of each slot
+    trailing: bool,
+    /// Timer time per real time in parts per million, scaling every wait
+    /// to make up for an imprecise timer clock
+    timer_correction: u32,
+    /// How each [red, green, blue] pin lights and darkens its LED
+    outputs: [OutputProfile; 3],
+    /// Hardware watchdog fed by the scan loop, if any
+    watchdog: Option<WatchdogHandle>,
+    /// Schedule of all waits, and the time source for timestamps
+    pacer: Pacer<EmbassyClock>,
 }
 
-impl Rgb {
-    /// Calculate tick time in microseconds from frame rate
-    ///
-    /// Frame rate determines how many complete RGB scans occur per second.
-    /// Each frame has 3 colors × LEVELS brightness steps, so:
-    /// tick_time = 1_000_000 μs/sec ÷ (3 colors × frame_rate × LEVELS)
-    ///
-    /// # Arguments
-    /// * `frame_rate` - Target frames per second
-    ///
-    /// # Returns
-    /// Microseconds per brightness tick
-    fn frame_tick_time(frame_rate: u64) -> u64 {
-        1_000_000 / (3 * frame_rate * LEVELS as u64)
+/// How the three channels share a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DriveMode {
+    /// One LED at a time, each in its own slot
+    #[default]
+    Scan,
+    /// One LED at a time, each channel's on-time spread across the frame
+    Spread,
+}
+
+impl DriveMode {
+    /// Lowercase name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            DriveMode::Scan => "scan",
+            DriveMode::Spread => "spread",
+        }
     }
+}
+
+/// Duty of each level of one channel, in DUTY_TABLE_ONEths of its slot
+pub type DutyTable = [u16; LEVELS as usize];
+
+/// Full duty in a [`DutyTable`]: the LED is lit for the whole slot
+pub const DUTY_TABLE_ONE: u16 = u16::MAX;
+
+/// Duty table following a 2.2 gamma curve for LEVELS levels (see
+/// [`gamma_duty_table`]), built at compile time
+pub const GAMMA_DUTY_TABLE: DutyTable = gamma_duty_table();
+
+/// Full duty of a measurement override (see [`Rgb::slot_times`]): one
+/// million parts per slot
+pub const DUTY_ONE: u32 = 1_000_000;
+
+/// Largest share of each frame any one LED may be lit, in DUTY_ONEths
+///
+/// The LED is wired without current-limiting resistors, so while lit its
+/// current is set only by the pin driver and the LED's forward voltage,
+/// assumed here to be up to about 20 mA: the continuous rating typical of
+/// 5 mm RGB LEDs, with no margin. Scanning the three colors keeps each
+/// die's average at a third of that at most, and this cap holds every
+/// mode to the same, including those that repeat one channel (`hold`,
+/// `identpins`, a `meas` duty on a held channel). A third is what a
+/// full-level scan gives anyway, so scanning is never limited.
+pub const MAX_DUTY: u32 = DUTY_ONE / 3;
+
+/// Default number of sub-frames per frame (one scan per frame)
+///
+/// With K sub-frames (the `subframes` command), each color is shown K times
+/// per frame for 1/K of its on-time each, which reduces the color break-up
+/// ("rainbow") seen when the eye or the LED moves. Total on-time per color
+/// and frame is unchanged.
+pub const DEFAULT_SUB_FRAMES: u32 = 1;
+
+/// Default maximum level change per frame (the `slew` command)
+///
+/// One level per frame ramps the whole range in LEVELS - 1 frames: 150 ms at
+/// 100 fps, 300 ms at the 50 fps strobe guard. Single knob steps still
+/// land within the frame. LEVELS or more turns the limit off.
+pub const DEFAULT_SLEW_RATE: u32 = 1;
+
+/// Length of the window over which the actual frame rate is measured
+const FRAME_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Length of one benchmark trial at a fixed frame rate
+const BENCHMARK_TRIAL: Duration = Duration::from_millis(100);
 
+/// Least share of the requested frame rate a benchmark trial must measure
+/// to count as sustained, in percent
+const BENCHMARK_SUSTAINED_PERCENT: u64 = 95;
+
+/// Default maximum rate in Hz at which shared state is re-read
+///
+/// The LEDs keep multiplexing at the full frame rate; only the snapshot of
+/// levels and frame rate is taken less often, at most once per frame.
+pub const DEFAULT_STATE_REFRESH_HZ: u64 = 50;
+
+impl Rgb {
     /// Create a new RGB controller with specified pins and frame rate
     ///
     /// # Arguments
+    /// * `shared` - State shared with the other tasks
     /// * `rgb` - Array of GPIO output pins [red, green, blue]
     /// * `frame_rate` - Target refresh rate in frames per second
     ///
     /// # Returns
-    /// New RGB controller instance
-    pub fn new(rgb: RgbPins, frame_rate: u64) -> Self {
-        let tick_time = Self::frame_tick_time(frame_rate);
+    /// New RGB controller instance timed by the Embassy timer
+    pub fn new(shared: &'static SharedState, rgb: RgbPins, frame_rate: u64) -> Self {
+        let tick_time = frame_tick_time(frame_rate, LEVELS);
         Self {
+            shared,
             rgb,
             levels: [0; 3], // Start with all LEDs off
+            targets: [0; 3],
+            next_targets: [0; 3],
+            fractions: [0; 3],
+            slew_rate: DEFAULT_SLEW_RATE,
             tick_time,
+            slot_time: frame_slot_time(frame_rate),
+            rounding: Rounding::default(),
+            duty_tables: [GAMMA_DUTY_TABLE; 3],
+            gamma: false,
+            sub_frames: DEFAULT_SUB_FRAMES,
+            refresh_interval: Duration::from_hz(DEFAULT_STATE_REFRESH_HZ),
+            last_snapshot: None,
+            frame_count: None,
+            cue: false,
+            hold: None,
+            slow_motion: None,
+            refresh: RefreshCounter::new(),
+            edge: PwmEdge::default(),
+            drive: DriveMode::default(),
+            spread_owed: [0; 3],
+            duties: [None; 3],
+            animation: Animation::Off,
+            trailing: false,
+            timer_correction: NO_CORRECTION,
+            outputs: [OutputProfile::DIRECT; 3],
+            watchdog: None,
+            pacer: Pacer::new(EmbassyClock),
+        }
+    }
+}
+
+impl Rgb {
+    /// Convert a nominal time to timer time with the timer correction
+    ///
+    /// # Arguments
+    /// * `micros` - Real time in microseconds
+    ///
+    /// # Returns
+    /// Timer time in microseconds, saturating at `u64::MAX`
+    fn corrected(&self, micros: u64) -> u64 {
+        let scaled = micros as u128 * self.timer_correction as u128 / NO_CORRECTION as u128;
+        scaled.min(u64::MAX as u128) as u64
+    }
+
+    /// Scale all frame timing for a timer clock that runs off nominal
+    ///
+    /// Tick and slot times are stretched or shrunk so frames last their
+    /// nominal real time, and the measured frame rate is reported in real
+    /// time too. See [`timer_correction`].
+    ///
+    /// # Arguments
+    /// * `ppm` - Timer time per real time in parts per million
+    ///   ([`NO_CORRECTION`] for none)
+    ///
+    /// # Returns
+    /// The controller with the new setting
+    pub fn with_timer_correction(mut self, ppm: u32) -> Self {
+        self.timer_correction = ppm;
+        self
+    }
+
+    /// Drive the LEDs through their output profiles
+    ///
+    /// The pins must have been configured with the same profiles (see
+    /// [`OutputProfile::output`]); the controller only needs to know which
+    /// level lights each LED.
+    ///
+    /// # Arguments
+    /// * `outputs` - Profiles of the [red, green, blue] pins (default
+    ///   [`OutputProfile::DIRECT`])
+    ///
+    /// # Returns
+    /// The controller with the new setting
+    pub fn with_outputs(mut self, outputs: [OutputProfile; 3]) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    /// Move each output level one frame's worth toward its target
+    ///
+    /// # Arguments
+    /// * `targets` - Levels to move toward this frame
+    fn advance_levels(&mut self, targets: [u32; 3]) {
+        for (level, target) in self.levels.iter_mut().zip(targets) {
+            *level = slew_toward(*level, target, self.slew_rate);
+        }
+    }
+
+    /// Replace the duty tables used while gamma is on
+    ///
+    /// The default is [`GAMMA_DUTY_TABLE`] for every channel (see the
+    /// module-level duty table notes). With gamma off the on-time is linear
+    /// in the level, rounded as set with the `rounding` command.
+    ///
+    /// # Arguments
+    /// * `tables` - Duty tables for [red, green, blue]
+    ///
+    /// # Returns
+    /// The controller with the new setting
+    pub fn with_duty_tables(mut self, tables: [DutyTable; 3]) -> Self {
+        self.duty_tables = tables;
+        self
+    }
+
+    /// Split a channel's slot into on and off times for a level
+    ///
+    /// # Arguments
+    /// * `led` - LED index (0=red, 1=green, 2=blue)
+    /// * `level` - Output level (0 to LEVELS-1)
+    ///
+    /// # Returns
+    /// (on-time, off-time) in microseconds, from the channel's duty table
+    /// while gamma is on
+    fn split_level(&self, led: usize, level: u32) -> (u64, u64) {
+        if !self.gamma {
+            return self
+                .rounding
+                .split(level, self.tick_time, self.slot_time, LEVELS);
+        }
+        let duty = self.duty_tables[led][level.min(LEVELS - 1) as usize] as u128;
+        let one = DUTY_TABLE_ONE as u128;
+        let on_time = ((self.slot_time as u128 * duty + one / 2) / one) as u64;
+        (on_time, self.slot_time - on_time.min(self.slot_time))
+    }
+
+    /// Feed a hardware watchdog from the scan loop
+    ///
+    /// The loop feeds it at the start of every frame, and every wait longer
+    /// than `WATCHDOG_FEED_MS` is broken up to feed it, so only a stalled
+    /// loop lets it run out (see the `watchdog` module).
+    ///
+    /// # Arguments
+    /// * `watchdog` - Handle of a started watchdog (see [`start_watchdog`])
+    ///
+    /// # Returns
+    /// The controller feeding the watchdog
+    pub fn with_watchdog(mut self, watchdog: WatchdogHandle) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Feed the watchdog, if there is one
+    fn feed_watchdog(&mut self) {
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.pet();
+        }
+    }
+
+    /// Wait for a future, feeding the watchdog every `WATCHDOG_FEED_MS`
+    /// until it completes
+    ///
+    /// # Arguments
+    /// * `future` - Future to wait for
+    ///
+    /// # Returns
+    /// The future's output
+    async fn fed<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        if self.watchdog.is_none() {
+            return future.await;
+        }
+        loop {
+            let feed = self.pacer.clock().delay_us(WATCHDOG_FEED_MS * 1000);
+            match select(future.as_mut(), feed).await {
+                Either::First(output) => return output,
+                Either::Second(()) => self.feed_watchdog(),
+            }
         }
     }
 
+    /// Wait for a span measured from the end of the previous wait
+    ///
+    /// # Arguments
+    /// * `micros` - Length of the span in microseconds
+    async fn wait(&mut self, micros: u64) {
+        let deadline = self.pacer.schedule(micros);
+
+        // Only slow motion makes a step long enough to need feeding
+        let feed = Duration::from_millis(WATCHDOG_FEED_MS);
+        while self.watchdog.is_some() && deadline.saturating_duration_since(self.pacer.now()) > feed
+        {
+            self.pacer.clock().delay_us(WATCHDOG_FEED_MS * 1000).await;
+            self.feed_watchdog();
+        }
+        self.pacer.clock().delay_until(deadline).await;
+    }
+
     /// Execute one time slice for a single LED color
     ///
     /// This implements pulse-width modulation by turning the LED on for a time
     /// proportional to its brightness level, then off for the remaining time.
     /// Total time per step is always the same to maintain consistent frame rate.
     ///
+    /// With several sub-frames, each step covers this LED's share of the
+    /// frame for one sub-frame.
+    ///
+    /// A channel that is not due for a refresh this frame (see the
+    /// module-level refresh multiplier notes) stays dark for the whole slot.
+    ///
+    /// A channel with a level fraction that has reached its target gets an
+    /// on-time between its level's and the next level's, the off-time
+    /// giving up the difference (see the module-level blending notes).
+    ///
     /// # Arguments
     /// * `led` - LED index (0=red, 1=green, 2=blue)
-    async fn step(&mut self, led: usize) {
-        let level = self.levels[led];
+    /// * `sub_frame` - Sub-frame index (0 to sub_frames-1)
+    async fn step(&mut self, led: usize, sub_frame: u32) {
+        let (on_total, off_total) = self.slot_times(led);
+
+        // Narrate each step when slowed down for watching
+        if self.slow_motion.is_some() {
+            let names = ["red", "green", "blue"];
+            rprintln!(
+                "slow: {} on {} ms, off {} ms",
+                names[led],
+                on_total / 1000,
+                off_total / 1000
+            );
+        }
+
+        // On a trailing-edge frame the off-time comes first
+        let on_time = share(on_total, sub_frame, self.sub_frames);
+        let off_time = share(off_total, sub_frame, self.sub_frames);
+        let [before, on_time, after] = slot_windows(on_time, off_time, self.trailing);
+        if before > 0 {
+            self.wait(before).await;
+        }
 
         // Turn LED on for time proportional to brightness level
-        if level > 0 {
-            self.rgb[led].set_high();
-            let on_time = level as u64 * self.tick_time;
-            Timer::after_micros(on_time).await;
-            self.rgb[led].set_low();
+        if on_time > 0 {
+            self.outputs[led].set(&mut self.rgb[led], true);
+            self.shared.set_active_led(Some(led));
+            self.wait(on_time).await;
+            self.shared.set_active_led(None);
+            self.outputs[led].set(&mut self.rgb[led], false);
         }
 
         // Turn LED off for remaining time to complete the time slice
-        let off_level = LEVELS - level;
-        if off_level > 0 {
-            let off_time = off_level as u64 * self.tick_time;
-            Timer::after_micros(off_time).await;
+        if after > 0 {
+            self.wait(after).await;
+        }
+    }
+
+    /// Light the channels one at a time, spread across one sub-frame
+    ///
+    /// The sub-frame is cut into 3 * LEVELS equal ticks, and each channel
+    /// accrues an equal share of its on-time every tick. Each tick goes to
+    /// the channel owed the most, lit for up to the whole tick; whatever
+    /// it could not get carries over to the next tick and frame, as the
+    /// error does in Bresenham's line algorithm. Every channel keeps its
+    /// on-time over a frame, and as the channels together are owed at most
+    /// a tick per tick, the carry stays within a few ticks. The PWM edge
+    /// does not apply.
+    ///
+    /// # Arguments
+    /// * `sub_frame` - Which sub-frame of the frame this is
+    async fn spread_step(&mut self, sub_frame: u32) {
+        let times = [0, 1, 2].map(|led| self.slot_times(led));
+        let on = times.map(|(on_total, _)| share(on_total, sub_frame, self.sub_frames));
+        let frame = times.iter().fold(0u64, |frame, (on_total, off_total)| {
+            frame.saturating_add(*on_total).saturating_add(*off_total)
+        });
+        let frame = share(frame, sub_frame, self.sub_frames);
+
+        if self.slow_motion.is_some() {
+            rprintln!(
+                "slow: spread on {} {} {} ms, frame {} ms",
+                on[0] / 1000,
+                on[1] / 1000,
+                on[2] / 1000,
+                frame / 1000
+            );
+        }
+
+        let ticks = 3 * LEVELS;
+        for tick in 0..ticks {
+            for (owed, &on_time) in self.spread_owed.iter_mut().zip(&on) {
+                *owed = owed.saturating_add(share(on_time, tick, ticks));
+            }
+            let tick_time = share(frame, tick, ticks);
+            let Some((led, owed)) = self
+                .spread_owed
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by_key(|&(_, owed)| owed)
+            else {
+                continue;
+            };
+            let lit = owed.min(tick_time);
+            if lit > 0 {
+                self.outputs[led].set(&mut self.rgb[led], true);
+                self.shared.set_active_led(Some(led));
+                self.wait(lit).await;
+                self.shared.set_active_led(None);
+                self.outputs[led].set(&mut self.rgb[led], false);
+                self.spread_owed[led] -= lit;
+            }
+            if tick_time > lit {
+                self.wait(tick_time - lit).await;
+            }
         }
     }
 
+    /// On and off time of a channel's slot in the current frame
+    ///
+    /// # Arguments
+    /// * `led` - LED index (0=red, 1=green, 2=blue)
+    ///
+    /// # Returns
+    /// The (on, off) times in microseconds over the whole frame, with the
+    /// on-time blended toward the next level and zero on a frame the
+    /// channel's refresh multiplier skips, or split exactly by the
+    /// channel's measurement duty if it has one; either way capped to
+    /// [`MAX_DUTY`] (see [`Rgb::cap_duty`])
+    fn slot_times(&self, led: usize) -> (u64, u64) {
+        if let Some(duty) = self.duties[led] {
+            let duty = duty.min(DUTY_ONE) as u128;
+            let on = (self.slot_time as u128 * duty + DUTY_ONE as u128 / 2) / DUTY_ONE as u128;
+            let on = on as u64;
+            return self.cap_duty(on, self.slot_time - on);
+        }
+        // Only a channel that has reached its target is blended
+        let level = self.levels[led];
+        let blend = (self.fractions[led] > 0 && level == self.targets[led])
+            .then_some((self.next_targets[led], self.fractions[led]));
+        let split = |level| self.split_level(led, level);
+        let (on_total, off_total) = slot_split(split, level, blend, LEVELS);
+        let (on_total, off_total) = self.refresh.slot(led, on_total, off_total);
+        self.cap_duty(on_total, off_total)
+    }
+
+    /// Limit a slot's on-time to [`MAX_DUTY`] of the frame
+    ///
+    /// The frame is the three slots of a scan, or the one slot repeated
+    /// while a channel is held. The excess moves to the off-time, so the
+    /// timing is unchanged. As the LED is always switched off after its
+    /// on-time, every frame then has it dark for at least two thirds of
+    /// the time, and no setting can leave it lit for good.
+    ///
+    /// # Arguments
+    /// * `on` - On-time in microseconds
+    /// * `off` - Off-time in microseconds
+    ///
+    /// # Returns
+    /// The capped (on, off) times
+    fn cap_duty(&self, on: u64, off: u64) -> (u64, u64) {
+        let slots = if self.hold.is_some() { 1 } else { 3 };
+        let frame = self.slot_time.saturating_mul(slots) as u128;
+        let max_on = (frame * MAX_DUTY as u128 + DUTY_ONE as u128 / 2) / DUTY_ONE as u128;
+        let capped = on.min(max_on as u64);
+        (capped, off.saturating_add(on - capped))
+    }
+
     /// Main RGB scanning loop
     ///
     /// Continuously cycles through red, green, and blue LEDs, displaying each
     /// for a time proportional to its brightness setting. Updates brightness
     /// levels and frame rate from shared state each frame to maintain
     /// consistent timing.
+    ///
+    /// Scanning starts only once the UI has initialized the shared state, so
+    /// the LEDs never show the placeholder levels at boot.
+    ///
+    /// When every channel is dark, the loop stops scanning and sleeps until
+    /// a state change makes some channel visible again.
     pub async fn run(mut self) -> ! {
+        // Subscribe before waiting so no startup update is missed; without a
+        // free subscriber slot the loop just keeps scanning when dark
+        let mut updates = self.shared.subscribe().ok();
+
+        // Keep the LEDs dark until the initial levels are known
+        self.fed(self.shared.wait_state_ready()).await;
+        let started = self.pacer.now();
+
         loop {
-            // Get latest brightness levels from UI
-            self.levels = get_rgb_levels().await;
-
-            // Get current frame rate and update tick time if changed
-            let current_frame_rate = get_frame_rate().await;
-            let expected_tick_time = Self::frame_tick_time(current_frame_rate);
-            if self.tick_time != expected_tick_time {
-                self.tick_time = expected_tick_time;
+            // Show the indicator's liveness pixel and the watchdog that the
+            // loop still runs
+            self.shared.mark_rgb_alive();
+            self.feed_watchdog();
+
+            // A benchmark takes over the LEDs for a moment; the snapshot
+            // afterwards restores normal output
+            if self.shared.take_benchmark_request() {
+                self.benchmark().await;
+                self.last_snapshot = None;
+                self.pacer.restart();
+                self.frame_count = None;
             }
 
-            // Scan through each color: red (0), green (1), blue (2)
-            for led in 0..3 {
-                self.step(led).await;
+            // Refresh the snapshot when due; no shared state is read after
+            // this point in the frame
+            let now = self.pacer.now();
+            let due = self
+                .last_snapshot
+                .is_none_or(|last| now.duration_since(last) >= self.refresh_interval);
+            let stopped = self.shared.emergency_off();
+            if due || stopped {
+                self.last_snapshot = Some(now);
+                self.snapshot().await;
             }
+
+            // Let the animation adjust a copy, so its changes never
+            // accumulate across frames sharing a snapshot
+            let mut targets = self.targets;
+            let elapsed_us = now.duration_since(started).as_micros();
+            self.animation.apply(elapsed_us, &mut targets);
+            self.advance_levels(targets);
+            if stopped || self.cue {
+                self.levels = [0; 3];
+            }
+
+            // Nothing to show: sleep instead of scanning pure off-time
+            if self.levels == [0; 3] && self.dark() {
+                if let Some(updates) = updates.as_mut() {
+                    self.shared.set_actual_frame_rate(0).await;
+                    self.idle(updates).await;
+                    self.frame_count = None;
+                    continue;
+                }
+            }
+
+            // Continue the schedule from the end of the previous frame, but
+            // start afresh after idling or if it fell over a frame behind
+            let frame = Duration::from_micros(self.slot_time.saturating_mul(3).min(MAX_WAIT_US));
+            self.pacer.catch_up(frame);
+
+            // Scan through each color: red (0), green (1), blue (2),
+            // once per sub-frame, or spread them across it; a held channel
+            // is repeated on its own at a steady duty instead
+            for sub_frame in 0..self.sub_frames {
+                match (self.hold, self.drive) {
+                    (Some(led), _) => self.step(led, sub_frame).await,
+                    (None, DriveMode::Spread) => self.spread_step(sub_frame).await,
+                    (None, DriveMode::Scan) => {
+                        for led in 0..3 {
+                            self.step(led, sub_frame).await;
+                        }
+                    }
+                }
+            }
+            self.count_frame().await;
+            self.refresh.advance();
+            self.trailing = self.edge.next_trailing(self.trailing);
+        }
+    }
+
+    /// Count a completed frame and report the measured frame rate once per
+    /// measurement window
+    async fn count_frame(&mut self) {
+        let now = self.pacer.now();
+        let (start, frames) = self.frame_count.unwrap_or((now, 0));
+        let frames = frames + 1;
+        let elapsed = now.duration_since(start);
+        if elapsed >= FRAME_RATE_WINDOW {
+            let millihertz = self.measured_millihertz(frames, elapsed);
+            self.shared
+                .set_actual_frame_rate(millihertz.min(u32::MAX as u64) as u32)
+                .await;
+            self.frame_count = Some((now, 0));
+        } else {
+            self.frame_count = Some((start, frames));
+        }
+    }
+
+    /// Convert a frame count over a span of timer time to a real frame rate
+    ///
+    /// # Arguments
+    /// * `frames` - Frames completed
+    /// * `elapsed` - Timer time they took
+    ///
+    /// # Returns
+    /// Frame rate in mHz, corrected for the timer clock
+    fn measured_millihertz(&self, frames: u32, elapsed: Duration) -> u64 {
+        let millihertz = frames as u64 * 1_000_000_000 / elapsed.as_micros().max(1);
+        millihertz.saturating_mul(self.timer_correction as u64) / NO_CORRECTION as u64
+    }
+
+    /// Find the highest frame rate the scan sustains
+    ///
+    /// Runs trials at doubling frame rates, starting at `MAX_FRAME_RATE`,
+    /// until one falls behind or the tick time would round to zero, then
+    /// bisects between the last sustained and first failed rate to within
+    /// 2%. The levels, blend, hold and slow motion of the snapshot are set
+    /// aside for the trials; the sub-frames and rounding stay, as they are
+    /// part of what is measured. The emergency off still darkens the LEDs
+    /// within a frame; the trials then carry on dark.
+    async fn benchmark(&mut self) {
+        let levels = self.levels;
+        self.levels = [LEVELS / 2; 3];
+        self.fractions = [0; 3];
+        self.refresh.restart();
+        self.hold = None;
+        self.slow_motion = None;
+        self.trailing = false;
+        rprintln!("benchmark: running");
+
+        // Above this the tick time rounds to zero
+        let tick_limit = 2_000_000 / (3 * LEVELS as u64);
+        let mut sustained = 0;
+        let mut failed = tick_limit + 1;
+        let mut frame_rate = MAX_FRAME_RATE.min(tick_limit);
+        while failed - sustained > (sustained / 50).max(1) {
+            let measured = self.benchmark_trial(frame_rate).await;
+            if measured * 100 >= frame_rate * 1000 * BENCHMARK_SUSTAINED_PERCENT {
+                sustained = frame_rate;
+            } else {
+                failed = frame_rate;
+            }
+            frame_rate = if failed > tick_limit {
+                (frame_rate * 2).min(tick_limit)
+            } else {
+                (sustained + failed) / 2
+            };
         }
+
+        if sustained == 0 {
+            rprintln!("benchmark: could not sustain {} fps", failed);
+        } else {
+            let limit = if failed > tick_limit {
+                "tick time"
+            } else {
+                "scan loop"
+            };
+            rprintln!(
+                "benchmark: max {} fps, limited by {} (tick limit {} fps)",
+                sustained,
+                limit,
+                tick_limit
+            );
+        }
+        self.levels = levels;
+    }
+
+    /// Scan at a fixed frame rate for one benchmark trial
+    ///
+    /// # Arguments
+    /// * `frame_rate` - Frame rate to try
+    ///
+    /// # Returns
+    /// Measured frame rate in mHz
+    async fn benchmark_trial(&mut self, frame_rate: u64) -> u64 {
+        self.tick_time = self.corrected(frame_tick_time(frame_rate, LEVELS));
+        self.slot_time = self.corrected(frame_slot_time(frame_rate));
+        let frames = (frame_rate.saturating_mul(BENCHMARK_TRIAL.as_millis()) / 1000)
+            .clamp(1, u32::MAX as u64) as u32;
+
+        self.pacer.restart();
+        let start = self.pacer.now();
+        for _ in 0..frames {
+            self.feed_watchdog();
+            if self.shared.emergency_off() {
+                self.levels = [0; 3];
+            }
+            for sub_frame in 0..self.sub_frames {
+                for led in 0..3 {
+                    self.step(led, sub_frame).await;
+                }
+            }
+        }
+        self.measured_millihertz(frames, self.pacer.now().duration_since(start))
+    }
+
+    /// Check whether the snapshot lights no channel at all
+    ///
+    /// # Returns
+    /// `true` if every target is off, no channel blends toward a lit
+    /// level and no measurement duty lights one
+    fn dark(&self) -> bool {
+        let blended = (0..3).any(|led| self.fractions[led] > 0 && self.next_targets[led] > 0);
+        let measured = self
+            .duties
+            .iter()
+            .any(|duty| duty.is_some_and(|duty| duty > 0));
+        self.targets == [0; 3] && !blended && !measured
+    }
+
+    /// Wait for a state change that lights some channel, or a benchmark
+    /// request
+    ///
+    /// All LEDs are already off at a frame boundary, so the task can simply
+    /// block on the state stream, letting the MCU sleep. While the liveness
+    /// pixel is on, it also wakes every `LIVENESS_MS` to keep it blinking,
+    /// and the watchdog is fed throughout.
+    ///
+    /// # Arguments
+    /// * `updates` - Subscription to the shared state stream
+    async fn idle(&mut self, updates: &mut StateSubscriber<'_>) {
+        while self.dark() && !self.shared.benchmark_requested() {
+            if self.shared.get_liveness().await {
+                let liveness = self.pacer.clock().delay_us(LIVENESS_MS * 1000);
+                let woken = select(updates.next(), liveness).await;
+                self.shared.mark_rgb_alive();
+                self.feed_watchdog();
+                if let Either::Second(()) = woken {
+                    continue;
+                }
+            } else {
+                self.fed(updates.next()).await;
+            }
+            self.snapshot().await;
+        }
+        self.last_snapshot = Some(self.pacer.now());
+    }
+
+    /// Snapshot the shared state for the next frame
+    ///
+    /// This is the only place the RGB task reads shared state (see the
+    /// module-level update contract). Levels and tick time stay fixed until
+    /// the next call.
+    async fn snapshot(&mut self) {
+        // Get latest brightness levels from UI, with disabled channels off
+        // and corrections applied once per snapshot rather than in every step;
+        // an LED cue blanks them for its few frames
+        self.cue = self.shared.led_cue_active().await;
+        self.targets = if self.shared.emergency_off() || self.cue {
+            [0; 3]
+        } else {
+            self.shared.get_output_levels().await
+        };
+
+        // Pick up any blend toward the next levels; none while stopped or
+        // animating, as the animated levels are no longer the targets
+        self.animation = self.shared.get_animation().await;
+        self.fractions = if self.shared.emergency_off() || self.animation.per_frame() {
+            [0; 3]
+        } else {
+            self.shared.get_level_fractions().await
+        };
+        if self.fractions != [0; 3] {
+            self.next_targets = self.shared.get_next_output_levels().await;
+        }
+
+        // Check whether one channel is held for measurement
+        self.hold = self.shared.get_hold_channel().await;
+        self.edge = self.shared.get_pwm_edge().await;
+        self.gamma = self.shared.get_gamma().await;
+        self.drive = self.shared.get_drive_mode().await;
+        if self.drive != DriveMode::Spread || self.shared.emergency_off() {
+            self.spread_owed = [0; 3];
+        }
+        self.duties = if self.shared.emergency_off() {
+            [None; 3]
+        } else {
+            self.shared.get_measure_duties().await
+        };
+
+        // A changed multiplier restarts that channel's count, so it is
+        // shown on the next frame
+        let multipliers = self.shared.get_refresh_multipliers().await;
+        self.refresh.set_multipliers(multipliers);
+
+        // Get current frame rate; the new tick time applies from the next
+        // step, which always starts a frame. Slow motion and the timer
+        // correction stretch both times alike, keeping the duty of every
+        // level.
+        let frame_rate = self.shared.get_frame_rate().await;
+        self.slow_motion = self.shared.get_slow_motion().await;
+        self.sub_frames = self.shared.get_sub_frames().await;
+        self.slew_rate = self.shared.get_slew_rate().await;
+        self.rounding = self.shared.get_rounding().await;
+        self.refresh_interval = Duration::from_hz(self.shared.get_state_refresh().await);
+        let stretch = self.slow_motion.unwrap_or(1) as u64;
+        self.tick_time =
+            self.corrected(frame_tick_time(frame_rate, LEVELS).saturating_mul(stretch));
+        self.slot_time = self.corrected(frame_slot_time(frame_rate).saturating_mul(stretch));
     }
 }
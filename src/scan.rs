@@ -0,0 +1,218 @@
+//! Scan Scheduling
+//!
+//! Frame-by-frame choices of the multiplex scan that do not depend on the
+//! hardware: which channels a frame refreshes (see [`RefreshCounter`]) and
+//! where each slot's on-window sits (see [`PwmEdge`] and [`slot_windows`]).
+//! The RGB task asks these about every slot, so the rules are tested on
+//! the host like the timing math in `calib`.
+
+/// Frame counters behind the per-channel refresh multipliers
+///
+/// A channel with multiplier N is shown on one frame in N and dark on the
+/// others. Each channel counts its frames on its own, starting with a
+/// shown frame; with every multiplier at 1 (the default) every channel is
+/// shown every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefreshCounter {
+    /// Frames per refresh of each channel [red, green, blue], at least 1
+    multipliers: [u32; 3],
+    /// Frames since each channel was last shown; a channel is shown when
+    /// its count is 0
+    frames: [u32; 3],
+}
+
+impl RefreshCounter {
+    /// Counter refreshing every channel every frame
+    pub const fn new() -> Self {
+        Self {
+            multipliers: [1; 3],
+            frames: [0; 3],
+        }
+    }
+
+    /// Change the multipliers
+    ///
+    /// A changed multiplier restarts that channel's count, so it is shown
+    /// on the next frame; the other channels keep their rhythm.
+    ///
+    /// # Arguments
+    /// * `multipliers` - Frames per refresh of each channel (0 counts as 1)
+    pub fn set_multipliers(&mut self, multipliers: [u32; 3]) {
+        let multipliers = multipliers.map(|multiplier| multiplier.max(1));
+        let counts = self.frames.iter_mut().zip(self.multipliers);
+        for ((count, old), new) in counts.zip(multipliers) {
+            if new != old {
+                *count = 0;
+            }
+        }
+        self.multipliers = multipliers;
+    }
+
+    /// Whether a channel is shown on the current frame
+    ///
+    /// # Arguments
+    /// * `led` - LED index (0=red, 1=green, 2=blue)
+    pub fn due(&self, led: usize) -> bool {
+        self.frames[led] == 0
+    }
+
+    /// Apply the multiplier to a channel's slot
+    ///
+    /// # Arguments
+    /// * `led` - LED index (0=red, 1=green, 2=blue)
+    /// * `on` - On-time of the slot in microseconds
+    /// * `off` - Off-time of the slot in microseconds
+    ///
+    /// # Returns
+    /// The slot unchanged on a frame the channel is shown, otherwise all
+    /// of it dark, so the slot keeps its length either way
+    pub fn slot(&self, led: usize, on: u64, off: u64) -> (u64, u64) {
+        if self.due(led) {
+            (on, off)
+        } else {
+            (0, off.saturating_add(on))
+        }
+    }
+
+    /// Count a completed frame for every channel
+    pub fn advance(&mut self) {
+        for (count, multiplier) in self.frames.iter_mut().zip(self.multipliers) {
+            *count = (*count + 1) % multiplier;
+        }
+    }
+
+    /// Show every channel on the next frame, keeping the multipliers
+    pub fn restart(&mut self) {
+        self.frames = [0; 3];
+    }
+}
+
+impl Default for RefreshCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Position of the on-window within each slot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PwmEdge {
+    /// On-window at the start of every slot
+    #[default]
+    Leading,
+    /// On-window at the start of the slot on even frames and at the end on
+    /// odd frames
+    Alternating,
+}
+
+impl PwmEdge {
+    /// Lowercase name used in commands and replies
+    pub fn name(self) -> &'static str {
+        match self {
+            PwmEdge::Leading => "leading",
+            PwmEdge::Alternating => "alternate",
+        }
+    }
+
+    /// Edge of the frame after one
+    ///
+    /// # Arguments
+    /// * `trailing` - Whether this frame's on-windows are at the end
+    ///
+    /// # Returns
+    /// Whether the next frame's on-windows are at the end of their slots
+    pub fn next_trailing(self, trailing: bool) -> bool {
+        self == PwmEdge::Alternating && !trailing
+    }
+}
+
+/// Place a slot's on-window
+///
+/// # Arguments
+/// * `on` - On-time of the slot in microseconds
+/// * `off` - Off-time of the slot in microseconds
+/// * `trailing` - Whether the on-window goes at the end of the slot
+///
+/// # Returns
+/// [off-time before the on-window, on-time, off-time after it]; only the
+/// order changes, never the times
+pub fn slot_windows(on: u64, off: u64, trailing: bool) -> [u64; 3] {
+    if trailing {
+        [off, on, 0]
+    } else {
+        [0, on, off]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slots of a few frames at levels 0, 5 and 15 (of 16, at 100 fps)
+    fn frames(counter: &mut RefreshCounter, count: usize) -> Vec<[(u64, u64); 3]> {
+        let slots = [(0, 3_333), (1_042, 2_291), (3_125, 208)];
+        (0..count)
+            .map(|_| {
+                let frame = [0, 1, 2].map(|led| counter.slot(led, slots[led].0, slots[led].1));
+                counter.advance();
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unit_multipliers_leave_the_scan_alone() {
+        let plain = [[(0, 3_333), (1_042, 2_291), (3_125, 208)]; 12];
+        let mut counter = RefreshCounter::default();
+        assert_eq!(frames(&mut counter, 12), plain);
+        // Setting them to 1 again, or to 0, changes nothing either
+        counter.set_multipliers([1; 3]);
+        assert_eq!(frames(&mut counter, 12), plain);
+        counter.set_multipliers([0; 3]);
+        assert_eq!(frames(&mut counter, 12), plain);
+        counter.restart();
+        assert_eq!(frames(&mut counter, 12), plain);
+    }
+
+    #[test]
+    fn channels_count_their_own_frames() {
+        let mut counter = RefreshCounter::new();
+        counter.set_multipliers([2, 1, 3]);
+        for frame in 0..6 {
+            let expected = [frame % 2 == 0, true, frame % 3 == 0];
+            assert_eq!([0, 1, 2].map(|led| counter.due(led)), expected);
+            // A skipped slot is dark for its whole length
+            let (on, off) = counter.slot(0, 1_042, 2_291);
+            assert_eq!(on + off, 3_333);
+            assert_eq!(on, if expected[0] { 1_042 } else { 0 });
+            counter.advance();
+        }
+        // Changing red's multiplier restarts only red
+        counter.advance();
+        counter.set_multipliers([4, 1, 3]);
+        assert_eq!([0, 1, 2].map(|led| counter.due(led)), [true, true, false]);
+    }
+
+    #[test]
+    fn alternating_edge_keeps_the_duty() {
+        // Level 5 of 16 at 100 fps, over eight frames
+        let (on, off) = (1_042, 2_291);
+        let mut trailing = false;
+        let mut lit = 0;
+        let mut starts = Vec::new();
+        for _ in 0..8 {
+            let [before, on_time, after] = slot_windows(on, off, trailing);
+            assert_eq!(before + on_time + after, on + off);
+            lit += on_time;
+            starts.push(before);
+            trailing = PwmEdge::Alternating.next_trailing(trailing);
+        }
+        // The window moves between the two ends of the slot every frame
+        assert_eq!(starts, [0, off, 0, off, 0, off, 0, off]);
+        assert_eq!(lit, 8 * on);
+
+        // Leading edge only: always at the start
+        assert!(!PwmEdge::Leading.next_trailing(false));
+        assert!(!PwmEdge::Leading.next_trailing(true));
+        assert_eq!(slot_windows(on, off, false), [0, on, off]);
+    }
+}
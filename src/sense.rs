@@ -0,0 +1,151 @@
+//! LED Health Sensing
+//!
+//! With the `led-sense` feature, a sense resistor in the LED's common cathode
+//! return is read on a second ADC channel. Whenever the knob is sampled, the
+//! sense voltage is recorded against the channel that was lit during the
+//! conversion. A channel that is repeatedly lit without any current flowing
+//! has most likely failed open and is reported as dead, so a "balanced" white
+//! computed with a missing die isn't trusted.
+//!
+//! The sense resistor is optional hardware, so it is probed at startup
+//! ([`probe_led_sense`]): a reading that stays flat whether or not a channel
+//! is lit means nothing usable is connected. The input is then marked
+//! unavailable and health queries return
+//! [`CalibrationError::SensorUnavailable`] instead
+//! of flagging every channel as dead. The same happens if all three channels
+//! later read dark together, which a missing sensor explains far better than
+//! three dies failing at once.
+
+use crate::*;
+
+#[cfg(feature = "led-sense")]
+use embassy_time::with_timeout;
+
+/// Raw sense reading (14-bit ADC counts) above which current is flowing
+pub const SENSE_THRESHOLD: i16 = 400;
+
+/// Time a channel is lit before the startup probe samples it
+#[cfg(feature = "led-sense")]
+const PROBE_SETTLE: Duration = Duration::from_millis(1);
+
+/// Longest a probe conversion may take before the sense input is given up
+#[cfg(feature = "led-sense")]
+const PROBE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Consecutive dark readings of a lit channel before it is reported dead
+const DEAD_OBSERVATIONS: u32 = 20;
+
+/// Per-channel conduction statistics
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedHealth {
+    /// Readings in a row that found the channel lit but not conducting
+    dark_readings: [u32; 3],
+    /// Whether the sense input is built in and gives plausible readings
+    available: bool,
+}
+
+impl LedHealth {
+    /// Statistics with no observations, with the sense input available if
+    /// it is built in
+    pub const fn new() -> Self {
+        Self {
+            dark_readings: [0; 3],
+            available: cfg!(feature = "led-sense"),
+        }
+    }
+
+    /// Record a sense reading taken while a channel was lit
+    ///
+    /// Readings are ignored once the input is unavailable. If every channel
+    /// has read dark long enough to count as dead, the input is marked
+    /// unavailable instead.
+    ///
+    /// # Arguments
+    /// * `led` - Channel lit for the whole conversion
+    /// * `sense` - Raw sense ADC reading
+    pub fn record(&mut self, led: usize, sense: i16) {
+        if !self.available {
+            return;
+        }
+        if sense >= SENSE_THRESHOLD {
+            self.dark_readings[led] = 0;
+        } else {
+            self.dark_readings[led] = self.dark_readings[led].saturating_add(1);
+        }
+        if self
+            .dark_readings
+            .iter()
+            .all(|&readings| readings >= DEAD_OBSERVATIONS)
+        {
+            self.mark_unavailable();
+        }
+    }
+
+    /// Give up on the sense input, clearing all observations
+    pub fn mark_unavailable(&mut self) {
+        self.available = false;
+        self.dark_readings = [0; 3];
+    }
+
+    /// Whether the sense input is built in and gives plausible readings
+    pub fn available(&self) -> bool {
+        self.available
+    }
+
+    /// Channels that appear to have failed open
+    ///
+    /// # Returns
+    /// Flags [red, green, blue], `true` for a channel repeatedly lit
+    /// without current, or [`CalibrationError::SensorUnavailable`]
+    pub fn dead(&self) -> Result<[bool; 3], CalibrationError> {
+        if !self.available {
+            return Err(CalibrationError::SensorUnavailable);
+        }
+        Ok(self
+            .dark_readings
+            .map(|readings| readings >= DEAD_OBSERVATIONS))
+    }
+}
+
+impl Default for LedHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check at startup whether a working sense resistor is connected
+///
+/// Samples the sense input with every pin low, then with each channel lit
+/// in turn for about a millisecond (too short to see). The input works if
+/// it reads no current while dark and current for at least one lit
+/// channel; a flat reading either way, or a stalled conversion, means it
+/// is missing or broken. Must run before the RGB task starts scanning.
+///
+/// # Arguments
+/// * `adc` - Calibrated ADC with the sense input on channel 1
+/// * `pins` - LED pins, all low
+///
+/// # Returns
+/// `Ok` if the sense input works, or [`CalibrationError::SensorUnavailable`]
+#[cfg(feature = "led-sense")]
+pub async fn probe_led_sense(adc: &mut Adc, pins: &mut RgbPins) -> Result<(), CalibrationError> {
+    let mut buf = [0; ADC_CHANNELS];
+    with_timeout(PROBE_TIMEOUT, adc.sample(&mut buf))
+        .await
+        .map_err(|_| CalibrationError::SensorUnavailable)?;
+    let dark = buf[1];
+    let mut lit = i16::MIN;
+    for (pin, profile) in pins.iter_mut().zip(LED_OUTPUTS) {
+        profile.set(pin, true);
+        Timer::after(PROBE_SETTLE).await;
+        let sampled = with_timeout(PROBE_TIMEOUT, adc.sample(&mut buf)).await;
+        profile.set(pin, false);
+        sampled.map_err(|_| CalibrationError::SensorUnavailable)?;
+        lit = lit.max(buf[1]);
+    }
+    if dark < SENSE_THRESHOLD && lit >= SENSE_THRESHOLD {
+        Ok(())
+    } else {
+        Err(CalibrationError::SensorUnavailable)
+    }
+}
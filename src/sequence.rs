@@ -0,0 +1,308 @@
+//! Color Sequence Player
+//!
+//! Plays a short list of color stops on a loop, each held for its own time,
+//! with an optional linear crossfade between them. The player is a task of
+//! its own that writes the requested levels like the UI and command tasks
+//! do, so playback goes through the enable mask, color correction and
+//! lookup tables and runs at whatever frame rate is set.
+//!
+//! Crossfades interpolate the requested levels (see [`crossfade`]), before
+//! the lookup tables, in steps finer than a level: the fraction toward the
+//! next level is handed to the RGB task, which blends the on-times of the
+//! two table entries. With a gamma table uploaded the requested levels are
+//! perceptual, so a fade is even to the eye rather than even in duty, and
+//! it no longer jumps by the table's uneven steps at each whole level.
+//!
+//! The same task also runs one-shot fades requested by the host (the `fade`
+//! command): from one color to another over a given time, with the same
+//! crossfade, then holding the end color. A fade reports when it is done,
+//! and is cut short by a newer fade, an animation or the emergency off.
+
+use crate::*;
+
+use embassy_futures::select::{select, Either};
+
+/// Most stops a sequence can hold
+pub const MAX_STOPS: usize = 8;
+
+/// Longest hold or crossfade accepted, in milliseconds
+pub const MAX_STOP_MS: u32 = 60_000;
+
+/// One stop of a sequence
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColorStop {
+    /// Requested [red, green, blue] levels (0 to LEVELS-1)
+    pub levels: [u32; 3],
+    /// How long the stop is held once reached, in milliseconds
+    pub hold_ms: u32,
+}
+
+/// One-shot fade between two colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fade {
+    /// Requested [red, green, blue] levels at the start
+    pub from: [u32; 3],
+    /// Requested [red, green, blue] levels at the end, held afterwards
+    pub to: [u32; 3],
+    /// Length of the fade in milliseconds (0 cuts straight to `to`)
+    pub ms: u32,
+}
+
+impl Fade {
+    /// Create a fade with its levels and time in range
+    ///
+    /// # Arguments
+    /// * `from` - Start levels (clamped to LEVELS-1)
+    /// * `to` - End levels (clamped to LEVELS-1)
+    /// * `ms` - Length in milliseconds (clamped to `MAX_STOP_MS`)
+    ///
+    /// # Returns
+    /// The clamped fade
+    pub fn new(from: [u32; 3], to: [u32; 3], ms: u32) -> Self {
+        Self {
+            from: from.map(|level| level.min(LEVELS - 1)),
+            to: to.map(|level| level.min(LEVELS - 1)),
+            ms: ms.min(MAX_STOP_MS),
+        }
+    }
+}
+
+/// Error from editing a sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The sequence already holds `MAX_STOPS` stops
+    Full,
+}
+
+/// Looping list of color stops
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    /// Stop storage; only the first `len` entries are used
+    stops: [ColorStop; MAX_STOPS],
+    /// Number of stops in use
+    len: usize,
+    /// Crossfade time from each stop to the next, in milliseconds (0 cuts)
+    fade_ms: u32,
+}
+
+impl Sequence {
+    /// Empty sequence without crossfades
+    pub const fn new() -> Self {
+        Self {
+            stops: [ColorStop {
+                levels: [0; 3],
+                hold_ms: 0,
+            }; MAX_STOPS],
+            len: 0,
+            fade_ms: 0,
+        }
+    }
+
+    /// Append a stop
+    ///
+    /// # Arguments
+    /// * `stop` - Stop to play after the current last one
+    ///
+    /// # Returns
+    /// `Ok(())`, or [`SequenceError::Full`] if there is no room
+    pub fn push(&mut self, stop: ColorStop) -> Result<(), SequenceError> {
+        if self.len == MAX_STOPS {
+            return Err(SequenceError::Full);
+        }
+        self.stops[self.len] = stop;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Remove every stop
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Stops in playing order
+    pub fn stops(&self) -> &[ColorStop] {
+        &self.stops[..self.len]
+    }
+
+    /// Crossfade time between stops in milliseconds
+    pub fn fade_ms(&self) -> u32 {
+        self.fade_ms
+    }
+
+    /// Set the crossfade time between stops
+    ///
+    /// # Arguments
+    /// * `fade_ms` - Crossfade time in milliseconds (0 cuts straight to the
+    ///   next stop; clamped to `MAX_STOP_MS`)
+    pub fn set_fade_ms(&mut self, fade_ms: u32) {
+        self.fade_ms = fade_ms.min(MAX_STOP_MS);
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Task playing the shared sequence and one-shot fades on request
+///
+/// Idle until asked to play; stopping restores the levels that were set
+/// before playback started. A fade instead leaves its end color set.
+pub struct Sequencer {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Levels to restore when playback stops
+    saved: [u32; 3],
+}
+
+impl Sequencer {
+    /// Create an idle sequence player
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    pub fn new(shared: &'static SharedState) -> Self {
+        Self {
+            shared,
+            saved: [0; 3],
+        }
+    }
+
+    /// Wait for a span unless a stop is requested first
+    ///
+    /// # Arguments
+    /// * `duration` - Time to wait
+    ///
+    /// # Returns
+    /// `true` if playback should continue, `false` if it was stopped
+    async fn pause(&self, duration: Duration) -> bool {
+        match select(Timer::after(duration), self.shared.wait_sequence_request()).await {
+            Either::First(()) => !self.shared.emergency_off(),
+            Either::Second(play) => play && !self.shared.emergency_off(),
+        }
+    }
+
+    /// Play the sequence until stopped
+    ///
+    /// Each stop is faded to over the crossfade time (one update per frame)
+    /// and then held for its hold time.
+    async fn play(&mut self) {
+        let sequence = self.shared.get_sequence().await;
+        let stops = sequence.stops();
+        if stops.is_empty() {
+            rprintln!("sequence: empty");
+            return;
+        }
+        self.saved = self.shared.get_rgb_levels().await;
+        let fade_ms = sequence.fade_ms() as u64;
+        let mut current = self.saved;
+        'playing: loop {
+            for stop in stops {
+                if fade_ms > 0 {
+                    let start = Instant::now();
+                    loop {
+                        let elapsed = start.elapsed().as_millis();
+                        let (levels, fractions) = crossfade(current, stop.levels, elapsed, fade_ms);
+                        self.shared.set_blended_levels(levels, fractions).await;
+                        if elapsed >= fade_ms {
+                            break;
+                        }
+                        let frame = Duration::from_hz(self.shared.get_frame_rate().await.max(1));
+                        if !self.pause(frame).await {
+                            break 'playing;
+                        }
+                    }
+                } else {
+                    self.shared.set_rgb_levels(|rgb| *rgb = stop.levels).await;
+                }
+                current = stop.levels;
+                if !self.pause(Duration::from_millis(stop.hold_ms as u64)).await {
+                    break 'playing;
+                }
+            }
+        }
+        // After an emergency off, make sure no fade step written after it
+        // survives; otherwise bring back the levels from before playback
+        // (dropping any fraction left by a fade cut short)
+        let levels = if self.shared.emergency_off() {
+            [0; 3]
+        } else {
+            self.saved
+        };
+        self.shared.set_blended_levels(levels, [0; 3]).await;
+        rprintln!("sequence: stopped");
+    }
+
+    /// Run a one-shot fade
+    ///
+    /// Steps once per frame from the start to the end color, then sets the
+    /// end color and reports completion. An animation or the emergency off
+    /// cancels it where it is; a newer fade replaces it.
+    ///
+    /// # Arguments
+    /// * `fade` - Colors and length of the fade
+    ///
+    /// # Returns
+    /// The fade that replaced this one, if any
+    async fn fade(&mut self, fade: Fade) -> Option<Fade> {
+        let fade_ms = fade.ms as u64;
+        if fade_ms > 0 {
+            let start = Instant::now();
+            loop {
+                let elapsed = start.elapsed().as_millis();
+                let (levels, fractions) = crossfade(fade.from, fade.to, elapsed, fade_ms);
+                self.shared.set_blended_levels(levels, fractions).await;
+                if elapsed >= fade_ms {
+                    break;
+                }
+                let frame = Duration::from_hz(self.shared.get_frame_rate().await.max(1));
+                if let Either::Second(next) =
+                    select(Timer::after(frame), self.shared.wait_fade_request()).await
+                {
+                    rprintln!("fade: replaced");
+                    return Some(next);
+                }
+                if self.shared.emergency_off()
+                    || self.shared.get_animation().await != Animation::Off
+                {
+                    rprintln!("fade: cancelled");
+                    return None;
+                }
+            }
+        }
+        self.shared.set_blended_levels(fade.to, [0; 3]).await;
+        let [red, green, blue] = fade.to;
+        rprintln!("fade: done {} {} {}", red, green, blue);
+        None
+    }
+
+    /// Main sequencer loop
+    ///
+    /// Waits for a play request and plays until a stop request (or the
+    /// emergency off), or for a fade request and runs the fade.
+    pub async fn run(mut self) -> ! {
+        let mut next_fade = None;
+        loop {
+            let fade = match next_fade.take() {
+                Some(fade) => fade,
+                None => match select(
+                    self.shared.wait_sequence_request(),
+                    self.shared.wait_fade_request(),
+                )
+                .await
+                {
+                    Either::First(play) => {
+                        if play && !self.shared.emergency_off() {
+                            self.play().await;
+                        }
+                        continue;
+                    }
+                    Either::Second(fade) => fade,
+                },
+            };
+            if !self.shared.emergency_off() {
+                next_fade = self.fade(fade).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Serial Console
+//!
+//! With the `serial` feature the text interface runs on the UART wired to
+//! the MicroBit's interface chip instead of the RTT terminal. The interface
+//! chip presents it to the host as a USB serial port on the same cable
+//! used for flashing, so commands work without a debug probe:
+//!
+//! - nRF TX: P0.06, nRF RX: P1.08 (the board's console UART)
+//! - 115200 baud, 8 data bits, no parity, one stop bit (8N1), no flow
+//!   control
+//!
+//! The choice is made at build time. The firmware prints its replies and
+//! the UI's state display with `rprint!` and `rprintln!` either way; with
+//! this feature those names refer to this module's macros, which queue the
+//! text for the UART rather than writing it to RTT, ending lines with
+//! CR LF for serial terminals. Like RTT, printing never blocks the task
+//! doing it: a message that does not fit in the queue is dropped whole.
+//! The command task reads its lines from [`SerialInput`] in place of the
+//! RTT down channel. The binary control link, telemetry and panic messages
+//! stay on RTT.
+
+use crate::*;
+
+use core::fmt::{self, Write};
+
+use embassy_futures::join::join;
+use embassy_sync::pipe::Pipe;
+use microbit_bsp::embassy_nrf::{
+    bind_interrupts,
+    peripherals::UARTE0,
+    uarte::{self, Uarte, UarteRx, UarteTx},
+};
+
+/// Baud rate of the serial console
+pub const SERIAL_BAUD: u32 = 115_200;
+
+/// Bytes of output queued for the UART, as much as the RTT terminal holds
+const OUTPUT_LEN: usize = 1024;
+
+/// Bytes of received input queued for the command task
+const INPUT_LEN: usize = 64;
+
+/// Text waiting to be sent
+static OUTPUT: Pipe<ThreadModeRawMutex, OUTPUT_LEN> = Pipe::new();
+
+/// Bytes received and not yet read by the command task
+static INPUT: Pipe<ThreadModeRawMutex, INPUT_LEN> = Pipe::new();
+
+bind_interrupts!(struct Irqs {
+    UARTE0_UART0 => uarte::InterruptHandler<UARTE0>;
+});
+
+/// Print to the serial console, like `rtt_target::rprint!`
+macro_rules! serial_print {
+    ($($arg:tt)*) => {
+        $crate::serial::print(core::format_args!($($arg)*))
+    };
+}
+
+/// Print a line to the serial console, like `rtt_target::rprintln!`
+macro_rules! serial_println {
+    () => {
+        $crate::serial::print(core::format_args!("\r\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::serial::print(core::format_args!("{}\r\n", core::format_args!($($arg)*)))
+    };
+}
+
+pub(crate) use serial_print as rprint;
+pub(crate) use serial_println as rprintln;
+
+/// Queue formatted text for the UART
+///
+/// The text is measured first and dropped whole if the queue can't take
+/// all of it, so the host never sees half a reply.
+///
+/// # Arguments
+/// * `args` - Text to print
+pub fn print(args: fmt::Arguments) {
+    let mut length = Length(0);
+    let _ = length.write_fmt(args);
+    if length.0 > OUTPUT.free_capacity() {
+        return;
+    }
+    let _ = Queue.write_fmt(args);
+}
+
+/// Counter of formatted bytes
+struct Length(usize);
+
+impl Write for Length {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.0 += text.len();
+        Ok(())
+    }
+}
+
+/// Writer into the output queue
+struct Queue;
+
+impl Write for Queue {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        // The queue may take the bytes in two parts where it wraps around
+        let mut bytes = text.as_bytes();
+        while let Ok(count) = OUTPUT.try_write(bytes) {
+            bytes = &bytes[count..];
+            if bytes.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Command input received on the UART
+///
+/// Read by the command task like the RTT down channel it replaces.
+pub struct SerialInput;
+
+impl SerialInput {
+    /// Take the bytes received so far, without waiting
+    ///
+    /// # Arguments
+    /// * `buf` - Buffer to fill
+    ///
+    /// # Returns
+    /// Number of bytes read, 0 if none are waiting
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        INPUT.try_read(buf).unwrap_or(0)
+    }
+}
+
+/// UART driver moving the queued text out and received bytes in
+pub struct Serial {
+    /// Transmit half, sending the output queue
+    tx: UarteTx<'static, UARTE0>,
+    /// Receive half, filling the input queue
+    rx: UarteRx<'static, UARTE0>,
+}
+
+impl Serial {
+    /// Set up the console UART
+    ///
+    /// # Arguments
+    /// * `uarte` - UART peripheral
+    /// * `rxd` - Pin receiving from the interface chip (P1.08)
+    /// * `txd` - Pin sending to the interface chip (P0.06)
+    ///
+    /// # Returns
+    /// The driver, and the input to hand to the command task
+    pub fn new(uarte: UARTE0, rxd: AnyPin, txd: AnyPin) -> (Self, SerialInput) {
+        let mut config = uarte::Config::default();
+        config.parity = uarte::Parity::EXCLUDED;
+        config.baudrate = uarte::Baudrate::BAUD115200;
+        let (tx, rx) = Uarte::new(uarte, Irqs, rxd, txd, config).split();
+        (Self { tx, rx }, SerialInput)
+    }
+
+    /// Move bytes between the UART and the queues - this never returns
+    ///
+    /// Input is read a byte at a time, which at 115200 baud leaves the
+    /// UART's receive FIFO plenty of time between reads. Received bytes
+    /// that don't fit the input queue are dropped, as RTT drops bytes a
+    /// full down channel can't take.
+    pub async fn run(self) -> ! {
+        let Self { mut tx, mut rx } = self;
+        let send = async {
+            let mut buf = [0; 64];
+            loop {
+                let count = OUTPUT.read(&mut buf).await;
+                // A failed write only loses that text; keep going
+                let _ = tx.write(&buf[..count]).await;
+            }
+        };
+        let receive = async {
+            let mut byte = [0];
+            loop {
+                // Framing and overrun errors drop the byte
+                if rx.read(&mut byte).await.is_ok() {
+                    let _ = INPUT.try_write(&byte);
+                }
+            }
+        };
+        join(send, receive).await;
+        unreachable!("serial console stopped")
+    }
+}
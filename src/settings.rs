@@ -0,0 +1,233 @@
+//! Saved Settings
+//!
+//! What is kept across resets besides the calibration: the preset slots
+//! with their tags, the white reference, the button-to-channel map, the
+//! color sequence and the knob's endpoints. They are packed into one blob,
+//! stored as the payload of the flash record at [`SETTINGS_PAGE`], which
+//! the command task rewrites whenever one of them changes and `main` loads
+//! before any task starts. The record is separate from the calibration's,
+//! so saving a preset leaves the startup calibration alone, and a `diff`
+//! compares against the presets actually in flash.
+//!
+//! # Blob Layout
+//!
+//! | Offset | Type        | Field                                         |
+//! |--------|-------------|-----------------------------------------------|
+//! | 0      | u8          | Version, always [`SETTINGS_VERSION`]          |
+//! | 1      | 19 x 4      | Preset slots 1 to 4, as below                 |
+//! | 77     | u8 x 3      | White reference: red, green, blue levels      |
+//! | 80     | u8 x 3      | Channel of A, B and A+B, 0 red to 2 blue      |
+//! | 83     | u16 x 2     | Knob raw min and max, little-endian           |
+//! | 87     | u16         | Sequence crossfade in ms, little-endian       |
+//! | 89     | u8          | Number of sequence stops (0-8)                |
+//! | 90     | 5 x 8       | Sequence stops: levels u8 x 3, hold ms u16    |
+//!
+//! Each preset slot:
+//!
+//! | Offset | Type        | Field                                         |
+//! |--------|-------------|-----------------------------------------------|
+//! | 0      | u8          | 1 if the slot holds a preset, 0 if empty      |
+//! | 1      | u8 x 3      | Red, green, blue levels                       |
+//! | 4      | u16         | Frame rate in fps, little-endian              |
+//! | 6      | u8          | Tag length, 0 if untagged                     |
+//! | 7      | u8 x 12     | Tag characters, padded with zeros             |
+//!
+//! An empty slot is all zeros. The blob has no checksum of its own; the
+//! flash record's CRC-32 covers it.
+
+use crate::*;
+
+/// Version byte of the current blob layout
+pub const SETTINGS_VERSION: u8 = 2;
+
+/// Length of an encoded settings blob in bytes (130)
+pub const SETTINGS_LEN: usize = STOPS_OFFSET + MAX_STOPS * STOP_BYTES;
+
+/// Offset of the preset slots in the blob
+const PRESETS_OFFSET: usize = 1;
+
+/// Bytes used by one preset slot
+const PRESET_BYTES: usize = 7 + TAG_MAX;
+
+/// Offset of the white reference in the blob
+const WHITE_OFFSET: usize = PRESETS_OFFSET + PRESET_SLOTS * PRESET_BYTES;
+
+/// Offset of the button-to-channel map in the blob
+const BUTTON_MAP_OFFSET: usize = WHITE_OFFSET + 3;
+
+/// Offset of the knob endpoints in the blob
+const KNOB_OFFSET: usize = BUTTON_MAP_OFFSET + 3;
+
+/// Offset of the sequence's crossfade time and stop count in the blob
+const SEQUENCE_OFFSET: usize = KNOB_OFFSET + 4;
+
+/// Offset of the sequence stops in the blob
+const STOPS_OFFSET: usize = SEQUENCE_OFFSET + 3;
+
+/// Bytes used by one sequence stop
+const STOP_BYTES: usize = 5;
+
+/// Settings kept in flash besides the calibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavedSettings {
+    /// Contents of every preset slot (`None` if empty)
+    pub presets: [Option<Preset>; PRESET_SLOTS],
+    /// [red, green, blue] levels that look white
+    pub white_reference: [u32; 3],
+    /// Channel each button combination selects
+    pub button_map: ButtonMap,
+    /// Color sequence played by `seq play`
+    pub sequence: Sequence,
+    /// Raw readings at the ends of the knob travel
+    pub knob_calibration: KnobCalibration,
+}
+
+impl SavedSettings {
+    /// Pack the settings into a blob
+    ///
+    /// # Returns
+    /// SETTINGS_LEN bytes
+    pub fn encode(&self) -> [u8; SETTINGS_LEN] {
+        let mut blob = [0; SETTINGS_LEN];
+        blob[0] = SETTINGS_VERSION;
+        for (packed, preset) in blob[PRESETS_OFFSET..]
+            .chunks_exact_mut(PRESET_BYTES)
+            .zip(self.presets)
+        {
+            let Some(preset) = preset else {
+                continue;
+            };
+            packed[0] = 1;
+            for (byte, level) in packed[1..4].iter_mut().zip(preset.levels) {
+                *byte = level as u8;
+            }
+            packed[4..6].copy_from_slice(&(preset.frame_rate as u16).to_le_bytes());
+            if let Some(tag) = preset.tag {
+                let text = tag.as_str().as_bytes();
+                packed[6] = text.len() as u8;
+                packed[7..7 + text.len()].copy_from_slice(text);
+            }
+        }
+        for (byte, level) in blob[WHITE_OFFSET..].iter_mut().zip(self.white_reference) {
+            *byte = level as u8;
+        }
+        for (byte, channel) in blob[BUTTON_MAP_OFFSET..]
+            .iter_mut()
+            .zip(self.button_map.channels())
+        {
+            *byte = channel as u8;
+        }
+        let knob = &self.knob_calibration;
+        blob[KNOB_OFFSET..KNOB_OFFSET + 2].copy_from_slice(&knob.min().to_le_bytes());
+        blob[KNOB_OFFSET + 2..SEQUENCE_OFFSET].copy_from_slice(&knob.max().to_le_bytes());
+        let fade_ms = self.sequence.fade_ms() as u16;
+        blob[SEQUENCE_OFFSET..SEQUENCE_OFFSET + 2].copy_from_slice(&fade_ms.to_le_bytes());
+        let stops = self.sequence.stops();
+        blob[SEQUENCE_OFFSET + 2] = stops.len() as u8;
+        for (packed, stop) in blob[STOPS_OFFSET..].chunks_exact_mut(STOP_BYTES).zip(stops) {
+            for (byte, &level) in packed[..3].iter_mut().zip(&stop.levels) {
+                *byte = level as u8;
+            }
+            packed[3..].copy_from_slice(&(stop.hold_ms as u16).to_le_bytes());
+        }
+        blob
+    }
+
+    /// Unpack and validate a blob
+    ///
+    /// # Arguments
+    /// * `blob` - SETTINGS_LEN bytes as produced by [`Self::encode`]
+    ///
+    /// # Returns
+    /// The settings, or the first problem found
+    pub fn decode(blob: &[u8; SETTINGS_LEN]) -> Result<Self, ConfigError> {
+        if blob[0] != SETTINGS_VERSION {
+            return Err(ConfigError::Version);
+        }
+        let mut presets = [None; PRESET_SLOTS];
+        for (preset, packed) in presets
+            .iter_mut()
+            .zip(blob[PRESETS_OFFSET..].chunks_exact(PRESET_BYTES))
+        {
+            *preset = match packed[0] {
+                0 => None,
+                1 => Some(decode_preset(packed)?),
+                _ => return Err(ConfigError::InvalidSetting),
+            };
+        }
+        let white_reference = decode_levels(&blob[WHITE_OFFSET..BUTTON_MAP_OFFSET])?;
+        let channels = [0, 1, 2].map(|i| usize::from(blob[BUTTON_MAP_OFFSET + i]));
+        let button_map = ButtonMap::new(channels).ok_or(ConfigError::InvalidSetting)?;
+        let raw = |offset: usize| u16::from_le_bytes([blob[offset], blob[offset + 1]]);
+        let knob_calibration = KnobCalibration::new(raw(KNOB_OFFSET), raw(KNOB_OFFSET + 2))
+            .ok_or(ConfigError::InvalidSetting)?;
+        let mut sequence = Sequence::new();
+        let fade_ms = u32::from(raw(SEQUENCE_OFFSET));
+        let count = usize::from(blob[SEQUENCE_OFFSET + 2]);
+        if fade_ms > MAX_STOP_MS || count > MAX_STOPS {
+            return Err(ConfigError::InvalidSetting);
+        }
+        sequence.set_fade_ms(fade_ms);
+        for packed in blob[STOPS_OFFSET..].chunks_exact(STOP_BYTES).take(count) {
+            let hold_ms = u32::from(u16::from_le_bytes([packed[3], packed[4]]));
+            if hold_ms > MAX_STOP_MS {
+                return Err(ConfigError::InvalidSetting);
+            }
+            let levels = decode_levels(&packed[..3])?;
+            sequence
+                .push(ColorStop { levels, hold_ms })
+                .map_err(|_| ConfigError::InvalidSetting)?;
+        }
+        Ok(SavedSettings {
+            presets,
+            white_reference,
+            button_map,
+            sequence,
+            knob_calibration,
+        })
+    }
+}
+
+/// Unpack three levels
+///
+/// # Arguments
+/// * `packed` - [red, green, blue] level bytes
+///
+/// # Returns
+/// The levels, or an error if one is above LEVELS-1
+fn decode_levels(packed: &[u8]) -> Result<[u32; 3], ConfigError> {
+    let levels = [packed[0], packed[1], packed[2]].map(u32::from);
+    if levels.iter().any(|&level| level >= LEVELS) {
+        return Err(ConfigError::LevelOutOfRange);
+    }
+    Ok(levels)
+}
+
+/// Unpack one occupied preset slot
+///
+/// # Arguments
+/// * `packed` - PRESET_BYTES bytes of the blob
+///
+/// # Returns
+/// The preset, or why it is not valid
+fn decode_preset(packed: &[u8]) -> Result<Preset, ConfigError> {
+    let levels = decode_levels(&packed[1..4])?;
+    let frame_rate = u64::from(u16::from_le_bytes([packed[4], packed[5]]));
+    if !(MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&frame_rate) {
+        return Err(ConfigError::FrameRateOutOfRange);
+    }
+    let tag = match usize::from(packed[6]) {
+        0 => None,
+        len if len <= TAG_MAX => {
+            let text = core::str::from_utf8(&packed[7..7 + len])
+                .map_err(|_| ConfigError::InvalidSetting)?;
+            Some(Tag::parse(text).ok_or(ConfigError::InvalidSetting)?)
+        }
+        _ => return Err(ConfigError::InvalidSetting),
+    };
+    Ok(Preset {
+        levels,
+        frame_rate,
+        tag,
+    })
+}
@@ -0,0 +1,2345 @@
+//! Shared Calibration State
+//!
+//! Holds the state shared between the RGB and UI tasks: the RGB brightness
+//! levels, the frame rate and the knob's control mode, among the rest. It
+//! is one [`SharedState`], created in `main` and handed to every task by
+//! reference, so the data flow between tasks is explicit. Each value is
+//! protected by a mutex, and every committed change is also published on a
+//! channel so that other tasks can `.await` the next change instead of
+//! polling.
+
+use crate::*;
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+use embassy_sync::pubsub::{PubSubChannel, Subscriber, WaitResult};
+use embassy_sync::signal::Signal;
+
+/// Marker in [`SharedState::active_led`] for "no channel lit"
+const NO_LED: u8 = u8::MAX;
+
+/// State shared between the tasks
+///
+/// Created once in `main` and handed to each task by reference; every
+/// value is behind a mutex, signal or atomic, so the tasks can read and
+/// change it concurrently through the accessor methods.
+pub struct SharedState {
+    /// RGB brightness levels [red, green, blue]
+    rgb_levels: Mutex<ThreadModeRawMutex, [u32; 3]>,
+    /// Frame rate (frames per second)
+    frame_rate: Mutex<ThreadModeRawMutex, u64>,
+    /// Flag enabling extra diagnostic output over RTT
+    debug_output: Mutex<ThreadModeRawMutex, bool>,
+    /// Output enable mask [red, green, blue]
+    /// Disabled channels keep their level but are output as off
+    channel_enable: Mutex<ThreadModeRawMutex, [bool; 3]>,
+    /// Color-correction matrix applied before output
+    color_matrix: Mutex<ThreadModeRawMutex, ColorMatrix>,
+    /// Output lookup tables [red, green, blue]
+    channel_luts: Mutex<ThreadModeRawMutex, [Lut; 3]>,
+    /// Minimum visible output levels [red, green, blue]
+    min_visible: Mutex<ThreadModeRawMutex, [u32; 3]>,
+    /// Interpretation of the requested levels
+    color_space: Mutex<ThreadModeRawMutex, ColorSpace>,
+    /// Freeze-frame setting: channel output alone at a steady
+    /// duty instead of the color scan, or `None` to scan normally
+    hold_channel: Mutex<ThreadModeRawMutex, Option<usize>>,
+    /// Refresh multipliers: each [red, green, blue] channel is
+    /// shown every Nth frame
+    refresh_multipliers: Mutex<ThreadModeRawMutex, [u32; 3]>,
+    /// Number of sub-frames each frame is divided into
+    sub_frames: Mutex<ThreadModeRawMutex, u32>,
+    /// Cap on how often the RGB task re-reads the shared state,
+    /// in Hz
+    state_refresh_hz: Mutex<ThreadModeRawMutex, u64>,
+    /// Slew rate: the largest change of an output level per frame
+    slew_rate: Mutex<ThreadModeRawMutex, u32>,
+    /// Rounding of slot times to whole microseconds
+    rounding: Mutex<ThreadModeRawMutex, Rounding>,
+    /// Position of the on-window within each slot
+    pwm_edge: Mutex<ThreadModeRawMutex, PwmEdge>,
+    /// Gamma setting: whether on-times follow the duty tables
+    /// rather than being linear in the level
+    gamma: Mutex<ThreadModeRawMutex, bool>,
+    /// Drive mode: channels scanned in turn or spread over the frame
+    drive_mode: Mutex<ThreadModeRawMutex, DriveMode>,
+    /// Measurement duties: an exact duty in DUTY_ONEths overriding
+    /// each [red, green, blue] channel's level, or `None`
+    measure_duties: Mutex<ThreadModeRawMutex, [Option<u32>; 3]>,
+    /// Slow-motion factor stretching every step of the scan, or
+    /// `None` for normal timing
+    slow_motion: Mutex<ThreadModeRawMutex, Option<u32>>,
+    /// Animation run instead of manual control
+    animation: Mutex<ThreadModeRawMutex, Animation>,
+    /// Breathing period in milliseconds, the last one started
+    breathe_period_ms: Mutex<ThreadModeRawMutex, u32>,
+    /// Undo history of the committed levels and frame rate
+    history: Mutex<ThreadModeRawMutex, History>,
+    /// Setting for the liveness pixel on the onboard matrix
+    liveness: Mutex<ThreadModeRawMutex, bool>,
+    /// Signaled by the RGB task on every pass of its loop, taken by the
+    /// indicator to toggle the liveness pixel
+    rgb_alive: Signal<ThreadModeRawMutex, ()>,
+    /// Selection of what the onboard matrix shows
+    indicator_mode: Mutex<ThreadModeRawMutex, IndicatorMode>,
+    /// Binary telemetry rate in Hz, or `None` when disabled
+    telemetry_rate: Mutex<ThreadModeRawMutex, Option<u32>>,
+    /// Heartbeat interval in seconds, or `None` when disabled
+    #[cfg(feature = "heartbeat")]
+    heartbeat_interval: Mutex<ThreadModeRawMutex, Option<u32>>,
+    /// Automatic save policy, or `None` when the calibration is
+    /// only saved on request
+    auto_save: Mutex<ThreadModeRawMutex, Option<SavePolicy>>,
+    /// Frame rate measured by the RGB task, in millihertz
+    /// Besides `active_led`, the only shared value the RGB task writes
+    actual_frame_rate: Mutex<ThreadModeRawMutex, u32>,
+    /// Channel whose pin is currently driven high, or `NO_LED`
+    /// Atomic rather than a mutex so the RGB task can set it around every
+    /// on-time
+    active_led: AtomicU8,
+    /// LED conduction statistics from the current sense input
+    led_health: Mutex<ThreadModeRawMutex, LedHealth>,
+    /// Encoder detents turned since the UI last read the encoder (positive
+    /// = clockwise), counted by the encoder's decoder task
+    encoder_detents: AtomicI32,
+    /// Per-channel thermal derating model
+    thermal_derating: Mutex<ThreadModeRawMutex, ThermalDerating>,
+    /// Average current budget and LED on-currents
+    power_budget: Mutex<ThreadModeRawMutex, PowerBudget>,
+    /// LED temperature in °C, if one has been supplied
+    led_temperature: Mutex<ThreadModeRawMutex, Option<i32>>,
+    /// nRF die temperature in °C, once the knob task has read it
+    die_temperature: Mutex<ThreadModeRawMutex, Option<i32>>,
+    /// Statistics of the current calibration session
+    session_stats: Mutex<ThreadModeRawMutex, SessionStats>,
+    /// Color sequence played by the sequencer
+    sequence: Mutex<ThreadModeRawMutex, Sequence>,
+    /// Preset slots
+    presets: Mutex<ThreadModeRawMutex, [Option<Preset>; PRESET_SLOTS]>,
+    /// Pair of frame rates under comparison
+    rate_compare: Mutex<ThreadModeRawMutex, RateCompare>,
+    /// Choice of presets the long press of A steps through
+    preset_cycle: Mutex<ThreadModeRawMutex, PresetFilter>,
+    /// Record of the preset last loaded, if any
+    active_preset: Mutex<ThreadModeRawMutex, Option<usize>>,
+    /// Setting for the RTT state display format
+    output_format: Mutex<ThreadModeRawMutex, OutputFormat>,
+    /// Personal flicker-fusion threshold in fps, if marked
+    fusion_floor: Mutex<ThreadModeRawMutex, Option<u64>>,
+    /// Lowest frame rate allowed for photosensitivity safety
+    strobe_guard: Mutex<ThreadModeRawMutex, StrobeGuard>,
+    /// White reference: the levels that look white on this LED
+    white_reference: Mutex<ThreadModeRawMutex, [u32; 3]>,
+    /// Selection of the unit the frame rate is shown in
+    frame_rate_unit: Mutex<ThreadModeRawMutex, FrameRateUnit>,
+    /// Setting for the knob's brightness response curve
+    knob_response: Mutex<ThreadModeRawMutex, KnobResponse>,
+    /// Dead travel at the [low, high] end of the knob, as
+    /// fractions of the scaled ADC range
+    knob_deadbands: Mutex<ThreadModeRawMutex, [f32; 2]>,
+    /// Longest time knob level changes are batched before
+    /// committing, in milliseconds
+    knob_coalesce_ms: Mutex<ThreadModeRawMutex, u64>,
+    /// Interval between UI polls of the knob and buttons, in
+    /// milliseconds
+    poll_ms: Mutex<ThreadModeRawMutex, u64>,
+    /// Setting for indicating the knob at the center of its travel
+    center_indicator: Mutex<ThreadModeRawMutex, CenterIndicator>,
+    /// End of the current LED cue, while the output is blanked to
+    /// signal something to the user
+    led_cue_until: Mutex<ThreadModeRawMutex, Option<Instant>>,
+    /// Raw knob readings at the ends of the travel
+    knob_calibration: Mutex<ThreadModeRawMutex, KnobCalibration>,
+    /// Switch for blending between adjacent knob levels
+    knob_blend: Mutex<ThreadModeRawMutex, bool>,
+    /// Switch making the knob pick up a parameter's value
+    /// before taking it over
+    knob_pickup: Mutex<ThreadModeRawMutex, bool>,
+    /// Switch making the knob set the hue, rather than the frame
+    /// rate, with no buttons held
+    knob_hue: Mutex<ThreadModeRawMutex, bool>,
+    /// Switch making the knob nudge the selected level relative to
+    /// its current value, rather than set it from the knob's position
+    knob_fine: Mutex<ThreadModeRawMutex, bool>,
+    /// Fraction of the way each channel sits toward its next
+    /// level, in [`FRACTION_ONE`]ths; all zero unless knob blending is on or a
+    /// sequence is fading
+    level_fractions: Mutex<ThreadModeRawMutex, [u32; 3]>,
+    /// Assignment of button combinations to channels
+    button_map: Mutex<ThreadModeRawMutex, ButtonMap>,
+    /// Copy of the physical input state, written by the UI
+    input_snapshot: Mutex<ThreadModeRawMutex, InputSnapshot>,
+    /// Record of which source last set each knob-controlled
+    /// parameter, maintained by the UI
+    parameter_sources: Mutex<ThreadModeRawMutex, ParameterSources>,
+    /// Record of the parameter the knob controls
+    control_mode: Mutex<ThreadModeRawMutex, ControlMode>,
+    /// Set while the emergency off holds every LED dark
+    /// Atomic rather than a mutex so the RGB task can check it every frame
+    emergency_off: AtomicBool,
+    /// Signaled once the UI has written the initial shared state
+    state_ready: Signal<ThreadModeRawMutex, ()>,
+    /// Set once the UI has written the initial shared state
+    /// Unlike `state_ready`, which the RGB task consumes, stays set for
+    /// every task to check
+    state_initialized: AtomicBool,
+    /// Signaled when the current calibration should be saved to flash
+    save_request: Signal<ThreadModeRawMutex, ()>,
+    /// Signaled when a saved setting (see [`SavedSettings`]) has changed and
+    /// its flash record should be rewritten
+    settings_save_request: Signal<ThreadModeRawMutex, ()>,
+    /// Signaled with the preset to compare against when the host asks for a
+    /// diff with a saved preset
+    diff_request: Signal<ThreadModeRawMutex, PresetRef>,
+    /// Signaled when the host asks the UI to start the calibration wizard
+    wizard_request: Signal<ThreadModeRawMutex, ()>,
+    /// Signaled with the duration in seconds when the host asks the UI to run a
+    /// knob sweep
+    sweep_request: Signal<ThreadModeRawMutex, u64>,
+    /// Signaled with the duration in seconds when the host asks for a raw ADC
+    /// stream
+    stream_request: Signal<ThreadModeRawMutex, u64>,
+    /// Signaled with the hold time in seconds when the host asks the UI to
+    /// show the test patterns
+    pattern_request: Signal<ThreadModeRawMutex, u64>,
+    /// Signaled with the hold time in seconds when the host asks the UI to
+    /// light each LED pin in turn
+    identify_request: Signal<ThreadModeRawMutex, u64>,
+    /// Signaled when the host asks the UI to find the minimum visible levels
+    visible_request: Signal<ThreadModeRawMutex, ()>,
+    /// Signaled with `true` to start and `false` to stop sequence playback
+    sequence_request: Signal<ThreadModeRawMutex, bool>,
+    /// Signaled with a one-shot fade for the sequencer to run
+    fade_request: Signal<ThreadModeRawMutex, Fade>,
+    /// Signaled when the host asks the RGB task to measure its frame rate
+    /// ceiling
+    benchmark_request: Signal<ThreadModeRawMutex, ()>,
+    /// Channel carrying state updates to all subscribers
+    state_updates: PubSubChannel<
+        ThreadModeRawMutex,
+        StateUpdate,
+        UPDATE_CAPACITY,
+        MAX_SUBSCRIBERS,
+        MAX_PUBLISHERS,
+    >,
+}
+
+impl SharedState {
+    /// Create the shared state with every value at its startup default
+    pub const fn new() -> Self {
+        SharedState {
+            rgb_levels: Mutex::new([0; 3]),
+            frame_rate: Mutex::new(100),
+            debug_output: Mutex::new(false),
+            channel_enable: Mutex::new([true; 3]),
+            color_matrix: Mutex::new(ColorMatrix::IDENTITY),
+            channel_luts: Mutex::new([Lut::IDENTITY; 3]),
+            min_visible: Mutex::new([MIN_VISIBLE_NONE; 3]),
+            color_space: Mutex::new(ColorSpace::Linear),
+            hold_channel: Mutex::new(None),
+            refresh_multipliers: Mutex::new([1; 3]),
+            sub_frames: Mutex::new(DEFAULT_SUB_FRAMES),
+            state_refresh_hz: Mutex::new(DEFAULT_STATE_REFRESH_HZ),
+            slew_rate: Mutex::new(DEFAULT_SLEW_RATE),
+            rounding: Mutex::new(Rounding::DistributeToOff),
+            pwm_edge: Mutex::new(PwmEdge::Leading),
+            gamma: Mutex::new(false),
+            drive_mode: Mutex::new(DriveMode::Scan),
+            measure_duties: Mutex::new([None; 3]),
+            slow_motion: Mutex::new(None),
+            animation: Mutex::new(Animation::Off),
+            breathe_period_ms: Mutex::new(DEFAULT_BREATHE_PERIOD_MS),
+            history: Mutex::new(History::new()),
+            liveness: Mutex::new(false),
+            rgb_alive: Signal::new(),
+            indicator_mode: Mutex::new(IndicatorMode::Mode),
+            telemetry_rate: Mutex::new(None),
+            #[cfg(feature = "heartbeat")]
+            heartbeat_interval: Mutex::new(Some(HEARTBEAT_SECONDS)),
+            auto_save: Mutex::new(None),
+            actual_frame_rate: Mutex::new(0),
+            active_led: AtomicU8::new(NO_LED),
+            led_health: Mutex::new(LedHealth::new()),
+            encoder_detents: AtomicI32::new(0),
+            thermal_derating: Mutex::new(ThermalDerating::DEFAULT),
+            power_budget: Mutex::new(PowerBudget::DEFAULT),
+            led_temperature: Mutex::new(None),
+            die_temperature: Mutex::new(None),
+            session_stats: Mutex::new(SessionStats::new()),
+            sequence: Mutex::new(Sequence::new()),
+            presets: Mutex::new([None; PRESET_SLOTS]),
+            rate_compare: Mutex::new(RateCompare::OFF),
+            preset_cycle: Mutex::new(PresetFilter::All),
+            active_preset: Mutex::new(None),
+            output_format: Mutex::new(OutputFormat::Text),
+            fusion_floor: Mutex::new(None),
+            strobe_guard: Mutex::new(StrobeGuard::DEFAULT),
+            white_reference: Mutex::new([LEVELS - 1; 3]),
+            frame_rate_unit: Mutex::new(FrameRateUnit::Scans),
+            knob_response: Mutex::new(KnobResponse::Linear),
+            knob_deadbands: Mutex::new([DEFAULT_LOW_DEADBAND, DEFAULT_HIGH_DEADBAND]),
+            knob_coalesce_ms: Mutex::new(DEFAULT_COALESCE_MS),
+            poll_ms: Mutex::new(DEFAULT_POLL_MS),
+            center_indicator: Mutex::new(CenterIndicator::Off),
+            led_cue_until: Mutex::new(None),
+            knob_calibration: Mutex::new(KnobCalibration::DEFAULT),
+            knob_blend: Mutex::new(false),
+            knob_pickup: Mutex::new(false),
+            knob_hue: Mutex::new(false),
+            knob_fine: Mutex::new(false),
+            level_fractions: Mutex::new([0; 3]),
+            button_map: Mutex::new(ButtonMap::DEFAULT),
+            input_snapshot: Mutex::new(InputSnapshot {
+                raw: 0,
+                level: 0,
+                buttons: [false; 2],
+            }),
+            parameter_sources: Mutex::new(ParameterSources::KNOB),
+            control_mode: Mutex::new(ControlMode::FrameRate),
+            emergency_off: AtomicBool::new(false),
+            state_ready: Signal::new(),
+            state_initialized: AtomicBool::new(false),
+            save_request: Signal::new(),
+            settings_save_request: Signal::new(),
+            diff_request: Signal::new(),
+            wizard_request: Signal::new(),
+            sweep_request: Signal::new(),
+            stream_request: Signal::new(),
+            pattern_request: Signal::new(),
+            identify_request: Signal::new(),
+            visible_request: Signal::new(),
+            sequence_request: Signal::new(),
+            fade_request: Signal::new(),
+            benchmark_request: Signal::new(),
+            state_updates: PubSubChannel::new(),
+        }
+    }
+}
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Largest per-channel refresh multiplier accepted
+pub const MAX_REFRESH_MULTIPLIER: u32 = 8;
+
+/// Largest number of sub-frames per frame accepted
+pub const MAX_SUB_FRAMES: u32 = 8;
+
+/// Slowest state refresh accepted, in Hz: changes still show within 0.1 s
+pub const MIN_STATE_REFRESH_HZ: u64 = 10;
+
+/// Fastest state refresh accepted, in Hz
+pub const MAX_STATE_REFRESH_HZ: u64 = 1000;
+
+/// Number of updates buffered per subscriber before it starts lagging
+const UPDATE_CAPACITY: usize = 4;
+/// Maximum number of concurrent state subscribers
+const MAX_SUBSCRIBERS: usize = 4;
+/// Number of publishers (unused: all publishing is immediate)
+const MAX_PUBLISHERS: usize = 1;
+
+/// Snapshot of the shared state published on every committed change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateUpdate {
+    /// RGB brightness levels [red, green, blue] (0 to LEVELS-1)
+    pub levels: [u32; 3],
+    /// Frame rate in frames per second
+    pub frame_rate: u64,
+    /// Parameter the knob controls
+    pub mode: ControlMode,
+}
+
+/// Receiver for the stream of shared state changes
+///
+/// The first call to [`StateSubscriber::next`] yields the current state
+/// immediately; later calls wait for the next committed change.
+pub struct StateSubscriber<'a> {
+    /// State the updates come from
+    shared: &'a SharedState,
+    /// Underlying channel subscription
+    sub: Subscriber<
+        'a,
+        ThreadModeRawMutex,
+        StateUpdate,
+        UPDATE_CAPACITY,
+        MAX_SUBSCRIBERS,
+        MAX_PUBLISHERS,
+    >,
+    /// Whether the current state has been delivered yet
+    primed: bool,
+}
+
+impl StateSubscriber<'_> {
+    /// Wait for the next state update
+    ///
+    /// If this subscriber fell behind and missed updates, the current state
+    /// is returned instead so the consumer always ends up in sync.
+    ///
+    /// # Returns
+    /// The current state on first call, the next published update afterward
+    pub async fn next(&mut self) -> StateUpdate {
+        if !self.primed {
+            self.primed = true;
+            return self.shared.get_state().await;
+        }
+        match self.sub.next_message().await {
+            WaitResult::Message(update) => update,
+            WaitResult::Lagged(_) => self.shared.get_state().await,
+        }
+    }
+}
+
+impl SharedState {
+    /// Subscribe to changes of the shared state
+    ///
+    /// # Returns
+    /// A new subscriber, or an error if all subscriber slots are taken
+    pub fn subscribe(&self) -> Result<StateSubscriber<'_>, embassy_sync::pubsub::Error> {
+        let sub = self.state_updates.subscriber()?;
+        Ok(StateSubscriber {
+            shared: self,
+            sub,
+            primed: false,
+        })
+    }
+
+    /// Announce that the shared state holds its initial values
+    ///
+    /// Called by the UI task once it has written the startup levels and frame
+    /// rate, releasing any task blocked in [`Self::wait_state_ready`].
+    pub fn mark_state_ready(&self) {
+        self.state_initialized.store(true, Ordering::Relaxed);
+        self.state_ready.signal(());
+    }
+
+    /// Check whether the shared state holds its initial values yet
+    ///
+    /// Returns: `true` once [`Self::mark_state_ready`] has been called
+    pub fn state_ready(&self) -> bool {
+        self.state_initialized.load(Ordering::Relaxed)
+    }
+
+    /// Ask the command task to save the calibration to flash
+    pub fn request_save(&self) {
+        self.save_request.signal(());
+    }
+
+    /// Check for and clear a pending save request
+    ///
+    /// Returns: `true` if a save was requested
+    pub fn take_save_request(&self) -> bool {
+        self.save_request.try_take().is_some()
+    }
+
+    /// Ask the command task to rewrite the saved settings in flash
+    pub fn request_settings_save(&self) {
+        self.settings_save_request.signal(());
+    }
+
+    /// Check for and clear a pending settings save request
+    ///
+    /// Returns: `true` if a settings save was requested
+    pub fn take_settings_save_request(&self) -> bool {
+        self.settings_save_request.try_take().is_some()
+    }
+
+    /// Ask the command task to compare the settings with a saved preset
+    ///
+    /// # Arguments
+    /// * `preset` - Slot or tag of the preset in flash
+    pub fn request_diff(&self, preset: PresetRef) {
+        self.diff_request.signal(preset);
+    }
+
+    /// Check for and clear a pending diff request
+    ///
+    /// Returns: The preset to compare against, if a diff was requested
+    pub fn take_diff_request(&self) -> Option<PresetRef> {
+        self.diff_request.try_take()
+    }
+
+    /// Wait until the shared state has been initialized
+    ///
+    /// The RGB task calls this before driving the LEDs so the first frame
+    /// shows the startup levels rather than the placeholders of
+    /// [`Self::new`].
+    pub async fn wait_state_ready(&self) {
+        self.state_ready.wait().await;
+    }
+
+    /// Ask the UI to start the calibration wizard
+    pub fn request_wizard(&self) {
+        self.wizard_request.signal(());
+    }
+
+    /// Check for and clear a pending wizard request
+    ///
+    /// Returns: `true` if [`Self::request_wizard`] was called since the last
+    /// check
+    pub fn take_wizard_request(&self) -> bool {
+        self.wizard_request.try_take().is_some()
+    }
+
+    /// Ask the UI to record knob sweep diagnostics
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to sample
+    pub fn request_sweep(&self, seconds: u64) {
+        self.sweep_request.signal(seconds);
+    }
+
+    /// Check for and clear a pending sweep request
+    ///
+    /// Returns: The requested duration in seconds, if a sweep was requested
+    pub fn take_sweep_request(&self) -> Option<u64> {
+        self.sweep_request.try_take()
+    }
+
+    /// Ask the UI to stream raw ADC readings
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to stream
+    pub fn request_adc_stream(&self, seconds: u64) {
+        self.stream_request.signal(seconds);
+    }
+
+    /// Check for and clear a pending ADC stream request
+    ///
+    /// Returns: The requested duration in seconds, if a stream was requested
+    pub fn take_adc_stream_request(&self) -> Option<u64> {
+        self.stream_request.try_take()
+    }
+
+    /// Ask the UI to show the test patterns
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to hold each pattern
+    pub fn request_test_pattern(&self, seconds: u64) {
+        self.pattern_request.signal(seconds);
+    }
+
+    /// Check for and clear a pending test pattern request
+    ///
+    /// Returns: The requested hold time in seconds, if patterns were requested
+    pub fn take_test_pattern_request(&self) -> Option<u64> {
+        self.pattern_request.try_take()
+    }
+
+    /// Ask the UI to light each LED pin in turn for labeling the wiring
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to light each pin
+    pub fn request_identify_pins(&self, seconds: u64) {
+        self.identify_request.signal(seconds);
+    }
+
+    /// Check for and clear a pending pin identification request
+    ///
+    /// Returns: The requested time per pin in seconds, if requested
+    pub fn take_identify_pins_request(&self) -> Option<u64> {
+        self.identify_request.try_take()
+    }
+
+    /// Ask the UI to find each channel's minimum visible level
+    pub fn request_min_visible(&self) {
+        self.visible_request.signal(());
+    }
+
+    /// Check for and clear a pending minimum visible level request
+    ///
+    /// Returns: `true` if the routine was requested
+    pub fn take_min_visible_request(&self) -> bool {
+        self.visible_request.try_take().is_some()
+    }
+
+    /// Count one encoder detent
+    ///
+    /// Called by the encoder's decoder task, which may count several
+    /// before the UI reads them.
+    ///
+    /// # Arguments
+    /// * `direction` - 1 for clockwise, -1 for counter-clockwise
+    pub fn add_encoder_detent(&self, direction: i32) {
+        self.encoder_detents.fetch_add(direction, Ordering::Relaxed);
+    }
+
+    /// Take the encoder detents counted since the last call
+    ///
+    /// Returns: Net detents turned (positive = clockwise)
+    pub fn take_encoder_detents(&self) -> i32 {
+        self.encoder_detents.swap(0, Ordering::Relaxed)
+    }
+
+    /// Whether the emergency off is engaged
+    ///
+    /// Returns: `true` while every LED must stay dark
+    pub fn emergency_off(&self) -> bool {
+        self.emergency_off.load(Ordering::Relaxed)
+    }
+
+    /// Engage or release the emergency off
+    ///
+    /// Engaging zeroes the requested levels as well and ends any animation, so
+    /// releasing it never brings back the light that prompted the stop. The
+    /// change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `engaged` - `true` to force every LED dark, `false` to release
+    pub async fn set_emergency_off(&self, engaged: bool) {
+        self.emergency_off.store(engaged, Ordering::Relaxed);
+        if engaged {
+            self.request_sequence(false);
+            *self.animation.lock().await = Animation::Off;
+            self.set_rgb_levels(|rgb| *rgb = [0; 3]).await;
+        } else {
+            self.publish_state().await;
+        }
+    }
+
+    /// Ask the sequencer to start or stop playback
+    ///
+    /// # Arguments
+    /// * `play` - `true` to play the sequence, `false` to stop it
+    pub fn request_sequence(&self, play: bool) {
+        self.sequence_request.signal(play);
+    }
+
+    /// Wait for the next playback request
+    ///
+    /// Returns: `true` to play, `false` to stop
+    pub async fn wait_sequence_request(&self) -> bool {
+        self.sequence_request.wait().await
+    }
+
+    /// Ask the sequencer to run a one-shot fade, replacing any running one
+    ///
+    /// # Arguments
+    /// * `fade` - Colors and length of the fade
+    pub fn request_fade(&self, fade: Fade) {
+        self.fade_request.signal(fade);
+    }
+
+    /// Wait for the next fade request
+    ///
+    /// Returns: The fade to run
+    pub async fn wait_fade_request(&self) -> Fade {
+        self.fade_request.wait().await
+    }
+
+    /// Ask the RGB task to benchmark its highest sustainable frame rate
+    ///
+    /// The state is republished so an RGB task sleeping on a dark LED wakes up
+    /// to run it.
+    pub async fn request_benchmark(&self) {
+        self.benchmark_request.signal(());
+        self.publish_state().await;
+    }
+
+    /// Check for a pending benchmark request without clearing it
+    pub fn benchmark_requested(&self) -> bool {
+        self.benchmark_request.signaled()
+    }
+
+    /// Check for and clear a pending benchmark request
+    ///
+    /// Returns: `true` if a benchmark was requested
+    pub fn take_benchmark_request(&self) -> bool {
+        self.benchmark_request.try_take().is_some()
+    }
+
+    /// Read a consistent snapshot of the whole shared state
+    ///
+    /// Returns: Current levels, frame rate and control mode
+    pub async fn get_state(&self) -> StateUpdate {
+        StateUpdate {
+            levels: self.get_rgb_levels().await,
+            frame_rate: self.get_frame_rate().await,
+            mode: self.get_control_mode().await,
+        }
+    }
+
+    /// Compute the levels actually driven from the shared state
+    ///
+    /// Returns: Output [red, green, blue] levels after the enable mask, color
+    /// correction, lookup tables and minimum visible levels (see
+    /// [`output_levels`]), limited to the
+    /// thermal ceilings and the power budget
+    pub async fn get_output_levels(&self) -> [u32; 3] {
+        self.limit_output_levels(self.get_unlimited_output_levels().await)
+            .await
+    }
+
+    /// Apply the thermal ceilings and then the power budget to output levels
+    ///
+    /// # Arguments
+    /// * `output` - Output [red, green, blue] levels before limiting
+    ///
+    /// # Returns
+    /// The levels to drive
+    pub async fn limit_output_levels(&self, mut output: [u32; 3]) -> [u32; 3] {
+        for (level, ceiling) in output.iter_mut().zip(self.get_thermal_ceilings().await) {
+            *level = (*level).min(ceiling);
+        }
+        self.get_power_budget()
+            .await
+            .limit(output, self.get_refresh_multipliers().await)
+    }
+
+    /// Estimate the average LED current before the power budget is applied
+    ///
+    /// Returns: Average current in µA of the output after the thermal
+    /// ceilings (see [`PowerBudget::estimate_ua`])
+    pub async fn get_power_estimate(&self) -> u32 {
+        let mut output = self.get_unlimited_output_levels().await;
+        for (level, ceiling) in output.iter_mut().zip(self.get_thermal_ceilings().await) {
+            *level = (*level).min(ceiling);
+        }
+        self.get_power_budget()
+            .await
+            .estimate_ua(output, self.get_refresh_multipliers().await)
+    }
+
+    /// Compute the levels the shared state asks for before thermal limiting
+    async fn get_unlimited_output_levels(&self) -> [u32; 3] {
+        output_levels(
+            self.get_rgb_levels().await,
+            self.get_color_space().await,
+            self.get_channel_enable().await,
+            &self.get_color_matrix().await,
+            &self.get_channel_luts().await,
+            self.get_min_visible().await,
+        )
+    }
+
+    /// Compute the output levels one requested level up, for blending
+    ///
+    /// Each channel is raised on its own, so the result for a channel is what
+    /// it would output at its next level with the others unchanged.
+    ///
+    /// Returns: Output [red, green, blue] levels at the next requested level,
+    /// limited to the thermal ceilings and the power budget
+    pub async fn get_next_output_levels(&self) -> [u32; 3] {
+        let levels = self.get_rgb_levels().await;
+        let space = self.get_color_space().await;
+        let enabled = self.get_channel_enable().await;
+        let matrix = self.get_color_matrix().await;
+        let luts = self.get_channel_luts().await;
+        let min_visible = self.get_min_visible().await;
+        let mut next_output = [0; 3];
+        for (channel, next_level) in next_output.iter_mut().enumerate() {
+            let mut next = levels;
+            next[channel] = (next[channel] + 1).min(LEVELS - 1);
+            let output = output_levels(next, space, enabled, &matrix, &luts, min_visible);
+            *next_level = self.limit_output_levels(output).await[channel];
+        }
+        next_output
+    }
+
+    /// Compute the current thermal brightness ceilings
+    ///
+    /// Returns: Highest output level allowed per channel [red, green, blue];
+    /// LEVELS-1 for every channel without a temperature reading
+    pub async fn get_thermal_ceilings(&self) -> [u32; 3] {
+        match self.get_thermal_temperature().await {
+            Some((temp_c, _)) => self.get_thermal_derating().await.ceilings(temp_c, LEVELS),
+            None => [LEVELS - 1; 3],
+        }
+    }
+
+    /// Check which channels the thermal ceilings are holding back
+    ///
+    /// Returns: Flags [red, green, blue], `true` where the requested output is
+    /// above the channel's ceiling
+    pub async fn get_throttled_channels(&self) -> [bool; 3] {
+        let output = self.get_unlimited_output_levels().await;
+        let ceilings = self.get_thermal_ceilings().await;
+        [0, 1, 2].map(|channel| output[channel] > ceilings[channel])
+    }
+
+    /// Publish the current shared state to all subscribers
+    ///
+    /// Never blocks: a subscriber that is too far behind loses its oldest
+    /// update.
+    async fn publish_state(&self) {
+        let update = self.get_state().await;
+        self.state_updates
+            .immediate_publisher()
+            .publish_immediate(update);
+    }
+
+    /// Safely read the current RGB brightness levels from shared state
+    ///
+    /// Returns: Array of [red, green, blue] brightness values (0-15)
+    pub async fn get_rgb_levels(&self) -> [u32; 3] {
+        let rgb_levels = self.rgb_levels.lock().await;
+        *rgb_levels
+    }
+
+    /// Safely modify the RGB brightness levels in shared state
+    ///
+    /// The change is published to all state subscribers. If any level changes,
+    /// the level fractions are cleared: a blend only holds for the levels it
+    /// was set against. Both are updated under their locks together, so no
+    /// reader sees new levels with the old fractions.
+    ///
+    /// # Arguments
+    /// * `setter` - Closure that modifies the RGB levels array
+    pub async fn set_rgb_levels<F>(&self, setter: F)
+    where
+        F: FnOnce(&mut [u32; 3]),
+    {
+        {
+            let mut rgb_levels = self.rgb_levels.lock().await;
+            let mut level_fractions = self.level_fractions.lock().await;
+            let previous = *rgb_levels;
+            setter(&mut rgb_levels);
+            if *rgb_levels != previous {
+                *level_fractions = [0; 3];
+            }
+        }
+        self.publish_state().await;
+    }
+
+    /// Set all three RGB brightness levels in a single update
+    ///
+    /// Levels are clamped to the valid range before committing, and all three
+    /// are written under one lock so the RGB task never sees a partial color.
+    ///
+    /// # Arguments
+    /// * `levels` - New [red, green, blue] levels
+    ///
+    /// # Returns
+    /// The clamped levels that were actually committed
+    pub async fn set_rgb(&self, levels: [u32; 3]) -> [u32; 3] {
+        let levels = levels.map(|level| level.min(LEVELS - 1));
+        self.set_rgb_levels(|rgb| *rgb = levels).await;
+        levels
+    }
+
+    /// Set all three levels together with their fractions toward the next
+    ///
+    /// Both are written in one update, so the RGB task never sees a new level
+    /// with the fraction meant for the old one (unlike [`Self::set_rgb_levels`]
+    /// followed by [`Self::set_level_fractions`]).
+    ///
+    /// # Arguments
+    /// * `levels` - New [red, green, blue] levels, clamped to the valid range
+    /// * `fractions` - Fraction of the way to each next level in
+    ///   [`FRACTION_ONE`]ths
+    pub async fn set_blended_levels(&self, levels: [u32; 3], fractions: [u32; 3]) {
+        {
+            let mut rgb_levels = self.rgb_levels.lock().await;
+            let mut level_fractions = self.level_fractions.lock().await;
+            *rgb_levels = levels.map(|level| level.min(LEVELS - 1));
+            *level_fractions = fractions.map(|fraction| fraction.min(FRACTION_ONE - 1));
+        }
+        self.publish_state().await;
+    }
+
+    /// Set a single RGB brightness level
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    /// * `level` - New level, clamped to the valid range
+    ///
+    /// # Returns
+    /// The clamped level that was actually committed
+    pub async fn set_channel_level(&self, channel: usize, level: u32) -> u32 {
+        let level = level.min(LEVELS - 1);
+        self.set_rgb_levels(|rgb| rgb[channel] = level).await;
+        level
+    }
+
+    /// Change a single RGB brightness level by a signed step
+    ///
+    /// The level is read, adjusted and written back under one lock, so a
+    /// concurrent change from the knob is never lost in between.
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    /// * `delta` - Signed change in levels
+    ///
+    /// # Returns
+    /// The level actually committed, and whether it had to be clamped
+    pub async fn adjust_channel_level(&self, channel: usize, delta: i32) -> (u32, bool) {
+        let mut result = (0, false);
+        self.set_rgb_levels(|rgb| {
+            let wanted = rgb[channel] as i64 + delta as i64;
+            let level = wanted.clamp(0, (LEVELS - 1) as i64);
+            rgb[channel] = level as u32;
+            result = (level as u32, level != wanted);
+        })
+        .await;
+        result
+    }
+
+    /// Safely read the current frame rate from shared state
+    ///
+    /// Returns: Current frame rate in frames per second
+    pub async fn get_frame_rate(&self) -> u64 {
+        let frame_rate = self.frame_rate.lock().await;
+        *frame_rate
+    }
+
+    /// Safely modify the frame rate in shared state
+    ///
+    /// The rate is clamped to MIN_FRAME_RATE..=MAX_FRAME_RATE and raised to
+    /// the flicker-fusion threshold, if one is marked, and the strobe
+    /// guard's floor if below them (see [`StrobeGuard::apply`]). The change
+    /// is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `new_rate` - New frame rate in frames per second
+    ///
+    /// # Returns
+    /// The frame rate actually committed
+    pub async fn set_frame_rate(&self, new_rate: u64) -> u64 {
+        let fusion_floor = self.get_fusion_floor().await;
+        self.commit_frame_rate(new_rate, fusion_floor).await
+    }
+
+    /// Set the frame rate for the wizard's fusion sweep
+    ///
+    /// As [`Self::set_frame_rate`], but the flicker-fusion threshold is not
+    /// held, so the sweep can find it again. The strobe guard still is.
+    ///
+    /// # Arguments
+    /// * `new_rate` - New frame rate in frames per second
+    ///
+    /// # Returns
+    /// The frame rate actually committed
+    pub async fn set_sweep_frame_rate(&self, new_rate: u64) -> u64 {
+        self.commit_frame_rate(new_rate, None).await
+    }
+
+    /// Guard, store and publish a frame rate
+    ///
+    /// # Arguments
+    /// * `new_rate` - New frame rate in frames per second
+    /// * `fusion_floor` - Flicker-fusion threshold to hold, if any
+    ///
+    /// # Returns
+    /// The frame rate actually committed
+    async fn commit_frame_rate(&self, new_rate: u64, fusion_floor: Option<u64>) -> u64 {
+        let new_rate = self.get_strobe_guard().await.apply(new_rate, fusion_floor);
+        {
+            let mut frame_rate = self.frame_rate.lock().await;
+            *frame_rate = new_rate;
+        }
+        self.publish_state().await;
+        new_rate
+    }
+
+    /// Change the frame rate by a signed step
+    ///
+    /// The frame rate is read, adjusted and written back under one lock, and
+    /// guarded as in [`Self::set_frame_rate`].
+    ///
+    /// # Arguments
+    /// * `delta` - Signed change in frames per second
+    ///
+    /// # Returns
+    /// The frame rate actually committed, and whether it had to be clamped
+    pub async fn adjust_frame_rate(&self, delta: i64) -> (u64, bool) {
+        let fusion_floor = self.get_fusion_floor().await;
+        let strobe_guard = self.get_strobe_guard().await;
+        let result = {
+            let mut frame_rate = self.frame_rate.lock().await;
+            let wanted = *frame_rate as i64 + delta;
+            *frame_rate = strobe_guard.apply(wanted.max(0) as u64, fusion_floor);
+            (*frame_rate, *frame_rate as i64 != wanted)
+        };
+        self.publish_state().await;
+        result
+    }
+
+    /// Read the output enable mask
+    ///
+    /// Returns: Array of [red, green, blue] enable flags
+    pub async fn get_channel_enable(&self) -> [bool; 3] {
+        let channel_enable = self.channel_enable.lock().await;
+        *channel_enable
+    }
+
+    /// Set the output enable mask
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `enabled` - New [red, green, blue] enable flags
+    pub async fn set_channel_enable(&self, enabled: [bool; 3]) {
+        {
+            let mut channel_enable = self.channel_enable.lock().await;
+            *channel_enable = enabled;
+        }
+        // Output changes, so wake anything waiting on the state stream
+        self.publish_state().await;
+    }
+
+    /// Read the parameter the knob controls
+    ///
+    /// Returns: Current control mode
+    pub async fn get_control_mode(&self) -> ControlMode {
+        let control_mode = self.control_mode.lock().await;
+        *control_mode
+    }
+
+    /// Record a change of the parameter the knob controls
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `mode` - New control mode
+    pub async fn set_control_mode(&self, mode: ControlMode) {
+        {
+            let mut control_mode = self.control_mode.lock().await;
+            *control_mode = mode;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the color-correction matrix
+    ///
+    /// Returns: Matrix applied to the levels before output
+    pub async fn get_color_matrix(&self) -> ColorMatrix {
+        let color_matrix = self.color_matrix.lock().await;
+        *color_matrix
+    }
+
+    /// Set the color-correction matrix
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `matrix` - New matrix applied to the levels before output
+    pub async fn set_color_matrix(&self, matrix: ColorMatrix) {
+        {
+            let mut color_matrix = self.color_matrix.lock().await;
+            *color_matrix = matrix;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the output lookup tables
+    ///
+    /// Returns: Tables for [red, green, blue]
+    pub async fn get_channel_luts(&self) -> [Lut; 3] {
+        let channel_luts = self.channel_luts.lock().await;
+        *channel_luts
+    }
+
+    /// Replace one channel's output lookup table
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    /// * `lut` - New lookup table
+    pub async fn set_channel_lut(&self, channel: usize, lut: Lut) {
+        {
+            let mut channel_luts = self.channel_luts.lock().await;
+            channel_luts[channel] = lut;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the minimum visible output levels
+    ///
+    /// Returns: Lowest visibly lit [red, green, blue] levels
+    pub async fn get_min_visible(&self) -> [u32; 3] {
+        let min_visible = self.min_visible.lock().await;
+        *min_visible
+    }
+
+    /// Set the minimum visible output levels
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `levels` - Lowest visibly lit [red, green, blue] levels
+    pub async fn set_min_visible(&self, levels: [u32; 3]) {
+        {
+            let mut min_visible = self.min_visible.lock().await;
+            *min_visible = levels;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read how the requested levels are interpreted
+    ///
+    /// Returns: The levels' [`ColorSpace`]
+    pub async fn get_color_space(&self) -> ColorSpace {
+        let color_space = self.color_space.lock().await;
+        *color_space
+    }
+
+    /// Declare how the requested levels are interpreted
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `space` - Color space of the requested levels
+    pub async fn set_color_space(&self, space: ColorSpace) {
+        {
+            let mut color_space = self.color_space.lock().await;
+            *color_space = space;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the complete calibration
+    ///
+    /// Returns: Levels, frame rate, enable mask, color matrix, lookup tables,
+    /// minimum visible levels and color space
+    pub async fn get_calibration_config(&self) -> CalibrationConfig {
+        CalibrationConfig {
+            levels: self.get_rgb_levels().await,
+            frame_rate: self.get_frame_rate().await,
+            enabled: self.get_channel_enable().await,
+            matrix: self.get_color_matrix().await,
+            luts: self.get_channel_luts().await,
+            min_visible: self.get_min_visible().await,
+            color_space: self.get_color_space().await,
+            fusion_floor: self.get_fusion_floor().await.map(|fps| fps as u16),
+        }
+    }
+
+    /// Replace the complete calibration in one commit
+    ///
+    /// All the locks are taken before anything is written and released only
+    /// after everything is, so no task can read a mix of the old and new
+    /// config; the change is then published to all state subscribers once.
+    /// Level fractions are cleared as for any level change, and the frame rate
+    /// is guarded as for any frame rate change, against the config's own
+    /// fusion threshold.
+    ///
+    /// # Arguments
+    /// * `config` - Validated config to apply
+    pub async fn set_calibration_config(&self, config: CalibrationConfig) {
+        let strobe_guard = self.get_strobe_guard().await;
+        {
+            let mut rgb_levels = self.rgb_levels.lock().await;
+            let mut frame_rate = self.frame_rate.lock().await;
+            let mut channel_enable = self.channel_enable.lock().await;
+            let mut color_matrix = self.color_matrix.lock().await;
+            let mut channel_luts = self.channel_luts.lock().await;
+            let mut min_visible = self.min_visible.lock().await;
+            let mut color_space = self.color_space.lock().await;
+            let mut fusion_floor = self.fusion_floor.lock().await;
+            let mut level_fractions = self.level_fractions.lock().await;
+            *rgb_levels = config.levels;
+            *fusion_floor = config.fusion_floor.map(u64::from);
+            *frame_rate = strobe_guard.apply(config.frame_rate, *fusion_floor);
+            *channel_enable = config.enabled;
+            *color_matrix = config.matrix;
+            *channel_luts = config.luts;
+            *min_visible = config.min_visible;
+            *color_space = config.color_space;
+            *level_fractions = [0; 3];
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the freeze-frame setting
+    ///
+    /// Returns: Channel held alone (0=red, 1=green, 2=blue), or `None`
+    pub async fn get_hold_channel(&self) -> Option<usize> {
+        let hold_channel = self.hold_channel.lock().await;
+        *hold_channel
+    }
+
+    /// Hold one channel alone at a steady duty, or resume the scan
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `channel` - Channel to hold (0=red, 1=green, 2=blue), or `None`
+    pub async fn set_hold_channel(&self, channel: Option<usize>) {
+        {
+            let mut hold_channel = self.hold_channel.lock().await;
+            *hold_channel = channel;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the running animation
+    ///
+    /// Returns: Animation shown instead of the manual levels, or
+    /// [`Animation::Off`]
+    pub async fn get_animation(&self) -> Animation {
+        let animation = self.animation.lock().await;
+        *animation
+    }
+
+    /// Start an animation, or return to manual control
+    ///
+    /// The sequencer is started for [`Animation::Sequence`] and stopped when
+    /// switching away from it, and a breathing period is kept for the button
+    /// toggle. The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `new_animation` - Animation to run, or [`Animation::Off`]
+    pub async fn set_animation(&self, new_animation: Animation) {
+        let old_animation = {
+            let mut animation = self.animation.lock().await;
+            core::mem::replace(&mut *animation, new_animation)
+        };
+        if let Animation::Breathe { period_ms } = new_animation {
+            *self.breathe_period_ms.lock().await = period_ms;
+        }
+        if new_animation == Animation::Sequence {
+            self.request_sequence(true);
+        } else if old_animation == Animation::Sequence {
+            self.request_sequence(false);
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the breathing period
+    ///
+    /// Returns: Period last given to breathing, in milliseconds
+    pub async fn get_breathe_period(&self) -> u32 {
+        let breathe_period_ms = self.breathe_period_ms.lock().await;
+        *breathe_period_ms
+    }
+
+    /// Read the committed levels and frame rate as a checkpoint
+    ///
+    /// Returns: The current [`Checkpoint`]
+    pub async fn get_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            levels: self.get_rgb_levels().await,
+            frame_rate: self.get_frame_rate().await,
+        }
+    }
+
+    /// Let the undo history follow the committed state
+    ///
+    /// Called by the UI on every poll; a state is recorded once it has
+    /// settled (see [`History::observe`]). Playback and the emergency off are
+    /// not recorded, as they are not changes to undo.
+    pub async fn observe_checkpoint(&self) {
+        if self.emergency_off() || self.get_animation().await != Animation::Off {
+            return;
+        }
+        let checkpoint = self.get_checkpoint().await;
+        let mut history = self.history.lock().await;
+        history.observe(checkpoint, Instant::now());
+    }
+
+    /// Step the undo history back or forward and restore that state
+    ///
+    /// A change that has not settled yet is recorded first, so undo always
+    /// returns to the state before the latest change.
+    ///
+    /// # Arguments
+    /// * `forward` - `true` to redo, `false` to undo
+    ///
+    /// # Returns
+    /// The restored checkpoint and the new position (see
+    /// [`History::position`]), or `None` if there is nothing to step to
+    pub async fn step_history(&self, forward: bool) -> Option<(Checkpoint, (usize, usize))> {
+        let current = self.get_checkpoint().await;
+        let (checkpoint, position) = {
+            let mut history = self.history.lock().await;
+            if !forward {
+                history.record(current);
+            }
+            let checkpoint = if forward {
+                history.redo()
+            } else {
+                history.undo()
+            }?;
+            (checkpoint, history.position())
+        };
+        self.set_rgb(checkpoint.levels).await;
+        self.set_frame_rate(checkpoint.frame_rate).await;
+        Some((checkpoint, position))
+    }
+
+    /// Read the position in the undo history
+    ///
+    /// Returns: The current checkpoint's number and the number recorded (see
+    /// [`History::position`])
+    pub async fn get_history_position(&self) -> (usize, usize) {
+        let history = self.history.lock().await;
+        history.position()
+    }
+
+    /// Read what the onboard matrix shows
+    ///
+    /// Returns: Selected [`IndicatorMode`]
+    pub async fn get_indicator_mode(&self) -> IndicatorMode {
+        let indicator_mode = self.indicator_mode.lock().await;
+        *indicator_mode
+    }
+
+    /// Select what the onboard matrix shows
+    ///
+    /// The change is published to all state subscribers, so the indicator task
+    /// redraws at once.
+    ///
+    /// # Arguments
+    /// * `mode` - New indicator mode
+    pub async fn set_indicator_mode(&self, mode: IndicatorMode) {
+        {
+            let mut indicator_mode = self.indicator_mode.lock().await;
+            *indicator_mode = mode;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read whether the liveness pixel blinks
+    ///
+    /// Returns: `true` if the matrix shows the RGB loop's liveness
+    pub async fn get_liveness(&self) -> bool {
+        let liveness = self.liveness.lock().await;
+        *liveness
+    }
+
+    /// Turn the liveness pixel on or off
+    ///
+    /// The change is published to all state subscribers, so the indicator
+    /// redraws and an idle RGB task starts or stops waking for it.
+    ///
+    /// # Arguments
+    /// * `on` - `true` to blink the liveness pixel
+    pub async fn set_liveness(&self, on: bool) {
+        {
+            let mut liveness = self.liveness.lock().await;
+            *liveness = on;
+        }
+        self.publish_state().await;
+    }
+
+    /// Note that the RGB task's loop is still running
+    pub fn mark_rgb_alive(&self) {
+        self.rgb_alive.signal(());
+    }
+
+    /// Check for and clear a sign of life from the RGB task
+    ///
+    /// Returns: `true` if the RGB loop ran since the last call
+    pub fn take_rgb_alive(&self) -> bool {
+        self.rgb_alive.try_take().is_some()
+    }
+
+    /// Read the slow-motion setting
+    ///
+    /// Returns: Factor every step is stretched by, or `None` for normal timing
+    pub async fn get_slow_motion(&self) -> Option<u32> {
+        let slow_motion = self.slow_motion.lock().await;
+        *slow_motion
+    }
+
+    /// Stretch the scan to be followed by eye, or restore normal timing
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `factor` - Factor every step is stretched by, or `None`
+    pub async fn set_slow_motion(&self, factor: Option<u32>) {
+        {
+            let mut slow_motion = self.slow_motion.lock().await;
+            *slow_motion = factor;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the per-channel refresh multipliers
+    ///
+    /// Returns: Frames per refresh of each [red, green, blue] channel
+    pub async fn get_refresh_multipliers(&self) -> [u32; 3] {
+        let refresh_multipliers = self.refresh_multipliers.lock().await;
+        *refresh_multipliers
+    }
+
+    /// Set the per-channel refresh multipliers
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `multipliers` - Frames per refresh of each [red, green, blue]
+    ///   channel, clamped to 1..=MAX_REFRESH_MULTIPLIER
+    pub async fn set_refresh_multipliers(&self, multipliers: [u32; 3]) {
+        {
+            let mut refresh_multipliers = self.refresh_multipliers.lock().await;
+            *refresh_multipliers =
+                multipliers.map(|multiplier| multiplier.clamp(1, MAX_REFRESH_MULTIPLIER));
+        }
+        self.publish_state().await;
+    }
+
+    /// Read how many sub-frames each frame is divided into
+    ///
+    /// Returns: Sub-frames per frame
+    pub async fn get_sub_frames(&self) -> u32 {
+        let sub_frames = self.sub_frames.lock().await;
+        *sub_frames
+    }
+
+    /// Set how many sub-frames each frame is divided into
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `count` - Sub-frames per frame, clamped to 1..=MAX_SUB_FRAMES
+    pub async fn set_sub_frames(&self, count: u32) {
+        {
+            let mut sub_frames = self.sub_frames.lock().await;
+            *sub_frames = count.clamp(1, MAX_SUB_FRAMES);
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the largest change of an output level per frame
+    ///
+    /// Returns: Slew rate in levels per frame (LEVELS or more for no limit)
+    pub async fn get_slew_rate(&self) -> u32 {
+        let slew_rate = self.slew_rate.lock().await;
+        *slew_rate
+    }
+
+    /// Limit how fast the output levels may change
+    ///
+    /// # Arguments
+    /// * `rate` - Levels per frame, clamped to 1..=LEVELS (LEVELS for no limit)
+    pub async fn set_slew_rate(&self, rate: u32) {
+        let mut slew_rate = self.slew_rate.lock().await;
+        *slew_rate = rate.clamp(1, LEVELS);
+    }
+
+    /// Read the cap on how often the RGB task re-reads the shared state
+    ///
+    /// Returns: Maximum snapshots per second
+    pub async fn get_state_refresh(&self) -> u64 {
+        let state_refresh_hz = self.state_refresh_hz.lock().await;
+        *state_refresh_hz
+    }
+
+    /// Cap how often the RGB task re-reads the shared state
+    ///
+    /// # Arguments
+    /// * `refresh_hz` - Maximum snapshots per second, clamped to
+    ///   MIN_STATE_REFRESH_HZ..=MAX_STATE_REFRESH_HZ
+    pub async fn set_state_refresh(&self, refresh_hz: u64) {
+        let mut state_refresh_hz = self.state_refresh_hz.lock().await;
+        *state_refresh_hz = refresh_hz.clamp(MIN_STATE_REFRESH_HZ, MAX_STATE_REFRESH_HZ);
+    }
+
+    /// Read how slot times are rounded to whole microseconds
+    ///
+    /// Returns: Current rounding policy
+    pub async fn get_rounding(&self) -> Rounding {
+        let rounding = self.rounding.lock().await;
+        *rounding
+    }
+
+    /// Choose how slot times are rounded to whole microseconds
+    ///
+    /// # Arguments
+    /// * `policy` - New rounding policy
+    pub async fn set_rounding(&self, policy: Rounding) {
+        let mut rounding = self.rounding.lock().await;
+        *rounding = policy;
+    }
+
+    /// Read where the on-window sits within each slot
+    ///
+    /// Returns: Current PWM edge setting
+    pub async fn get_pwm_edge(&self) -> PwmEdge {
+        let pwm_edge = self.pwm_edge.lock().await;
+        *pwm_edge
+    }
+
+    /// Choose where the on-window sits within each slot
+    ///
+    /// # Arguments
+    /// * `edge` - New PWM edge setting
+    pub async fn set_pwm_edge(&self, edge: PwmEdge) {
+        let mut pwm_edge = self.pwm_edge.lock().await;
+        *pwm_edge = edge;
+    }
+
+    /// Read whether on-times follow the gamma duty tables
+    ///
+    /// Returns: `true` while gamma is on
+    pub async fn get_gamma(&self) -> bool {
+        let gamma = self.gamma.lock().await;
+        *gamma
+    }
+
+    /// Switch on-times between the gamma duty tables and linear in the level
+    ///
+    /// # Arguments
+    /// * `enabled` - New gamma setting
+    pub async fn set_gamma(&self, enabled: bool) {
+        let mut gamma = self.gamma.lock().await;
+        *gamma = enabled;
+    }
+
+    /// Read the measurement duty overrides
+    ///
+    /// Returns: Duty of each [red, green, blue] channel in DUTY_ONEths, or
+    /// `None` where the channel follows its level
+    pub async fn get_measure_duties(&self) -> [Option<u32>; 3] {
+        let measure_duties = self.measure_duties.lock().await;
+        *measure_duties
+    }
+
+    /// Override a channel's level with an exact duty, or clear the override
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `channel` - Channel (0=red, 1=green, 2=blue)
+    /// * `duty` - Duty in DUTY_ONEths (clamped to DUTY_ONE), or `None` to
+    ///   follow the level again
+    pub async fn set_measure_duty(&self, channel: usize, duty: Option<u32>) {
+        {
+            let mut measure_duties = self.measure_duties.lock().await;
+            measure_duties[channel] = duty.map(|duty| duty.min(DUTY_ONE));
+        }
+        self.publish_state().await;
+    }
+
+    /// Read how the channels share a frame
+    ///
+    /// Returns: Current drive mode
+    pub async fn get_drive_mode(&self) -> DriveMode {
+        let drive_mode = self.drive_mode.lock().await;
+        *drive_mode
+    }
+
+    /// Choose whether the channels are scanned or lit together
+    ///
+    /// # Arguments
+    /// * `mode` - New drive mode, taken by the RGB task at the next frame
+    pub async fn set_drive_mode(&self, mode: DriveMode) {
+        {
+            let mut drive_mode = self.drive_mode.lock().await;
+            *drive_mode = mode;
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the binary telemetry rate
+    ///
+    /// Returns: Packets per second, or `None` when telemetry is off
+    pub async fn get_telemetry_rate(&self) -> Option<u32> {
+        let telemetry_rate = self.telemetry_rate.lock().await;
+        *telemetry_rate
+    }
+
+    /// Enable binary telemetry at the given rate, or disable it
+    ///
+    /// # Arguments
+    /// * `rate` - Packets per second, or `None` to stop
+    pub async fn set_telemetry_rate(&self, rate: Option<u32>) {
+        let mut telemetry_rate = self.telemetry_rate.lock().await;
+        *telemetry_rate = rate;
+    }
+
+    /// Read the heartbeat interval
+    ///
+    /// Returns: Seconds between heartbeat lines, or `None` when off
+    #[cfg(feature = "heartbeat")]
+    pub async fn get_heartbeat_interval(&self) -> Option<u32> {
+        let heartbeat_interval = self.heartbeat_interval.lock().await;
+        *heartbeat_interval
+    }
+
+    /// Set the heartbeat interval, or disable the heartbeat
+    ///
+    /// # Arguments
+    /// * `seconds` - Seconds between heartbeat lines, or `None` to stop
+    #[cfg(feature = "heartbeat")]
+    pub async fn set_heartbeat_interval(&self, seconds: Option<u32>) {
+        let mut heartbeat_interval = self.heartbeat_interval.lock().await;
+        *heartbeat_interval = seconds;
+    }
+
+    /// Read the automatic save policy
+    ///
+    /// Returns: The policy, or `None` when saving automatically is off
+    pub async fn get_auto_save(&self) -> Option<SavePolicy> {
+        let auto_save = self.auto_save.lock().await;
+        *auto_save
+    }
+
+    /// Save automatically under a policy, or stop saving automatically
+    ///
+    /// # Arguments
+    /// * `policy` - Automatic save policy, or `None` to only save on request
+    pub async fn set_auto_save(&self, policy: Option<SavePolicy>) {
+        let mut auto_save = self.auto_save.lock().await;
+        *auto_save = policy;
+    }
+
+    /// Read the frame rate measured by the RGB task
+    ///
+    /// Returns: Frames per second over the last measurement window, in
+    /// millihertz (0 while the LEDs are idle)
+    pub async fn get_actual_frame_rate(&self) -> u32 {
+        let actual_frame_rate = self.actual_frame_rate.lock().await;
+        *actual_frame_rate
+    }
+
+    /// Record the frame rate measured by the RGB task
+    ///
+    /// # Arguments
+    /// * `millihertz` - Measured frames per second, in millihertz
+    pub async fn set_actual_frame_rate(&self, millihertz: u32) {
+        let mut actual_frame_rate = self.actual_frame_rate.lock().await;
+        *actual_frame_rate = millihertz;
+    }
+
+    /// Record which channel's pin is driven high
+    ///
+    /// Called by the RGB task around each on-time.
+    ///
+    /// # Arguments
+    /// * `led` - Channel index (0=red, 1=green, 2=blue), or `None` when all
+    ///   pins are low
+    pub fn set_active_led(&self, led: Option<usize>) {
+        let marker = led.map_or(NO_LED, |led| led as u8);
+        self.active_led.store(marker, Ordering::Relaxed);
+    }
+
+    /// Channel whose pin is currently driven high
+    ///
+    /// Returns: Channel index (0=red, 1=green, 2=blue), or `None` if all
+    /// pins are low
+    pub fn active_led(&self) -> Option<usize> {
+        match self.active_led.load(Ordering::Relaxed) {
+            NO_LED => None,
+            led => Some(led as usize),
+        }
+    }
+
+    /// Record an LED current sense reading
+    ///
+    /// # Arguments
+    /// * `led` - Channel lit during the reading (0=red, 1=green, 2=blue)
+    /// * `sense` - Raw sense ADC reading
+    pub async fn record_led_sense(&self, led: usize, sense: i16) {
+        let mut led_health = self.led_health.lock().await;
+        let available = led_health.available();
+        led_health.record(led, sense);
+        if available && !led_health.available() {
+            rprintln!("warning: LED sense reads no current on any channel, sensor not available");
+        }
+    }
+
+    /// Mark the LED current sense input as unavailable
+    ///
+    /// Called when the startup probe finds no working sense resistor.
+    pub async fn disable_led_sense(&self) {
+        let mut led_health = self.led_health.lock().await;
+        led_health.mark_unavailable();
+    }
+
+    /// Whether the LED current sense input is built in and working
+    ///
+    /// Returns: `true` if failed-open detection is active
+    pub async fn led_sense_available(&self) -> bool {
+        let led_health = self.led_health.lock().await;
+        led_health.available()
+    }
+
+    /// Read which channels appear to have failed open
+    ///
+    /// Returns: Flags [red, green, blue], or
+    /// [`CalibrationError::SensorUnavailable`]
+    /// without a working current sense input
+    pub async fn get_dead_leds(&self) -> Result<[bool; 3], CalibrationError> {
+        let led_health = self.led_health.lock().await;
+        led_health.dead()
+    }
+
+    /// Record the current settings in the session statistics
+    ///
+    /// # Arguments
+    /// * `levels` - Current [red, green, blue] levels
+    /// * `frame_rate` - Current frame rate in fps
+    /// * `mode` - Parameter the knob currently controls
+    pub async fn record_session(&self, levels: [u32; 3], frame_rate: u64, mode: ControlMode) {
+        let mut session_stats = self.session_stats.lock().await;
+        session_stats.record(levels, frame_rate, mode, Instant::now());
+    }
+
+    /// Read the session statistics
+    ///
+    /// Returns: Statistics accumulated since boot or the last reset
+    pub async fn get_session_stats(&self) -> SessionStats {
+        let session_stats = self.session_stats.lock().await;
+        *session_stats
+    }
+
+    /// Start a new session, discarding the statistics so far
+    pub async fn reset_session_stats(&self) {
+        let mut session_stats = self.session_stats.lock().await;
+        *session_stats = SessionStats::new();
+    }
+
+    /// Read the color sequence
+    ///
+    /// Returns: Copy of the sequence's stops and crossfade time
+    pub async fn get_sequence(&self) -> Sequence {
+        let sequence = self.sequence.lock().await;
+        *sequence
+    }
+
+    /// Safely modify the color sequence
+    ///
+    /// Changes take effect the next time playback starts, and are kept in
+    /// flash with the saved settings.
+    ///
+    /// # Arguments
+    /// * `setter` - Closure that modifies the sequence
+    ///
+    /// # Returns
+    /// Whatever the closure returns
+    pub async fn set_sequence<F, R>(&self, setter: F) -> R
+    where
+        F: FnOnce(&mut Sequence) -> R,
+    {
+        let result = {
+            let mut sequence = self.sequence.lock().await;
+            setter(&mut sequence)
+        };
+        self.request_settings_save();
+        result
+    }
+
+    /// Read the thermal derating model
+    ///
+    /// Returns: Start temperature and per-channel coefficients
+    pub async fn get_thermal_derating(&self) -> ThermalDerating {
+        let thermal_derating = self.thermal_derating.lock().await;
+        *thermal_derating
+    }
+
+    /// Replace the thermal derating model
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `derating` - New start temperature and coefficients
+    pub async fn set_thermal_derating(&self, derating: ThermalDerating) {
+        {
+            let mut thermal_derating = self.thermal_derating.lock().await;
+            *thermal_derating = derating;
+        }
+        // The ceilings may change, so wake anything waiting on the state stream
+        self.publish_state().await;
+    }
+
+    /// Read the power budget
+    ///
+    /// Returns: Budget, action and per-channel on-currents
+    pub async fn get_power_budget(&self) -> PowerBudget {
+        let power_budget = self.power_budget.lock().await;
+        *power_budget
+    }
+
+    /// Modify the power budget
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `setter` - Closure that modifies the budget
+    pub async fn set_power_budget<F>(&self, setter: F)
+    where
+        F: FnOnce(&mut PowerBudget),
+    {
+        {
+            let mut power_budget = self.power_budget.lock().await;
+            setter(&mut power_budget);
+        }
+        // A cap may change the output, so wake anything waiting on the state stream
+        self.publish_state().await;
+    }
+
+    /// Read the supplied LED temperature
+    ///
+    /// Returns: Temperature in °C, or `None` if none has been supplied
+    pub async fn get_led_temperature(&self) -> Option<i32> {
+        let led_temperature = self.led_temperature.lock().await;
+        *led_temperature
+    }
+
+    /// Supply or clear the LED temperature
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `temp_c` - Temperature in °C, or `None` to derate by the die
+    ///   temperature again
+    pub async fn set_led_temperature(&self, temp_c: Option<i32>) {
+        {
+            let mut led_temperature = self.led_temperature.lock().await;
+            *led_temperature = temp_c;
+        }
+        // The ceilings may change, so wake anything waiting on the state stream
+        self.publish_state().await;
+    }
+
+    /// Read the nRF's die temperature
+    ///
+    /// Returns: Temperature in °C, or `None` before the knob task has read
+    /// it
+    pub async fn get_die_temperature(&self) -> Option<i32> {
+        let die_temperature = self.die_temperature.lock().await;
+        *die_temperature
+    }
+
+    /// Record the nRF's die temperature
+    ///
+    /// Called by the knob task with every reading; a change is published
+    /// to all state subscribers, as the ceilings may follow it.
+    ///
+    /// # Arguments
+    /// * `temp_c` - Temperature in °C
+    pub async fn set_die_temperature(&self, temp_c: i32) {
+        {
+            let mut die_temperature = self.die_temperature.lock().await;
+            if *die_temperature == Some(temp_c) {
+                return;
+            }
+            *die_temperature = Some(temp_c);
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the temperature the thermal ceilings follow
+    ///
+    /// Returns: The supplied LED temperature in °C, else the die
+    /// temperature, with `true` if it was supplied; `None` with neither
+    pub async fn get_thermal_temperature(&self) -> Option<(i32, bool)> {
+        match self.get_led_temperature().await {
+            Some(temp_c) => Some((temp_c, true)),
+            None => self
+                .get_die_temperature()
+                .await
+                .map(|temp_c| (temp_c, false)),
+        }
+    }
+
+    /// Read all preset slots
+    ///
+    /// Returns: Contents of every slot (`None` if empty)
+    pub async fn get_presets(&self) -> [Option<Preset>; PRESET_SLOTS] {
+        let presets = self.presets.lock().await;
+        *presets
+    }
+
+    /// Store or clear one preset slot
+    ///
+    /// The slot is kept in flash with the saved settings.
+    ///
+    /// # Arguments
+    /// * `slot` - Preset slot (0 to PRESET_SLOTS-1)
+    /// * `preset` - New contents, or `None` to clear the slot
+    pub async fn set_preset(&self, slot: usize, preset: Option<Preset>) {
+        {
+            let mut presets = self.presets.lock().await;
+            presets[slot] = preset;
+        }
+        self.request_settings_save();
+    }
+
+    /// Snapshot the settings kept in flash besides the calibration
+    ///
+    /// Returns: Settings as saved by the command task
+    pub async fn get_saved_settings(&self) -> SavedSettings {
+        SavedSettings {
+            presets: self.get_presets().await,
+            white_reference: self.get_white_reference().await,
+            button_map: self.get_button_map().await,
+            sequence: self.get_sequence().await,
+            knob_calibration: self.get_knob_calibration().await,
+        }
+    }
+
+    /// Restore the settings kept in flash besides the calibration
+    ///
+    /// Used at boot with the settings loaded from flash, so unlike the
+    /// individual setters this asks for no save.
+    ///
+    /// # Arguments
+    /// * `settings` - Validated settings to apply
+    pub async fn set_saved_settings(&self, settings: SavedSettings) {
+        let mut presets = self.presets.lock().await;
+        let mut white_reference = self.white_reference.lock().await;
+        let mut button_map = self.button_map.lock().await;
+        let mut sequence = self.sequence.lock().await;
+        let mut knob_calibration = self.knob_calibration.lock().await;
+        *presets = settings.presets;
+        *white_reference = settings.white_reference;
+        *button_map = settings.button_map;
+        *sequence = settings.sequence;
+        *knob_calibration = settings.knob_calibration;
+    }
+
+    /// Read the frame rate comparison
+    ///
+    /// Returns: Stored setpoints and the one applied, if comparing
+    pub async fn get_rate_compare(&self) -> RateCompare {
+        let rate_compare = self.rate_compare.lock().await;
+        *rate_compare
+    }
+
+    /// Replace the frame rate comparison
+    ///
+    /// Only the record changes; applying a setpoint is up to the caller (see
+    /// [`toggle_rate_compare`]).
+    ///
+    /// # Arguments
+    /// * `compare` - New setpoints and active setpoint
+    pub async fn set_rate_compare(&self, compare: RateCompare) {
+        let mut rate_compare = self.rate_compare.lock().await;
+        *rate_compare = compare;
+    }
+
+    /// Read which presets the long press of A steps through
+    ///
+    /// Returns: Presets visited
+    pub async fn get_preset_cycle(&self) -> PresetFilter {
+        let preset_cycle = self.preset_cycle.lock().await;
+        *preset_cycle
+    }
+
+    /// Choose which presets the long press of A steps through
+    ///
+    /// # Arguments
+    /// * `filter` - Presets to visit
+    pub async fn set_preset_cycle(&self, filter: PresetFilter) {
+        let mut preset_cycle = self.preset_cycle.lock().await;
+        *preset_cycle = filter;
+    }
+
+    /// Read which preset was loaded last
+    ///
+    /// Returns: Slot of the last loaded preset, or `None`
+    pub async fn get_active_preset(&self) -> Option<usize> {
+        let active_preset = self.active_preset.lock().await;
+        *active_preset
+    }
+
+    /// Record which preset was loaded last
+    ///
+    /// # Arguments
+    /// * `slot` - Slot of the loaded preset, or `None`
+    pub async fn set_active_preset(&self, slot: Option<usize>) {
+        let mut active_preset = self.active_preset.lock().await;
+        *active_preset = slot;
+    }
+
+    /// Check whether diagnostic output is enabled
+    ///
+    /// Returns: `true` if debug output should be printed
+    pub async fn get_debug_output(&self) -> bool {
+        let debug_output = self.debug_output.lock().await;
+        *debug_output
+    }
+
+    /// Enable or disable diagnostic output
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether debug output should be printed
+    pub async fn set_debug_output(&self, enabled: bool) {
+        let mut debug_output = self.debug_output.lock().await;
+        *debug_output = enabled;
+    }
+
+    /// Read the selected knob response curve
+    ///
+    /// Returns: Curve used to map knob travel to brightness levels
+    pub async fn get_knob_response(&self) -> KnobResponse {
+        let knob_response = self.knob_response.lock().await;
+        *knob_response
+    }
+
+    /// Select the knob response curve
+    ///
+    /// # Arguments
+    /// * `response` - New curve for mapping knob travel to brightness levels
+    pub async fn set_knob_response(&self, response: KnobResponse) {
+        let mut knob_response = self.knob_response.lock().await;
+        *knob_response = response;
+    }
+
+    /// Read the dead travel at each end of the knob
+    ///
+    /// Returns: Fractions of the scaled ADC range dead at the [low, high] end
+    pub async fn get_knob_deadbands(&self) -> [f32; 2] {
+        let knob_deadbands = self.knob_deadbands.lock().await;
+        *knob_deadbands
+    }
+
+    /// Set the dead travel at each end of the knob
+    ///
+    /// # Arguments
+    /// * `deadbands` - Fractions of the scaled ADC range dead at the [low,
+    ///   high] end, each clamped to 0.0..=MAX_DEADBAND
+    pub async fn set_knob_deadbands(&self, deadbands: [f32; 2]) {
+        let mut knob_deadbands = self.knob_deadbands.lock().await;
+        *knob_deadbands = deadbands.map(|deadband| deadband.clamp(0.0, MAX_DEADBAND));
+    }
+
+    /// Read the window over which knob level changes are batched
+    ///
+    /// Returns: Longest time a change is held back, in milliseconds
+    pub async fn get_knob_coalesce_ms(&self) -> u64 {
+        let knob_coalesce_ms = self.knob_coalesce_ms.lock().await;
+        *knob_coalesce_ms
+    }
+
+    /// Set the window over which knob level changes are batched
+    ///
+    /// # Arguments
+    /// * `window_ms` - Longest time a change is held back in milliseconds
+    ///   (zero commits every change at once), clamped to MAX_COALESCE_MS
+    pub async fn set_knob_coalesce_ms(&self, window_ms: u64) {
+        let mut knob_coalesce_ms = self.knob_coalesce_ms.lock().await;
+        *knob_coalesce_ms = window_ms.min(MAX_COALESCE_MS);
+    }
+
+    /// Read how often the knob and buttons are polled
+    ///
+    /// Returns: Interval between polls in milliseconds
+    pub async fn get_poll_ms(&self) -> u64 {
+        let poll_ms = self.poll_ms.lock().await;
+        *poll_ms
+    }
+
+    /// Set how often the knob and buttons are polled
+    ///
+    /// # Arguments
+    /// * `interval_ms` - Interval between polls in milliseconds, clamped to
+    ///   MIN_POLL_MS..=MAX_POLL_MS
+    pub async fn set_poll_ms(&self, interval_ms: u64) {
+        let mut poll_ms = self.poll_ms.lock().await;
+        *poll_ms = interval_ms.clamp(MIN_POLL_MS, MAX_POLL_MS);
+    }
+
+    /// Read how the knob reaching its center is indicated
+    ///
+    /// Returns: Current center indicator setting
+    pub async fn get_center_indicator(&self) -> CenterIndicator {
+        let center_indicator = self.center_indicator.lock().await;
+        *center_indicator
+    }
+
+    /// Select how the knob reaching its center is indicated
+    ///
+    /// # Arguments
+    /// * `indicator` - New center indicator setting
+    pub async fn set_center_indicator(&self, indicator: CenterIndicator) {
+        let mut center_indicator = self.center_indicator.lock().await;
+        *center_indicator = indicator;
+    }
+
+    /// Blank the LED output briefly as a cue to the user
+    ///
+    /// # Arguments
+    /// * `duration` - How long to keep the LED dark
+    pub async fn start_led_cue(&self, duration: Duration) {
+        let mut led_cue_until = self.led_cue_until.lock().await;
+        *led_cue_until = Some(Instant::now() + duration);
+    }
+
+    /// Check whether an LED cue is blanking the output
+    ///
+    /// Returns: `true` until the cue's time is up
+    pub async fn led_cue_active(&self) -> bool {
+        let led_cue_until = self.led_cue_until.lock().await;
+        led_cue_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Read the knob calibration
+    ///
+    /// Returns: Raw readings at the ends of the knob travel
+    pub async fn get_knob_calibration(&self) -> KnobCalibration {
+        let knob_calibration = self.knob_calibration.lock().await;
+        *knob_calibration
+    }
+
+    /// Replace the knob calibration
+    ///
+    /// The endpoints are kept in flash with the saved settings.
+    ///
+    /// # Arguments
+    /// * `calibration` - New raw readings at the ends of the knob travel
+    pub async fn set_knob_calibration(&self, calibration: KnobCalibration) {
+        {
+            let mut knob_calibration = self.knob_calibration.lock().await;
+            *knob_calibration = calibration;
+        }
+        self.request_settings_save();
+    }
+
+    /// Check whether knob readings between two levels are blended
+    ///
+    /// Returns: `true` if blending is on
+    pub async fn get_knob_blend(&self) -> bool {
+        let knob_blend = self.knob_blend.lock().await;
+        *knob_blend
+    }
+
+    /// Turn blending between adjacent knob levels on or off
+    ///
+    /// Turning it off drops any fractions already set.
+    ///
+    /// # Arguments
+    /// * `enabled` - `true` to blend
+    pub async fn set_knob_blend(&self, enabled: bool) {
+        {
+            let mut knob_blend = self.knob_blend.lock().await;
+            *knob_blend = enabled;
+        }
+        if !enabled {
+            self.set_level_fractions([0; 3]).await;
+        }
+    }
+
+    /// Check whether the knob picks up values before taking them over
+    ///
+    /// Returns: `true` if pickup is on
+    pub async fn get_knob_pickup(&self) -> bool {
+        let knob_pickup = self.knob_pickup.lock().await;
+        *knob_pickup
+    }
+
+    /// Turn knob pickup on or off
+    ///
+    /// # Arguments
+    /// * `enabled` - `true` to make the knob pass through a value first
+    pub async fn set_knob_pickup(&self, enabled: bool) {
+        let mut knob_pickup = self.knob_pickup.lock().await;
+        *knob_pickup = enabled;
+    }
+
+    /// Check whether the knob sets the hue with no buttons held
+    ///
+    /// Returns: `true` in hue mode, `false` for frame rate control
+    pub async fn get_knob_hue(&self) -> bool {
+        let knob_hue = self.knob_hue.lock().await;
+        *knob_hue
+    }
+
+    /// Turn the knob's hue mode on or off
+    ///
+    /// # Arguments
+    /// * `enabled` - `true` to set the hue with no buttons held
+    pub async fn set_knob_hue(&self, enabled: bool) {
+        let mut knob_hue = self.knob_hue.lock().await;
+        *knob_hue = enabled;
+    }
+
+    /// Check whether the knob nudges levels in fine steps
+    ///
+    /// Returns: `true` in fine mode, `false` for absolute (coarse) control
+    pub async fn get_knob_fine(&self) -> bool {
+        let knob_fine = self.knob_fine.lock().await;
+        *knob_fine
+    }
+
+    /// Turn the knob's fine mode on or off
+    ///
+    /// # Arguments
+    /// * `enabled` - `true` to nudge levels relative to their current value
+    pub async fn set_knob_fine(&self, enabled: bool) {
+        let mut knob_fine = self.knob_fine.lock().await;
+        *knob_fine = enabled;
+    }
+
+    /// Read how far each channel sits toward its next level
+    ///
+    /// Returns: [red, green, blue] fractions in [`FRACTION_ONE`]ths
+    pub async fn get_level_fractions(&self) -> [u32; 3] {
+        let level_fractions = self.level_fractions.lock().await;
+        *level_fractions
+    }
+
+    /// Set how far each channel sits toward its next level
+    ///
+    /// The change is published to all state subscribers.
+    ///
+    /// # Arguments
+    /// * `fractions` - [red, green, blue] fractions in [`FRACTION_ONE`]ths,
+    ///   clamped below one
+    pub async fn set_level_fractions(&self, fractions: [u32; 3]) {
+        {
+            let mut level_fractions = self.level_fractions.lock().await;
+            *level_fractions = fractions.map(|fraction| fraction.min(FRACTION_ONE - 1));
+        }
+        self.publish_state().await;
+    }
+
+    /// Read the button-to-channel assignment
+    ///
+    /// Returns: Channel each button combination selects and toggles
+    pub async fn get_button_map(&self) -> ButtonMap {
+        let button_map = self.button_map.lock().await;
+        *button_map
+    }
+
+    /// Change the button-to-channel assignment
+    ///
+    /// The assignment is kept in flash with the saved settings.
+    ///
+    /// # Arguments
+    /// * `map` - New channel for each button combination
+    pub async fn set_button_map(&self, map: ButtonMap) {
+        {
+            let mut button_map = self.button_map.lock().await;
+            *button_map = map;
+        }
+        self.request_settings_save();
+    }
+
+    /// Read the physical input state last seen by the UI
+    ///
+    /// Returns: Knob reading and button states
+    pub async fn get_input_snapshot(&self) -> InputSnapshot {
+        let input_snapshot = self.input_snapshot.lock().await;
+        *input_snapshot
+    }
+
+    /// Record the physical input state
+    ///
+    /// # Arguments
+    /// * `snapshot` - Knob reading and button states from this UI pass
+    pub async fn set_input_snapshot(&self, snapshot: InputSnapshot) {
+        let mut input_snapshot = self.input_snapshot.lock().await;
+        *input_snapshot = snapshot;
+    }
+
+    /// Read which source last set each knob-controlled parameter
+    ///
+    /// Returns: Sources of the frame rate and the three levels
+    pub async fn get_parameter_sources(&self) -> ParameterSources {
+        let parameter_sources = self.parameter_sources.lock().await;
+        *parameter_sources
+    }
+
+    /// Record which source last set each knob-controlled parameter
+    ///
+    /// # Arguments
+    /// * `sources` - Sources of the frame rate and the three levels
+    pub async fn set_parameter_sources(&self, sources: ParameterSources) {
+        let mut parameter_sources = self.parameter_sources.lock().await;
+        *parameter_sources = sources;
+    }
+
+    /// Read the marked flicker-fusion threshold
+    ///
+    /// Returns: Lowest frame rate in fps the controls allow, or `None` if no
+    /// threshold has been marked
+    pub async fn get_fusion_floor(&self) -> Option<u64> {
+        let fusion_floor = self.fusion_floor.lock().await;
+        *fusion_floor
+    }
+
+    /// Mark or clear the flicker-fusion threshold
+    ///
+    /// A frame rate already below the new threshold is raised to it at once.
+    ///
+    /// # Arguments
+    /// * `floor` - Frame rate in fps below which flicker was seen, or `None`
+    ///   to remove the limit
+    pub async fn set_fusion_floor(&self, floor: Option<u64>) {
+        {
+            let mut fusion_floor = self.fusion_floor.lock().await;
+            *fusion_floor = floor;
+        }
+        let frame_rate = self.get_frame_rate().await;
+        if let Some(floor) = floor.filter(|&floor| frame_rate < floor) {
+            self.set_frame_rate(floor).await;
+        }
+    }
+
+    /// Read the strobe guard
+    ///
+    /// Returns: Safe minimum frame rate and whether strobing is allowed
+    pub async fn get_strobe_guard(&self) -> StrobeGuard {
+        let strobe_guard = self.strobe_guard.lock().await;
+        *strobe_guard
+    }
+
+    /// Replace the strobe guard
+    ///
+    /// A frame rate already below the new floor is raised to it at once.
+    ///
+    /// # Arguments
+    /// * `guard` - New safe minimum and override
+    pub async fn set_strobe_guard(&self, guard: StrobeGuard) {
+        {
+            let mut strobe_guard = self.strobe_guard.lock().await;
+            *strobe_guard = guard;
+        }
+        if self.get_frame_rate().await < guard.floor() {
+            self.set_frame_rate(guard.floor()).await;
+        }
+    }
+
+    /// Read the white reference
+    ///
+    /// Returns: [red, green, blue] levels that look white
+    pub async fn get_white_reference(&self) -> [u32; 3] {
+        let white_reference = self.white_reference.lock().await;
+        *white_reference
+    }
+
+    /// Store the white reference
+    ///
+    /// The reference is kept in flash with the saved settings.
+    ///
+    /// # Arguments
+    /// * `levels` - [red, green, blue] levels that look white
+    pub async fn set_white_reference(&self, levels: [u32; 3]) {
+        {
+            let mut white_reference = self.white_reference.lock().await;
+            *white_reference = levels;
+        }
+        self.request_settings_save();
+    }
+
+    /// Read the unit the frame rate is shown and commanded in
+    ///
+    /// Returns: Selected frame rate unit
+    pub async fn get_frame_rate_unit(&self) -> FrameRateUnit {
+        let frame_rate_unit = self.frame_rate_unit.lock().await;
+        *frame_rate_unit
+    }
+
+    /// Select the unit the frame rate is shown and commanded in
+    ///
+    /// # Arguments
+    /// * `unit` - New frame rate unit
+    pub async fn set_frame_rate_unit(&self, unit: FrameRateUnit) {
+        let mut frame_rate_unit = self.frame_rate_unit.lock().await;
+        *frame_rate_unit = unit;
+    }
+
+    /// Read the selected state display format
+    ///
+    /// Returns: Format used when the UI shows the state
+    pub async fn get_output_format(&self) -> OutputFormat {
+        let output_format = self.output_format.lock().await;
+        *output_format
+    }
+
+    /// Select the state display format
+    ///
+    /// # Arguments
+    /// * `format` - New format used when the UI shows the state
+    pub async fn set_output_format(&self, format: OutputFormat) {
+        let mut output_format = self.output_format.lock().await;
+        *output_format = format;
+    }
+}
@@ -0,0 +1,131 @@
+//! Session Statistics
+//!
+//! Summarizes a calibration session: how long each channel spent at each
+//! level, the range of frame rates tried and where the settings ended up,
+//! and how often and how long each knob mode was used, to show which parts
+//! of the button scheme get used. The UI records the current settings on
+//! every pass; the `stats` command prints the summary. All counters are
+//! fixed-size and saturate, so a session of any length fits.
+
+use crate::*;
+
+/// Knob modes tracked by the mode statistics, in reporting order
+pub const TRACKED_MODES: [ControlMode; 4] = [
+    ControlMode::FrameRate,
+    ControlMode::Red,
+    ControlMode::Green,
+    ControlMode::Blue,
+];
+
+/// Accumulated statistics of the current session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Milliseconds spent at each level, per channel [red, green, blue]
+    level_ms: [[u32; LEVELS as usize]; 3],
+    /// Lowest and highest frame rate seen, or `None` before the first
+    /// record
+    frame_rates: Option<(u64, u64)>,
+    /// Milliseconds spent in each mode, indexed like [`TRACKED_MODES`]
+    mode_ms: [u32; 4],
+    /// Number of times each mode was entered, indexed like
+    /// [`TRACKED_MODES`]
+    mode_entries: [u32; 4],
+    /// Levels, frame rate and mode at the last record, with its time
+    last: Option<([u32; 3], u64, ControlMode, Instant)>,
+}
+
+impl SessionStats {
+    /// Statistics with no observations
+    pub const fn new() -> Self {
+        Self {
+            level_ms: [[0; LEVELS as usize]; 3],
+            frame_rates: None,
+            mode_ms: [0; 4],
+            mode_entries: [0; 4],
+            last: None,
+        }
+    }
+
+    /// Record the settings in effect now
+    ///
+    /// The time since the previous record is credited to the levels and
+    /// mode that were in effect then. A mode differing from the previous
+    /// record's (or the first one) counts as an entry into it.
+    ///
+    /// # Arguments
+    /// * `levels` - Current [red, green, blue] levels
+    /// * `frame_rate` - Current frame rate in fps
+    /// * `mode` - Parameter the knob currently controls
+    /// * `now` - Current time
+    pub fn record(&mut self, levels: [u32; 3], frame_rate: u64, mode: ControlMode, now: Instant) {
+        let mut entered = true;
+        if let Some((previous, _, previous_mode, since)) = self.last {
+            let elapsed = now.duration_since(since).as_millis();
+            let elapsed = elapsed.min(u32::MAX as u64) as u32;
+            for (times, level) in self.level_ms.iter_mut().zip(previous) {
+                let level = level.min(LEVELS - 1) as usize;
+                times[level] = times[level].saturating_add(elapsed);
+            }
+            if let Some(index) = mode_index(previous_mode) {
+                self.mode_ms[index] = self.mode_ms[index].saturating_add(elapsed);
+            }
+            entered = mode != previous_mode;
+        }
+        if let Some(index) = mode_index(mode).filter(|_| entered) {
+            self.mode_entries[index] = self.mode_entries[index].saturating_add(1);
+        }
+        self.frame_rates = Some(match self.frame_rates {
+            Some((min, max)) => (min.min(frame_rate), max.max(frame_rate)),
+            None => (frame_rate, frame_rate),
+        });
+        self.last = Some((levels, frame_rate, mode, now));
+    }
+
+    /// Milliseconds a channel spent at each level
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    pub fn level_ms(&self, channel: usize) -> &[u32; LEVELS as usize] {
+        &self.level_ms[channel]
+    }
+
+    /// Lowest and highest frame rate seen
+    ///
+    /// # Returns
+    /// (min, max) in fps, or `None` if nothing was recorded
+    pub fn frame_rate_range(&self) -> Option<(u64, u64)> {
+        self.frame_rates
+    }
+
+    /// Time spent in and entries into each knob mode
+    ///
+    /// # Returns
+    /// (milliseconds, entries) per mode, indexed like [`TRACKED_MODES`]
+    pub fn mode_usage(&self) -> [(u32, u32); 4] {
+        [0, 1, 2, 3].map(|index| (self.mode_ms[index], self.mode_entries[index]))
+    }
+
+    /// Settings at the most recent record
+    ///
+    /// # Returns
+    /// ([red, green, blue] levels, frame rate), or `None` if nothing was
+    /// recorded
+    pub fn latest(&self) -> Option<([u32; 3], u64)> {
+        self.last
+            .map(|(levels, frame_rate, _, _)| (levels, frame_rate))
+    }
+}
+
+/// Position of a mode in [`TRACKED_MODES`]
+///
+/// # Returns
+/// Index, or `None` for a mode that is not tracked (inactive)
+fn mode_index(mode: ControlMode) -> Option<usize> {
+    TRACKED_MODES.iter().position(|&tracked| tracked == mode)
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,665 @@
+//! Flash Storage
+//!
+//! Keeps the saved calibration in a flash page reserved for it (see
+//! `memory.x`), so it survives a reset or power cycle. The page holds one
+//! record:
+//!
+//! | Offset  | Type     | Field                                        |
+//! |---------|----------|----------------------------------------------|
+//! | 0       | u32      | [`RECORD_MAGIC`], little-endian              |
+//! | 4       | u8       | Version, always [`RECORD_VERSION`]           |
+//! | 5       | u8       | Reserved, 0                                  |
+//! | 6       | u16      | Payload length, little-endian                |
+//! | 8       | u8 x len | Payload                                      |
+//! | 8 + len | u32      | CRC-32 of bytes 0 to 7 + len, little-endian  |
+//!
+//! padded with 0xFF to the flash's write size. The payload is opaque here;
+//! the firmware stores its calibration config blob, which carries the
+//! levels and frame rate among the rest, and keeps its other settings in a
+//! record of their own in a second page (see [`Storage::page`]). A page
+//! that is blank, written by another program or damaged is reported as a
+//! [`StorageError`], and the firmware starts from its defaults instead.
+//!
+//! A save is read back before it counts: a record that does not read back
+//! as written, or a flash error on the way, is retried up to
+//! [`SAVE_ATTEMPTS`] times, waiting [`SAVE_BACKOFF_US`] before the first
+//! retry and twice as long before each further one.
+//!
+//! Saving automatically is left to a [`SavePolicy`]: a save is only worth
+//! its flash wear once the levels or the frame rate have moved past a
+//! threshold (so knob jitter is not saved over and over), or another setting
+//! has changed, and never sooner than a minimum interval after the last
+//! save. [`SaveThrottle`] tracks the last save and applies the policy.
+//!
+//! The flash is reached through `embedded-storage`'s [`NorFlash`] trait, so
+//! the host tests run against a RAM-backed mock.
+
+use crate::*;
+
+use embedded_storage::nor_flash::NorFlash;
+
+/// Marks a page holding a record ("RGBC" in flash byte order)
+pub const RECORD_MAGIC: u32 = u32::from_le_bytes(*b"RGBC");
+
+/// Version byte of the current record layout
+pub const RECORD_VERSION: u8 = 1;
+
+/// Longest payload a record holds, in bytes
+pub const MAX_RECORD_PAYLOAD: usize = 1012;
+
+/// Attempts at writing a record before a save fails
+pub const SAVE_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry of a failed save, in microseconds
+pub const SAVE_BACKOFF_US: u64 = 10_000;
+
+/// Automatic save policy: thresholds of 2 levels and 5 fps, at most one
+/// save a minute
+pub const DEFAULT_SAVE_POLICY: SavePolicy = SavePolicy {
+    level_threshold: 2,
+    frame_rate_threshold: 5,
+    min_interval_s: 60,
+};
+
+/// Bytes before the payload: magic, version, reserved byte and length
+const HEADER_LEN: usize = 8;
+
+/// Bytes after the payload: the CRC-32
+const CHECKSUM_LEN: usize = 4;
+
+/// Longest record, in bytes
+const MAX_RECORD: usize = HEADER_LEN + MAX_RECORD_PAYLOAD + CHECKSUM_LEN;
+
+/// Reasons a record could not be loaded or saved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// The page is erased: nothing has been saved
+    Blank,
+    /// The page holds something other than a record
+    NoRecord,
+    /// The record was written by an unknown layout version
+    Version,
+    /// The payload length is not the one expected
+    Length,
+    /// The record's checksum does not match its contents
+    Checksum,
+    /// The payload is longer than MAX_RECORD_PAYLOAD
+    TooLarge,
+    /// The flash refused an erase, write or read
+    Flash,
+    /// The written record did not read back as written
+    Verify,
+}
+
+impl StorageError {
+    /// Short human-readable description for replies and warnings
+    pub fn message(self) -> &'static str {
+        match self {
+            StorageError::Blank => "nothing saved",
+            StorageError::NoRecord => "no saved record",
+            StorageError::Version => "saved record has an unknown version",
+            StorageError::Length => "saved record has the wrong length",
+            StorageError::Checksum => "saved record checksum mismatch",
+            StorageError::TooLarge => "record too large",
+            StorageError::Flash => "flash access failed",
+            StorageError::Verify => "saved record did not read back",
+        }
+    }
+}
+
+/// One record in a reserved flash page
+pub struct Storage<F> {
+    /// Flash holding the page
+    flash: F,
+    /// Start of the page, from the start of the flash
+    offset: u32,
+}
+
+impl<F: NorFlash> Storage<F> {
+    /// Keep the record in a flash page
+    ///
+    /// # Arguments
+    /// * `flash` - Flash holding the page
+    /// * `offset` - Start of the page (a multiple of the erase size), from
+    ///   the start of the flash
+    ///
+    /// # Returns
+    /// The storage
+    pub fn new(flash: F, offset: u32) -> Self {
+        Storage { flash, offset }
+    }
+
+    /// The record in another reserved page of the same flash
+    ///
+    /// # Arguments
+    /// * `offset` - Start of the other page (a multiple of the erase size),
+    ///   from the start of the flash
+    ///
+    /// # Returns
+    /// Storage for that page, borrowing this one's flash
+    pub fn page(&mut self, offset: u32) -> Storage<&mut F> {
+        Storage::new(&mut self.flash, offset)
+    }
+
+    /// Length of the record holding a payload, padded to the write size
+    ///
+    /// # Arguments
+    /// * `payload_len` - Payload length in bytes
+    fn record_len(payload_len: usize) -> usize {
+        (HEADER_LEN + payload_len + CHECKSUM_LEN).next_multiple_of(F::WRITE_SIZE)
+    }
+
+    /// Read the saved record
+    ///
+    /// # Arguments
+    /// * `payload` - Filled with the saved payload; its length is the one
+    ///   expected
+    ///
+    /// # Returns
+    /// `Ok` once `payload` holds a valid record's payload, or why there is
+    /// none
+    pub fn load(&mut self, payload: &mut [u8]) -> Result<(), StorageError> {
+        if payload.len() > MAX_RECORD_PAYLOAD {
+            return Err(StorageError::TooLarge);
+        }
+        let mut record = [0; MAX_RECORD];
+        let record = &mut record[..Self::record_len(payload.len())];
+        self.flash
+            .read(self.offset, record)
+            .map_err(|_| StorageError::Flash)?;
+        let payload_end = HEADER_LEN + payload.len();
+        let magic = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+        if magic != RECORD_MAGIC {
+            return Err(match magic {
+                u32::MAX => StorageError::Blank,
+                _ => StorageError::NoRecord,
+            });
+        }
+        if record[4] != RECORD_VERSION {
+            return Err(StorageError::Version);
+        }
+        if usize::from(u16::from_le_bytes([record[6], record[7]])) != payload.len() {
+            return Err(StorageError::Length);
+        }
+        let checksum = &record[payload_end..payload_end + CHECKSUM_LEN];
+        if crc32(&record[..payload_end]).to_le_bytes() != checksum {
+            return Err(StorageError::Checksum);
+        }
+        payload.copy_from_slice(&record[HEADER_LEN..payload_end]);
+        Ok(())
+    }
+
+    /// Replace the saved record
+    ///
+    /// The page is erased first, so a save interrupted by a reset leaves a
+    /// record that fails its checksum rather than a stale one. A failed
+    /// attempt is retried after a backoff, up to SAVE_ATTEMPTS in all.
+    ///
+    /// # Arguments
+    /// * `payload` - Payload to save, at most MAX_RECORD_PAYLOAD bytes
+    /// * `clock` - Clock timing the backoff between attempts
+    ///
+    /// # Returns
+    /// `Ok` once the record has been written and read back, or why the last
+    /// attempt failed
+    pub async fn save<C: Clock>(
+        &mut self,
+        payload: &[u8],
+        clock: &mut C,
+    ) -> Result<(), StorageError> {
+        if payload.len() > MAX_RECORD_PAYLOAD {
+            return Err(StorageError::TooLarge);
+        }
+        let mut record = [0xff; MAX_RECORD];
+        let record = &mut record[..Self::record_len(payload.len())];
+        let payload_end = HEADER_LEN + payload.len();
+        record[..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        record[4] = RECORD_VERSION;
+        record[5] = 0;
+        record[6..8].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        record[HEADER_LEN..payload_end].copy_from_slice(payload);
+        let checksum = crc32(&record[..payload_end]);
+        record[payload_end..payload_end + CHECKSUM_LEN].copy_from_slice(&checksum.to_le_bytes());
+
+        let mut backoff = SAVE_BACKOFF_US;
+        let mut attempt = 1;
+        loop {
+            match self.write_record(record) {
+                Err(_) if attempt < SAVE_ATTEMPTS => {
+                    clock.delay_us(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Erase the page, write a record to it and read it back
+    ///
+    /// # Arguments
+    /// * `record` - Complete record, padded to the write size
+    ///
+    /// # Returns
+    /// `Ok` once the page holds the record, or why it does not
+    fn write_record(&mut self, record: &[u8]) -> Result<(), StorageError> {
+        let page_end = self.offset + F::ERASE_SIZE as u32;
+        self.flash
+            .erase(self.offset, page_end)
+            .map_err(|_| StorageError::Flash)?;
+        self.flash
+            .write(self.offset, record)
+            .map_err(|_| StorageError::Flash)?;
+        let mut written = [0; MAX_RECORD];
+        let written = &mut written[..record.len()];
+        self.flash
+            .read(self.offset, written)
+            .map_err(|_| StorageError::Flash)?;
+        if written != record {
+            return Err(StorageError::Verify);
+        }
+        Ok(())
+    }
+}
+
+/// What an automatic save compares with the last save
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavedFields {
+    /// Requested [red, green, blue] levels
+    pub levels: [u32; 3],
+    /// Frame rate in fps
+    pub frame_rate: u64,
+    /// Checksum of every other saved setting, any change of which counts
+    pub settings: u32,
+}
+
+/// When a change is worth saving automatically
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavePolicy {
+    /// Smallest change of a level that is saved
+    pub level_threshold: u32,
+    /// Smallest change of the frame rate that is saved, in fps
+    pub frame_rate_threshold: u64,
+    /// Shortest time from one save to the next, in seconds
+    pub min_interval_s: u32,
+}
+
+impl SavePolicy {
+    /// Check whether values have moved far enough from the saved ones
+    ///
+    /// # Arguments
+    /// * `saved` - Values last saved
+    /// * `current` - Values now
+    ///
+    /// # Returns
+    /// `true` if a level or the frame rate has changed by at least its
+    /// threshold, or any other setting has changed
+    pub fn differs(&self, saved: &SavedFields, current: &SavedFields) -> bool {
+        let level_moved = saved
+            .levels
+            .iter()
+            .zip(current.levels)
+            .any(|(&saved, current)| saved.abs_diff(current) >= self.level_threshold.max(1));
+        let frame_rate_moved =
+            saved.frame_rate.abs_diff(current.frame_rate) >= self.frame_rate_threshold.max(1);
+        level_moved || frame_rate_moved || saved.settings != current.settings
+    }
+}
+
+/// Decides when to save automatically
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SaveThrottle {
+    /// Values in the record, if it holds a valid one
+    saved: Option<SavedFields>,
+    /// When the record was last written, if it has been since the boot
+    last_save: Option<Instant>,
+}
+
+impl SaveThrottle {
+    /// Start tracking saves
+    ///
+    /// # Arguments
+    /// * `saved` - Values in the record found at boot, if any
+    ///
+    /// # Returns
+    /// A throttle that has not seen a save yet
+    pub const fn new(saved: Option<SavedFields>) -> Self {
+        SaveThrottle {
+            saved,
+            last_save: None,
+        }
+    }
+
+    /// Note a save, automatic or not
+    ///
+    /// # Arguments
+    /// * `fields` - Values saved
+    /// * `now` - Time of the save
+    pub fn saved(&mut self, fields: SavedFields, now: Instant) {
+        self.saved = Some(fields);
+        self.last_save = Some(now);
+    }
+
+    /// Note a save that failed, so the next try waits as long as after a
+    /// save
+    ///
+    /// # Arguments
+    /// * `now` - Time of the failure
+    pub fn failed(&mut self, now: Instant) {
+        self.last_save = Some(now);
+    }
+
+    /// Check whether the minimum interval since the last save has passed
+    ///
+    /// # Arguments
+    /// * `policy` - Automatic save policy
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// `true` if a save would not come too soon after the last one
+    pub fn ready(&self, policy: &SavePolicy, now: Instant) -> bool {
+        let interval = Duration::from_secs(policy.min_interval_s.into());
+        self.last_save
+            .is_none_or(|last_save| now.saturating_duration_since(last_save) >= interval)
+    }
+
+    /// Check whether to save automatically
+    ///
+    /// # Arguments
+    /// * `policy` - Automatic save policy
+    /// * `current` - Values now
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// `true` if the values differ enough from the saved ones (or nothing is
+    /// saved) and the minimum interval has passed
+    pub fn due(&self, policy: &SavePolicy, current: &SavedFields, now: Instant) -> bool {
+        self.ready(policy, now)
+            && self
+                .saved
+                .is_none_or(|saved| policy.differs(&saved, current))
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as used by zip and Ethernet) of some bytes
+///
+/// # Arguments
+/// * `bytes` - Data to check
+///
+/// # Returns
+/// The checksum
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    /// Size of each of the mock's two pages
+    const PAGE: usize = 4096;
+
+    /// RAM-backed flash with two pages that behaves like NOR flash: erasing
+    /// sets every bit, writing can only clear bits
+    struct MockFlash {
+        /// Contents of both pages
+        bytes: [u8; 2 * PAGE],
+        /// Writes still to be garbled (a bit left set, as by a weak cell)
+        garbled_writes: u32,
+        /// Erases still to be refused
+        failed_erases: u32,
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            MockFlash {
+                bytes: [0xff; 2 * PAGE],
+                garbled_writes: 0,
+                failed_erases: 0,
+            }
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let start = offset as usize;
+            let source = self
+                .bytes
+                .get(start..start + bytes.len())
+                .ok_or(NorFlashErrorKind::OutOfBounds)?;
+            bytes.copy_from_slice(source);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            2 * PAGE
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = PAGE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            if self.failed_erases > 0 {
+                self.failed_erases -= 1;
+                return Err(NorFlashErrorKind::Other);
+            }
+            let page = self
+                .bytes
+                .get_mut(from as usize..to as usize)
+                .ok_or(NorFlashErrorKind::OutOfBounds)?;
+            page.fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            if !(offset as usize).is_multiple_of(Self::WRITE_SIZE)
+                || !bytes.len().is_multiple_of(Self::WRITE_SIZE)
+            {
+                return Err(NorFlashErrorKind::NotAligned);
+            }
+            let start = offset as usize;
+            let target = self
+                .bytes
+                .get_mut(start..start + bytes.len())
+                .ok_or(NorFlashErrorKind::OutOfBounds)?;
+            for (cell, byte) in target.iter_mut().zip(bytes) {
+                *cell &= byte;
+            }
+            if self.garbled_writes > 0 {
+                self.garbled_writes -= 1;
+                target[HEADER_LEN] |= 0x01;
+            }
+            Ok(())
+        }
+    }
+
+    /// Save without retries being timed
+    fn save(storage: &mut Storage<MockFlash>, payload: &[u8]) -> Result<(), StorageError> {
+        block_on(storage.save(payload, &mut VirtualClock::new()))
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn save_then_load() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        let mut payload = [0; 7];
+        assert_eq!(storage.load(&mut payload), Err(StorageError::Blank));
+        save(&mut storage, &[1, 2, 3, 4, 5, 6, 7]).unwrap();
+        storage.load(&mut payload).unwrap();
+        assert_eq!(payload, [1, 2, 3, 4, 5, 6, 7]);
+        // A later save replaces the record, even where it clears no bits
+        save(&mut storage, &[0xff; 7]).unwrap();
+        storage.load(&mut payload).unwrap();
+        assert_eq!(payload, [0xff; 7]);
+    }
+
+    #[test]
+    fn pages_keep_their_own_records() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        save(&mut storage, &[1, 2, 3, 4]).unwrap();
+        let mut other = storage.page(PAGE as u32);
+        assert_eq!(other.load(&mut [0; 2]), Err(StorageError::Blank));
+        block_on(other.save(&[5, 6], &mut VirtualClock::new())).unwrap();
+        // Saving one page leaves the other's record alone
+        let mut payload = [0; 4];
+        storage.load(&mut payload).unwrap();
+        assert_eq!(payload, [1, 2, 3, 4]);
+        let mut payload = [0; 2];
+        storage.page(PAGE as u32).load(&mut payload).unwrap();
+        assert_eq!(payload, [5, 6]);
+    }
+
+    #[test]
+    fn damaged_records_are_rejected() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        let mut payload = [0; 4];
+        save(&mut storage, &[9, 8, 7, 6]).unwrap();
+
+        storage.flash.bytes[HEADER_LEN + 1] ^= 0x10;
+        assert_eq!(storage.load(&mut payload), Err(StorageError::Checksum));
+        save(&mut storage, &[9, 8, 7, 6]).unwrap();
+        storage.flash.bytes[4] = RECORD_VERSION + 1;
+        assert_eq!(storage.load(&mut payload), Err(StorageError::Version));
+        save(&mut storage, &[9, 8, 7, 6]).unwrap();
+        assert_eq!(storage.load(&mut [0; 5]), Err(StorageError::Length));
+        storage.flash.bytes[0] = 0;
+        assert_eq!(storage.load(&mut payload), Err(StorageError::NoRecord));
+        assert_eq!(payload, [0; 4]);
+    }
+
+    #[test]
+    fn oversized_payload_is_refused() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        let payload = [0; MAX_RECORD_PAYLOAD + 1];
+        assert_eq!(save(&mut storage, &payload), Err(StorageError::TooLarge));
+        save(&mut storage, &payload[..MAX_RECORD_PAYLOAD]).unwrap();
+    }
+
+    #[test]
+    fn failed_saves_are_retried() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        let mut clock = VirtualClock::new();
+        let mut payload = [0; 4];
+
+        // A garbled write is caught by the read-back and written again
+        storage.flash.garbled_writes = 1;
+        block_on(storage.save(&[0, 0, 0, 0], &mut clock)).unwrap();
+        assert_eq!(clock.elapsed_us(), SAVE_BACKOFF_US);
+        storage.load(&mut payload).unwrap();
+        assert_eq!(payload, [0; 4]);
+
+        // So is a refused erase, after a longer wait each time
+        let mut clock = VirtualClock::new();
+        storage.flash.failed_erases = 2;
+        block_on(storage.save(&[1, 2, 3, 4], &mut clock)).unwrap();
+        assert_eq!(clock.elapsed_us(), 3 * SAVE_BACKOFF_US);
+        storage.load(&mut payload).unwrap();
+        assert_eq!(payload, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn persistent_failures_are_reported() {
+        let mut storage = Storage::new(MockFlash::new(), 0);
+        let mut clock = VirtualClock::new();
+        storage.flash.garbled_writes = SAVE_ATTEMPTS;
+        assert_eq!(
+            block_on(storage.save(&[0, 0, 0, 0], &mut clock)),
+            Err(StorageError::Verify)
+        );
+        assert_eq!(clock.elapsed_us(), 3 * SAVE_BACKOFF_US);
+
+        storage.flash.failed_erases = SAVE_ATTEMPTS;
+        assert_eq!(save(&mut storage, &[0; 4]), Err(StorageError::Flash));
+        // One attempt too few to give up
+        storage.flash.failed_erases = SAVE_ATTEMPTS - 1;
+        save(&mut storage, &[0; 4]).unwrap();
+    }
+
+    /// Fields with the given levels and frame rate
+    fn fields(levels: [u32; 3], frame_rate: u64) -> SavedFields {
+        SavedFields {
+            levels,
+            frame_rate,
+            settings: 0,
+        }
+    }
+
+    #[test]
+    fn small_changes_are_not_saved() {
+        let policy = SavePolicy {
+            level_threshold: 3,
+            frame_rate_threshold: 10,
+            min_interval_s: 0,
+        };
+        let saved = fields([10, 10, 10], 100);
+        assert!(!policy.differs(&saved, &saved));
+        assert!(!policy.differs(&saved, &fields([12, 8, 10], 109)));
+        assert!(policy.differs(&saved, &fields([10, 13, 10], 100)));
+        assert!(policy.differs(&saved, &fields([10, 10, 7], 100)));
+        assert!(policy.differs(&saved, &fields([10, 10, 10], 90)));
+        let other = SavedFields {
+            settings: 1,
+            ..saved
+        };
+        assert!(policy.differs(&saved, &other));
+        // A zero threshold still ignores an unchanged value
+        let exact = SavePolicy {
+            level_threshold: 0,
+            frame_rate_threshold: 0,
+            ..policy
+        };
+        assert!(!exact.differs(&saved, &saved));
+        assert!(exact.differs(&saved, &fields([10, 10, 11], 100)));
+    }
+
+    #[test]
+    fn saves_are_rate_limited() {
+        let policy = DEFAULT_SAVE_POLICY;
+        let start = Instant::from_secs(5);
+        let interval = Duration::from_secs(policy.min_interval_s.into());
+        let first = fields([0, 0, 0], 100);
+        let moved = fields([9, 9, 9], 100);
+
+        // Nothing saved yet: anything is due
+        let mut throttle = SaveThrottle::new(None);
+        assert!(throttle.due(&policy, &first, start));
+        throttle.saved(first, start);
+        assert!(!throttle.due(&policy, &first, start + interval));
+        assert!(!throttle.due(&policy, &moved, start + interval / 2));
+        assert!(throttle.due(&policy, &moved, start + interval));
+        // A failed save is not retried sooner
+        throttle.failed(start + interval);
+        assert!(!throttle.due(&policy, &moved, start + interval * 3 / 2));
+        assert!(throttle.due(&policy, &moved, start + interval * 2));
+
+        // The record found at boot counts as saved, but not as recent
+        let throttle = SaveThrottle::new(Some(first));
+        assert!(!throttle.due(&policy, &first, start));
+        assert!(throttle.due(&policy, &moved, start));
+    }
+}
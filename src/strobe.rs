@@ -0,0 +1,147 @@
+//! Strobe Guard
+//!
+//! At very low frame rates the LED visibly strobes, which is uncomfortable
+//! and can trigger photosensitive seizures. Unlike the flicker line of the
+//! state display, which only informs, the guard holds every frame rate
+//! write at or above a flicker-free minimum (50 fps by default). Going
+//! lower takes an explicit `strobe allow`, which is announced with a
+//! warning and lasts until `strobe guard` or the next reset.
+//!
+//! The guard is applied where the firmware writes the shared frame rate
+//! (`set_frame_rate`), so the knob, commands, presets, the binary control
+//! protocol and the wizard's sweep all respect it. The same place holds
+//! the marked flicker-fusion threshold, except during the wizard's sweep.
+//! The knob's travel spans the guarded range, from [`StrobeGuard::floor`]
+//! to `MAX_FRAME_RATE`.
+
+use crate::*;
+
+/// Default lowest frame rate allowed while the guard is on, in fps
+///
+/// The eye fuses a flickering light once it flickers faster than its
+/// critical flicker fusion frequency, around 50-60 Hz for a bright light
+/// seen head-on and lower in the dim periphery. Each die lights once per
+/// frame, so the frame rate is the flicker frequency of every color, and
+/// below about 50 fps the colors visibly break apart instead of mixing to
+/// white. The photosensitive seizure risk is highest between about 15 and
+/// 25 Hz, well below this. Measuring a particular LED's fusion threshold
+/// below the guard takes `strobe allow` (down to `MIN_FRAME_RATE`).
+pub const STROBE_MIN_FPS: u64 = 50;
+
+const _: () = assert!(
+    MIN_FRAME_RATE < STROBE_MIN_FPS && STROBE_MIN_FPS < MAX_FRAME_RATE,
+    "the strobe guard must fall inside the frame rate range"
+);
+
+/// Lowest frame rate below which strobing is refused, and its override
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StrobeGuard {
+    /// Lowest frame rate allowed while guarding, in fps
+    pub min_fps: u64,
+    /// Whether the user has allowed strobing frame rates
+    pub allowed: bool,
+}
+
+impl StrobeGuard {
+    /// Guarding at `STROBE_MIN_FPS`
+    pub const DEFAULT: Self = StrobeGuard {
+        min_fps: STROBE_MIN_FPS,
+        allowed: false,
+    };
+
+    /// Lowest frame rate the guard lets through
+    ///
+    /// # Returns
+    /// `min_fps` while guarding, `MIN_FRAME_RATE` once strobing is allowed
+    pub fn floor(&self) -> u64 {
+        if self.allowed {
+            MIN_FRAME_RATE
+        } else {
+            self.min_fps.max(MIN_FRAME_RATE)
+        }
+    }
+
+    /// Apply the guard and the fusion threshold to a frame rate
+    ///
+    /// Every frame rate written to the shared state passes through here.
+    ///
+    /// # Arguments
+    /// * `frame_rate` - Requested frame rate in fps
+    /// * `fusion_floor` - Marked flicker-fusion threshold to hold, or `None`
+    ///   (none marked, or the wizard's sweep looking for it)
+    ///
+    /// # Returns
+    /// The frame rate to use, clamped to MIN_FRAME_RATE..=MAX_FRAME_RATE
+    /// and raised to [`Self::floor`] and the fusion threshold if below them
+    pub fn apply(&self, frame_rate: u64, fusion_floor: Option<u64>) -> u64 {
+        let floor = self.floor().max(fusion_floor.unwrap_or(0));
+        frame_rate.clamp(floor.min(MAX_FRAME_RATE), MAX_FRAME_RATE)
+    }
+}
+
+impl Default for StrobeGuard {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_floor() {
+        assert_eq!(StrobeGuard::DEFAULT.floor(), 50);
+        let allowed = StrobeGuard {
+            allowed: true,
+            ..StrobeGuard::DEFAULT
+        };
+        assert_eq!(allowed.floor(), MIN_FRAME_RATE);
+        // A guard below the absolute minimum still holds that
+        let low = StrobeGuard {
+            min_fps: 1,
+            allowed: false,
+        };
+        assert_eq!(low.floor(), MIN_FRAME_RATE);
+    }
+
+    #[test]
+    fn guard_apply() {
+        let guard = StrobeGuard::DEFAULT;
+        assert_eq!(guard.apply(0, None), 50);
+        assert_eq!(guard.apply(49, None), 50);
+        assert_eq!(guard.apply(50, None), 50);
+        assert_eq!(guard.apply(120, None), 120);
+        assert_eq!(guard.apply(1000, None), MAX_FRAME_RATE);
+    }
+
+    #[test]
+    fn fusion_floor_holds_above_the_guard() {
+        let guard = StrobeGuard::DEFAULT;
+        assert_eq!(guard.apply(60, Some(72)), 72);
+        assert_eq!(guard.apply(90, Some(72)), 90);
+        // The higher of the two floors wins
+        assert_eq!(guard.apply(20, Some(30)), 50);
+        let allowed = StrobeGuard {
+            allowed: true,
+            ..StrobeGuard::DEFAULT
+        };
+        assert_eq!(allowed.apply(20, Some(30)), 30);
+        assert_eq!(allowed.apply(0, None), MIN_FRAME_RATE);
+        // A threshold above the range still leaves a valid rate
+        assert_eq!(guard.apply(100, Some(500)), MAX_FRAME_RATE);
+    }
+
+    #[test]
+    fn knob_spans_the_guarded_range() {
+        let floor = StrobeGuard::DEFAULT.floor();
+        assert_eq!(level_to_frame_rate(0.0, floor, 16), 50.0);
+        assert_eq!(level_to_frame_rate(15.0, floor, 16), 160.0);
+        let allowed = StrobeGuard {
+            allowed: true,
+            ..StrobeGuard::DEFAULT
+        };
+        assert_eq!(level_to_frame_rate(0.0, allowed.floor(), 16), 10.0);
+        assert_eq!(level_to_frame_rate(15.0, allowed.floor(), 16), 160.0);
+    }
+}
@@ -0,0 +1,125 @@
+//! Binary Telemetry
+//!
+//! Streams fixed-layout binary packets on a dedicated RTT up channel
+//! ("Telemetry", channel 1) for host-side plotting of long runs, where the
+//! text output would be too bulky. Enable it with the `telemetry <hz>`
+//! command.
+//!
+//! # Packet Layout
+//! Every packet is [`PACKET_LEN`] bytes, little-endian, and unpacks in
+//! Python with `struct.unpack("<BB3BxHIQ", packet)`:
+//!
+//! | Offset | Type   | Field                                        |
+//! |--------|--------|----------------------------------------------|
+//! | 0      | u8     | Sync byte, always [`SYNC`] (0xA5)            |
+//! | 1      | u8     | Sequence number, wraps at 255                |
+//! | 2      | u8 x 3 | Requested levels red, green, blue            |
+//! | 5      | u8     | Reserved (0)                                 |
+//! | 6      | u16    | Frame rate setting in fps                    |
+//! | 8      | u32    | Measured frame rate in millihertz            |
+//! | 12     | u64    | Timestamp in microseconds since boot         |
+//!
+//! Packets are written whole or not at all, so a gap in the sequence numbers
+//! means the host fell behind and packets were dropped.
+
+use crate::*;
+
+use rtt_target::UpChannel;
+
+/// Sync byte starting every packet
+pub const SYNC: u8 = 0xa5;
+
+/// Length of a telemetry packet in bytes
+pub const PACKET_LEN: usize = 20;
+
+/// Highest packet rate accepted in Hz
+pub const MAX_TELEMETRY_HZ: u32 = 200;
+
+/// Interval between checks for telemetry being enabled, in milliseconds
+const DISABLED_POLL_MS: u64 = 100;
+
+/// One telemetry sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TelemetryPacket {
+    /// Sequence number
+    pub seq: u8,
+    /// Requested [red, green, blue] levels
+    pub levels: [u32; 3],
+    /// Frame rate setting in fps
+    pub frame_rate: u64,
+    /// Measured frame rate in millihertz
+    pub actual_millihertz: u32,
+    /// Time of the sample in microseconds since boot
+    pub timestamp: u64,
+}
+
+impl TelemetryPacket {
+    /// Pack the sample into its wire layout (see the module docs)
+    ///
+    /// # Returns
+    /// The encoded packet
+    pub fn encode(&self) -> [u8; PACKET_LEN] {
+        let mut packet = [0; PACKET_LEN];
+        packet[0] = SYNC;
+        packet[1] = self.seq;
+        for (byte, level) in packet[2..5].iter_mut().zip(self.levels) {
+            *byte = level.min(u8::MAX as u32) as u8;
+        }
+        let frame_rate = self.frame_rate.min(u16::MAX as u64) as u16;
+        packet[6..8].copy_from_slice(&frame_rate.to_le_bytes());
+        packet[8..12].copy_from_slice(&self.actual_millihertz.to_le_bytes());
+        packet[12..20].copy_from_slice(&self.timestamp.to_le_bytes());
+        packet
+    }
+}
+
+/// Telemetry writer on its own RTT channel
+pub struct Telemetry {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// RTT channel receiving the packets
+    output: UpChannel,
+    /// Sequence number of the next packet
+    seq: u8,
+}
+
+impl Telemetry {
+    /// Create a telemetry writer on the given RTT channel
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    /// * `output` - RTT up channel reserved for binary packets
+    ///
+    /// # Returns
+    /// New writer, idle until telemetry is enabled
+    pub fn new(shared: &'static SharedState, output: UpChannel) -> Self {
+        Self {
+            shared,
+            output,
+            seq: 0,
+        }
+    }
+
+    /// Main telemetry loop
+    ///
+    /// Writes one packet per period while telemetry is enabled.
+    pub async fn run(mut self) -> ! {
+        loop {
+            let Some(rate) = self.shared.get_telemetry_rate().await else {
+                Timer::after_millis(DISABLED_POLL_MS).await;
+                continue;
+            };
+            let state = self.shared.get_state().await;
+            let packet = TelemetryPacket {
+                seq: self.seq,
+                levels: state.levels,
+                frame_rate: state.frame_rate,
+                actual_millihertz: self.shared.get_actual_frame_rate().await,
+                timestamp: Instant::now().as_micros(),
+            };
+            self.output.write(&packet.encode());
+            self.seq = self.seq.wrapping_add(1);
+            Timer::after(Duration::from_hz(rate as u64)).await;
+        }
+    }
+}
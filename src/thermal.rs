@@ -0,0 +1,119 @@
+//! Thermal Derating
+//!
+//! Without current-limiting resistors the LED dies heat up with brightness,
+//! and not equally: the red die, with its lower forward voltage, tends to run
+//! hotter for the same perceived output. Each channel therefore gets its own
+//! brightness ceiling that falls as the temperature rises above a start
+//! point, at a per-channel rate.
+//!
+//! The board has no sensor on the LED, so an LED temperature can be
+//! supplied from outside (the `thermal temp` command, e.g. from a
+//! thermocouple read by the host). Without one the firmware derates by the
+//! nRF's own die temperature, which the knob task reads before every
+//! measurement: it follows the board and the air around the LED, if not
+//! the die itself.
+//!
+//! The model is plain numbers, with the number of levels passed in (the
+//! firmware passes `LEVELS`), so it is part of the library and tested on
+//! the host.
+
+/// Temperature-dependent brightness ceilings per channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThermalDerating {
+    /// Temperature in °C above which the ceilings start to fall
+    pub start_c: i32,
+    /// Ceiling reduction per °C above `start_c`, in tenths of a level, per
+    /// channel [red, green, blue]
+    pub coefficients: [u32; 3],
+}
+
+impl ThermalDerating {
+    /// Derating from 40 °C, red falling twice as fast as green and blue
+    pub const DEFAULT: Self = ThermalDerating {
+        start_c: 40,
+        coefficients: [4, 2, 2],
+    };
+
+    /// Brightness ceilings at a temperature
+    ///
+    /// Each ceiling falls linearly from levels-1 at `start_c` by the
+    /// channel's coefficient per °C, rounded to the nearest level, and
+    /// stops at 0.
+    ///
+    /// # Arguments
+    /// * `temp_c` - LED temperature in °C
+    /// * `levels` - Number of brightness levels
+    ///
+    /// # Returns
+    /// Highest output level allowed per channel [red, green, blue]
+    pub fn ceilings(&self, temp_c: i32, levels: u32) -> [u32; 3] {
+        let excess = temp_c.saturating_sub(self.start_c).max(0) as u32;
+        self.coefficients.map(|coefficient| {
+            let drop = excess.saturating_mul(coefficient).saturating_add(5) / 10;
+            (levels - 1).saturating_sub(drop)
+        })
+    }
+}
+
+impl Default for ThermalDerating {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_up_to_the_start() {
+        let derating = ThermalDerating::DEFAULT;
+        for temp_c in [i32::MIN, -20, 0, 25, 39, 40] {
+            assert_eq!(derating.ceilings(temp_c, 16), [15; 3], "{temp_c} C");
+        }
+        assert_eq!(derating.ceilings(40, 256), [255; 3]);
+    }
+
+    #[test]
+    fn each_channel_falls_at_its_own_rate() {
+        let derating = ThermalDerating {
+            start_c: 30,
+            coefficients: [10, 20, 0],
+        };
+        // One level per °C for red, two for green, none for blue
+        assert_eq!(derating.ceilings(31, 16), [14, 13, 15]);
+        assert_eq!(derating.ceilings(35, 16), [10, 5, 15]);
+        // The default: red 0.4 levels per °C, green and blue 0.2
+        assert_eq!(ThermalDerating::DEFAULT.ceilings(65, 16), [5, 10, 10]);
+    }
+
+    #[test]
+    fn drops_round_to_the_nearest_level() {
+        let derating = ThermalDerating {
+            start_c: 40,
+            coefficients: [4, 5, 6],
+        };
+        // 0.4, 0.5 and 0.6 levels
+        assert_eq!(derating.ceilings(41, 16), [15, 14, 14]);
+        // 0.8, 1.0 and 1.2 levels
+        assert_eq!(derating.ceilings(42, 16), [14, 14, 14]);
+        // 1.2, 1.5 and 1.8 levels
+        assert_eq!(derating.ceilings(43, 16), [14, 13, 13]);
+    }
+
+    #[test]
+    fn ceilings_stop_at_zero() {
+        let derating = ThermalDerating {
+            start_c: 0,
+            coefficients: [10, 1, u32::MAX],
+        };
+        assert_eq!(derating.ceilings(15, 16), [0, 13, 0]);
+        assert_eq!(derating.ceilings(1000, 16), [0, 0, 0]);
+        assert_eq!(derating.ceilings(i32::MAX, 16), [0, 0, 0]);
+        let derating = ThermalDerating {
+            start_c: i32::MIN,
+            coefficients: [1; 3],
+        };
+        assert_eq!(derating.ceilings(i32::MAX, 256), [0; 3]);
+    }
+}
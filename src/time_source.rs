@@ -0,0 +1,312 @@
+//! Time Source Abstraction
+//!
+//! The RGB engine reads the time and waits through the [`Clock`] trait
+//! instead of calling Embassy directly, so its timing can be driven by a
+//! virtual clock (e.g. to check the exact microseconds a frame consumes).
+//! On target it uses [`EmbassyClock`]; the host tests use
+//! [`VirtualClock`].
+//!
+//! The frame schedule itself is kept by a [`Pacer`]: every wait ends a
+//! fixed span after the previous one ended, not after it returned, so
+//! scheduling latency never stretches a frame.
+
+use crate::*;
+
+/// Longest single wait in microseconds (an hour)
+///
+/// Slot times only get this long through extreme frame rates or slow-motion
+/// factors; capping the wait keeps its conversion to timer ticks from
+/// overflowing.
+pub const MAX_WAIT_US: u64 = 3_600_000_000;
+
+/// Source of the current time and of microsecond delays
+#[allow(async_fn_in_trait)]
+pub trait Clock {
+    /// Current time
+    fn now(&self) -> Instant;
+
+    /// Wait for the given number of microseconds
+    ///
+    /// # Arguments
+    /// * `micros` - Delay in microseconds
+    async fn delay_us(&mut self, micros: u64);
+
+    /// Wait until the given time (returns at once if it has passed)
+    ///
+    /// # Arguments
+    /// * `deadline` - Time to wait for
+    async fn delay_until(&mut self, deadline: Instant);
+}
+
+/// Real time from the Embassy time driver
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn delay_us(&mut self, micros: u64) {
+        Timer::after_micros(micros).await;
+    }
+
+    async fn delay_until(&mut self, deadline: Instant) {
+        Timer::at(deadline).await;
+    }
+}
+
+/// Clock that only moves when it is waited on
+///
+/// Every delay returns at once, advancing the virtual time by exactly the
+/// delay, so the time a schedule consumes can be checked to the
+/// microsecond (at the host's 1 MHz tick rate). Time spent outside the
+/// waits, e.g. a late wake-up, is simulated with [`VirtualClock::advance`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VirtualClock {
+    /// Virtual time since the clock was created, in microseconds
+    micros: u64,
+}
+
+impl VirtualClock {
+    /// Create a clock at time zero
+    ///
+    /// # Returns
+    /// The clock
+    pub const fn new() -> Self {
+        VirtualClock { micros: 0 }
+    }
+
+    /// Virtual time since the clock was created
+    ///
+    /// # Returns
+    /// Elapsed microseconds
+    pub fn elapsed_us(&self) -> u64 {
+        self.micros
+    }
+
+    /// Let time pass without waiting
+    ///
+    /// # Arguments
+    /// * `micros` - Microseconds to move on
+    pub fn advance(&mut self, micros: u64) {
+        self.micros = self.micros.saturating_add(micros);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        Instant::from_micros(self.micros)
+    }
+
+    async fn delay_us(&mut self, micros: u64) {
+        self.advance(micros);
+    }
+
+    async fn delay_until(&mut self, deadline: Instant) {
+        self.micros = self.micros.max(deadline.as_micros());
+    }
+}
+
+/// Chain of waits, each ending a given span after the previous one ended
+///
+/// Owns the clock of the schedule it keeps.
+#[derive(Debug)]
+pub struct Pacer<C: Clock> {
+    /// Time source for the waits
+    clock: C,
+    /// End of the last scheduled wait, or `None` to start afresh
+    deadline: Option<Instant>,
+}
+
+impl<C: Clock> Pacer<C> {
+    /// Create a pacer that starts its schedule at the first wait
+    ///
+    /// # Arguments
+    /// * `clock` - Time source for the waits
+    ///
+    /// # Returns
+    /// The pacer
+    pub fn new(clock: C) -> Self {
+        Pacer {
+            clock,
+            deadline: None,
+        }
+    }
+
+    /// Current time on the pacer's clock
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// The pacer's clock, for waits outside the schedule
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Start the schedule afresh at the next wait, e.g. after idling
+    pub fn restart(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Start the schedule afresh now if it has fallen a frame behind
+    ///
+    /// # Arguments
+    /// * `frame` - Length of a frame
+    pub fn catch_up(&mut self, frame: Duration) {
+        let now = self.clock.now();
+        let behind = |deadline: Instant| deadline.checked_add(frame).is_some_and(|end| end < now);
+        if self.deadline.is_none_or(behind) {
+            self.deadline = Some(now);
+        }
+    }
+
+    /// Schedule the next wait without waiting
+    ///
+    /// # Arguments
+    /// * `micros` - Length of the span in microseconds (capped to
+    ///   [`MAX_WAIT_US`])
+    ///
+    /// # Returns
+    /// When the span ends: the end of the previous span plus `micros`, or
+    /// now plus `micros` at the start of a schedule
+    pub fn schedule(&mut self, micros: u64) -> Instant {
+        let start = self.deadline.unwrap_or_else(|| self.clock.now());
+        let deadline = start
+            .checked_add(Duration::from_micros(micros.min(MAX_WAIT_US)))
+            .unwrap_or(Instant::MAX);
+        self.deadline = Some(deadline);
+        deadline
+    }
+
+    /// Wait for a span measured from the end of the previous wait
+    ///
+    /// # Arguments
+    /// * `micros` - Length of the span in microseconds
+    pub async fn wait(&mut self, micros: u64) {
+        let deadline = self.schedule(micros);
+        self.clock.delay_until(deadline).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    /// Levels of the stock build
+    const LEVELS: u32 = 16;
+
+    /// Wait out one scan frame as the RGB task times it: each channel's
+    /// slot split into on and off time, each shared over the sub-frames
+    async fn scan_frame(
+        pacer: &mut Pacer<VirtualClock>,
+        levels: [u32; 3],
+        frame_rate: u64,
+        sub_frames: u32,
+        rounding: Rounding,
+    ) {
+        let tick_time = frame_tick_time(frame_rate, LEVELS);
+        let slot_time = frame_slot_time(frame_rate);
+        for sub_frame in 0..sub_frames {
+            for level in levels {
+                let (on, off) = rounding.split(level, tick_time, slot_time, LEVELS);
+                for time in [on, off] {
+                    if time > 0 {
+                        pacer.wait(share(time, sub_frame, sub_frames)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn virtual_clock_moves_only_when_waited() {
+        block_on(async {
+            let mut clock = VirtualClock::new();
+            assert_eq!(clock.now(), Instant::from_micros(0));
+            clock.delay_us(250).await;
+            assert_eq!(clock.elapsed_us(), 250);
+            clock.delay_until(Instant::from_micros(100)).await;
+            assert_eq!(clock.elapsed_us(), 250);
+            clock.delay_until(Instant::from_micros(1_000)).await;
+            assert_eq!(clock.elapsed_us(), 1_000);
+            clock.advance(5);
+            assert_eq!(clock.now(), Instant::from_micros(1_005));
+        });
+    }
+
+    #[test]
+    fn frame_takes_exactly_three_slots() {
+        block_on(async {
+            for frame_rate in [50, 100, 123, 160] {
+                for levels in [[0, 0, 0], [15, 15, 15], [15, 11, 7], [1, 0, 14]] {
+                    for sub_frames in 1..=4 {
+                        let mut pacer = Pacer::new(VirtualClock::new());
+                        let rounding = Rounding::DistributeToOff;
+                        scan_frame(&mut pacer, levels, frame_rate, sub_frames, rounding).await;
+                        assert_eq!(
+                            pacer.clock().elapsed_us(),
+                            3 * frame_slot_time(frame_rate),
+                            "{frame_rate} fps, {levels:?}, {sub_frames} sub-frames"
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn truncated_frame_takes_whole_ticks() {
+        block_on(async {
+            let mut pacer = Pacer::new(VirtualClock::new());
+            scan_frame(&mut pacer, [15, 11, 7], 100, 1, Rounding::Truncate).await;
+            // 3 slots of 16 ticks of 208 µs
+            assert_eq!(pacer.clock().elapsed_us(), 48 * 208);
+        });
+    }
+
+    #[test]
+    fn late_wake_up_does_not_stretch_the_frame() {
+        block_on(async {
+            let mut pacer = Pacer::new(VirtualClock::new());
+            pacer.wait(1_000).await;
+            // The task resumed 40 µs late; the next wait makes up for it
+            pacer.clock().advance(40);
+            pacer.wait(1_000).await;
+            assert_eq!(pacer.clock().elapsed_us(), 2_000);
+            // So do frames back to back
+            scan_frame(&mut pacer, [15, 11, 7], 100, 1, Rounding::DistributeToOff).await;
+            assert_eq!(pacer.clock().elapsed_us(), 2_000 + 9_999);
+        });
+    }
+
+    #[test]
+    fn catch_up_restarts_a_schedule_a_frame_behind() {
+        block_on(async {
+            let frame = Duration::from_micros(10_000);
+            let mut pacer = Pacer::new(VirtualClock::new());
+            pacer.catch_up(frame);
+            pacer.wait(1_000).await;
+            // Slightly late: the schedule continues from the deadline
+            pacer.clock().advance(500);
+            pacer.catch_up(frame);
+            pacer.wait(1_000).await;
+            assert_eq!(pacer.clock().elapsed_us(), 2_000);
+            // More than a frame late: it starts afresh from now
+            pacer.clock().advance(20_000);
+            pacer.catch_up(frame);
+            pacer.wait(1_000).await;
+            assert_eq!(pacer.clock().elapsed_us(), 23_000);
+        });
+    }
+
+    #[test]
+    fn waits_are_capped() {
+        block_on(async {
+            let mut pacer = Pacer::new(VirtualClock::new());
+            pacer.wait(u64::MAX).await;
+            assert_eq!(pacer.clock().elapsed_us(), MAX_WAIT_US);
+        });
+    }
+}
@@ -5,6 +5,253 @@
 
 use crate::*;
 
+/// Distance in fps the knob must move past the committed frame rate before a
+/// new value is committed. Values above 0.5 keep ADC noise from toggling
+/// between adjacent frame rates while every integer rate stays reachable.
+const FRAME_RATE_HYSTERESIS: f32 = 0.75;
+
+/// Distance in levels the knob must turn after a button change before it
+/// takes control of the selected parameter. This lets buttons be pressed for
+/// gestures without snapping the parameter to the knob's position. (With
+/// pickup on, the knob must also reach the parameter's value.)
+const ENGAGE_THRESHOLD: f32 = 0.5;
+
+/// Levels a level moves per level of knob travel in fine mode: the whole
+/// travel covers a quarter of the levels
+pub const KNOB_FINE_SCALE: f32 = 0.25;
+
+/// Knob travel in levels below which fine mode leaves the level alone, so
+/// ADC noise on a resting knob can't creep it; slower turns add up until
+/// they get past it
+const KNOB_FINE_NOISE: f32 = 0.1;
+
+/// Frame rate used when neither the startup settings nor the knob give one
+const DEFAULT_FRAME_RATE: u64 = 100;
+
+/// Levels used when the startup settings give none: full white, which is
+/// easy to calibrate from but harsh, and without resistors draws the most
+/// current at power-up
+const DEFAULT_LEVELS: [u32; 3] = [LEVELS - 1; 3];
+
+/// Saved levels and frame rate the UI can start with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Startup {
+    /// Initial [red, green, blue] levels, or `None` if none are saved
+    pub levels: Option<[u32; 3]>,
+    /// Initial frame rate in fps, or `None` if none is saved
+    pub frame_rate: Option<u64>,
+}
+
+/// Startup settings used unless [`Ui::with_startup`] replaces them
+///
+/// Nothing is saved by default; set levels here to start dim, or a frame
+/// rate to ignore the knob position at boot (with the default
+/// [`BOOT_PRIORITY`]).
+pub const STARTUP: Startup = Startup {
+    levels: None,
+    frame_rate: None,
+};
+
+/// Order the starting values are looked for in, unless
+/// [`Ui::with_boot_priority`] replaces it: the saved settings if they set
+/// the value, else the knob position (frame rate only), else the defaults
+pub const BOOT_PRIORITY: [BootSource; 3] =
+    [BootSource::Saved, BootSource::Knob, BootSource::Default];
+
+/// Default longest time a level change from the knob is held back while
+/// the knob keeps moving, in milliseconds (the `knob coalesce` command)
+///
+/// While the knob keeps moving, level changes are committed to the shared
+/// state at most once per window, with the latest value; as soon as the
+/// knob stops, the settled value is committed. This cuts state churn and
+/// display output during fast turns.
+pub const DEFAULT_COALESCE_MS: u64 = 100;
+
+/// Longest coalescing window accepted, in milliseconds
+pub const MAX_COALESCE_MS: u64 = 1000;
+
+/// Default interval between polls of the knob and buttons, in
+/// milliseconds (20 Hz; the `poll` command)
+///
+/// This trades responsiveness against CPU time and power: a longer
+/// interval suits battery use, a shorter one gives snappier response. Taps,
+/// double taps, long presses, the button settle time and the coalescing
+/// window are all timed from the clock, so they keep their durations at any
+/// interval, but each is only seen at the next poll, up to one interval
+/// late.
+pub const DEFAULT_POLL_MS: u64 = 50;
+
+/// Shortest poll interval accepted, in milliseconds
+pub const MIN_POLL_MS: u64 = 5;
+
+/// Longest poll interval accepted, in milliseconds
+///
+/// Gesture and settle timing is measured in time, not polls, so it holds at
+/// any interval, but only to within one poll, and a press or gap shorter
+/// than the interval can go unseen. At 100 ms a 300 ms tap or double-tap
+/// gap still spans at least two polls.
+pub const MAX_POLL_MS: u64 = 100;
+
+/// How long the LED is blanked when the knob reaches its center, in
+/// milliseconds (a few frames)
+const CENTER_CUE_MS: u64 = 40;
+
+/// How long each level is shown while looking for a channel's minimum
+/// visible level, in milliseconds
+const VISIBLE_STEP_MS: u64 = 700;
+
+/// How often the buttons are polled during the minimum visible level
+/// routine, in milliseconds
+const VISIBLE_POLL_MS: u64 = 10;
+
+/// Width in characters of the bar chart bars
+const BAR_WIDTH: usize = (LEVELS - 1) as usize;
+
+/// Format used when displaying the state over RTT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One `name: value` line per setting
+    Text,
+    /// Fixed-width ASCII bar chart of each setting
+    Bars,
+    /// One JSON object per update, for host scripts (see
+    /// [`UiState::show_machine`])
+    Json,
+}
+
+/// Parameter the knob is currently controlling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlMode {
+    /// Frame rate (no buttons held)
+    FrameRate,
+    /// Red level (A+B held)
+    Red,
+    /// Green level (B held)
+    Green,
+    /// Blue level (A held)
+    Blue,
+    /// Hue of a saturated color (no buttons held, with `knob hue on`)
+    Hue,
+    /// Nothing (e.g. the wizard's white capture step)
+    Inactive,
+}
+
+impl ControlMode {
+    /// Mode that adjusts the given channel
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    pub fn from_channel(channel: usize) -> Self {
+        match channel {
+            0 => ControlMode::Red,
+            1 => ControlMode::Green,
+            _ => ControlMode::Blue,
+        }
+    }
+
+    /// Channel this mode adjusts
+    ///
+    /// # Returns
+    /// Channel index (0=red, 1=green, 2=blue), or `None` for other modes
+    pub fn channel(self) -> Option<usize> {
+        match self {
+            ControlMode::Red => Some(0),
+            ControlMode::Green => Some(1),
+            ControlMode::Blue => Some(2),
+            ControlMode::FrameRate | ControlMode::Hue | ControlMode::Inactive => None,
+        }
+    }
+
+    /// Lowercase name used in mode events
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlMode::FrameRate => "frame rate",
+            ControlMode::Red => "red",
+            ControlMode::Green => "green",
+            ControlMode::Blue => "blue",
+            ControlMode::Hue => "hue",
+            ControlMode::Inactive => "none",
+        }
+    }
+}
+
+/// Render a fixed-width ASCII bar
+///
+/// # Arguments
+/// * `buf` - Buffer receiving the bar characters
+/// * `filled` - Number of filled cells (clamped to BAR_WIDTH)
+///
+/// # Returns
+/// The bar as a string, e.g. `#####     `
+fn bar(buf: &mut [u8; BAR_WIDTH], filled: usize) -> &str {
+    for (i, cell) in buf.iter_mut().enumerate() {
+        *cell = if i < filled { b'#' } else { b' ' };
+    }
+    // Only ASCII was written, so this cannot fail
+    core::str::from_utf8(buf).unwrap_or("")
+}
+
+/// Print the white point line of `show`, unless the output is dark
+///
+/// # Arguments
+/// * `output` - Output [red, green, blue] levels actually driven
+fn show_white_point(output: [u32; 3]) {
+    let Some(xy) = chromaticity(output, LEVELS) else {
+        return;
+    };
+    let kelvin = color_temperature(xy);
+    let [x, y] = xy.map(|c| (c * 1000.0 + 0.5) as u32);
+    rprintln!(
+        "white point: x 0.{:03} y 0.{:03}, {} K {}",
+        x,
+        y,
+        kelvin,
+        white_balance(kelvin)
+    );
+}
+
+/// Print the power line of `show`, if a power budget is set
+///
+/// # Arguments
+/// * `budget` - Power budget in effect
+/// * `estimate` - Estimated average current before the budget, in µA
+/// * `capped` - Estimated average current actually driven, in µA
+fn show_power(budget: &PowerBudget, estimate: u32, capped: u32) {
+    let Some(budget_ua) = budget.budget_ua else {
+        return;
+    };
+    let (budget_ma, budget_tenths) = milliamps(budget_ua);
+    let (ma, tenths) = milliamps(estimate);
+    if !budget.over(estimate) {
+        rprintln!(
+            "power: {}.{} mA of {}.{} mA budget",
+            ma,
+            tenths,
+            budget_ma,
+            budget_tenths
+        );
+    } else if capped < estimate {
+        let (capped_ma, capped_tenths) = milliamps(capped);
+        rprintln!(
+            "power: {}.{} mA over {}.{} mA budget, capped to {}.{} mA",
+            ma,
+            tenths,
+            budget_ma,
+            budget_tenths,
+            capped_ma,
+            capped_tenths
+        );
+    } else {
+        rprintln!(
+            "power: {}.{} mA over {}.{} mA budget (not capped)",
+            ma,
+            tenths,
+            budget_ma,
+            budget_tenths
+        );
+    }
+}
+
 /// Internal state for the user interface
 ///
 /// Tracks current brightness levels and frame rate settings that are
@@ -14,40 +261,262 @@ struct UiState {
     levels: [u32; 3],
     /// Current frame rate in frames per second
     frame_rate: u64,
+    /// Reference levels recalled by the white shortcut
+    white: [u32; 3],
+    /// Output enable mask [red, green, blue]; disabled channels keep their
+    /// level but are output as off
+    enabled: [bool; 3],
+    /// Format used by `show`
+    format: OutputFormat,
+    /// Unit of the frame rate shown by `show`
+    unit: FrameRateUnit,
+    /// Sub-frames per scan, for the per-color frame rate
+    sub_frames: u32,
+    /// Parameter the knob currently controls
+    mode: ControlMode,
+    /// Channels the current sense reports as failed open [red, green, blue]
+    dead: [bool; 3],
+    /// Number being entered with button taps, if any
+    entry: Option<NumberEntry>,
+    /// Channels held below their requested output by the thermal
+    /// ceilings [red, green, blue]
+    throttled: [bool; 3],
+    /// Whether the estimated LED current is over the power budget
+    over_budget: bool,
+    /// Slot and contents of the preset loaded last, if any
+    preset: Option<(usize, Preset)>,
+    /// Frame rates under comparison
+    compare: RateCompare,
 }
 
 impl UiState {
     /// Display current RGB levels and frame rate via RTT debug output
     ///
     /// Prints the current state to help users see the effect of their adjustments.
+    /// Disabled channels are marked `(off)`, channels the current sense
+    /// reports as failed open `(dead?)` and channels held back by their
+    /// thermal ceiling `(hot)`.
     /// Output format:
     /// ```
     /// red: 15
-    /// green: 12  
+    /// green: 12 (off)
     /// blue: 8
-    /// frame rate: 100
+    /// frame rate: 100 fps
+    /// white: no
+    /// preset: 2 warm_white (changed)
+    /// compare: B (A 90, B 100 fps)
+    /// power: 7.5 mA over 6.0 mA budget, capped to 5.9 mA
+    /// effective: 239 0 128
+    /// white point: x 0.313 y 0.329, 6506 K neutral
+    /// flicker: 100% at 100 Hz
     /// ```
-    fn show(&self) {
+    ///
+    /// The power line gives the estimated average LED current, only while a
+    /// power budget is set (see the `power` module). The preset line names
+    /// the preset loaded last, marked `(changed)` once the settings no
+    /// longer match it, and is left out before any preset is loaded. The
+    /// compare line shows which of the two compared frame rates is applied,
+    /// only while comparing. The effective line is the time-averaged color
+    /// (0-255 per channel) after the enable mask and corrections, i.e. what
+    /// the eye sees; the white point line estimates its CIE xy chromaticity
+    /// and color temperature (see [`chromaticity`]), left out while dark;
+    /// and the flicker line how strongly its brightness pulses at the frame
+    /// rate (see [`flicker_percent`]). During numeric entry, the number so
+    /// far follows on an `entry:` line.
+    ///
+    /// In bar chart mode the same information is drawn as bars instead:
+    /// ```
+    /// R [###############] 15
+    /// G [############   ] 12 (off)
+    /// B [########       ] 8
+    /// F [#########      ] 100 fps
+    /// preset: 2 warm_white (changed)
+    /// compare: B (A 90, B 100 fps)
+    /// power: 7.5 mA over 6.0 mA budget, capped to 5.9 mA
+    /// effective: 239 0 128
+    /// white point: x 0.313 y 0.329, 6506 K neutral
+    /// flicker: 100% at 100 Hz
+    /// ```
+    ///
+    /// In JSON mode only the line of [`UiState::show_machine`] is printed.
+    ///
+    /// # Arguments
+    /// * `shared` - State shared with the other tasks
+    async fn show(&self, shared: &SharedState) {
+        if self.format == OutputFormat::Json {
+            self.show_machine();
+            return;
+        }
+        let mut output = output_levels(
+            self.levels,
+            shared.get_color_space().await,
+            self.enabled,
+            &shared.get_color_matrix().await,
+            &shared.get_channel_luts().await,
+            shared.get_min_visible().await,
+        );
+        for (level, ceiling) in output.iter_mut().zip(shared.get_thermal_ceilings().await) {
+            *level = (*level).min(ceiling);
+        }
+        let budget = shared.get_power_budget().await;
+        let multipliers = shared.get_refresh_multipliers().await;
+        let estimate = budget.estimate_ua(output, multipliers);
+        let output = budget.limit(output, multipliers);
+        let capped = budget.estimate_ua(output, multipliers);
+        let [red, green, blue] = effective_color(output);
+        let flicker = flicker_percent(output);
+        let drive = shared.get_drive_mode().await;
+        if self.format == OutputFormat::Bars {
+            self.show_bars();
+            self.show_preset();
+            self.show_compare();
+            show_power(&budget, estimate, capped);
+            rprintln!("effective: {} {} {}", red, green, blue);
+            show_white_point(output);
+            rprintln!("flicker: {}% at {} Hz", flicker, self.frame_rate);
+            rprintln!("drive: {}", drive.name());
+            if let Some(entry) = &self.entry {
+                entry.show();
+            }
+            return;
+        }
         let names = ["red", "green", "blue"];
         rprintln!(); // Blank line for readability
                      // Print each color level
-        for (name, level) in names.iter().zip(self.levels.iter()) {
-            rprintln!("{}: {}", name, level);
+        for (i, name) in names.iter().enumerate() {
+            let (mask, dead, hot) = self.markers(i);
+            rprintln!("{}: {}{}{}{}", name, self.levels[i], mask, dead, hot);
+        }
+        rprintln!(
+            "frame rate: {} {}",
+            self.unit.from_scans(self.frame_rate, self.sub_frames),
+            self.unit.label()
+        );
+        let is_white = self.levels == self.white;
+        rprintln!("white: {}", if is_white { "yes" } else { "no" });
+        self.show_preset();
+        self.show_compare();
+        show_power(&budget, estimate, capped);
+        rprintln!("effective: {} {} {}", red, green, blue);
+        show_white_point(output);
+        rprintln!("flicker: {}% at {} Hz", flicker, self.frame_rate);
+        rprintln!("drive: {}", drive.name());
+        if let Some(entry) = &self.entry {
+            entry.show();
+        }
+    }
+
+    /// Display current RGB levels and frame rate as one JSON line
+    ///
+    /// Meant for logging sweeps: a host script keeps the lines starting
+    /// with `{` and drops the other log output. The frame rate is always
+    /// in fps, whatever unit is selected.
+    /// Output format:
+    /// ```
+    /// {"r":15,"g":12,"b":8,"fps":100}
+    /// ```
+    fn show_machine(&self) {
+        let [red, green, blue] = self.levels;
+        rprintln!(
+            "{{\"r\":{},\"g\":{},\"b\":{},\"fps\":{}}}",
+            red,
+            green,
+            blue,
+            self.frame_rate
+        );
+    }
+
+    /// Print the preset line of `show`, if a preset was loaded
+    fn show_preset(&self) {
+        if let Some((slot, preset)) = &self.preset {
+            let tag = preset.tag.as_ref().map_or("-", Tag::as_str);
+            let changed = preset.levels != self.levels || preset.frame_rate != self.frame_rate;
+            let marker = if changed { " (changed)" } else { "" };
+            rprintln!("preset: {} {}{}", slot + 1, tag, marker);
+        }
+    }
+
+    /// Print the compare line of `show`, while comparing frame rates
+    fn show_compare(&self) {
+        let Some(active) = self.compare.active else {
+            return;
+        };
+        let rate = |setpoint: usize| {
+            let frame_rate = self.compare.setpoints[setpoint].unwrap_or(self.frame_rate);
+            self.unit.from_scans(frame_rate, self.sub_frames)
+        };
+        rprintln!(
+            "compare: {} (A {}, B {} {})",
+            RateCompare::name(active),
+            rate(0),
+            rate(1),
+            self.unit.label()
+        );
+    }
+
+    /// Status markers shown after a channel's level
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    ///
+    /// # Returns
+    /// The disabled marker, the failed-open marker and the thermal
+    /// marker (each possibly empty)
+    fn markers(&self, channel: usize) -> (&'static str, &'static str, &'static str) {
+        let mask = if self.enabled[channel] { "" } else { " (off)" };
+        let dead = if self.dead[channel] { " (dead?)" } else { "" };
+        let hot = if self.throttled[channel] {
+            " (hot)"
+        } else {
+            ""
+        };
+        (mask, dead, hot)
+    }
+
+    /// Display current RGB levels and frame rate as an ASCII bar chart
+    fn show_bars(&self) {
+        let names = ["R", "G", "B"];
+        let mut buf = [0; BAR_WIDTH];
+        rprintln!(); // Blank line for readability
+        for (i, name) in names.iter().enumerate() {
+            let (mask, dead, hot) = self.markers(i);
+            let level = self.levels[i];
+            let bar = bar(&mut buf, level as usize);
+            rprintln!("{} [{}] {}{}{}{}", name, bar, level, mask, dead, hot);
         }
-        rprintln!("frame rate: {}", self.frame_rate);
+        let filled = (self.frame_rate * BAR_WIDTH as u64 / MAX_FRAME_RATE) as usize;
+        rprintln!(
+            "F [{}] {} {}",
+            bar(&mut buf, filled),
+            self.unit.from_scans(self.frame_rate, self.sub_frames),
+            self.unit.label()
+        );
     }
 }
 
 impl Default for UiState {
     /// Create initial UI state with sensible defaults
     ///
-    /// Starts with all colors at maximum brightness (LEVELS-1 = 15)
-    /// and a moderate frame rate of 100 fps.
+    /// Starts with the levels and frame rate of [`STARTUP`], falling back
+    /// to all colors at maximum brightness (LEVELS-1 = 15) and a moderate
+    /// frame rate of 100 fps. [`Ui::run`] settles the values by boot
+    /// priority before they are shown.
     fn default() -> Self {
         Self {
-            // Start with all colors at max brightness for easy calibration
-            levels: [LEVELS - 1, LEVELS - 1, LEVELS - 1],
-            frame_rate: 100, // 100 fps default frame rate
+            levels: STARTUP.levels.unwrap_or(DEFAULT_LEVELS),
+            frame_rate: STARTUP.frame_rate.unwrap_or(DEFAULT_FRAME_RATE),
+            white: [LEVELS - 1, LEVELS - 1, LEVELS - 1],
+            enabled: [true; 3],
+            format: OutputFormat::Text,
+            unit: FrameRateUnit::Scans,
+            sub_frames: DEFAULT_SUB_FRAMES,
+            mode: ControlMode::FrameRate,
+            dead: [false; 3],
+            entry: None,
+            throttled: [false; 3],
+            over_budget: false,
+            preset: None,
+            compare: RateCompare::OFF,
         }
     }
 }
@@ -56,133 +525,1282 @@ impl Default for UiState {
 ///
 /// Manages knob input and button states to control which parameter
 /// the knob adjusts. Button combinations determine the control mode:
-/// - No buttons: Frame rate control (10-160 fps in steps of 10)
+/// - No buttons: Frame rate control (continuous, 50-160 fps)
 /// - A button: Blue brightness control (0-15)
 /// - B button: Green brightness control (0-15)  
 /// - A+B buttons: Red brightness control (0-15)
-pub struct Ui {
-    /// Potentiometer interface for analog input
-    knob: Knob,
+///
+/// After a button change the knob only takes control once it is turned, so
+/// buttons can also be used for gestures. Long and very long presses count
+/// once released, and only if the knob was not turned meanwhile:
+/// - Tap A / B / A+B: Toggle blue / green / red output on or off
+/// - Double tap A: Set all channels to the white reference
+/// - Double tap B: Capture the current levels as the white reference
+/// - Long press A+B: Start or stop breathing (see `anim`), or cancel the
+///   guided calibration wizard (started with the `wizard` command)
+/// - Long press A: Select and load the next preset slot (or the next
+///   tagged or white preset, see `preset cycle`)
+/// - Very long press A: Save the levels and frame rate to the selected
+///   preset slot
+/// - While comparing frame rates, tap any: Switch to the other rate
+/// - Long press B: Enter a number with taps (see [`NumberEntry`])
+/// - Very long press B: Lock or unlock the settings against the knob and
+///   buttons
+/// - Double tap A+B: Emergency off (zero all levels and hold the LEDs
+///   dark), or release it
+///
+/// While the wizard runs, the current step decides what the knob controls
+/// and tapping A confirms the step.
+///
+/// The knob may be any [`LevelInput`]: with a relative input such as an
+/// encoder, selecting a parameter moves the input to its current value.
+pub struct Ui<I: LevelInput> {
+    /// State shared with the other tasks
+    shared: &'static SharedState,
+    /// Position input (potentiometer or encoder)
+    knob: I,
     /// Button A input for mode selection
     button_a: Button,
     /// Button B input for mode selection
     button_b: Button,
     /// Current UI state (brightness levels and frame rate)
     state: UiState,
+    /// Decoder turning button presses into gestures
+    gestures: GestureDecoder,
+    /// Button states [A, B] that selected the current control mode
+    buttons: [bool; 2],
+    /// Filter accepting a new button combination once it has settled
+    settle: ButtonSettle,
+    /// Knob position when the current control mode was selected
+    anchor: f32,
+    /// Whether the knob has taken control of the current mode's parameter
+    engaged: bool,
+    /// Parameters the knob has to pass through before taking them over
+    pickup: Pickup,
+    /// Knob level at the last poll, for debug output on change
+    last_level: u32,
+    /// Detector for the knob reaching the center of its travel
+    center: CenterDetector,
+    /// Current calibration wizard step, or `None` when not running
+    wizard: Option<WizardStep>,
+    /// Channel each button combination selects, from shared state
+    button_map: ButtonMap,
+    /// Whether the knob sets the hue instead of the frame rate with no
+    /// buttons held, from shared state
+    hue_mode: bool,
+    /// Whether the knob nudges levels relative to their current value,
+    /// from shared state
+    fine_mode: bool,
+    /// Frame rate at the bottom of the knob's travel: the strobe guard's
+    /// floor, from shared state
+    rate_floor: u64,
+    /// Frame rate the knob last asked for while the shared state held the
+    /// rate at a floor above it
+    held_rate: Option<f32>,
+    /// Part of a level each [red, green, blue] channel has been nudged past
+    /// its level in fine mode (0.0 to below 1.0)
+    fine_carry: [f32; 3],
+    /// Knob position the last fine nudge was measured from
+    fine_last: f32,
+    /// Whether the settings are locked: the knob and button gestures
+    /// change nothing until unlocked
+    locked: bool,
+    /// Preset slot last selected with the buttons, which the save gesture
+    /// saves to
+    preset_slot: Option<usize>,
+    /// Longest time knob level changes are batched before committing,
+    /// refreshed from the shared state every poll
+    coalesce: Duration,
+    /// Interval between polls of the knob and buttons, refreshed from the
+    /// shared state every poll
+    poll: Duration,
+    /// When the oldest uncommitted level change was made, or `None` if the
+    /// shared levels are up to date
+    pending_since: Option<Instant>,
+    /// Saved levels and frame rate to start with
+    startup: Startup,
+    /// Order the starting values are looked for in
+    boot_priority: [BootSource; 3],
+    /// Source that last set each knob-controlled parameter
+    sources: ParameterSources,
 }
 
-impl Ui {
+impl<I: LevelInput> Ui<I> {
     /// Create a new UI controller with specified hardware interfaces
     ///
     /// # Arguments
-    /// * `knob` - Calibrated potentiometer interface
+    /// * `shared` - State shared with the other tasks
+    /// * `knob` - Position input (calibrated potentiometer or encoder)
     /// * `button_a` - MicroBit button A for mode selection
     /// * `button_b` - MicroBit button B for mode selection
     ///
     /// # Returns
     /// New UI controller with default initial state
-    pub fn new(knob: Knob, button_a: Button, button_b: Button) -> Self {
+    pub fn new(shared: &'static SharedState, knob: I, button_a: Button, button_b: Button) -> Self {
         Self {
+            shared,
             knob,
             button_a,
             button_b,
             state: UiState::default(),
+            gestures: GestureDecoder::default(),
+            buttons: [false; 2],
+            settle: ButtonSettle::default(),
+            anchor: 0.0,
+            engaged: false,
+            pickup: Pickup::default(),
+            last_level: 0,
+            center: CenterDetector::default(),
+            wizard: None,
+            button_map: ButtonMap::DEFAULT,
+            hue_mode: false,
+            rate_floor: StrobeGuard::DEFAULT.floor(),
+            held_rate: None,
+            fine_mode: false,
+            fine_carry: [0.0; 3],
+            fine_last: 0.0,
+            locked: false,
+            preset_slot: None,
+            coalesce: Duration::from_millis(DEFAULT_COALESCE_MS),
+            poll: Duration::from_millis(DEFAULT_POLL_MS),
+            pending_since: None,
+            startup: STARTUP,
+            boot_priority: BOOT_PRIORITY,
+            sources: ParameterSources::KNOB,
         }
     }
 
-    /// Convert knob level (0-15) to frame rate (10-160 fps in steps of 10)
+    /// Start with different levels and frame rate
     ///
-    /// Maps the 16 knob positions to frame rates from 10 to 160 fps.
-    /// Each step increases the frame rate by 10 fps.
+    /// E.g. a saved calibration, instead of [`STARTUP`]; it is the
+    /// [`BootSource::Saved`] source of the boot priority. Levels are clamped
+    /// to LEVELS-1 and the frame rate to MIN_FRAME_RATE..=MAX_FRAME_RATE.
     ///
     /// # Arguments
-    /// * `level` - Knob position (0 to LEVELS-1)
+    /// * `startup` - Saved levels and frame rate, each `None` if not saved
     ///
     /// # Returns
-    /// Frame rate in fps (10, 20, 30, ..., 160)
-    fn level_to_frame_rate(level: u32) -> u64 {
-        (level as u64 + 1) * 10
+    /// The UI with the new setting
+    pub fn with_startup(mut self, startup: Startup) -> Self {
+        self.startup = Startup {
+            levels: startup
+                .levels
+                .map(|levels| levels.map(|level| level.min(LEVELS - 1))),
+            frame_rate: startup
+                .frame_rate
+                .map(|rate| rate.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE)),
+        };
+        self.state.levels = self.startup.levels.unwrap_or(DEFAULT_LEVELS);
+        self.state.frame_rate = self.startup.frame_rate.unwrap_or(DEFAULT_FRAME_RATE);
+        self
+    }
+
+    /// Look for the starting values in a different order
+    ///
+    /// E.g. `[BootSource::Knob, BootSource::Saved, BootSource::Default]` to
+    /// let the knob position win over a saved frame rate. The built-in
+    /// defaults apply whenever no listed source has a value.
+    ///
+    /// # Arguments
+    /// * `priority` - Sources in the order they are tried
+    ///
+    /// # Returns
+    /// The UI with the new setting
+    pub fn with_boot_priority(mut self, priority: [BootSource; 3]) -> Self {
+        self.boot_priority = priority;
+        self
+    }
+
+    /// Parameter the knob currently controls
+    ///
+    /// The wizard step takes precedence over the held buttons.
+    fn selected_mode(&self) -> ControlMode {
+        match self.wizard {
+            Some(WizardStep::FrameRate) => ControlMode::FrameRate,
+            Some(step) => step
+                .channel()
+                .map_or(ControlMode::Inactive, ControlMode::from_channel),
+            None => match self.buttons {
+                [false, false] if self.hue_mode => ControlMode::Hue,
+                [false, false] => ControlMode::FrameRate,
+                [true, false] => ControlMode::from_channel(self.button_map.channel(Buttons::A)),
+                [false, true] => ControlMode::from_channel(self.button_map.channel(Buttons::B)),
+                [true, true] => ControlMode::from_channel(self.button_map.channel(Buttons::Both)),
+            },
+        }
+    }
+
+    /// Current value of the parameter the knob controls, in knob units
+    fn selected_position(&self) -> f32 {
+        match self.state.mode {
+            ControlMode::FrameRate => {
+                frame_rate_to_level(self.state.frame_rate, self.rate_floor, LEVELS)
+            }
+            ControlMode::Hue | ControlMode::Inactive => self.knob.last_good(),
+            mode => mode.channel().map_or(self.knob.last_good(), |channel| {
+                self.state.levels[channel] as f32
+            }),
+        }
+    }
+
+    /// Hand the knob a newly selected parameter
+    ///
+    /// A change of control mode is announced over RTT as `mode: <name>` and
+    /// published on the state stream. Relative inputs are moved to the
+    /// parameter's value, and the knob only takes control once it has been
+    /// turned from there. With pickup on, an absolute knob away from the
+    /// value must also pass through it first.
+    async fn select_parameter(&mut self) {
+        let mode = self.selected_mode();
+        if mode != self.state.mode {
+            self.state.mode = mode;
+            rprintln!("mode: {}", mode.name());
+            self.shared.set_control_mode(mode).await;
+        }
+        self.knob.set_position(self.selected_position());
+        self.anchor = self.knob.last_good();
+        self.engaged = false;
+        self.pickup.arm(mode, self.anchor, self.selected_position());
+        if self.shared.get_knob_pickup().await && !self.nudging() {
+            if let Some(above) = self.pickup.pending(mode) {
+                rprintln!(
+                    "knob: turn {} to pick up",
+                    if above { "down" } else { "up" }
+                );
+            }
+        }
+    }
+
+    /// Check whether the knob has picked up the current mode's parameter
+    ///
+    /// With pickup off this is always the case; with it on, a pending
+    /// pickup completes (and is logged) once the knob reaches the value.
+    ///
+    /// # Arguments
+    /// * `fine` - Fractional knob position (0.0 to LEVELS-1)
+    async fn picked_up(&mut self, fine: f32) -> bool {
+        if !self.shared.get_knob_pickup().await {
+            self.pickup.clear();
+            return true;
+        }
+        // Fine mode nudges from the current value: nothing to pick up
+        if self.nudging() {
+            return true;
+        }
+        let mode = self.state.mode;
+        let pending = self.pickup.pending(mode).is_some();
+        let picked_up = self.pickup.update(mode, fine, self.selected_position());
+        if pending && picked_up {
+            rprintln!("knob: picked up");
+        }
+        picked_up
+    }
+
+    /// Adjust the frame rate from the knob
+    ///
+    /// Applies hysteresis so ADC noise doesn't thrash the RGB task's tick
+    /// time, and commits a changed rate to the shared state.
+    ///
+    /// Outside the wizard the rate is held at or above the marked
+    /// flicker-fusion threshold; the wizard's sweep may go below it so the
+    /// threshold can be found again. The strobe guard applies throughout.
+    ///
+    /// # Arguments
+    /// * `fine` - Fractional knob position (0.0 to LEVELS-1)
+    async fn adjust_frame_rate(&mut self, fine: f32) {
+        let requested = level_to_frame_rate(fine, self.rate_floor, LEVELS);
+        // While held, only a turn away from the held request counts
+        let current = self.held_rate.unwrap_or(self.state.frame_rate as f32);
+        if (requested - current).abs() < FRAME_RATE_HYSTERESIS {
+            return;
+        }
+        let frame_rate = requested.round() as u64;
+        self.state.frame_rate = match self.wizard {
+            Some(_) => self.shared.set_sweep_frame_rate(frame_rate).await,
+            None => self.shared.set_frame_rate(frame_rate).await,
+        };
+        self.held_rate = (self.state.frame_rate > frame_rate).then_some(requested);
+        if self.held_rate.is_some() {
+            let limit = match self.shared.get_fusion_floor().await {
+                Some(floor) if floor == self.state.frame_rate && self.wizard.is_none() => {
+                    "fusion threshold"
+                }
+                _ => "strobe guard",
+            };
+            rprintln!("frame rate held at {} {} fps", limit, self.state.frame_rate);
+        }
+    }
+
+    /// Set a saturated hue from the knob
+    ///
+    /// The knob's travel spans the color wheel once, red at both ends. The
+    /// brightest channel stays at the brightest current level (full if all
+    /// are dark), so the brightness holds while the hue turns.
+    ///
+    /// # Arguments
+    /// * `fine` - Fractional knob position (0.0 to LEVELS-1)
+    fn adjust_hue(&mut self, fine: f32) {
+        let hue = (fine / (LEVELS - 1) as f32 * 360.0).round() as u32;
+        let value = match self.state.levels.iter().copied().max() {
+            Some(0) | None => LEVELS - 1,
+            Some(value) => value,
+        };
+        self.state.levels = hsv_to_rgb(hue, 100, value, LEVELS);
+    }
+
+    /// Whether the knob is nudging a level in fine mode
+    fn nudging(&self) -> bool {
+        self.fine_mode && self.state.mode.channel().is_some()
+    }
+
+    /// Set a channel's level from the knob
+    ///
+    /// Normally the level follows the knob's position. In fine mode it is
+    /// nudged instead by the knob's travel since the last nudge, scaled by
+    /// `KNOB_FINE_SCALE`, and the part of a level turned so far is kept in
+    /// the channel's carry. Travel under `KNOB_FINE_NOISE` is left to add
+    /// up, so noise can't creep the level, and the level stops at either
+    /// end without storing up travel past it.
+    ///
+    /// # Arguments
+    /// * `channel` - Channel index (0=red, 1=green, 2=blue)
+    /// * `fine` - Fractional knob position (0.0 to LEVELS-1)
+    /// * `level` - Knob position as a level
+    fn adjust_level(&mut self, channel: usize, fine: f32, level: u32) {
+        if !self.fine_mode {
+            self.state.levels[channel] = level;
+            return;
+        }
+        let moved = fine - self.fine_last;
+        if moved.abs() < KNOB_FINE_NOISE {
+            return;
+        }
+        self.fine_last = fine;
+        let position = self.state.levels[channel] as f32 + self.fine_carry[channel];
+        let position = (position + moved * KNOB_FINE_SCALE).clamp(0.0, (LEVELS - 1) as f32);
+        let level = position.floor();
+        self.state.levels[channel] = level as u32;
+        self.fine_carry[channel] = position - level;
+    }
+
+    /// Record and report the knob's raw range over a sweep
+    ///
+    /// Blocks the UI for the duration; the LEDs keep running.
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to sample
+    async fn sweep(&mut self, seconds: u64) {
+        rprintln!("sweep: turn the knob fully both ways within {}s", seconds);
+        let duration = Duration::from_secs(seconds);
+        match self.knob.sweep_diagnostics(duration).await {
+            Some(report) => {
+                rprintln!(
+                    "sweep: raw min {} max {} mean {} ({} samples, {} rejected)",
+                    report.min,
+                    report.max,
+                    report.mean,
+                    report.samples,
+                    report.rejected
+                );
+                if KnobCalibration::new(report.min, report.max).is_some() {
+                    rprintln!(
+                        "sweep: to map this range, send set knobcal {} {}",
+                        report.min,
+                        report.max
+                    );
+                }
+            }
+            None => rprintln!("sweep: no readings"),
+        }
+    }
+
+    /// Stream raw knob readings to the host
+    ///
+    /// Blocks the UI for the duration; the LEDs keep running.
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to stream
+    async fn stream_adc(&mut self, seconds: u64) {
+        rprintln!("stream: adc for {}s", seconds);
+        match self.knob.stream_raw(Duration::from_secs(seconds)).await {
+            Some(samples) => rprintln!("stream: done, {} samples", samples),
+            None => rprintln!("stream: no raw readings from this input"),
+        }
+    }
+
+    /// Show each test pattern in turn, then restore the levels
+    ///
+    /// Blocks the UI for the duration. Each pattern is logged with its
+    /// requested levels and the output levels the calibration turns them
+    /// into.
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to hold each pattern
+    async fn test_pattern(&mut self, seconds: u64) {
+        let saved = self.shared.get_rgb_levels().await;
+        let hold = Duration::from_secs(seconds);
+        for pattern in TEST_PATTERNS {
+            self.shared
+                .set_rgb_levels(|rgb| *rgb = pattern.levels)
+                .await;
+            let [red, green, blue] = pattern.levels;
+            let [out_red, out_green, out_blue] = self.shared.get_output_levels().await;
+            rprintln!(
+                "pattern: {} requested {} {} {} output {} {} {}",
+                pattern.name,
+                red,
+                green,
+                blue,
+                out_red,
+                out_green,
+                out_blue
+            );
+            Timer::after(hold).await;
+        }
+        self.shared.set_rgb_levels(|rgb| *rgb = saved).await;
+        self.state.levels = saved;
+        rprintln!("pattern: done");
+        self.state.show(self.shared).await;
+    }
+
+    /// Light each LED pin alone in turn so the wiring can be labeled
+    ///
+    /// Each channel is held steadily at full level with the others off,
+    /// using the freeze-frame hold, while its color and pin name are
+    /// printed. Levels, enable mask and hold are restored afterwards.
+    ///
+    /// # Arguments
+    /// * `seconds` - How long to light each pin
+    async fn identify_pins(&mut self, seconds: u64) {
+        let saved_levels = self.shared.get_rgb_levels().await;
+        let saved_enable = self.shared.get_channel_enable().await;
+        let saved_hold = self.shared.get_hold_channel().await;
+        let names = ["RED", "GREEN", "BLUE"];
+        self.shared.set_channel_enable([true; 3]).await;
+        for (channel, (name, pin)) in names.iter().zip(LED_PIN_NAMES).enumerate() {
+            self.shared
+                .set_rgb_levels(|rgb| {
+                    *rgb = [0; 3];
+                    rgb[channel] = LEVELS - 1;
+                })
+                .await;
+            self.shared.set_hold_channel(Some(channel)).await;
+            rprintln!("{} on {}", name, pin);
+            Timer::after(Duration::from_secs(seconds)).await;
+        }
+        self.shared.set_hold_channel(saved_hold).await;
+        self.shared.set_channel_enable(saved_enable).await;
+        self.shared.set_rgb_levels(|rgb| *rgb = saved_levels).await;
+        self.state.levels = saved_levels;
+        self.state.enabled = saved_enable;
+        rprintln!("identify pins: done");
+        self.state.show(self.shared).await;
+    }
+
+    /// Find each channel's minimum visible level with the user's help
+    ///
+    /// Each channel is held alone and ramped up from level 1, one level
+    /// every `VISIBLE_STEP_MS`, with the matrix, lookup tables and old
+    /// thresholds set aside so the raw output levels are shown. Tapping A at
+    /// the first visible light records that level; tapping B, or reaching
+    /// full level without a tap, keeps the channel's old threshold. Levels,
+    /// enable mask, hold and corrections are restored afterwards and the
+    /// new thresholds committed.
+    async fn find_min_visible(&mut self) {
+        let saved_levels = self.shared.get_rgb_levels().await;
+        let saved_enable = self.shared.get_channel_enable().await;
+        let saved_hold = self.shared.get_hold_channel().await;
+        let saved_matrix = self.shared.get_color_matrix().await;
+        let saved_luts = self.shared.get_channel_luts().await;
+        let mut min_visible = self.shared.get_min_visible().await;
+        self.shared.set_channel_enable([true; 3]).await;
+        self.shared.set_color_matrix(ColorMatrix::IDENTITY).await;
+        for channel in 0..3 {
+            self.shared.set_channel_lut(channel, Lut::IDENTITY).await;
+        }
+        self.shared.set_min_visible([MIN_VISIBLE_NONE; 3]).await;
+        rprintln!("visible: tap A at the first light, B to skip");
+
+        let names = ["red", "green", "blue"];
+        for (channel, name) in names.iter().enumerate() {
+            self.shared.set_hold_channel(Some(channel)).await;
+            self.wait_buttons_released().await;
+            let mut outcome = "not seen";
+            'ramp: for level in MIN_VISIBLE_NONE..LEVELS {
+                self.shared
+                    .set_rgb_levels(|rgb| {
+                        *rgb = [0; 3];
+                        rgb[channel] = level;
+                    })
+                    .await;
+                let until = Instant::now() + Duration::from_millis(VISIBLE_STEP_MS);
+                while Instant::now() < until {
+                    if self.button_a.is_low() {
+                        min_visible[channel] = level;
+                        outcome = "seen";
+                        break 'ramp;
+                    }
+                    if self.button_b.is_low() {
+                        outcome = "skipped";
+                        break 'ramp;
+                    }
+                    Timer::after(Duration::from_millis(VISIBLE_POLL_MS)).await;
+                }
+            }
+            rprintln!(
+                "visible: {} {}, from level {}",
+                name,
+                outcome,
+                min_visible[channel]
+            );
+        }
+        self.wait_buttons_released().await;
+
+        self.shared.set_hold_channel(saved_hold).await;
+        self.shared.set_channel_enable(saved_enable).await;
+        self.shared.set_color_matrix(saved_matrix).await;
+        for (channel, lut) in saved_luts.into_iter().enumerate() {
+            self.shared.set_channel_lut(channel, lut).await;
+        }
+        self.shared.set_min_visible(min_visible).await;
+        self.shared.set_rgb_levels(|rgb| *rgb = saved_levels).await;
+        self.state.levels = saved_levels;
+        self.state.enabled = saved_enable;
+        rprintln!("visible: done");
+        self.state.show(self.shared).await;
+    }
+
+    /// Wait until neither button is held
+    async fn wait_buttons_released(&mut self) {
+        while self.button_a.is_low() || self.button_b.is_low() {
+            Timer::after(Duration::from_millis(VISIBLE_POLL_MS)).await;
+        }
+    }
+
+    /// Start the calibration wizard at its first step
+    async fn start_wizard(&mut self) {
+        rprintln!("wizard started (long press A+B to cancel)");
+        self.enter_wizard_step(WizardStep::FIRST).await;
+    }
+
+    /// Begin a calibration wizard step and print its instructions
+    ///
+    /// The knob must be turned before it takes control in the new step, so
+    /// a value set in the previous step is not overwritten.
+    ///
+    /// # Arguments
+    /// * `step` - Step to begin
+    async fn enter_wizard_step(&mut self, step: WizardStep) {
+        self.wizard = Some(step);
+        self.select_parameter().await;
+        rprintln!(
+            "wizard step {}/{}: {}",
+            step.number(),
+            WizardStep::COUNT,
+            step.prompt()
+        );
+    }
+
+    /// Confirm the current wizard step and move on to the next one
+    ///
+    /// # Arguments
+    /// * `step` - Step being confirmed
+    async fn advance_wizard(&mut self, step: WizardStep) {
+        if step == WizardStep::White {
+            self.state.white = self.state.levels;
+            self.shared.set_white_reference(self.state.white).await;
+            rprintln!("white captured");
+        }
+        if step == WizardStep::FrameRate {
+            // Where flicker stopped is the user's fusion threshold
+            self.shared
+                .set_fusion_floor(Some(self.state.frame_rate))
+                .await;
+            rprintln!("fusion threshold marked at {} fps", self.state.frame_rate);
+        }
+        match step.next() {
+            Some(next) => self.enter_wizard_step(next).await,
+            None => {
+                self.wizard = None;
+                rprintln!("wizard done");
+                self.select_parameter().await;
+                self.state.show(self.shared).await;
+            }
+        }
+    }
+
+    /// Let the knob adjust the parameter of the current wizard step
+    ///
+    /// # Arguments
+    /// * `fine` - Fractional knob position (0.0 to LEVELS-1)
+    /// * `level` - Knob position as a brightness level
+    async fn adjust_wizard(&mut self, fine: f32, level: u32) {
+        match self.wizard {
+            Some(WizardStep::FrameRate) => self.adjust_frame_rate(fine).await,
+            Some(step) => {
+                if let Some(channel) = step.channel() {
+                    self.state.levels[channel] = level;
+                }
+            }
+            None => (),
+        }
+    }
+
+    /// Handle a gesture during numeric entry
+    ///
+    /// Tap B adds one to the current digit, long press B moves to the next
+    /// digit or confirms the last, tap A switches the parameter and long
+    /// press A+B cancels.
+    ///
+    /// # Arguments
+    /// * `entry` - Number being entered
+    /// * `gesture` - Recognized gesture
+    async fn handle_entry(&mut self, mut entry: NumberEntry, gesture: Gesture) {
+        match gesture {
+            Gesture::Tap(Buttons::B) => entry.increment(),
+            Gesture::Tap(Buttons::A) => entry.next_target(),
+            Gesture::LongPress(Buttons::B) => {
+                if let Some(value) = entry.advance() {
+                    self.state.entry = None;
+                    self.commit_entry(entry.target(), value).await;
+                    return;
+                }
+            }
+            Gesture::LongPress(Buttons::Both) => {
+                self.state.entry = None;
+                rprintln!("entry cancelled");
+                return;
+            }
+            _ => return,
+        }
+        self.state.entry = Some(entry);
+        entry.show();
+    }
+
+    /// Apply a number entered with button taps
+    ///
+    /// Out-of-range values are clamped: frame rates to 10-160 fps, the
+    /// strobe guard's floor and the fusion threshold, if marked, levels to
+    /// 0-15.
+    ///
+    /// # Arguments
+    /// * `target` - Parameter to set
+    /// * `value` - Entered number
+    async fn commit_entry(&mut self, target: ControlMode, value: u32) {
+        match target.channel() {
+            Some(channel) => {
+                let level = value.min(LEVELS - 1);
+                self.state.levels[channel] = level;
+                self.shared.set_rgb_levels(|rgb| rgb[channel] = level).await;
+            }
+            None => {
+                self.state.frame_rate = self.shared.set_frame_rate(value as u64).await;
+            }
+        }
+        rprintln!("entry: {} set", target.name());
+        self.select_parameter().await;
+        self.state.show(self.shared).await;
+    }
+
+    /// Reset the calibration to the built-in defaults
+    ///
+    /// Sets full white with every channel enabled at the default frame
+    /// rate, ending any wizard, numeric entry or animation. The white
+    /// reference, presets and settings are kept. The knob is handed the
+    /// selected parameter afresh, so the press that triggered the reset
+    /// doesn't also adjust it.
+    async fn reset_defaults(&mut self) {
+        self.wizard = None;
+        self.state.entry = None;
+        if self.shared.get_animation().await != Animation::Off {
+            self.shared.set_animation(Animation::Off).await;
+        }
+        self.state.enabled = [true; 3];
+        self.shared.set_channel_enable(self.state.enabled).await;
+        self.state.levels = self.shared.set_rgb(DEFAULT_LEVELS).await;
+        self.state.frame_rate = self.shared.set_frame_rate(DEFAULT_FRAME_RATE).await;
+        self.pending_since = None;
+        self.select_parameter().await;
+        rprintln!("reset");
+        self.state.show(self.shared).await;
+    }
+
+    /// Lock or unlock the settings against the knob and buttons
+    ///
+    /// Locking ends any numeric entry and lets go of the knob; the shared
+    /// state keeps its values and host commands still apply. The knob stays
+    /// idle while locked, so it can't cancel the press that unlocks.
+    /// Unlocking takes the knob's current position as the new anchor, as at
+    /// startup, so the parameter only moves once the knob is turned again.
+    async fn toggle_lock(&mut self) {
+        self.locked = !self.locked;
+        if self.locked {
+            self.state.entry = None;
+            self.engaged = false;
+            self.anchor = self.knob.last_good();
+            rprintln!("locked (hold B 2 s and release to unlock)");
+        } else {
+            self.select_parameter().await;
+            rprintln!("unlocked");
+        }
+    }
+
+    /// Load the next preset
+    ///
+    /// By default every slot is visited in turn: an empty one is selected
+    /// for the save gesture but loads nothing, with a warning. After
+    /// `preset cycle tagged` or `white`, only those presets are visited.
+    async fn cycle_presets(&mut self) {
+        let filter = self.shared.get_preset_cycle().await;
+        let loaded = if filter == PresetFilter::All {
+            let current = self.preset_slot.or(self.shared.get_active_preset().await);
+            let slot = current.map_or(0, |slot| (slot + 1) % PRESET_SLOTS);
+            self.preset_slot = Some(slot);
+            match load_preset(self.shared, slot).await {
+                Some(preset) => Some((slot, preset)),
+                None => {
+                    rprintln!(
+                        "warning: preset {} is empty (very long press A saves to it)",
+                        slot + 1
+                    );
+                    return;
+                }
+            }
+        } else {
+            load_next(self.shared, filter).await
+        };
+        match loaded {
+            Some((slot, preset)) => {
+                self.preset_slot = Some(slot);
+                self.state.levels = preset.levels;
+                self.state.frame_rate = preset.frame_rate;
+                self.state.preset = Some((slot, preset));
+                let tag = preset.tag.as_ref().map_or("-", Tag::as_str);
+                rprintln!("preset {} {}", slot + 1, tag);
+                self.state.show(self.shared).await;
+            }
+            None => rprintln!("no {} presets", filter.name()),
+        }
+    }
+
+    /// Save the levels and frame rate to the selected preset slot
+    ///
+    /// The slot is the one last selected with the buttons, or else the
+    /// preset last loaded, or else slot 1. A tag already on the slot is
+    /// kept.
+    async fn save_preset(&mut self) {
+        let slot = match self.preset_slot {
+            Some(slot) => slot,
+            None => self.shared.get_active_preset().await.unwrap_or(0),
+        };
+        let tag = self.shared.get_presets().await[slot].and_then(|preset| preset.tag);
+        let preset = Preset {
+            levels: self.state.levels,
+            frame_rate: self.state.frame_rate,
+            tag,
+        };
+        self.shared.set_preset(slot, Some(preset)).await;
+        self.shared.set_active_preset(Some(slot)).await;
+        self.preset_slot = Some(slot);
+        self.state.preset = Some((slot, preset));
+        rprintln!("preset {} saved", slot + 1);
+        self.state.show(self.shared).await;
+    }
+
+    /// Perform the action bound to a button gesture
+    ///
+    /// While the wizard or numeric entry runs, only its own gestures and
+    /// the emergency off are recognized. While locked, only the lock
+    /// toggle and the emergency off are.
+    ///
+    /// # Arguments
+    /// * `gesture` - Recognized gesture
+    async fn handle_gesture(&mut self, gesture: Gesture) {
+        // The emergency off works in every mode, including the wizard
+        if gesture == Gesture::DoubleTap(Buttons::Both) {
+            let engaged = !self.shared.emergency_off();
+            self.shared.set_emergency_off(engaged).await;
+            if engaged {
+                self.state.levels = [0; 3];
+                rprintln!("EMERGENCY OFF: LEDs held dark (double tap A+B or `resume` to release)");
+            } else {
+                rprintln!("emergency off released");
+            }
+            self.state.show(self.shared).await;
+            return;
+        }
+
+        // Holding B for the very long press locks or unlocks the settings,
+        // in every mode
+        if gesture == Gesture::VeryLongPress(Buttons::B) {
+            self.toggle_lock().await;
+            return;
+        }
+        if self.locked {
+            return;
+        }
+
+        // Holding A+B for the very long press resets to the defaults, in
+        // every mode
+        if gesture == Gesture::VeryLongPress(Buttons::Both) {
+            self.reset_defaults().await;
+            return;
+        }
+
+        if let Some(entry) = self.state.entry {
+            self.handle_entry(entry, gesture).await;
+            return;
+        }
+
+        if let Some(step) = self.wizard {
+            match gesture {
+                Gesture::Tap(Buttons::A) => self.advance_wizard(step).await,
+                Gesture::LongPress(Buttons::Both) => {
+                    self.wizard = None;
+                    rprintln!("wizard cancelled");
+                    self.select_parameter().await;
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        // While comparing frame rates, any tap switches between them
+        if let Gesture::Tap(_) = gesture {
+            if self.state.compare.active.is_some() {
+                if let Some((setpoint, frame_rate)) = toggle_rate_compare(self.shared).await {
+                    self.state.frame_rate = frame_rate;
+                    self.state.compare = self.shared.get_rate_compare().await;
+                    rprintln!("compare: {}", RateCompare::name(setpoint));
+                    self.state.show(self.shared).await;
+                }
+                return;
+            }
+        }
+        match gesture {
+            Gesture::LongPress(Buttons::B) => {
+                // Exact values without the knob, starting with the frame rate
+                let entry = NumberEntry::new(ControlMode::FrameRate);
+                self.state.entry = Some(entry);
+                rprintln!("entry started (tap B +1, long press B next/confirm, tap A parameter)");
+                entry.show();
+            }
+            Gesture::LongPress(Buttons::Both) => {
+                // Breathe the current color, or stop breathing
+                let animation = match self.shared.get_animation().await {
+                    Animation::Breathe { .. } => Animation::Off,
+                    _ => Animation::Breathe {
+                        period_ms: self.shared.get_breathe_period().await,
+                    },
+                };
+                self.shared.set_animation(animation).await;
+                match animation.period_ms() {
+                    Some(period_ms) => rprintln!("anim breathe {} ms", period_ms),
+                    None => rprintln!("anim off"),
+                }
+            }
+            Gesture::LongPress(Buttons::A) => self.cycle_presets().await,
+            Gesture::VeryLongPress(Buttons::A) => self.save_preset().await,
+            Gesture::DoubleTap(Buttons::A) => {
+                // White shortcut: recall the white reference in one update
+                self.state.levels = self.shared.set_rgb(self.state.white).await;
+                rprintln!("white");
+                self.state.show(self.shared).await;
+            }
+            Gesture::DoubleTap(Buttons::B) => {
+                // Capture the current levels as the white reference
+                self.state.white = self.state.levels;
+                self.shared.set_white_reference(self.state.white).await;
+                rprintln!("white captured");
+                self.state.show(self.shared).await;
+            }
+            Gesture::Tap(buttons) => {
+                // Toggle the channel this combination controls, keeping its level
+                let channel = self.button_map.channel(buttons);
+                self.state.enabled[channel] = !self.state.enabled[channel];
+                self.shared.set_channel_enable(self.state.enabled).await;
+                self.state.show(self.shared).await;
+            }
+            _ => (),
+        }
     }
 
     /// Main UI processing loop
     ///
     /// Handles knob input based on button state:
-    /// - No buttons: Frame rate control (10-160 fps in steps of 10)
+    /// - No buttons: Frame rate control (continuous, 50-160 fps), or the
+    ///   hue with `knob hue on`
     /// - A button: Blue brightness control (0-15)
-    /// - B button: Green brightness control (0-15)  
+    /// - B button: Green brightness control (0-15)
     /// - A+B buttons: Red brightness control (0-15)
+    ///
+    /// (The channels are those of the default [`ButtonMap`].)
     pub async fn run(&mut self) -> ! {
-        // Initialize state from current knob position
-        let initial_level = self.knob.measure().await;
-        self.state.frame_rate = Self::level_to_frame_rate(initial_level);
+        // Read the knob position as a boot source; it gives no frame rate if
+        // it can't be read (relative inputs just report the position they
+        // are given)
+        self.rate_floor = self.shared.get_strobe_guard().await.floor();
+        self.knob.set_position(frame_rate_to_level(
+            self.state.frame_rate,
+            self.rate_floor,
+            LEVELS,
+        ));
+        let knob_rate = self
+            .knob
+            .measure_fine()
+            .await
+            .ok()
+            .map(|level| level_to_frame_rate(level, self.rate_floor, LEVELS).round() as u64);
+        self.anchor = self.knob.last_good();
+
+        // Take each starting value from the first boot source that has one
+        let priority = self.boot_priority;
+        let (levels, levels_source) =
+            resolve_boot_source(&priority, self.startup.levels, None, DEFAULT_LEVELS);
+        let (frame_rate, rate_source) = resolve_boot_source(
+            &priority,
+            self.startup.frame_rate,
+            knob_rate,
+            DEFAULT_FRAME_RATE,
+        );
+        self.state.levels = levels;
+        self.state.frame_rate = frame_rate;
+        rprintln!(
+            "boot: levels from {}, frame rate from {}",
+            levels_source.name(),
+            rate_source.name()
+        );
 
-        // Initialize shared state
-        set_rgb_levels(|rgb| {
-            *rgb = self.state.levels;
-        })
-        .await;
-        set_frame_rate(self.state.frame_rate).await;
+        // Initialize shared state; the RGB task stays dark until it is
+        // marked ready, so the first frame already shows the chosen values
+        self.shared
+            .set_rgb_levels(|rgb| {
+                *rgb = self.state.levels;
+            })
+            .await;
+        self.state.frame_rate = self.shared.set_frame_rate(self.state.frame_rate).await;
+        self.shared.mark_state_ready();
 
         // Show initial state
-        self.state.show();
+        self.state.show(self.shared).await;
 
         loop {
             // Read button states
             let button_a_pressed = self.button_a.is_low();
             let button_b_pressed = self.button_b.is_low();
 
-            // Read current knob position (0 to LEVELS-1)
-            let level = self.knob.measure().await;
-
-            // Determine control mode and update appropriate parameter
-            let mut state_changed = false;
-
-            match (button_a_pressed, button_b_pressed) {
-                (false, false) => {
-                    // No buttons: Frame rate control
-                    let new_frame_rate = Self::level_to_frame_rate(level);
-                    if new_frame_rate != self.state.frame_rate {
-                        self.state.frame_rate = new_frame_rate;
-                        set_frame_rate(self.state.frame_rate).await;
-                        state_changed = true;
+            // Warn once when the current sense finds a channel failed open
+            let dead = self.shared.get_dead_leds().await.unwrap_or([false; 3]);
+            if dead != self.state.dead {
+                let names = ["red", "green", "blue"];
+                for (name, (now, before)) in names.iter().zip(dead.iter().zip(self.state.dead)) {
+                    if *now && !before {
+                        rprintln!(
+                            "warning: {} LED lit but not conducting (failed open?)",
+                            name
+                        );
                     }
                 }
-                (true, false) => {
-                    // A button: Blue brightness control
-                    if level != self.state.levels[2] {
-                        self.state.levels[2] = level;
-                        state_changed = true;
+                self.state.dead = dead;
+                self.state.show(self.shared).await;
+            }
+
+            // Note when the thermal ceilings start or stop holding a channel back
+            let throttled = self.shared.get_throttled_channels().await;
+            if throttled != self.state.throttled {
+                let names = ["red", "green", "blue"];
+                let ceilings = self.shared.get_thermal_ceilings().await;
+                for (i, name) in names.iter().enumerate() {
+                    if throttled[i] && !self.state.throttled[i] {
+                        rprintln!("thermal: {} limited to {}", name, ceilings[i]);
                     }
                 }
-                (false, true) => {
-                    // B button: Green brightness control
-                    if level != self.state.levels[1] {
-                        self.state.levels[1] = level;
-                        state_changed = true;
+                self.state.throttled = throttled;
+                self.state.show(self.shared).await;
+            }
+
+            // Note when the estimated current goes over the power budget
+            let budget = self.shared.get_power_budget().await;
+            let estimate = self.shared.get_power_estimate().await;
+            let over_budget = budget.over(estimate);
+            if over_budget != self.state.over_budget {
+                if over_budget {
+                    let (ma, tenths) = milliamps(estimate);
+                    match budget.action {
+                        BudgetAction::Cap => {
+                            rprintln!("power: {}.{} mA over budget, capping", ma, tenths)
+                        }
+                        BudgetAction::Warn => {
+                            rprintln!("warning: {}.{} mA over power budget", ma, tenths)
+                        }
                     }
                 }
-                (true, true) => {
-                    // A+B buttons: Red brightness control
-                    if level != self.state.levels[0] {
-                        self.state.levels[0] = level;
-                        state_changed = true;
+                self.state.over_budget = over_budget;
+                self.state.show(self.shared).await;
+            }
+
+            // Pick up levels, frame rate, display and knob settings changed
+            // by host commands; levels only once our own are committed. Any
+            // value that differs from ours was set by some other source.
+            let sources = self.sources;
+            let shown = (self.state.levels, self.state.frame_rate);
+            if self.pending_since.is_none() {
+                let levels = self.shared.get_rgb_levels().await;
+                for (channel, level) in levels.iter().enumerate() {
+                    if *level != self.state.levels[channel] {
+                        self.sources.levels[channel] = InputSource::Host;
+                        self.fine_carry[channel] = 0.0;
                     }
                 }
+                self.state.levels = levels;
+            }
+            let frame_rate = self.shared.get_frame_rate().await;
+            if frame_rate != self.state.frame_rate {
+                self.sources.frame_rate = InputSource::Host;
+            }
+            self.state.frame_rate = frame_rate;
+            self.state.enabled = self.shared.get_channel_enable().await;
+            self.state.format = self.shared.get_output_format().await;
+            // Machine-readable output logs every change, including those
+            // from commands, animations and fades
+            let changed = (self.state.levels, self.state.frame_rate) != shown;
+            if changed && self.state.format == OutputFormat::Json {
+                self.state.show_machine();
+            }
+            self.state.unit = self.shared.get_frame_rate_unit().await;
+            self.state.sub_frames = self.shared.get_sub_frames().await;
+            self.state.white = self.shared.get_white_reference().await;
+            self.state.compare = self.shared.get_rate_compare().await;
+            self.state.preset = match self.shared.get_active_preset().await {
+                Some(slot) => self.shared.get_presets().await[slot].map(|preset| (slot, preset)),
+                None => None,
+            };
+            self.button_map = self.shared.get_button_map().await;
+            let hue_mode = self.shared.get_knob_hue().await;
+            if hue_mode != self.hue_mode {
+                self.hue_mode = hue_mode;
+                self.select_parameter().await;
+            }
+            let rate_floor = self.shared.get_strobe_guard().await.floor();
+            if rate_floor != self.rate_floor {
+                self.rate_floor = rate_floor;
+                self.select_parameter().await;
+            }
+            let fine_mode = self.shared.get_knob_fine().await;
+            if fine_mode != self.fine_mode {
+                self.fine_mode = fine_mode;
+                self.fine_carry = [0.0; 3];
+                self.select_parameter().await;
+            }
+            self.knob
+                .set_response(self.shared.get_knob_response().await);
+            self.knob
+                .set_calibration(self.shared.get_knob_calibration().await);
+            self.knob
+                .set_deadbands(self.shared.get_knob_deadbands().await);
+            self.coalesce = Duration::from_millis(self.shared.get_knob_coalesce_ms().await);
+            self.poll = Duration::from_millis(self.shared.get_poll_ms().await);
+
+            // Read current knob position, both continuous and as a level
+            // A failed measurement keeps the previous reading
+            let fine = match self.knob.measure_fine().await {
+                Ok(fine) => fine,
+                Err(_) => self.knob.last_good(),
+            };
+            let level = self.knob.level(fine);
+
+            // In debug mode, show raw ADC and derived level as the knob moves
+            if level != self.last_level && self.shared.get_debug_output().await {
+                rprintln!(
+                    "knob: raw {} fine {} level {}",
+                    self.knob.raw(),
+                    fine,
+                    level
+                );
+            }
+            self.last_level = level;
+
+            // Let the user feel for the center of the travel, if asked to
+            if self.center.update(level, self.knob.fraction(fine)) {
+                let indicator = self.shared.get_center_indicator().await;
+                if indicator != CenterIndicator::Off {
+                    rprintln!("knob: center");
+                }
+                if indicator == CenterIndicator::Cue {
+                    self.shared
+                        .start_led_cue(Duration::from_millis(CENTER_CUE_MS))
+                        .await;
+                }
             }
 
-            // Update shared RGB state if brightness levels changed
-            if state_changed {
-                if button_a_pressed || button_b_pressed {
-                    set_rgb_levels(|rgb| {
+            // A host write to the parameter under the knob holds it for the
+            // host: the knob lets go until it is physically turned again
+            if self.engaged && self.sources.get(self.state.mode) == Some(InputSource::Host) {
+                self.engaged = false;
+                self.anchor = fine;
+                self.pickup
+                    .arm(self.state.mode, fine, self.selected_position());
+            }
+
+            // Let the undo history record the committed state once settled
+            self.shared.observe_checkpoint().await;
+
+            // Account the time since the last pass to the session statistics
+            self.shared
+                .record_session(self.state.levels, self.state.frame_rate, self.state.mode)
+                .await;
+
+            // Let the host see the physical inputs
+            self.shared
+                .set_input_snapshot(InputSnapshot {
+                    raw: self.knob.raw(),
+                    level,
+                    buttons: [button_a_pressed, button_b_pressed],
+                })
+                .await;
+
+            // Run a knob sweep if the host asked for one
+            if let Some(seconds) = self.shared.take_sweep_request() {
+                self.sweep(seconds).await;
+            }
+
+            // Stream raw readings if the host asked for them
+            if let Some(seconds) = self.shared.take_adc_stream_request() {
+                self.stream_adc(seconds).await;
+            }
+
+            // Show the test patterns if the host asked for them
+            if let Some(seconds) = self.shared.take_test_pattern_request() {
+                self.test_pattern(seconds).await;
+            }
+
+            // Light each pin in turn if the host asked for it
+            if let Some(seconds) = self.shared.take_identify_pins_request() {
+                self.identify_pins(seconds).await;
+            }
+
+            // Find the minimum visible levels if the host asked for it
+            if self.shared.take_min_visible_request() {
+                self.find_min_visible().await;
+            }
+
+            // Start the wizard if the host asked for it
+            if self.wizard.is_none() && self.shared.take_wizard_request() {
+                self.start_wizard().await;
+            }
+
+            // Handle completed button gestures
+            let now = Instant::now();
+            let gesture = self
+                .gestures
+                .update(button_a_pressed, button_b_pressed, now);
+            if let Some(gesture) = gesture {
+                self.handle_gesture(gesture).await;
+            }
+
+            // A button change selects a new mode once it has settled, so
+            // a staggered A+B press doesn't pass through A or B alone; the
+            // knob takes over only once it has been turned, which also
+            // cancels any gesture. During numeric entry it never does, so a
+            // bumped knob can't swallow the long press that moves on
+            let buttons = [button_a_pressed, button_b_pressed];
+            if let Some(buttons) = self.settle.update(buttons, now) {
+                self.buttons = buttons;
+                if !self.locked {
+                    self.select_parameter().await;
+                }
+            }
+            let settling = self.settle.settling();
+            let picked_up = self.picked_up(fine).await;
+            let turned = (fine - self.anchor).abs() >= ENGAGE_THRESHOLD;
+            let idle = self.locked || self.state.entry.is_some();
+            if !idle && !settling && !self.engaged && picked_up && turned {
+                self.engaged = true;
+                self.gestures.cancel();
+                self.sources.set(self.state.mode, InputSource::Knob);
+            }
+            if self.sources != sources {
+                self.shared.set_parameter_sources(self.sources).await;
+            }
+
+            // Fine nudges are measured from where the knob was when it
+            // took over
+            if !self.engaged || !self.nudging() {
+                self.fine_last = fine;
+            }
+
+            // Determine control mode and update appropriate parameter
+            let levels = self.state.levels;
+            let frame_rate = self.state.frame_rate;
+
+            match self.buttons {
+                _ if !self.engaged || settling => (),
+                // The wizard step decides what the knob controls
+                // The knob is idle while a number is entered
+                _ if self.state.entry.is_some() => (),
+                _ if self.wizard.is_some() => self.adjust_wizard(fine, level).await,
+                // No buttons: Hue control, if selected
+                [false, false] if self.hue_mode => self.adjust_hue(fine),
+                // No buttons: Frame rate control
+                [false, false] => self.adjust_frame_rate(fine).await,
+                // A button: Blue brightness control (by default)
+                [true, false] => {
+                    self.adjust_level(self.button_map.channel(Buttons::A), fine, level)
+                }
+                // B button: Green brightness control (by default)
+                [false, true] => {
+                    self.adjust_level(self.button_map.channel(Buttons::B), fine, level)
+                }
+                // A+B buttons: Red brightness control (by default)
+                [true, true] => {
+                    self.adjust_level(self.button_map.channel(Buttons::Both), fine, level)
+                }
+            }
+
+            // Update shared RGB state once the levels settle or the
+            // coalescing window runs out, whichever comes first
+            let moved = self.state.levels != levels;
+            if moved && self.pending_since.is_none() {
+                self.pending_since = Some(now);
+            }
+            let commit = self
+                .pending_since
+                .is_some_and(|since| !moved || now.duration_since(since) >= self.coalesce);
+            if commit {
+                self.pending_since = None;
+                self.shared
+                    .set_rgb_levels(|rgb| {
                         *rgb = self.state.levels;
                     })
                     .await;
+            }
+            if commit || self.state.frame_rate != frame_rate {
+                self.state.show(self.shared).await; // Display updated state
+            }
+
+            // With blending on, pass the knob's position between levels on
+            // to the RGB task, but only against a committed level
+            let knob_level = self.engaged && self.wizard.is_none() && self.state.entry.is_none();
+            if knob_level && self.pending_since.is_none() && self.shared.get_knob_blend().await {
+                if let Some(channel) = self.state.mode.channel() {
+                    let mut fractions = self.shared.get_level_fractions().await;
+                    // In fine mode the carry is the position between levels
+                    let fraction = if self.fine_mode {
+                        let carry = self.fine_carry[channel] * FRACTION_ONE as f32;
+                        (carry as u32).min(FRACTION_ONE - 1)
+                    } else {
+                        self.knob.fraction(fine)
+                    };
+                    if fractions[channel] != fraction {
+                        fractions[channel] = fraction;
+                        self.shared.set_level_fractions(fractions).await;
+                    }
                 }
-                self.state.show(); // Display updated state
             }
 
-            // Poll at 20Hz (every 50ms) to balance responsiveness and CPU usage
-            Timer::after_millis(50).await;
+            // Poll at the configured rate (20Hz by default) to balance
+            // responsiveness and CPU usage
+            Timer::after(self.poll).await;
         }
     }
 }
@@ -0,0 +1,66 @@
+//! Hardware Watchdog
+//!
+//! Without current-limiting resistors, an LED left fully on by a hung scan
+//! loop can overheat. The nRF's watchdog timer (WDT) guards against that:
+//! the RGB task feeds it every frame (see [`Rgb::with_watchdog`]), and if
+//! the loop stops, e.g. stuck on an await that never completes or a mutex
+//! held too long, the chip resets and comes back up with the LEDs dark
+//! until the UI has chosen its startup levels.
+//!
+//! Feeding is the RGB task's job alone; a hang in any other task only
+//! trips the watchdog if it starves the scan loop too. Waits longer than
+//! [`WATCHDOG_FEED_MS`] (slow motion, idling dark, waiting for the startup
+//! levels) are broken up to feed it, so no normal operation lets it run
+//! out. The watchdog is paused while the CPU is halted by a debugger, so
+//! breakpoints don't reset the board.
+
+use crate::*;
+
+use microbit_bsp::embassy_nrf::{
+    peripherals::WDT,
+    wdt::{self, Watchdog, WatchdogHandle},
+};
+
+/// Time without a feed after which the watchdog resets the chip, in ms
+///
+/// The slowest frame at a normal frame rate lasts 100 ms
+/// (`MIN_FRAME_RATE`), and longer waits feed every [`WATCHDOG_FEED_MS`],
+/// so 2 s leaves a margin of 20 slow frames for a late wake-up or a
+/// busy moment in another task, while still cutting off a stuck LED
+/// quickly.
+pub const WATCHDOG_TIMEOUT_MS: u32 = 2000;
+
+/// Longest the RGB task waits without feeding the watchdog, in ms
+pub const WATCHDOG_FEED_MS: u64 = 250;
+
+/// Watchdog clock rate (the 32.768 kHz low-frequency clock)
+const WATCHDOG_TICK_HZ: u32 = 32_768;
+
+/// Start the watchdog
+///
+/// Once started it can't be stopped or reconfigured until the next reset,
+/// so this is called only once the RGB task is about to run.
+///
+/// # Arguments
+/// * `wdt` - Watchdog peripheral
+///
+/// # Returns
+/// The handle the RGB task feeds it through, or `None` if it was already
+/// running with another configuration (e.g. started by a bootloader); it
+/// then can't be fed and resets the chip when it runs out
+pub fn start_watchdog(wdt: WDT) -> Option<WatchdogHandle> {
+    let mut config = wdt::Config::default();
+    config.timeout_ticks = WATCHDOG_TICK_HZ * WATCHDOG_TIMEOUT_MS / 1000;
+    config.run_during_sleep = true;
+    config.run_during_debug_halt = false;
+    match Watchdog::try_new::<1>(wdt, config) {
+        Ok((_watchdog, [handle])) => {
+            rprintln!("watchdog: {} ms", WATCHDOG_TIMEOUT_MS);
+            Some(handle)
+        }
+        Err(_) => {
+            rprintln!("watchdog: error: already running with another configuration");
+            None
+        }
+    }
+}
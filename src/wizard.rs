@@ -0,0 +1,82 @@
+//! Guided Calibration Wizard
+//!
+//! Walks the user through the calibration procedure one step at a time over
+//! RTT: red, green and blue levels, white capture and a frame-rate sweep. The
+//! UI drives the steps; this module only defines their order and prompts.
+
+/// One step of the calibration wizard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WizardStep {
+    /// Knob sets the red level
+    Red,
+    /// Knob sets the green level
+    Green,
+    /// Knob sets the blue level
+    Blue,
+    /// Confirming captures the current levels as the white reference
+    White,
+    /// Knob sweeps the frame rate
+    FrameRate,
+}
+
+impl WizardStep {
+    /// Step the wizard starts with
+    pub const FIRST: Self = WizardStep::Red;
+
+    /// Total number of steps
+    pub const COUNT: usize = 5;
+
+    /// Step following this one
+    ///
+    /// # Returns
+    /// The next step, or `None` after the last step
+    pub fn next(self) -> Option<Self> {
+        match self {
+            WizardStep::Red => Some(WizardStep::Green),
+            WizardStep::Green => Some(WizardStep::Blue),
+            WizardStep::Blue => Some(WizardStep::White),
+            WizardStep::White => Some(WizardStep::FrameRate),
+            WizardStep::FrameRate => None,
+        }
+    }
+
+    /// Position of this step, counting from 1
+    pub fn number(self) -> usize {
+        match self {
+            WizardStep::Red => 1,
+            WizardStep::Green => 2,
+            WizardStep::Blue => 3,
+            WizardStep::White => 4,
+            WizardStep::FrameRate => 5,
+        }
+    }
+
+    /// Channel the knob adjusts in this step
+    ///
+    /// # Returns
+    /// Channel index (0=red, 1=green, 2=blue), or `None` for steps that
+    /// don't set a level
+    pub fn channel(self) -> Option<usize> {
+        match self {
+            WizardStep::Red => Some(0),
+            WizardStep::Green => Some(1),
+            WizardStep::Blue => Some(2),
+            WizardStep::White | WizardStep::FrameRate => None,
+        }
+    }
+
+    /// Instruction printed when the step begins
+    pub fn prompt(self) -> &'static str {
+        match self {
+            WizardStep::Red => "turn knob to set red, tap A to confirm",
+            WizardStep::Green => "turn knob to set green, tap A to confirm",
+            WizardStep::Blue => "turn knob to set blue, tap A to confirm",
+            WizardStep::White => {
+                "if the LED looks white, tap A to capture it as the white reference"
+            }
+            WizardStep::FrameRate => {
+                "turn knob down until the LED flickers, then up until it stops; tap A to mark it"
+            }
+        }
+    }
+}